@@ -0,0 +1,283 @@
+//! A mock OpenID Connect provider, so logging in with one runs without network access.
+//!
+//! It implements just enough of a real provider for the authorization code flow: discovery,
+//! an authorization endpoint that logs in whoever [`MockIssuer::sign_in_as`] named without
+//! asking, a token endpoint that checks PKCE and the client credentials, and its public keys.
+//! It also counts how often its keys are fetched, and can sign with a key it doesn't publish.
+
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Form, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use realworld_core::{
+    keys::{KeyConfig, KeyRing},
+    oidc::ProviderConfig,
+};
+use reqwest::Url;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+pub const PROVIDER: &str = "mock";
+pub const CLIENT_ID: &str = "realworld";
+pub const CLIENT_SECRET: &str = "mock-secret";
+
+/// The frontend page the mock sends users back to. Nothing listens there, tests read the code
+/// and state off the redirect instead.
+pub const REDIRECT_URL: &str = "http://localhost:3000/oidc/callback";
+
+/// Names the key the mock signs with but doesn't publish.
+const UNPUBLISHED_KID: &str = "unpublished";
+
+const SECRETS: &str = include_str!("../../shuttle_sqlx/Secrets.toml");
+
+/// Who the mock logs in next.
+#[derive(Debug, Clone)]
+pub struct MockIdentity {
+    pub subject: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+/// A code handed out by the authorization endpoint, not yet swapped for tokens.
+struct Grant {
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: Option<String>,
+    identity: MockIdentity,
+}
+
+#[derive(Default)]
+struct Issued {
+    identity: Option<MockIdentity>,
+    grants: HashMap<String, Grant>,
+    /// Signs the next ID token with the unpublished key.
+    unpublished_key: bool,
+    jwks_fetches: usize,
+}
+
+#[derive(Clone)]
+struct Issuer {
+    url: String,
+    keys: KeyRing,
+    unpublished: KeyRing,
+    issued: Arc<Mutex<Issued>>,
+}
+
+pub struct MockIssuer {
+    pub addr: SocketAddr,
+    issuer: Issuer,
+}
+
+impl MockIssuer {
+    pub fn spawn() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind a random port");
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let secrets: toml::Table = SECRETS.parse().expect("invalid Secrets.toml");
+        let secret = |key: &str| secrets[key].as_str().unwrap().to_string();
+        let ring = |kid: &str| {
+            KeyRing::new(
+                kid,
+                &[KeyConfig::rsa(
+                    kid,
+                    &secret("PRIVATE_KEY"),
+                    &secret("PUBLIC_KEY"),
+                )],
+            )
+            .expect("invalid mock issuer keys")
+        };
+
+        let issuer = Issuer {
+            url: format!("http://{addr}"),
+            keys: ring(PROVIDER),
+            unpublished: ring(UNPUBLISHED_KID),
+            issued: Arc::default(),
+        };
+
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .route("/jwks", get(jwks))
+            .with_state(issuer.clone());
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to listen")
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+
+        Self { addr, issuer }
+    }
+
+    /// How a backend is configured to log in with the mock.
+    pub fn provider(&self) -> ProviderConfig {
+        ProviderConfig {
+            name: PROVIDER.to_string(),
+            issuer: self.issuer.url.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: CLIENT_SECRET.to_string(),
+            redirect_url: REDIRECT_URL.to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+        }
+    }
+
+    /// Logs in `identity` on every following authorization request.
+    pub fn sign_in_as(&self, identity: MockIdentity) {
+        self.issuer.issued.lock().unwrap().identity = Some(identity);
+    }
+
+    /// Signs the next ID token with a key missing from the published ones.
+    pub fn sign_with_unpublished_key(&self) {
+        self.issuer.issued.lock().unwrap().unpublished_key = true;
+    }
+
+    /// How often the published keys were fetched.
+    pub fn jwks_fetches(&self) -> usize {
+        self.issuer.issued.lock().unwrap().jwks_fetches
+    }
+
+    /// Visits an authorization URL like a browser would, answering with the code and state
+    /// the mock sent back to the frontend.
+    pub async fn authorize(&self, url: &str) -> (String, String) {
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = http.get(url).send().await.expect("request failed");
+        assert_eq!(response.status(), StatusCode::FOUND, "{url}");
+
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let location = Url::parse(location).unwrap();
+        assert!(location.as_str().starts_with(REDIRECT_URL), "{location}");
+
+        let params: HashMap<_, _> = location.query_pairs().into_owned().collect();
+        (params["code"].clone(), params["state"].clone())
+    }
+}
+
+async fn discovery(State(issuer): State<Issuer>) -> impl IntoResponse {
+    Json(json!({
+        "issuer": issuer.url,
+        "authorization_endpoint": format!("{}/authorize", issuer.url),
+        "token_endpoint": format!("{}/token", issuer.url),
+        "jwks_uri": format!("{}/jwks", issuer.url),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+async fn authorize(
+    State(issuer): State<Issuer>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+    if param("response_type") != "code"
+        || param("client_id") != CLIENT_ID
+        || param("redirect_uri") != REDIRECT_URL
+        || param("code_challenge_method") != "S256"
+        || param("code_challenge").is_empty()
+        || param("state").is_empty()
+        || !param("scope").split(' ').any(|scope| scope == "openid")
+    {
+        return (StatusCode::BAD_REQUEST, "invalid authorization request").into_response();
+    }
+
+    let mut issued = issuer.issued.lock().unwrap();
+    let Some(identity) = issued.identity.clone() else {
+        return (StatusCode::BAD_REQUEST, "nobody to sign in").into_response();
+    };
+
+    let code = uuid::Uuid::new_v4().simple().to_string();
+    issued.grants.insert(
+        code.clone(),
+        Grant {
+            redirect_uri: param("redirect_uri"),
+            code_challenge: param("code_challenge"),
+            nonce: params.get("nonce").cloned(),
+            identity,
+        },
+    );
+
+    let location = Url::parse_with_params(
+        &param("redirect_uri"),
+        &[("code", code), ("state", param("state"))],
+    )
+    .unwrap();
+    (
+        StatusCode::FOUND,
+        [(header::LOCATION, location.to_string())],
+    )
+        .into_response()
+}
+
+async fn token(
+    State(issuer): State<Issuer>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let field = |name: &str| form.get(name).cloned().unwrap_or_default();
+    let error = |error: &str| (StatusCode::BAD_REQUEST, Json(json!({ "error": error })));
+
+    if field("client_id") != CLIENT_ID || field("client_secret") != CLIENT_SECRET {
+        return error("invalid_client").into_response();
+    }
+    if field("grant_type") != "authorization_code" {
+        return error("unsupported_grant_type").into_response();
+    }
+
+    // Codes are single use, even when the exchange fails
+    let grant = issuer.issued.lock().unwrap().grants.remove(&field("code"));
+    let Some(grant) = grant else {
+        return error("invalid_grant").into_response();
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(field("code_verifier").as_bytes()));
+    if grant.redirect_uri != field("redirect_uri") || grant.code_challenge != challenge {
+        return error("invalid_grant").into_response();
+    }
+
+    let now = Utc::now();
+    let identity = grant.identity;
+    let keys = match std::mem::take(&mut issuer.issued.lock().unwrap().unpublished_key) {
+        true => &issuer.unpublished,
+        false => &issuer.keys,
+    };
+    let id_token = keys
+        .encode(&json!({
+            "iss": issuer.url,
+            "sub": identity.subject,
+            "aud": CLIENT_ID,
+            "iat": now.timestamp(),
+            "exp": (now + Duration::minutes(5)).timestamp(),
+            "nonce": grant.nonce,
+            "email": identity.email,
+            "email_verified": identity.email_verified,
+            "preferred_username": identity.preferred_username,
+        }))
+        .unwrap();
+
+    Json(json!({
+        "access_token": uuid::Uuid::new_v4().simple().to_string(),
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+    }))
+    .into_response()
+}
+
+async fn jwks(State(issuer): State<Issuer>) -> impl IntoResponse {
+    issuer.issued.lock().unwrap().jwks_fetches += 1;
+    Json(issuer.keys.jwks().clone())
+}
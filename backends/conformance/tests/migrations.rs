@@ -0,0 +1,156 @@
+//! Schema upgrades of databases that hold data the new schema refuses.
+//!
+//! Each test works on a database of its own, created next to the one in the backend's url and
+//! dropped afterwards, so the suite's own data is never touched.
+
+use realworld_conformance::{database_url, unique};
+use sqlx::{Connection, Executor, PgConnection};
+
+const DISEL_UNIQUE_USERS: &str = "20261018210000";
+
+/// A fresh database, named after `prefix`, next to the one `url` points to.
+struct Scratch {
+    admin: PgConnection,
+    name: String,
+    url: String,
+}
+
+impl Scratch {
+    async fn create(url: &str, prefix: &str) -> Self {
+        let mut admin = PgConnection::connect(url)
+            .await
+            .expect("failed to connect to postgres");
+        let name = unique(prefix);
+        admin
+            .execute(format!("CREATE DATABASE {name}").as_str())
+            .await
+            .expect("failed to create the scratch database");
+
+        let (server, _) = url.rsplit_once('/').expect("no database in url");
+        let url = format!("{server}/{name}");
+        Self { admin, name, url }
+    }
+
+    async fn connect(&self) -> PgConnection {
+        PgConnection::connect(&self.url)
+            .await
+            .expect("failed to connect to the scratch database")
+    }
+
+    async fn drop(mut self) {
+        self.admin
+            .execute(format!("DROP DATABASE {} WITH (FORCE)", self.name).as_str())
+            .await
+            .expect("failed to drop the scratch database");
+    }
+}
+
+#[tokio::test]
+async fn sqlx_reports_users_that_differ_in_case() {
+    let url = database_url("SQLX_DATABASE_URL", "realworld_sqlx");
+    let scratch = Scratch::create(&url, "sqlx_case_").await;
+    let pool = sqlx::PgPool::connect(&scratch.url).await.unwrap();
+
+    // A database from before usernames and emails were unique ignoring case
+    static_next_server::db::prepare_db(&pool).await.unwrap();
+    pool.execute(
+        "
+        DROP INDEX users_username_lower_key;
+        DROP INDEX users_email_lower_key;
+        INSERT INTO users (username, email, hash) VALUES
+            ('Alice', 'alice@example.com', ''),
+            ('alice', 'ALICE@example.com', ''),
+            ('bob', 'bob@example.com', '');
+        ",
+    )
+    .await
+    .unwrap();
+
+    let error = static_next_server::db::prepare_db(&pool)
+        .await
+        .expect_err("duplicates went unnoticed")
+        .to_string();
+    assert!(error.contains("users differ only in case"), "{error}");
+    assert!(error.contains("username 'alice' (ids 1, 2)"), "{error}");
+    assert!(
+        error.contains("email 'alice@example.com' (ids 1, 2)"),
+        "{error}"
+    );
+    assert!(!error.contains("bob"), "{error}");
+
+    pool.execute("UPDATE users SET username = 'alice2', email = 'alice2@example.com' WHERE id = 2")
+        .await
+        .unwrap();
+    static_next_server::db::prepare_db(&pool).await.unwrap();
+    let error = pool
+        .execute(
+            "INSERT INTO users (username, email, hash) VALUES ('BOB', 'other@example.com', '')",
+        )
+        .await
+        .expect_err("the unique index is missing");
+    assert!(
+        error.to_string().contains("users_username_lower_key"),
+        "{error}"
+    );
+
+    pool.close().await;
+    scratch.drop().await;
+}
+
+#[tokio::test]
+async fn diesel_reports_users_that_differ_in_case() {
+    let url = database_url("DISEL_DATABASE_URL", "realworld_disel");
+    let scratch = Scratch::create(&url, "disel_case_").await;
+    shuttle_disel_server::db::run_migrations(&scratch.url).unwrap();
+
+    // Rolls back to before usernames and emails were unique ignoring case
+    let mut conn = scratch.connect().await;
+    conn.execute(include_str!(
+        "../../shuttle_disel/migrations/2026-10-18-210000_unique_users/down.sql"
+    ))
+    .await
+    .unwrap();
+    sqlx::query("DELETE FROM __diesel_schema_migrations WHERE version = $1")
+        .bind(DISEL_UNIQUE_USERS)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    conn.execute(
+        "
+        INSERT INTO users (username, email, password) VALUES
+            ('Carol', 'carol@example.com', ''),
+            ('carol', 'dave@example.com', ''),
+            ('dave', 'DAVE@example.com', '');
+        ",
+    )
+    .await
+    .unwrap();
+
+    let error = shuttle_disel_server::db::run_migrations(&scratch.url)
+        .expect_err("duplicates went unnoticed")
+        .to_string();
+    assert!(error.contains("users differ only in case"), "{error}");
+    assert!(error.contains("username 'carol'"), "{error}");
+    assert!(error.contains("email 'dave@example.com'"), "{error}");
+
+    conn.execute(
+        "
+        UPDATE users SET username = 'carol2' WHERE username = 'carol';
+        UPDATE users SET email = 'dave2@example.com' WHERE username = 'dave';
+        ",
+    )
+    .await
+    .unwrap();
+    shuttle_disel_server::db::run_migrations(&scratch.url).unwrap();
+    let (applied,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM __diesel_schema_migrations WHERE version = $1)",
+    )
+    .bind(DISEL_UNIQUE_USERS)
+    .fetch_one(&mut conn)
+    .await
+    .unwrap();
+    assert!(applied);
+
+    conn.close().await.unwrap();
+    scratch.drop().await;
+}
@@ -0,0 +1,2480 @@
+//! The RealWorld API spec, run end to end against every backend and storage.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use realworld_conformance::{
+    issuer::{MockIdentity, PROVIDER},
+    redact, unique, unique_address, Backend, Client, Storage, TestServer,
+};
+use realworld_core::totp;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, SET_COOKIE},
+    Method, StatusCode,
+};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn diesel_postgres() {
+    run(Backend::Diesel, Storage::Postgres).await;
+}
+
+#[tokio::test]
+async fn diesel_memory() {
+    run(Backend::Diesel, Storage::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlx_postgres() {
+    run(Backend::Sqlx, Storage::Postgres).await;
+}
+
+#[tokio::test]
+async fn sqlx_memory() {
+    run(Backend::Sqlx, Storage::Memory).await;
+}
+
+async fn run(backend: Backend, storage: Storage) {
+    let server = TestServer::spawn(backend, storage).await;
+    let anon = server.client();
+
+    let (alice, alice_name) = users(&anon).await;
+    let (bob, bob_name) = register(&anon, "bob").await;
+
+    profiles(&anon, &alice, &alice_name, &bob_name).await;
+    usernames(&anon, &alice, &alice_name, &bob_name).await;
+    errors(&anon, &alice, &bob_name).await;
+    request_ids(&anon, &alice).await;
+    let slug = articles(&anon, &alice, &bob, &alice_name, &bob_name).await;
+    comments(&anon, &alice, &bob, &slug, &alice_name, &bob_name).await;
+    pagination(&anon, &alice, &alice_name).await;
+    search(&anon, &alice, &alice_name).await;
+    drafts(&anon, &alice, &bob).await;
+    revisions(&anon, &alice, &bob, &alice_name).await;
+    slugs(&anon, &alice).await;
+    sessions(&anon).await;
+    keys(backend, &anon, &server.root_client()).await;
+    accounts(&server, &anon).await;
+    two_factor(&anon).await;
+    social_login(&server, &anon).await;
+    access_tokens(&anon).await;
+    roles(&server, &anon).await;
+    lockout(&server, &anon).await;
+    credentials(&anon).await;
+    privacy(&anon).await;
+    metrics(&server, backend, storage).await;
+
+    let (status, body) = bob.delete(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "message": "OK" }));
+
+    let (status, _) = anon.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn register(anon: &Client, prefix: &str) -> (Client, String) {
+    let username = unique(prefix);
+    let email = format!("{username}@example.com");
+
+    let (status, body) = anon
+        .post(
+            "/users",
+            json!({ "user": { "username": username, "email": email, "password": "password123" } }),
+        )
+        .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let token = body["user"]["token"]
+        .as_str()
+        .expect("no token")
+        .to_string();
+    assert_eq!(
+        redact(body),
+        json!({ "user": {
+            "username": username,
+            "email": email,
+            "bio": null,
+            "image": null,
+            "emailVerified": false,
+            "role": "user",
+            "token": "<token>",
+            "refreshToken": "<token>",
+        } })
+    );
+
+    (anon.with_token(&token), username)
+}
+
+// ================================== Users ================================== //
+
+async fn users(anon: &Client) -> (Client, String) {
+    let (alice, username) = register(anon, "alice").await;
+    let email = format!("{username}@example.com");
+
+    let (status, body) = anon
+        .post(
+            "/users/login",
+            json!({ "user": { "email": email, "password": "password123" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        redact(body),
+        json!({ "user": {
+            "username": username,
+            "email": email,
+            "bio": null,
+            "image": null,
+            "emailVerified": false,
+            "role": "user",
+            "token": "<token>",
+            "refreshToken": "<token>",
+        } })
+    );
+
+    // Failed logins, from an address of their own so they don't add up across runs
+    let guesser = anon.forwarded_for(&unique_address());
+    let (status, body) = guesser
+        .post(
+            "/users/login",
+            json!({ "user": { "email": email, "password": "password456" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "{body}");
+    let wrong_password = body;
+    let nobody = format!("{}@example.com", unique("nobody"));
+    let (status, body) = guesser
+        .post(
+            "/users/login",
+            json!({ "user": { "email": nobody, "password": "password123" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "{body}");
+    // Neither tells which emails are registered
+    assert_eq!(body, wrong_password);
+    assert_eq!(
+        body,
+        json!({ "errors": { "body": ["email or password is invalid"] } })
+    );
+
+    // Emails and usernames are taken whatever their case
+    let (status, body) = anon
+        .post(
+            "/users",
+            json!({ "user": {
+                "username": unique("other"),
+                "email": email.to_uppercase(),
+                "password": "password123",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["errors"],
+        json!({ "email": ["has already been taken"] })
+    );
+    let (status, body) = anon
+        .post(
+            "/users",
+            json!({ "user": {
+                "username": username.to_uppercase(),
+                "email": format!("{}@example.com", unique("other")),
+                "password": "password123",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["errors"],
+        json!({ "username": ["has already been taken"] })
+    );
+
+    let (status, body) = anon
+        .post(
+            "/users",
+            json!({ "user": { "username": "", "email": "nope", "password": "x" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["username"].is_array(), "{body}");
+    assert!(body["errors"]["email"].is_array(), "{body}");
+    assert!(body["errors"]["password"].is_array(), "{body}");
+
+    let (status, body) = alice.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["user"]["username"], json!(username));
+
+    let (status, _) = anon.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = alice
+        .put(
+            "/user",
+            json!({ "user": { "bio": "I like to skateboard", "image": "https://i.stack.imgur.com/xHWG8.jpg" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        redact(body),
+        json!({ "user": {
+            "username": username,
+            "email": email,
+            "bio": "I like to skateboard",
+            "image": "https://i.stack.imgur.com/xHWG8.jpg",
+            "emailVerified": false,
+            "role": "user",
+            "token": "<token>",
+        } })
+    );
+
+    (alice, username)
+}
+
+// ================================== Profiles ================================== //
+
+async fn profiles(anon: &Client, alice: &Client, alice_name: &str, bob_name: &str) {
+    let profile = |following: bool| {
+        json!({ "profile": {
+            "username": bob_name,
+            "bio": null,
+            "image": null,
+            "following": following,
+        } })
+    };
+
+    let (status, body) = anon.get(&format!("/profiles/{bob_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, profile(false));
+
+    let (status, _) = anon.get(&format!("/profiles/{}", unique("nobody"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = alice
+        .post(&format!("/profiles/{bob_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, profile(true));
+
+    let (status, body) = alice.get(&format!("/profiles/{bob_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, profile(true));
+
+    let (status, body) = alice.delete(&format!("/profiles/{bob_name}/follow")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, profile(false));
+
+    let (status, _) = anon
+        .post(&format!("/profiles/{bob_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let follow_self = format!("/profiles/{alice_name}/follow");
+    let (status, body) = alice.post(&follow_self, json!({})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({ "errors": { "body": ["You cannot follow yourself"] } })
+    );
+    let (status, _) = alice.delete(&follow_self).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Follow again so bob's articles show up in alice's feed.
+    let (status, _) = alice
+        .post(&format!("/profiles/{bob_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn usernames(anon: &Client, alice: &Client, alice_name: &str, bob_name: &str) {
+    let register = |username: String, email: String| {
+        anon.post(
+            "/users",
+            json!({ "user": { "username": username, "email": email, "password": "password123" } }),
+        )
+    };
+
+    // Usernames and emails are each taken on their own, whatever their case
+    let (status, body) = register(
+        alice_name.to_uppercase(),
+        format!("{}@example.com", unique("other")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({ "errors": { "username": ["has already been taken"] } })
+    );
+    let (status, body) = register(
+        unique("other"),
+        format!("{alice_name}@example.com").to_uppercase(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({ "errors": { "email": ["has already been taken"] } })
+    );
+
+    // Logging in ignores the case of the email too
+    let login = json!({ "user": {
+        "email": format!("{alice_name}@example.com").to_uppercase(),
+        "password": "password123",
+    } });
+    let (status, body) = anon.post("/users/login", login).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(alice_name));
+
+    for reserved in ["admin", "Settings", "API", "deleted-0123456789ab"] {
+        let email = format!("{}@example.com", unique("other"));
+        let (status, body) = register(reserved.to_string(), email).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{reserved}");
+        assert_eq!(body, json!({ "errors": { "username": ["is reserved"] } }));
+    }
+
+    let (status, body) = alice
+        .put("/user", json!({ "user": { "username": "Admin" } }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body, json!({ "errors": { "username": ["is reserved"] } }));
+    let (status, body) = alice
+        .put(
+            "/user",
+            json!({ "user": { "username": bob_name.to_uppercase() } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({ "errors": { "username": ["has already been taken"] } })
+    );
+    let (status, body) = alice
+        .put(
+            "/user",
+            json!({ "user": { "email": format!("{bob_name}@example.com") } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({ "errors": { "email": ["has already been taken"] } })
+    );
+
+    // Changing only the case of their own name is fine
+    let (status, body) = alice
+        .put(
+            "/user",
+            json!({ "user": { "username": alice_name.to_uppercase() } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = alice
+        .put("/user", json!({ "user": { "username": alice_name } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ================================== Errors ================================== //
+
+async fn errors(anon: &Client, alice: &Client, bob_name: &str) {
+    // The RealWorld shape by default
+    let (status, body) = anon.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(body["errors"]["body"][0].is_string(), "{body}");
+    let (status, body) = anon.get("/articles/no-such-article").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["errors"]["body"][0].is_string(), "{body}");
+    let (status, body) = anon.get("/no-such-route").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["errors"]["body"][0].is_string(), "{body}");
+
+    // Problem details for whoever asks for them, with the same status and a stable code
+    let problems = anon.header("Accept", "application/json, application/problem+json");
+    let problem = |status: StatusCode, headers: &HeaderMap, body: &Value, code: &str| {
+        assert_eq!(headers[CONTENT_TYPE], "application/problem+json", "{body}");
+        assert_eq!(body["type"], json!(format!("urn:realworld:error:{code}")));
+        assert_eq!(body["status"], json!(status.as_u16()));
+        assert_eq!(body["code"], json!(code));
+        assert!(body["title"].is_string(), "{body}");
+        assert!(body["detail"].is_string(), "{body}");
+    };
+
+    let (status, headers, body) = problems.exchange(Method::GET, "/user", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    problem(status, &headers, &body, "unauthorized");
+    assert_eq!(body["title"], json!("Unauthorized"));
+
+    let path = "/articles/no-such-article";
+    let (status, headers, body) = problems.exchange(Method::GET, path, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    problem(status, &headers, &body, "not_found");
+    let (status, headers, body) = problems.exchange(Method::GET, "/no-such-route", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    problem(status, &headers, &body, "not_found");
+
+    let forbidden = alice.header("Accept", "application/problem+json");
+    let (status, headers, body) = forbidden.exchange(Method::GET, "/admin/users", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    problem(status, &headers, &body, "forbidden");
+
+    let invalid = json!({ "user": { "username": "", "email": "nope", "password": "x" } });
+    let (status, headers, body) = problems
+        .exchange(Method::POST, "/users", Some(invalid))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    problem(status, &headers, &body, "validation_failed");
+    assert!(body["errors"]["username"].is_array(), "{body}");
+    assert!(body["errors"]["email"].is_array(), "{body}");
+
+    let taken = json!({ "user": {
+        "username": bob_name,
+        "email": format!("{}@example.com", unique("other")),
+        "password": "password123",
+    } });
+    let (status, headers, body) = problems.exchange(Method::POST, "/users", Some(taken)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    problem(status, &headers, &body, "already_exists");
+    assert_eq!(
+        body["errors"],
+        json!({ "username": ["has already been taken"] })
+    );
+
+    // Requests that can't be read are refused like any other, not in plain text
+    let refused = |body: &Value, detail: &str| {
+        assert_eq!(body, &json!({ "errors": { "body": [detail] } }));
+    };
+    let login = "/users/login";
+    let (status, _, body) = anon
+        .exchange_raw(Method::POST, login, "application/json", r#"{"user": {"#)
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    refused(&body, "The body is not valid JSON");
+    let (status, _, body) = anon
+        .exchange_raw(
+            Method::POST,
+            login,
+            "application/json",
+            r#"{"user": {"email": 1}}"#,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    refused(
+        &body,
+        "The body is missing fields or has fields of the wrong type",
+    );
+    let credentials = r#"{"user": {"email": "a@example.com", "password": "password123"}}"#;
+    let (status, _, body) = anon
+        .exchange_raw(Method::POST, login, "text/plain", credentials)
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    refused(&body, "The body must be sent as application/json");
+    let (status, body) = anon.get("/articles?limit=many").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    refused(&body, "The query string has invalid parameters");
+    let (status, body) = alice
+        .delete("/articles/no-such-article/comments/not-an-id")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    refused(&body, "Record not found");
+
+    let (status, headers, body) = problems
+        .exchange_raw(Method::POST, login, "application/json", "nope")
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    problem(status, &headers, &body, "validation_failed");
+    assert_eq!(body["detail"], json!("The body is not valid JSON"));
+
+    // Successful responses are unchanged
+    let (status, headers, body) = problems.exchange(Method::GET, "/tags", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["tags"].is_array(), "{body}");
+    assert_ne!(headers[CONTENT_TYPE], "application/problem+json");
+}
+
+async fn request_ids(anon: &Client, alice: &Client) {
+    // Generated when the client sends none, errors and unknown routes included
+    for (client, path) in [
+        (anon, "/tags"),
+        (alice, "/user"),
+        (anon, "/user"),
+        (anon, "/nope"),
+    ] {
+        let (_, headers, _) = client.exchange(Method::GET, path, None).await;
+        let id = headers["x-request-id"].to_str().unwrap();
+        assert_eq!(id.len(), 36, "{path}: {id}");
+    }
+    let (_, first, _) = anon.exchange(Method::GET, "/tags", None).await;
+    let (_, second, _) = anon.exchange(Method::GET, "/tags", None).await;
+    assert_ne!(first["x-request-id"], second["x-request-id"]);
+
+    // Propagated from the caller
+    let id = unique("trace");
+    let traced = alice.header("X-Request-Id", &id);
+    let (status, headers, _) = traced.exchange(Method::GET, "/user", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["x-request-id"], id.as_str());
+
+    // Unless it would not be safe to log
+    let long = "a".repeat(200);
+    for id in [long.as_str(), "two words"] {
+        let (_, headers, _) = anon
+            .header("X-Request-Id", id)
+            .exchange(Method::GET, "/tags", None)
+            .await;
+        assert_ne!(headers["x-request-id"], id);
+    }
+}
+
+// ================================== Articles ================================== //
+
+async fn articles(
+    anon: &Client,
+    alice: &Client,
+    bob: &Client,
+    alice_name: &str,
+    bob_name: &str,
+) -> String {
+    let suffix = unique("");
+    let title = format!("How to train your dragon {suffix}");
+    let slug = format!("how-to-train-your-dragon-{suffix}");
+    let tags = vec![unique("dragons"), unique("training")];
+
+    let article = |favorited: bool, favorites_count: i64, following: bool| {
+        json!({
+            "slug": slug,
+            "title": title,
+            "description": "Ever wonder how?",
+            "body": "You have to believe",
+            "tagList": tags,
+            "createdAt": "<timestamp>",
+            "updatedAt": "<timestamp>",
+            "favorited": favorited,
+            "favoritesCount": favorites_count,
+            "author": {
+                "username": bob_name,
+                "bio": null,
+                "image": null,
+                "following": following,
+            },
+            "status": "published",
+            "publishedAt": "<timestamp>",
+        })
+    };
+
+    let (status, _) = anon
+        .post(
+            "/articles",
+            json!({ "article": { "title": title, "description": "d", "body": "b" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Tags come back sorted by name
+    let (status, body) = bob
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": title,
+                "description": "Ever wonder how?",
+                "body": "You have to believe",
+                "tagList": [tags[1], tags[0]],
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), json!({ "article": article(false, 0, false) }));
+
+    let (status, body) = bob
+        .post(
+            "/articles",
+            json!({ "article": { "title": "", "description": "", "body": "" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["title"].is_array(), "{body}");
+
+    let (status, body) = anon.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), json!({ "article": article(false, 0, false) }));
+
+    let (status, body) = alice.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), json!({ "article": article(false, 0, true) }));
+
+    let (status, _) = anon.get(&format!("/articles/{}", unique("missing-"))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // ==== Listing ==== //
+
+    let list =
+        |articles: Vec<Value>| json!({ "articlesCount": articles.len(), "articles": articles });
+
+    let (status, body) = anon.get(&format!("/articles?author={bob_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), list(vec![article(false, 0, false)]));
+
+    let (status, body) = anon.get(&format!("/articles?tag={}", tags[0])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), list(vec![article(false, 0, false)]));
+
+    let (status, body) = anon.get(&format!("/articles?author={alice_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, list(vec![]));
+
+    let (status, body) = anon.get("/articles?limit=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["articles"].as_array().unwrap().len(), 1);
+
+    // ==== Favorites ==== //
+
+    let (status, body) = alice
+        .post(&format!("/articles/{slug}/favorite"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), json!({ "article": article(true, 1, true) }));
+
+    let (status, body) = anon.get(&format!("/articles?favorited={alice_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), list(vec![article(false, 1, false)]));
+
+    let (status, body) = alice.delete(&format!("/articles/{slug}/favorite")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), json!({ "article": article(false, 0, true) }));
+
+    let (status, body) = anon.get(&format!("/articles?favorited={alice_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, list(vec![]));
+
+    let (status, _) = anon
+        .post(&format!("/articles/{slug}/favorite"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // ==== Feed ==== //
+
+    let (status, body) = alice.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(redact(body), list(vec![article(false, 0, true)]));
+
+    let (status, body) = bob.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, list(vec![]));
+
+    let (status, _) = anon.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // ==== Tags ==== //
+
+    let (status, body) = anon.get("/tags").await;
+    assert_eq!(status, StatusCode::OK);
+    let all_tags = body["tags"].as_array().expect("no tags");
+    for tag in &tags {
+        assert!(all_tags.contains(&json!(tag)), "{tag} missing from {body}");
+    }
+
+    // ==== Updates ==== //
+
+    let (status, body) = bob
+        .put(
+            &format!("/articles/{slug}"),
+            json!({ "article": { "body": "With two hands" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut expected = article(false, 0, false);
+    expected["body"] = json!("With two hands");
+    assert_eq!(redact(body), json!({ "article": expected }));
+
+    let new_title = format!("Did you train your dragon {suffix}");
+    let new_slug = format!("did-you-train-your-dragon-{suffix}");
+    let (status, body) = bob
+        .put(
+            &format!("/articles/{slug}"),
+            json!({ "article": { "title": new_title } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["slug"], json!(new_slug));
+    assert_eq!(body["article"]["title"], json!(new_title));
+    assert_eq!(body["article"]["body"], json!("With two hands"));
+
+    // ==== Ownership ==== //
+
+    let path = format!("/articles/{new_slug}");
+    let (status, _) = alice
+        .put(&path, json!({ "article": { "title": "Hijacked" } }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = alice.delete(&path).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = anon.get(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["title"], json!(new_title));
+
+    new_slug
+}
+
+// ================================== Comments ================================== //
+
+async fn comments(
+    anon: &Client,
+    alice: &Client,
+    bob: &Client,
+    slug: &str,
+    alice_name: &str,
+    bob_name: &str,
+) {
+    let path = format!("/articles/{slug}/comments");
+
+    let (status, body) = anon.get(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "comments": [] }));
+
+    let (status, _) = anon
+        .post(&path, json!({ "comment": { "body": "Hi" } }))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = alice
+        .post(
+            &path,
+            json!({ "comment": { "body": "Thank you so much!" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let first_id = body["comment"]["id"].as_i64().expect("no comment id");
+    assert_eq!(
+        redact(body),
+        json!({ "comment": {
+            "id": "<id>",
+            "body": "Thank you so much!",
+            "createdAt": "<timestamp>",
+            "updatedAt": "<timestamp>",
+            "author": {
+                "username": alice_name,
+                "bio": "I like to skateboard",
+                "image": "https://i.stack.imgur.com/xHWG8.jpg",
+                "following": false,
+            },
+        } })
+    );
+
+    let (status, body) = bob
+        .post(&path, json!({ "comment": { "body": "Anytime" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let second_id = body["comment"]["id"].as_i64().expect("no comment id");
+
+    // Most recent first, and alice follows bob.
+    let (status, body) = alice.get(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    let comments = body["comments"].as_array().expect("no comments");
+    assert_eq!(
+        comments
+            .iter()
+            .map(|c| c["id"].as_i64().unwrap())
+            .collect::<Vec<_>>(),
+        vec![second_id, first_id]
+    );
+    assert_eq!(comments[0]["author"]["username"], json!(bob_name));
+    assert_eq!(comments[0]["author"]["following"], json!(true));
+    assert_eq!(comments[1]["author"]["following"], json!(false));
+
+    let (status, _) = bob.delete(&format!("{path}/{first_id}")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = alice.delete(&format!("{path}/{first_id}")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = anon.get(&path).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["comments"].as_array().unwrap().len(), 1);
+    assert_eq!(body["comments"][0]["id"], json!(second_id));
+}
+
+// ================================== Pagination ================================== //
+
+async fn pagination(anon: &Client, alice: &Client, alice_name: &str) {
+    let tag = unique("paging");
+
+    let mut titles = Vec::new();
+    for n in 0..3 {
+        let title = format!("Page {n} {}", unique(""));
+        let (status, _) = alice
+            .post(
+                "/articles",
+                json!({ "article": {
+                    "title": title,
+                    "description": "d",
+                    "body": "b",
+                    "tagList": [tag],
+                } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        titles.insert(0, title);
+    }
+
+    let listed = |body: &Value| -> Vec<String> {
+        body["articles"]
+            .as_array()
+            .expect("no articles")
+            .iter()
+            .map(|article| article["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // `articlesCount` counts every match, not just the page
+    let (status, body) = anon.get(&format!("/articles?tag={tag}&limit=2")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed(&body), titles[..2]);
+    assert_eq!(body["articlesCount"], json!(3));
+    assert!(body.get("nextCursor").is_none(), "{body}");
+
+    let (status, body) = anon
+        .get(&format!("/articles?tag={tag}&limit=2&offset=2"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed(&body), titles[2..]);
+    assert_eq!(body["articlesCount"], json!(3));
+
+    // An empty cursor starts from the newest article
+    let (status, body) = anon
+        .get(&format!("/articles?tag={tag}&limit=2&cursor="))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed(&body), titles[..2]);
+    assert_eq!(body["articlesCount"], json!(3));
+    let cursor = body["nextCursor"].as_str().expect("no nextCursor");
+
+    let (status, body) = anon
+        .get(&format!("/articles?tag={tag}&limit=2&cursor={cursor}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed(&body), titles[2..]);
+    assert_eq!(body["articlesCount"], json!(3));
+    assert!(body.get("nextCursor").is_none(), "{body}");
+
+    let (status, body) = anon
+        .get(&format!("/articles?tag={tag}&cursor=not-a-cursor"))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["cursor"].is_array(), "{body}");
+
+    // The feed pages the same way
+    let (carol, _) = register(anon, "carol").await;
+    let (status, _) = carol
+        .post(&format!("/profiles/{alice_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = carol.get("/articles/feed?limit=2&cursor=").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed(&body), titles[..2]);
+    assert_eq!(body["articlesCount"], json!(3));
+    assert!(body["nextCursor"].is_string(), "{body}");
+}
+
+// ================================== Search ================================== //
+
+async fn search(anon: &Client, alice: &Client, alice_name: &str) {
+    // Words with digits are never stemmed, so they match exactly everywhere
+    let word = unique("zephyr");
+    let other = unique("quokka");
+
+    let create = |title: String, description: String, body: String| async move {
+        let (status, body) = alice
+            .post(
+                "/articles",
+                json!({ "article": { "title": title, "description": description, "body": body } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        body["article"]["slug"].as_str().unwrap().to_string()
+    };
+
+    let in_title = create(
+        format!("All about {word}"),
+        "d".into(),
+        format!("The quick {word} jumps over {other} fences"),
+    )
+    .await;
+    let in_description = create(
+        format!("Something else {}", unique("")),
+        format!("Mentions {word} once"),
+        "Nothing to see here".into(),
+    )
+    .await;
+
+    let found = |body: &Value| -> Vec<String> {
+        body["articles"]
+            .as_array()
+            .expect("no articles")
+            .iter()
+            .map(|article| article["slug"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // Title matches rank above description matches
+    let (status, body) = anon.get(&format!("/articles/search?q={word}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(found(&body), [in_title.as_str(), in_description.as_str()]);
+    assert_eq!(body["articlesCount"], json!(2));
+    assert_eq!(
+        body["articles"][0]["snippet"],
+        json!(format!(
+            "The quick <mark>{word}</mark> jumps over {other} fences"
+        ))
+    );
+    assert_eq!(body["articles"][0]["author"]["username"], json!(alice_name));
+
+    let (_, body) = anon
+        .get(&format!("/articles/search?q={word}&limit=1&offset=1"))
+        .await;
+    assert_eq!(found(&body), [in_description.as_str()]);
+    assert_eq!(body["articlesCount"], json!(2));
+
+    // Prefixes
+    let (_, body) = anon
+        .get(&format!("/articles/search?q={}*", &word[..10]))
+        .await;
+    assert_eq!(found(&body), [in_title.as_str(), in_description.as_str()]);
+
+    // Every term has to match, phrases in order
+    let (_, body) = anon
+        .get(&format!("/articles/search?q={word} {other}"))
+        .await;
+    assert_eq!(found(&body), [in_title.as_str()]);
+
+    let (_, body) = anon
+        .get(&format!("/articles/search?q=\"{word} jumps\""))
+        .await;
+    assert_eq!(found(&body), [in_title.as_str()]);
+
+    let (_, body) = anon
+        .get(&format!("/articles/search?q=\"jumps {word}\""))
+        .await;
+    assert!(found(&body).is_empty(), "{body}");
+    assert_eq!(body["articlesCount"], json!(0));
+
+    // Viewer dependent fields
+    let (carol, _) = register(anon, "carol").await;
+    let (status, _) = carol
+        .post(&format!("/profiles/{alice_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = carol
+        .post(&format!("/articles/{in_title}/favorite"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = carol.get(&format!("/articles/search?q={word}")).await;
+    assert_eq!(body["articles"][0]["favorited"], json!(true));
+    assert_eq!(body["articles"][0]["favoritesCount"], json!(1));
+    assert_eq!(body["articles"][0]["author"]["following"], json!(true));
+    assert_eq!(body["articles"][1]["favorited"], json!(false));
+
+    let (_, body) = anon.get(&format!("/articles/search?q={word}")).await;
+    assert_eq!(body["articles"][0]["favorited"], json!(false));
+    assert_eq!(body["articles"][0]["author"]["following"], json!(false));
+
+    // Snippets are HTML, only the marks are markup
+    let markup = unique("markup");
+    create(
+        format!("Markup {}", unique("")),
+        "d".into(),
+        format!(r#"<img src=x onerror="alert('{markup}')"> & <b>{markup}</b>"#),
+    )
+    .await;
+    let (_, body) = anon.get(&format!("/articles/search?q={markup}")).await;
+    let snippet = body["articles"][0]["snippet"].as_str().expect("no snippet");
+    let text = snippet.replace("<mark>", "").replace("</mark>", "");
+    assert!(!text.contains(['<', '>', '"', '\'']), "{snippet}");
+    assert!(text.contains("onerror=&quot;alert(&#39;"), "{snippet}");
+    assert!(text.contains("&amp; &lt;b&gt;"), "{snippet}");
+    assert!(snippet.contains("<mark>"), "{snippet}");
+
+    for q in ["", "%20", "%22%22"] {
+        let (status, body) = anon.get(&format!("/articles/search?q={q}")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["errors"]["q"].is_array(), "{body}");
+    }
+}
+
+// ================================== Drafts ================================== //
+
+async fn drafts(anon: &Client, alice: &Client, bob: &Client) {
+    let word = unique("draft");
+    let tag = unique("drafts");
+
+    let (status, body) = alice
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": format!("Work in progress {word}"),
+                "description": "Not done yet",
+                "body": "Still writing",
+                "tagList": [tag],
+                "status": "draft",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("draft"));
+    assert_eq!(body["article"]["publishedAt"], json!(null));
+    let slug = body["article"]["slug"].as_str().unwrap().to_string();
+
+    let listed = |body: &Value| {
+        body["articles"]
+            .as_array()
+            .expect("no articles")
+            .iter()
+            .any(|article| article["slug"] == json!(slug))
+    };
+
+    // Only the author sees a draft
+    let (status, _) = anon.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = bob.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = alice.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("draft"));
+
+    for client in [anon, alice] {
+        let (_, body) = client.get(&format!("/articles?tag={tag}")).await;
+        assert_eq!(body, json!({ "articlesCount": 0, "articles": [] }));
+        let (_, body) = client.get(&format!("/articles/search?q={word}")).await;
+        assert_eq!(body, json!({ "articlesCount": 0, "articles": [] }));
+    }
+    let (_, body) = anon.get("/tags").await;
+    assert!(!body["tags"].as_array().unwrap().contains(&json!(tag)));
+
+    let (status, _) = bob
+        .post(&format!("/articles/{slug}/publish"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = anon
+        .post(&format!("/articles/{slug}/publish"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Publishing lists it
+    let (status, body) = alice
+        .post(&format!("/articles/{slug}/publish"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("published"));
+    assert_eq!(redact(body)["article"]["publishedAt"], json!("<timestamp>"));
+
+    let (status, _) = anon.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = anon.get(&format!("/articles?tag={tag}")).await;
+    assert!(listed(&body), "{body}");
+    let (_, body) = anon.get(&format!("/articles/search?q={word}")).await;
+    assert!(listed(&body), "{body}");
+
+    // Unpublishing turns it back into a draft
+    let (status, body) = alice
+        .post(&format!("/articles/{slug}/unpublish"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("draft"));
+    assert_eq!(body["article"]["publishedAt"], json!(null));
+
+    let (status, _) = anon.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Unlisted articles are reachable by slug only
+    let (status, body) = alice
+        .put(
+            &format!("/articles/{slug}"),
+            json!({ "article": { "status": "unlisted" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("unlisted"));
+
+    let (status, body) = bob.get(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["status"], json!("unlisted"));
+    let (_, body) = anon.get(&format!("/articles?tag={tag}")).await;
+    assert!(!listed(&body), "{body}");
+
+    let (status, _) = alice.delete(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ================================== Revisions ================================== //
+
+async fn revisions(anon: &Client, alice: &Client, bob: &Client, alice_name: &str) {
+    let (status, body) = alice
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": format!("Edited a lot {}", unique("")),
+                "description": "First",
+                "body": "line one\nline two",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let slug = body["article"]["slug"].as_str().unwrap().to_string();
+    let title = body["article"]["title"].clone();
+    let revisions = format!("/articles/{slug}/revisions");
+
+    let (status, body) = alice.get(&revisions).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        redact(body),
+        json!({
+            "revisionsCount": 1,
+            "revisions": [{
+                "number": 1,
+                "title": title,
+                "description": "First",
+                "body": "line one\nline two",
+                "changedFields": ["title", "description", "body"],
+                "author": {
+                    "username": alice_name,
+                    "bio": "I like to skateboard",
+                    "image": "https://i.stack.imgur.com/xHWG8.jpg",
+                    "following": false,
+                },
+                "createdAt": "<timestamp>",
+            }],
+        })
+    );
+
+    // Only content edits are recorded
+    let (status, _) = alice
+        .put(
+            &format!("/articles/{slug}"),
+            json!({ "article": { "body": "line one\nline 2\nline three", "description": "First" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = alice
+        .put(
+            &format!("/articles/{slug}"),
+            json!({ "article": { "tagList": [unique("tag")] } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = alice.get(&revisions).await;
+    assert_eq!(body["revisionsCount"], json!(2));
+    assert_eq!(body["revisions"][0]["number"], json!(2));
+    assert_eq!(body["revisions"][0]["changedFields"], json!(["body"]));
+    assert_eq!(body["revisions"][1]["number"], json!(1));
+
+    let (status, body) = alice.get(&format!("{revisions}/1")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["revision"]["body"], json!("line one\nline two"));
+
+    let (status, body) = alice.get(&format!("{revisions}/1/diff/2")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["diff"]["from"], json!(1));
+    assert_eq!(body["diff"]["to"], json!(2));
+    assert_eq!(
+        body["diff"]["title"],
+        json!([{ "op": "equal", "text": title }])
+    );
+    assert_eq!(
+        body["diff"]["body"],
+        json!([
+            { "op": "equal", "text": "line one" },
+            { "op": "delete", "text": "line two" },
+            { "op": "insert", "text": "line 2" },
+            { "op": "insert", "text": "line three" },
+        ])
+    );
+
+    // Restoring adds a revision rather than rewriting history
+    let (status, body) = alice
+        .post(&format!("{revisions}/1/restore"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["body"], json!("line one\nline two"));
+    assert_eq!(body["article"]["slug"], json!(slug));
+
+    let (_, body) = alice.get(&revisions).await;
+    assert_eq!(body["revisionsCount"], json!(3));
+    assert_eq!(body["revisions"][0]["changedFields"], json!(["body"]));
+    assert_eq!(body["revisions"][0]["body"], json!("line one\nline two"));
+
+    let (status, _) = alice.get(&format!("{revisions}/42")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = anon.get(&revisions).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = bob.get(&revisions).await;
+    assert!(status.is_client_error(), "{status}");
+    let (status, _) = bob.post(&format!("{revisions}/1/restore"), json!({})).await;
+    assert!(status.is_client_error(), "{status}");
+
+    let (status, _) = alice.delete(&format!("/articles/{slug}")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ================================== Slugs ================================== //
+
+async fn slugs(anon: &Client, alice: &Client) {
+    let suffix = unique("");
+    let title = format!("Same title {suffix}");
+    let base = format!("same-title-{suffix}");
+
+    let create = |title: String| async move {
+        let (status, body) = alice
+            .post(
+                "/articles",
+                json!({ "article": { "title": title, "description": "d", "body": "b" } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        body["article"]["slug"].as_str().unwrap().to_string()
+    };
+    let rename = |slug: String, title: String| async move {
+        let (status, body) = alice
+            .put(
+                &format!("/articles/{slug}"),
+                json!({ "article": { "title": title } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        body["article"]["slug"].as_str().unwrap().to_string()
+    };
+
+    // Taken slugs get a numeric suffix
+    let first = create(title.clone()).await;
+    let second = create(title.clone()).await;
+    assert_eq!(first, base);
+    assert_eq!(second, format!("{base}-2"));
+
+    // Keeping the same title keeps the slug, even a suffixed one
+    assert_eq!(rename(second.clone(), title.clone()).await, second);
+
+    // Renamed articles redirect from their old slug
+    let renamed = rename(first.clone(), format!("Other title {suffix}")).await;
+    assert_eq!(renamed, format!("other-title-{suffix}"));
+
+    let (status, body) = anon.get(&format!("/articles/{first}")).await;
+    assert_eq!(status, StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        body,
+        json!({ "redirect": {
+            "slug": renamed,
+            "location": format!("/api/articles/{renamed}"),
+        } })
+    );
+
+    // Old slugs stay reserved for the article that had them
+    let third = create(title.clone()).await;
+    assert_eq!(third, format!("{base}-3"));
+    assert_eq!(rename(renamed.clone(), title.clone()).await, first);
+
+    let (status, body) = anon.get(&format!("/articles/{first}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["article"]["title"], json!(title));
+    let (status, body) = anon.get(&format!("/articles/{renamed}")).await;
+    assert_eq!(status, StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(body["redirect"]["slug"], json!(first));
+
+    for slug in [first, second, third] {
+        let (status, _) = alice.delete(&format!("/articles/{slug}")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = anon.get(&format!("/articles/{renamed}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ================================== Sessions ================================== //
+
+async fn sessions(anon: &Client) {
+    let (_, username) = register(anon, "carol").await;
+    let email = format!("{username}@example.com");
+
+    let login = || async {
+        let (status, body) = anon
+            .post(
+                "/users/login",
+                json!({ "user": { "email": email, "password": "password123" } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        tokens(anon, &body)
+    };
+    let refresh = |refresh_token: String| async move {
+        anon.post(
+            "/users/refresh",
+            json!({ "user": { "refreshToken": refresh_token } }),
+        )
+        .await
+    };
+
+    // Refreshing hands out a new refresh token, and keeps the session alive
+    let (phone, phone_refresh) = login().await;
+    let (status, body) = refresh(phone_refresh.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(username));
+    let (refreshed, refreshed_refresh) = tokens(anon, &body);
+    assert_ne!(refreshed_refresh, phone_refresh);
+    let (status, _) = refreshed.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Replaying a used refresh token revokes the whole session
+    let (status, _) = refresh(phone_refresh).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = refresh(refreshed_refresh).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    for client in [&phone, &refreshed] {
+        let (status, _) = client.get("/user").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // Logging out ends only the current session
+    let (laptop, laptop_refresh) = login().await;
+    let (desktop, _) = login().await;
+    let (status, body) = laptop.post("/users/logout", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "message": "OK" }));
+
+    let (status, _) = laptop.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = refresh(laptop_refresh).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = desktop.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Logging out everywhere ends every session
+    let (tablet, tablet_refresh) = login().await;
+    let (status, _) = tablet.post("/users/logout-all", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    for client in [&tablet, &desktop] {
+        let (status, _) = client.get("/user").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _) = refresh(tablet_refresh).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = refresh("not-a-refresh-token".to_string()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = refresh(String::new()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["refreshToken"].is_array(), "{body}");
+    let (status, _) = anon.post("/users/logout", json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ================================== Keys ================================== //
+
+async fn keys(backend: Backend, anon: &Client, root: &Client) {
+    let (_, username) = register(anon, "dave").await;
+    let (status, body) = anon
+        .post(
+            "/users/login",
+            json!({ "user": { "email": format!("{username}@example.com"), "password": "password123" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // Tokens name the key they were signed with
+    let token = body["user"]["token"].as_str().expect("no token");
+    let header = token.split('.').next().unwrap();
+    let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+    assert_eq!(header["kid"], json!("default"));
+
+    // Only public keys are published, the diesel backend signs with an HMAC secret
+    let (status, body) = root.get("/.well-known/jwks.json").await;
+    assert_eq!(status, StatusCode::OK);
+    match backend {
+        Backend::Diesel => assert_eq!(body, json!({ "keys": [] })),
+        Backend::Sqlx => {
+            let [key] = body["keys"].as_array().unwrap().as_slice() else {
+                panic!("expected a single key: {body}");
+            };
+            assert_eq!(key["kty"], json!("RSA"));
+            assert_eq!(key["kid"], json!("default"));
+            assert_eq!(key["use"], json!("sig"));
+            assert_eq!(key["alg"], json!("RS384"));
+            assert!(key["n"].as_str().is_some_and(|n| n.len() > 300), "{key}");
+            assert_eq!(key["e"], json!("AQAB"));
+        }
+    }
+}
+
+// ================================== Accounts ================================== //
+
+async fn accounts(server: &TestServer, anon: &Client) {
+    let (erin, username) = register(anon, "erin").await;
+    let email = format!("{username}@example.com");
+
+    // Registering mails a verification link, which works once
+    let mail = server.inbox(&email, 1).await;
+    let token = mailed_token(&mail[0], "/verify-email");
+    let verify = |token: String| async move {
+        anon.post("/users/verify-email", json!({ "user": { "token": token } }))
+            .await
+    };
+
+    let (status, body) = verify(token.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, json!({ "message": "OK" }));
+    let (_, body) = erin.get("/user").await;
+    assert_eq!(body["user"]["emailVerified"], json!(true));
+    let (status, _) = verify(token).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = verify(String::new()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["token"].is_array(), "{body}");
+
+    // A new address has to be verified again, and only the latest link works
+    let email = format!("{username}@example.org");
+    let (status, body) = erin
+        .put("/user", json!({ "user": { "email": email } }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["emailVerified"], json!(false));
+    let (status, _) = erin.post("/user/verify-email", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mail = server.inbox(&email, 2).await;
+    let (status, _) = verify(mailed_token(&mail[0], "/verify-email")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = verify(mailed_token(&mail[1], "/verify-email")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = erin.get("/user").await;
+    assert_eq!(body["user"]["emailVerified"], json!(true));
+
+    // Unknown addresses get the same answer as registered ones
+    let request_reset = |email: String| async move {
+        anon.post(
+            "/users/password-reset",
+            json!({ "user": { "email": email } }),
+        )
+        .await
+    };
+    let (status, body) = request_reset(format!("{}@example.com", unique("nobody"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "message": "OK" }));
+
+    let (status, _) = request_reset(email.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let mail = server.inbox(&email, 3).await;
+    let token = mailed_token(&mail[2], "/reset-password");
+    let confirm = |token: String, password: &'static str| async move {
+        anon.post(
+            "/users/password-reset/confirm",
+            json!({ "user": { "token": token, "password": password } }),
+        )
+        .await
+    };
+
+    let (status, body) = confirm(token.clone(), "short").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["password"].is_array(), "{body}");
+
+    // Resetting the password logs out every session, and the link only works once
+    let (status, body) = confirm(token.clone(), "new-password123").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = erin.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = confirm(token, "other-password123").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let login = |password: &'static str| {
+        let email = email.clone();
+        async move {
+            anon.post(
+                "/users/login",
+                json!({ "user": { "email": email, "password": password } }),
+            )
+            .await
+        }
+    };
+    let (status, _) = login("password123").await;
+    assert_ne!(status, StatusCode::OK);
+    let (status, body) = login("new-password123").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, _) = confirm("not-a-token".to_string(), "new-password123").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ================================== Two-Factor ================================== //
+
+async fn two_factor(anon: &Client) {
+    // Wrong codes count against the address, which is kept to this test
+    let anon = &anon.forwarded_for(&unique_address());
+    let (frank, username) = register(anon, "frank").await;
+    let email = format!("{username}@example.com");
+
+    let login = || async {
+        anon.post(
+            "/users/login",
+            json!({ "user": { "email": email, "password": "password123" } }),
+        )
+        .await
+    };
+    let exchange = |user: Value| async move {
+        anon.post("/users/login/totp", json!({ "user": user }))
+            .await
+    };
+
+    // Enrolling hands out a secret, which only takes effect once a code is confirmed
+    let (status, body) = frank.post("/user/totp", json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let secret = body["totp"]["secret"].as_str().expect("no secret");
+    let uri = body["totp"]["otpauthUri"].as_str().expect("no otpauth URI");
+    assert!(uri.starts_with("otpauth://totp/RealWorld%3A"), "{uri}");
+    assert!(uri.contains(&format!("secret={secret}")), "{uri}");
+
+    let (status, body) = login().await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let access_token = body["user"]["token"]
+        .as_str()
+        .expect("no token")
+        .to_string();
+
+    let code = |step: i64| totp::code(secret, step).unwrap();
+    let confirm = |code: String| {
+        let frank = &frank;
+        async move {
+            frank
+                .post("/user/totp/confirm", json!({ "totp": { "code": code } }))
+                .await
+        }
+    };
+    let step = totp::step_at(Utc::now());
+
+    let (status, body) = confirm(code(step - 10)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["code"].is_array(), "{body}");
+    let (status, body) = confirm(code(step)).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let recovery_codes: Vec<&str> = body["totp"]["recoveryCodes"]
+        .as_array()
+        .expect("no recovery codes")
+        .iter()
+        .map(|code| code.as_str().unwrap())
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+
+    // An enabled secret can't be replaced
+    let (status, body) = frank.post("/user/totp", json!({})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["totp"].is_array(), "{body}");
+
+    // The password alone only gets a challenge, which is no access token
+    let challenge = || async {
+        let (status, body) = login().await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert!(body.get("user").is_none(), "{body}");
+        body["challenge"]["token"]
+            .as_str()
+            .expect("no challenge")
+            .to_string()
+    };
+    let first = challenge().await;
+    let (status, _) = anon.with_token(&first).get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Wrong codes, and the code used to confirm, are refused
+    let (status, _) = exchange(json!({ "challenge": first, "code": code(step - 10) })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = exchange(json!({ "challenge": first, "code": code(step) })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Codes from the next step are accepted for clock drift
+    let (status, body) = exchange(json!({ "challenge": first, "code": code(step + 1) })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (session, _) = tokens(anon, &body);
+    let (status, body) = session.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["user"]["username"], json!(username));
+
+    // A challenge is used up once exchanged, whatever comes with it
+    let (status, _) = exchange(json!({ "challenge": first, "code": code(step + 1) })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) =
+        exchange(json!({ "challenge": first, "recoveryCode": recovery_codes[0] })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Recovery codes work once, however they are typed
+    let second = challenge().await;
+    let (status, _) = exchange(json!({ "challenge": second })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let recovery_code = recovery_codes[0].to_uppercase();
+    let (status, body) =
+        exchange(json!({ "challenge": second, "recoveryCode": recovery_code })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) =
+        exchange(json!({ "challenge": challenge().await, "recoveryCode": recovery_codes[0] }))
+            .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A challenge is used up by three wrong codes too
+    let burned = challenge().await;
+    for _ in 0..3 {
+        let (status, _) = exchange(json!({ "challenge": burned, "code": code(step - 10) })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, _) =
+        exchange(json!({ "challenge": burned, "recoveryCode": recovery_codes[1] })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) =
+        exchange(json!({ "challenge": challenge().await, "recoveryCode": recovery_codes[1] }))
+            .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // Only one of concurrent exchanges gets through, even with a right code each
+    let raced = challenge().await;
+    let (one, other) = tokio::join!(
+        exchange(json!({ "challenge": raced, "recoveryCode": recovery_codes[2] })),
+        exchange(json!({ "challenge": raced, "recoveryCode": recovery_codes[3] })),
+    );
+    let mut statuses = [one.0.as_u16(), other.0.as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 401], "{} {}", one.1, other.1);
+
+    // Neither fresh challenges nor fresh addresses make for more guesses, wrong codes lock the
+    // account like wrong passwords
+    for attempt in 1..=5 {
+        let guesser = anon.forwarded_for(&unique_address());
+        let (status, body) = guesser
+            .post(
+                "/users/login",
+                json!({ "user": { "email": email, "password": "password123" } }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let fresh = body["challenge"]["token"].as_str().expect("no challenge");
+        let (status, _) = guesser
+            .post(
+                "/users/login/totp",
+                json!({ "user": { "challenge": fresh, "code": code(step - 10) } }),
+            )
+            .await;
+        match attempt {
+            5 => assert_eq!(status, StatusCode::TOO_MANY_REQUESTS),
+            _ => assert_eq!(status, StatusCode::UNAUTHORIZED),
+        }
+    }
+    let (status, _) = login().await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Access tokens are no challenge
+    for challenge in [access_token.as_str(), "not-a-challenge"] {
+        let (status, _) =
+            exchange(json!({ "challenge": challenge, "recoveryCode": recovery_codes[1] })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, body) = exchange(json!({ "challenge": "", "code": code(step) })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["challenge"].is_array(), "{body}");
+}
+
+// ================================== Social Login ================================== //
+
+async fn social_login(server: &TestServer, anon: &Client) {
+    let (status, body) = anon.get("/users/oidc").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "providers": [PROVIDER] }));
+
+    let (status, _) = anon.post("/users/oidc/nowhere/authorize", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The code and state the provider sends back for whoever it logs in
+    let authorize = |identity: MockIdentity| async move {
+        server.issuer.sign_in_as(identity);
+        let (status, body) = anon
+            .post(&format!("/users/oidc/{PROVIDER}/authorize"), json!({}))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let url = body["authorization"]["url"].as_str().expect("no url");
+        assert!(url.contains("code_challenge_method=S256"), "{url}");
+        server.issuer.authorize(url).await
+    };
+    let callback = |code: String, state: String| async move {
+        anon.post(
+            &format!("/users/oidc/{PROVIDER}/callback"),
+            json!({ "user": { "code": code, "state": state } }),
+        )
+        .await
+    };
+
+    // The first login provisions an account, verified if the provider says so
+    let username = unique("grace");
+    let grace = MockIdentity {
+        subject: unique("subject-"),
+        email: format!("{username}@example.com"),
+        email_verified: true,
+        preferred_username: Some(username.clone()),
+    };
+    let (code, state) = authorize(grace.clone()).await;
+    let (status, body) = callback(code.clone(), state.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(username));
+    assert_eq!(body["user"]["emailVerified"], json!(true));
+    let (session, _) = tokens(anon, &body);
+    let (status, body) = session.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["user"]["email"], json!(grace.email));
+
+    // States and codes are single use
+    let (status, _) = callback(code, state).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = callback("not-a-code".to_string(), "not-a-state".to_string()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, state) = authorize(grace.clone()).await;
+    let (status, _) = callback("not-a-code".to_string(), state).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = callback(String::new(), String::new()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["code"].is_array(), "{body}");
+
+    // Later logins find the linked account, whatever the provider now calls it
+    let renamed = MockIdentity {
+        email: format!("{}@example.org", unique("grace")),
+        preferred_username: None,
+        ..grace.clone()
+    };
+    let (code, state) = authorize(renamed).await;
+    let (status, body) = callback(code, state).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(username));
+    assert_eq!(body["user"]["email"], json!(grace.email));
+
+    // A verified email links the identity to the account registered with it
+    let (_, heidi) = register(anon, "heidi").await;
+    let (code, state) = authorize(MockIdentity {
+        subject: unique("subject-"),
+        email: format!("{heidi}@example.com"),
+        email_verified: true,
+        preferred_username: Some(unique("other")),
+    })
+    .await;
+    let (status, body) = callback(code, state).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(heidi));
+    let (status, _) = anon
+        .post(
+            "/users/login",
+            json!({ "user": { "email": format!("{heidi}@example.com"), "password": "password123" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Without a username, the local part of the email is used. Unverified emails get a link
+    let local = unique("ivan");
+    let email = format!("{local}@example.net");
+    let (code, state) = authorize(MockIdentity {
+        subject: unique("subject-"),
+        email: email.clone(),
+        email_verified: false,
+        preferred_username: None,
+    })
+    .await;
+    let (status, body) = callback(code, state).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(local));
+    assert_eq!(body["user"]["emailVerified"], json!(false));
+    let mail = server.inbox(&email, 1).await;
+    mailed_token(&mail[0], "/verify-email");
+
+    // Suggested usernames lose their control characters and are cut short
+    let provision = |preferred_username: String| async move {
+        let (code, state) = authorize(MockIdentity {
+            subject: unique("subject-"),
+            email: format!("{}@example.com", unique("judy")),
+            email_verified: true,
+            preferred_username: Some(preferred_username),
+        })
+        .await;
+        callback(code, state).await
+    };
+    let name = unique("judy");
+    let (status, body) = provision(format!("\u{7}{name}\n")).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(name));
+    let long = format!("{}{}", unique("judy"), "y".repeat(100));
+    let (status, body) = provision(long.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(long[..48]));
+
+    // Emails have to pass the rules of registering
+    let (code, state) = authorize(MockIdentity {
+        subject: unique("subject-"),
+        email: format!("{}{}@example.com", unique("judy"), "y".repeat(60)),
+        email_verified: true,
+        preferred_username: None,
+    })
+    .await;
+    let (status, body) = callback(code, state).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["email"].is_array(), "{body}");
+
+    // Tokens signed with keys the provider doesn't publish are refused, without fetching its
+    // keys for each of them
+    let fetches = server.issuer.jwks_fetches();
+    for _ in 0..3 {
+        server.issuer.sign_with_unpublished_key();
+        let (code, state) = authorize(grace.clone()).await;
+        let (status, _) = callback(code, state).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    assert!(server.issuer.jwks_fetches() <= fetches + 1);
+}
+
+// ================================== Access Tokens ================================== //
+
+async fn access_tokens(anon: &Client) {
+    let (judy, judy_name) = register(anon, "judy").await;
+    let (_, kevin_name) = register(anon, "kevin").await;
+
+    let create = |name: &str, scopes: Value| {
+        let judy = &judy;
+        let body = json!({ "accessToken": { "name": name, "scopes": scopes, "expiresInDays": 7 } });
+        async move { judy.post("/user/tokens", body).await }
+    };
+
+    let (status, body) = create("ci", json!(["articles:write", "read"])).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let id = body["accessToken"]["id"]
+        .as_str()
+        .expect("no id")
+        .to_string();
+    let token = body["accessToken"]["token"].as_str().expect("no token");
+    assert!(token.starts_with("rwat_"), "{token}");
+    assert_eq!(
+        body["accessToken"]["scopes"],
+        json!(["read", "articles:write"])
+    );
+    let bot = anon.with_token(token);
+
+    // Tokens are only shown once
+    let (status, body) = judy.get("/user/tokens").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let listed = body["accessTokens"].as_array().expect("no access tokens");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["name"], json!("ci"));
+    assert!(listed[0].get("token").is_none(), "{body}");
+
+    // Within its scopes, a token acts as its user
+    let (status, body) = bot
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": unique("Scoped"),
+                "description": "Written by a bot",
+                "body": "Beep",
+                "tagList": [],
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["article"]["author"]["username"], json!(judy_name));
+    let slug = body["article"]["slug"].as_str().unwrap().to_string();
+    let (status, _) = bot.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Outside of them it is refused
+    let (status, _) = bot
+        .post(
+            &format!("/articles/{slug}/comments"),
+            json!({ "comment": { "body": "Beep" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = bot
+        .post(&format!("/profiles/{kevin_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Managing the account takes a login
+    let (status, _) = bot.get("/user").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = bot.get("/user/tokens").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = create("commenter", json!(["comments:write"])).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let commenter = anon.with_token(body["accessToken"]["token"].as_str().unwrap());
+    let (status, _) = commenter.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = commenter
+        .post(
+            &format!("/articles/{slug}/comments"),
+            json!({ "comment": { "body": "Beep" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, body) = create("ci", json!(["admin"])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["scopes"].is_array(), "{body}");
+    let (status, body) = create("", json!(["read"])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["name"].is_array(), "{body}");
+
+    // Revoked tokens stop working at once
+    let (status, _) = judy.delete(&format!("/user/tokens/{id}")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = bot.get("/articles/feed").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = judy.delete(&format!("/user/tokens/{id}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = judy.get("/user/tokens").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["accessTokens"].as_array().unwrap().len(), 1, "{body}");
+}
+
+// ================================== Roles ================================== //
+
+async fn roles(server: &TestServer, anon: &Client) {
+    let (status, body) = anon
+        .post(
+            "/users",
+            json!({ "user": {
+                "username": unique("admin"),
+                "email": server.admin_email,
+                "password": "password123",
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["role"], json!("admin"));
+    let (admin, _) = tokens(anon, &body);
+
+    let (mallory, _) = register(anon, "mallory").await;
+    let (moderator, moderator_name) = register(anon, "moderator").await;
+    let (writer, writer_name) = register(anon, "writer").await;
+
+    // Only admins manage users
+    let (status, _) = mallory.get("/admin/users").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = mallory
+        .put(
+            &format!("/admin/users/{moderator_name}/role"),
+            json!({ "user": { "role": "admin" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Earlier runs against the same database left users behind, so the writer may be on any page
+    let mut offset = 0;
+    let listed = loop {
+        let (status, body) = admin
+            .get(&format!("/admin/users?limit=100&offset={offset}"))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert!(body["usersCount"].as_i64().unwrap() >= 4, "{body}");
+        let users = body["users"].as_array().expect("no users");
+        assert!(!users.is_empty(), "writer not listed");
+        if let Some(user) = users
+            .iter()
+            .find(|user| user["username"] == json!(writer_name))
+        {
+            break user.clone();
+        }
+        offset += users.len();
+    };
+    assert_eq!(listed["role"], json!("user"));
+    assert!(listed.get("token").is_none(), "{listed}");
+
+    let role_path = format!("/admin/users/{moderator_name}/role");
+    let (status, body) = admin
+        .put(&role_path, json!({ "user": { "role": "overlord" } }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["role"].is_array(), "{body}");
+    let (status, body) = admin
+        .put(&role_path, json!({ "user": { "role": "moderator" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["role"], json!("moderator"));
+    let (status, body) = moderator.get("/user").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["user"]["role"], json!("moderator"));
+
+    let (status, body) = writer
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": unique("Moderated"),
+                "description": "Up for moderation",
+                "body": "Something controversial",
+                "tagList": [],
+            } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let slug = body["article"]["slug"].as_str().unwrap().to_string();
+    let article = format!("/articles/{slug}");
+    let comments = format!("{article}/comments");
+    let (status, body) = mallory
+        .post(&comments, json!({ "comment": { "body": "Spam" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let comment = format!("{comments}/{}", body["comment"]["id"]);
+
+    // Users only moderate what they wrote
+    let (status, _) = mallory.delete(&article).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = mallory.post(&format!("{article}/hide"), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = writer.delete(&comment).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = writer.post(&format!("{comment}/hide"), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Hidden articles stay visible to their author only
+    let (status, _) = moderator.post(&format!("{article}/hide"), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = anon.get(&article).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = anon.get(&format!("/articles?author={writer_name}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["articlesCount"], json!(0), "{body}");
+    let (status, _) = writer.get(&article).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = moderator
+        .post(&format!("{article}/unhide"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = anon.get(&article).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // So do hidden comments
+    let (status, _) = moderator.post(&format!("{comment}/hide"), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = anon.get(&comments).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "comments": [] }));
+    let (status, body) = mallory.get(&comments).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["comments"].as_array().unwrap().len(), 1, "{body}");
+
+    // Moderators delete, but don't edit, anyone's content
+    let (status, _) = moderator
+        .put(&article, json!({ "article": { "body": "Rewritten" } }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = moderator.get("/admin/users").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = moderator.delete(&comment).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = mallory.get(&comments).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "comments": [] }));
+    let (status, _) = moderator.delete(&article).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = writer.get(&article).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// ================================== Lockout ================================== //
+
+async fn lockout(server: &TestServer, anon: &Client) {
+    let login = |client: Client, email: String, password: &'static str| async move {
+        let body = json!({ "user": { "email": email, "password": password } });
+        client.post("/users/login", body).await.0
+    };
+    let max_failures = server.lockout.max_failures;
+
+    let (status, body) = anon
+        .post(
+            "/users/login",
+            json!({ "user": { "email": server.admin_email, "password": "password123" } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (admin, _) = tokens(anon, &body);
+    let (mallory, _) = register(anon, "mallory").await;
+    let (_, victim_name) = register(anon, "victim").await;
+    let victim_email = format!("{victim_name}@example.com");
+
+    // Guessing from several addresses locks the account for all of them
+    for attempt in 1..=max_failures {
+        let guesser = anon.forwarded_for(&unique_address());
+        let status = login(guesser, victim_email.clone(), "guess").await;
+        match attempt == max_failures {
+            true => assert_eq!(status, StatusCode::TOO_MANY_REQUESTS),
+            false => assert_eq!(status, StatusCode::UNAUTHORIZED),
+        }
+    }
+    let status = login(anon.clone(), victim_email.clone(), "password123").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Only admins lift it
+    let unlock = format!("/admin/users/{victim_name}/unlock");
+    let (status, _) = mallory.post(&unlock, json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = admin.post(&unlock, json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(victim_name));
+    let status = login(anon.clone(), victim_email.clone(), "guess").await;
+    assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+    let status = login(anon.clone(), victim_email.clone(), "password123").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Guessing many accounts from one address locks the address, for any account
+    let guesser = anon.forwarded_for(&unique_address());
+    for attempt in 1..=server.lockout.max_ip_failures {
+        let email = format!("{}@example.com", unique("nobody"));
+        let status = login(guesser.clone(), email, "guess").await;
+        assert_eq!(
+            status == StatusCode::TOO_MANY_REQUESTS,
+            attempt == server.lockout.max_ip_failures,
+        );
+    }
+    let status = login(guesser, victim_email.clone(), "password123").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let status = login(anon.clone(), victim_email, "password123").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ================================== Credentials ================================== //
+
+async fn credentials(anon: &Client) {
+    let (_, username) = register(anon, "dave").await;
+    let email = format!("{username}@example.com");
+    let login = json!({ "user": { "email": email, "password": "password123" } });
+
+    let (status, headers, body) = anon
+        .exchange(Method::POST, "/users/login", Some(login))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let token = body["user"]["token"].as_str().expect("no token");
+
+    // Either scheme is accepted, in any case
+    for scheme in ["Token", "Bearer", "token", "BEARER"] {
+        let client = anon.header("Authorization", &format!("{scheme} {token}"));
+        let (status, body) = client.get("/user").await;
+        assert_eq!(status, StatusCode::OK, "{scheme}: {body}");
+    }
+
+    // Malformed headers are refused, without taking the server down
+    for header in ["Token", "", "Basic dXNlcjpwYXNzd29yZA==", "Token a b"] {
+        let (status, _) = anon.header("Authorization", header).get("/user").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{header:?}");
+    }
+    let (status, _) = anon
+        .header("Authorization", &format!("Bearer {token}"))
+        .get("/user")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Logging in starts a cookie session too, the CSRF cookie is the only one scripts can read
+    let cookies = set_cookies(&headers);
+    assert!(cookies["realworld_session"].contains("HttpOnly"));
+    assert!(cookies["realworld_refresh"].contains("HttpOnly"));
+    assert!(cookies["realworld_refresh"].contains("Path=/api/users/refresh"));
+    assert!(!cookies["realworld_csrf"].contains("HttpOnly"));
+    let session = cookie_value(&cookies["realworld_session"]);
+    let refresh = cookie_value(&cookies["realworld_refresh"]);
+    let csrf = cookie_value(&cookies["realworld_csrf"]);
+    assert_eq!(session, token);
+
+    let browser = anon.header(
+        "Cookie",
+        &format!("realworld_session={session}; realworld_csrf={csrf}"),
+    );
+    let (status, body) = browser.get("/user").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(username));
+
+    // Changes have to repeat the CSRF cookie in a header
+    let update = json!({ "user": { "bio": "Cookies only" } });
+    let (status, _) = browser.put("/user", update.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let forged = browser.header("X-CSRF-Token", "forged");
+    let (status, _) = forged.put("/user", update.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = browser
+        .header("X-CSRF-Token", csrf)
+        .put("/user", update)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["bio"], json!("Cookies only"));
+
+    // The refresh cookie refreshes the session without a token in the body
+    let refresher = anon.header(
+        "Cookie",
+        &format!("realworld_refresh={refresh}; realworld_csrf={csrf}"),
+    );
+    let (status, _) = refresher.post("/users/refresh", json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, headers, body) = refresher
+        .header("X-CSRF-Token", csrf)
+        .exchange(Method::POST, "/users/refresh", Some(json!({})))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let cookies = set_cookies(&headers);
+    let session = cookie_value(&cookies["realworld_session"]);
+    let csrf = cookie_value(&cookies["realworld_csrf"]);
+
+    // Logging out clears the cookies, and ends the session
+    let browser = anon
+        .header(
+            "Cookie",
+            &format!("realworld_session={session}; realworld_csrf={csrf}"),
+        )
+        .header("X-CSRF-Token", csrf);
+    let (status, headers, _) = browser
+        .exchange(Method::POST, "/users/logout", Some(json!({})))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let cookies = set_cookies(&headers);
+    for name in ["realworld_session", "realworld_refresh", "realworld_csrf"] {
+        assert!(cookies[name].contains("Max-Age=0"), "{}", cookies[name]);
+    }
+    let (status, _) = browser.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+// ================================== Privacy ================================== //
+
+async fn privacy(anon: &Client) {
+    let (erin, erin_name) = register(anon, "erin").await;
+    let (frank, frank_name) = register(anon, "frank").await;
+
+    let published = write_article(&erin, "published").await;
+    let draft = write_article(&erin, "draft").await;
+    let comments = format!("/articles/{published}/comments");
+    let (status, _) = erin
+        .post(&comments, json!({ "comment": { "body": "Mine" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = frank
+        .post(&comments, json!({ "comment": { "body": "Nice" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = erin
+        .post(&format!("/articles/{published}/favorite"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = erin
+        .post(&format!("/profiles/{frank_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = frank
+        .post(&format!("/profiles/{erin_name}/follow"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The export has drafts too
+    let (status, _) = anon.get("/user/export").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = erin.get("/user/export").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["user"]["username"], json!(erin_name));
+    assert_eq!(
+        body["user"]["email"],
+        json!(format!("{erin_name}@example.com"))
+    );
+    let slugs: Vec<&Value> = body["articles"]
+        .as_array()
+        .expect("no articles")
+        .iter()
+        .map(|article| &article["slug"])
+        .collect();
+    assert_eq!(slugs, [&json!(published), &json!(draft)]);
+    assert_eq!(body["articles"][1]["status"], json!("draft"));
+    assert_eq!(
+        redact(body["comments"].clone()),
+        json!([{
+            "id": "<id>",
+            "article": published,
+            "body": "Mine",
+            "createdAt": "<timestamp>",
+            "updatedAt": "<timestamp>",
+        }])
+    );
+    assert_eq!(body["favorites"], json!([published]));
+    assert_eq!(body["following"], json!([frank_name]));
+    assert_eq!(body["followers"], json!([frank_name]));
+
+    let (status, headers, archive) = erin
+        .download(Method::GET, "/user/export?format=zip", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "application/zip");
+    assert!(archive.starts_with(b"PK\x03\x04"));
+    for file in [
+        "user.json",
+        "articles.json",
+        "comments.json",
+        "follows.json",
+    ] {
+        let name = file.as_bytes();
+        assert!(
+            archive.windows(name.len()).any(|window| window == name),
+            "{file}"
+        );
+    }
+    let (status, body) = erin.get("/user/export?format=xml").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["format"].is_array(), "{body}");
+
+    // By default, published articles and comments stay under an anonymous author
+    let (status, body) = erin.delete("/user?content=keep").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["errors"]["content"].is_array(), "{body}");
+    let (status, body) = erin.delete("/user").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, json!({ "message": "OK" }));
+
+    let (status, _) = erin.get("/user").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let login = json!({ "user": { "email": format!("{erin_name}@example.com"), "password": "password123" } });
+    let (status, _) = anon.post("/users/login", login).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = anon.get(&format!("/profiles/{erin_name}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = anon.get(&format!("/articles/{published}")).await;
+    assert_eq!(status, StatusCode::OK);
+    let author = body["article"]["author"]["username"].as_str().unwrap();
+    assert!(author.starts_with("deleted-"), "{author}");
+    assert_eq!(body["article"]["favoritesCount"], json!(0));
+    let (_, body) = anon.get(&comments).await;
+    let authors: Vec<&Value> = body["comments"]
+        .as_array()
+        .expect("no comments")
+        .iter()
+        .map(|comment| &comment["author"]["username"])
+        .collect();
+    assert_eq!(authors.len(), 2);
+    assert!(authors.contains(&&json!(author)));
+
+    let (_, body) = frank.get("/user/export").await;
+    assert_eq!(body["following"], json!([]));
+    assert_eq!(body["followers"], json!([]));
+
+    // Removing takes everything they wrote along
+    let (grace, grace_name) = register(anon, "grace").await;
+    let theirs = write_article(&grace, "published").await;
+    let (status, _) = grace
+        .post(&comments, json!({ "comment": { "body": "Gone soon" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = frank
+        .post(&format!("/articles/{theirs}/favorite"), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = grace.delete("/user?content=remove").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = anon.get(&format!("/articles/{theirs}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = anon.get(&format!("/profiles/{grace_name}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = anon.get(&comments).await;
+    assert_eq!(body["comments"].as_array().expect("no comments").len(), 2);
+    let (_, body) = frank.get("/user/export").await;
+    assert_eq!(body["favorites"], json!([]));
+}
+
+// ================================== Metrics ================================== //
+
+async fn metrics(server: &TestServer, backend: Backend, storage: Storage) {
+    let root = server.root_client();
+    let (status, _, _) = root.download(Method::GET, "/metrics", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let wrong = root.header("Authorization", "Bearer wrong");
+    let (status, _, _) = wrong.download(Method::GET, "/metrics", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let scraper = root.header("Authorization", &format!("Bearer {}", server.metrics_token));
+    let (status, headers, bytes) = scraper.download(Method::GET, "/metrics", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let text = String::from_utf8(bytes).unwrap();
+    let value = |series: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap_or_else(|| panic!("no {series} in\n{text}"))
+    };
+
+    // Requests by route and status, with the route as registered
+    let tags = r#"method="GET",route="/api/tags",status="200""#;
+    assert!(value(&format!("http_requests_total{{{tags}}}")) >= 1.0);
+    assert!(value(&format!("http_request_duration_seconds_count{{{tags}}}")) >= 1.0);
+    assert!(
+        text.contains("http_request_duration_seconds_bucket{"),
+        "{text}"
+    );
+    assert!(text.contains(r#"status="401""#), "{text}");
+
+    for event in ["registration", "login", "article_created", "favorite"] {
+        assert!(value(&format!("realworld_events_total{{event=\"{event}\"}}")) >= 1.0);
+    }
+
+    match storage {
+        Storage::Postgres => {
+            assert!(value("db_pool_max_connections") >= 1.0);
+            assert!(value("db_pool_connections") <= value("db_pool_max_connections"));
+            assert!(value("db_pool_idle_connections") <= value("db_pool_connections"));
+            // Only bb8 counts waits
+            assert_eq!(
+                text.contains("db_pool_wait_seconds_total"),
+                matches!(backend, Backend::Diesel),
+                "{text}"
+            );
+        }
+        Storage::Memory => assert!(!text.contains("db_pool_"), "{text}"),
+    }
+}
+
+/// Writes an article with `status`, returning its slug.
+async fn write_article(client: &Client, status: &str) -> String {
+    let (code, body) = client
+        .post(
+            "/articles",
+            json!({ "article": {
+                "title": unique("Personal data "),
+                "description": "About me",
+                "body": "Everything about me",
+                "status": status,
+            } }),
+        )
+        .await;
+    assert_eq!(code, StatusCode::OK, "{body}");
+    body["article"]["slug"].as_str().unwrap().to_string()
+}
+
+/// The `Set-Cookie` headers of a response, by cookie name.
+fn set_cookies(headers: &HeaderMap) -> std::collections::HashMap<String, String> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|header| header.to_str().unwrap().to_string())
+        .map(|cookie| (cookie.split('=').next().unwrap().to_string(), cookie))
+        .collect()
+}
+
+/// The value a `Set-Cookie` header sets.
+fn cookie_value(cookie: &str) -> &str {
+    let pair = cookie.split(';').next().unwrap();
+    pair.split_once('=').unwrap().1
+}
+
+/// The token in the link to `path` of a mail.
+fn mailed_token(mail: &str, path: &str) -> String {
+    let link = format!("{path}?token=");
+    let start = mail
+        .find(&link)
+        .unwrap_or_else(|| panic!("no {path} link in {mail}"))
+        + link.len();
+    mail[start..].split_whitespace().next().unwrap().to_string()
+}
+
+/// A client using the access token of a login response, and its refresh token.
+fn tokens(anon: &Client, body: &Value) -> (Client, String) {
+    let token = body["user"]["token"].as_str().expect("no token");
+    let refresh_token = body["user"]["refreshToken"]
+        .as_str()
+        .expect("no refresh token");
+    (anon.with_token(token), refresh_token.to_string())
+}
@@ -0,0 +1,199 @@
+//! Personal access tokens, for scripts and bots acting on behalf of a user.
+//!
+//! Unlike login tokens they are opaque (see [`crate::token`]), named, and limited to the scopes
+//! they were created with. They expire on their own, and users can list and revoke them. Account
+//! endpoints, including managing access tokens, still need a login.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::{error::validate_known, repo::AccessToken, token::new_token, CustomDateTime};
+
+/// Tells access tokens apart from login tokens, and makes them easy to spot in leaked text.
+pub const PREFIX: &str = "rwat_";
+
+const DEFAULT_TTL_DAYS: i64 = 30;
+
+/// What an access token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    /// Reading anything a logged in user can, such as their feed and drafts.
+    #[serde(rename = "read")]
+    Read,
+    /// Writing, publishing, deleting and favoriting articles.
+    #[serde(rename = "articles:write")]
+    ArticlesWrite,
+    /// Writing and deleting comments.
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+    /// Following and unfollowing users.
+    #[serde(rename = "profiles:write")]
+    ProfilesWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::Read,
+        Scope::ArticlesWrite,
+        Scope::CommentsWrite,
+        Scope::ProfilesWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ArticlesWrite => "articles:write",
+            Scope::CommentsWrite => "comments:write",
+            Scope::ProfilesWrite => "profiles:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// Scopes as stored, skipping any this version doesn't know.
+pub fn parse_scopes<S: AsRef<str>>(scopes: &[S]) -> Vec<Scope> {
+    scopes
+        .iter()
+        .filter_map(|scope| scope.as_ref().parse().ok())
+        .collect()
+}
+
+/// 256 random bits behind [`PREFIX`].
+pub fn new_access_token() -> String {
+    format!("{PREFIX}{}", new_token())
+}
+
+/// Whether `token` is an access token rather than a login token. Says nothing about whether
+/// it is valid.
+pub fn is_access_token(token: &str) -> bool {
+    token.starts_with(PREFIX)
+}
+
+impl<I> AccessToken<I> {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct NewAccessTokenData {
+    #[validate(length(min = 1, max = 100, message = "name must be 1 to 100 characters long"))]
+    pub name: String,
+
+    /// See [`validate_known`].
+    #[validate(
+        length(min = 1, message = "scopes can't be empty"),
+        custom = "validate_scopes"
+    )]
+    pub scopes: Vec<String>,
+
+    #[serde(rename = "expiresInDays")]
+    #[validate(range(min = 1, max = 365, message = "expiresInDays must be 1 to 365"))]
+    pub expires_in_days: Option<i64>,
+}
+
+impl NewAccessTokenData {
+    /// Only valid once validated.
+    pub fn scopes(&self) -> Vec<Scope> {
+        let mut scopes = parse_scopes(&self.scopes);
+        scopes.sort();
+        scopes.dedup();
+        scopes
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + Duration::days(self.expires_in_days.unwrap_or(DEFAULT_TTL_DAYS))
+    }
+}
+
+fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    scopes
+        .iter()
+        .try_for_each(|scope| validate_known::<Scope>(scope, "scope"))
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokenResponse {
+    #[serde(rename = "accessToken")]
+    pub access_token: AccessTokenResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokenListResponse {
+    #[serde(rename = "accessTokens")]
+    pub access_tokens: Vec<AccessTokenResponseInner>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccessTokenResponseInner {
+    /// A string whatever the backend's ids are, since clients only pass it back.
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Only shown once, when the token is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: CustomDateTime,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: CustomDateTime,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<CustomDateTime>,
+}
+
+impl<I: fmt::Display> From<AccessToken<I>> for AccessTokenResponseInner {
+    fn from(access_token: AccessToken<I>) -> Self {
+        AccessTokenResponseInner {
+            id: access_token.id.to_string(),
+            name: access_token.name,
+            scopes: access_token.scopes,
+            token: None,
+            created_at: access_token.created_at.into(),
+            expires_at: access_token.expires_at.into(),
+            last_used_at: access_token.last_used_at.map(Into::into),
+        }
+    }
+}
+
+impl AccessTokenResponse {
+    /// The only response that carries the token itself.
+    pub fn created<I: fmt::Display>(access_token: AccessToken<I>, token: String) -> Self {
+        AccessTokenResponse {
+            access_token: AccessTokenResponseInner {
+                token: Some(token),
+                ..access_token.into()
+            },
+        }
+    }
+}
+
+impl<I: fmt::Display> From<Vec<AccessToken<I>>> for AccessTokenListResponse {
+    fn from(access_tokens: Vec<AccessToken<I>>) -> Self {
+        AccessTokenListResponse {
+            access_tokens: access_tokens.into_iter().map(Into::into).collect(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Password resets and email verification.
+//!
+//! Both work by mailing the user a link with a single-use token (see [`crate::token`]) that
+//! expires after a while. Issuing a new token for the same purpose invalidates older ones, so
+//! only the most recent mail works.
+
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::mail::{Mail, Outbox};
+
+/// Frontend pages the mailed links point to.
+pub const PASSWORD_RESET_PATH: &str = "/reset-password";
+pub const VERIFY_EMAIL_PATH: &str = "/verify-email";
+
+/// What a single-use token can be redeemed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailVerification => "email_verification",
+        }
+    }
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(
+        email(message = "invalid email address"),
+        length(min = 1, message = "email can't be blank")
+    )]
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct PasswordResetConfirm {
+    #[validate(length(min = 1, message = "token can't be blank"))]
+    pub token: String,
+
+    #[validate(
+        non_control_character(message = "password can't contain non-ascii charactors"),
+        length(min = 8, message = "password must be at least 8 characters long"),
+        length(max = 64, message = "too long password")
+    )]
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct EmailVerification {
+    #[validate(length(min = 1, message = "token can't be blank"))]
+    pub token: String,
+}
+
+// ================================== Mail ================================== //
+
+pub fn password_reset_mail(outbox: &Outbox, to: &str, token: &str) -> Mail {
+    Mail {
+        to: to.to_string(),
+        subject: "Reset your RealWorld password".to_string(),
+        body: format!(
+            "Someone asked to reset the password of your RealWorld account. If it was you, \
+             choose a new password here:\n\n{}\n\nOtherwise you can ignore this mail.",
+            outbox.link(PASSWORD_RESET_PATH, token)
+        ),
+    }
+}
+
+pub fn verification_mail(outbox: &Outbox, to: &str, token: &str) -> Mail {
+    Mail {
+        to: to.to_string(),
+        subject: "Confirm your RealWorld email address".to_string(),
+        body: format!(
+            "Please confirm this is your email address:\n\n{}",
+            outbox.link(VERIFY_EMAIL_PATH, token)
+        ),
+    }
+}
@@ -0,0 +1,102 @@
+//! Managing users, for admins only.
+
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    article::page_limit,
+    error::validate_known,
+    policy::Role,
+    repo::{User, UserList},
+};
+
+// ================================== Extractors ================================== //
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UsersParams {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl UsersParams {
+    pub fn limit(&self) -> usize {
+        page_limit(self.limit)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct UpdateRoleData {
+    /// See [`validate_known`].
+    #[validate(custom = "validate_role")]
+    pub role: String,
+}
+
+impl UpdateRoleData {
+    pub fn role(&self) -> Role {
+        self.role.parse().unwrap_or_default()
+    }
+}
+
+fn validate_role(role: &str) -> Result<(), ValidationError> {
+    validate_known::<Role>(role, "role")
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserResponse {
+    pub user: AdminUser,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserListResponse {
+    pub users: Vec<AdminUser>,
+    #[serde(rename = "usersCount")]
+    pub users_count: i64,
+}
+
+/// A user as admins see them, with their email and role but without any token.
+#[derive(Debug, Serialize)]
+pub struct AdminUser {
+    pub username: String,
+    pub email: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    pub role: Role,
+}
+
+impl<I> From<User<I>> for AdminUser {
+    fn from(user: User<I>) -> Self {
+        AdminUser {
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            image: user.image,
+            email_verified: user.email_verified,
+            role: user.role,
+        }
+    }
+}
+
+impl<I> From<User<I>> for AdminUserResponse {
+    fn from(user: User<I>) -> Self {
+        AdminUserResponse { user: user.into() }
+    }
+}
+
+impl<I> From<UserList<I>> for AdminUserListResponse {
+    fn from(list: UserList<I>) -> Self {
+        AdminUserListResponse {
+            users: list.users.into_iter().map(Into::into).collect(),
+            users_count: list.total,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::{
+    profile::ProfileResponseInner,
+    repo::{Cursor, Identifier, Page},
+    search::SearchQuery,
+    CustomDateTime,
+};
+
+/// Only published articles show up in lists, feeds and search results. Unlisted ones can
+/// still be read by anyone knowing their slug, drafts only by their author.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleStatus {
+    Draft,
+    #[default]
+    Published,
+    Unlisted,
+}
+
+impl ArticleStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArticleStatus::Draft => "draft",
+            ArticleStatus::Published => "published",
+            ArticleStatus::Unlisted => "unlisted",
+        }
+    }
+}
+
+impl FromStr for ArticleStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "draft" => Ok(ArticleStatus::Draft),
+            "published" => Ok(ArticleStatus::Published),
+            "unlisted" => Ok(ArticleStatus::Unlisted),
+            _ => Err(format!("unknown article status {status:?}")),
+        }
+    }
+}
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+
+// ================================== Extractors ================================== //
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ArticlesParams {
+    pub tag: Option<String>,
+    pub author: Option<String>,
+    pub favorited: Option<String>,
+    pub limit: Option<usize>,  // <- if not set, is 20
+    pub offset: Option<usize>, // <- if not set, is 0
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedParams {
+    pub limit: Option<usize>,  // <- if not set, is 20
+    pub offset: Option<usize>, // <- if not set, is 0
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<usize>,  // <- if not set, is 20
+    pub offset: Option<usize>, // <- if not set, is 0
+}
+
+/// Clamps a requested page size to `1..=MAX_LIMIT`, defaulting to `DEFAULT_LIMIT`.
+pub fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Any `cursor` parameter, even an empty one, switches a list to keyset pagination: an
+/// empty cursor starts at the newest article, and every page hands out the `nextCursor` to
+/// continue from. `offset` is ignored then.
+pub fn page<I: Identifier>(
+    limit: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<&str>,
+) -> Result<Page<I>, ValidationErrors> {
+    let limit = page_limit(limit);
+
+    match cursor {
+        None => Ok(Page::new(limit, offset.unwrap_or(0))),
+        Some("") => Ok(Page::keyset(limit, None)),
+        Some(cursor) => match Cursor::decode(cursor) {
+            Some(cursor) => Ok(Page::keyset(limit, Some(cursor))),
+            None => Err(invalid_param("cursor", "cursor is invalid")),
+        },
+    }
+}
+
+impl ArticlesParams {
+    pub fn limit(&self) -> usize {
+        page_limit(self.limit)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn page<I: Identifier>(&self) -> Result<Page<I>, ValidationErrors> {
+        page(self.limit, self.offset, self.cursor.as_deref())
+    }
+}
+
+impl FeedParams {
+    pub fn limit(&self) -> usize {
+        page_limit(self.limit)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn page<I: Identifier>(&self) -> Result<Page<I>, ValidationErrors> {
+        page(self.limit, self.offset, self.cursor.as_deref())
+    }
+}
+
+impl SearchParams {
+    pub fn page<I: Identifier>(&self) -> Page<I> {
+        Page::new(page_limit(self.limit), self.offset.unwrap_or(0))
+    }
+
+    pub fn query(&self) -> Result<SearchQuery, ValidationErrors> {
+        if let Some(query) = SearchQuery::parse(&self.q) {
+            return Ok(query);
+        }
+
+        let message = match self.q.trim().is_empty() {
+            true => "q can't be blank",
+            false => "q has no words to search for",
+        };
+        Err(invalid_param("q", message))
+    }
+}
+
+fn invalid_param(param: &'static str, message: &'static str) -> ValidationErrors {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+
+    let mut errors = ValidationErrors::new();
+    errors.add(param, error);
+    errors
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateArticleData {
+    #[serde(default)]
+    pub tag_list: Vec<String>,
+    #[validate(length(min = 1, message = "title can't be blank"))]
+    pub title: String,
+    #[validate(length(min = 1, message = "description can't be blank"))]
+    pub description: String,
+    #[validate(length(min = 1, message = "body can't be blank"))]
+    pub body: String,
+    /// Published right away if not set.
+    #[serde(default)]
+    pub status: ArticleStatus,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateArticleData {
+    #[validate(length(min = 1, message = "title can't be blank"))]
+    pub title: Option<String>,
+    #[validate(length(min = 1, message = "description can't be blank"))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, message = "body can't be blank"))]
+    pub body: Option<String>,
+    pub tag_list: Option<Vec<String>>,
+    pub status: Option<ArticleStatus>,
+}
+
+// ================================== JSON response objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct ArticleResponse {
+    pub article: ArticleResponseInner,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleResponseInner {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub tag_list: Vec<String>,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+    pub favorited: bool,
+    pub favorites_count: i64,
+    pub author: ProfileResponseInner,
+    pub status: ArticleStatus,
+    pub published_at: Option<CustomDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleListResponse {
+    pub articles: Vec<ArticleResponseInner>,
+    /// Every article matching the filters, not just the ones on this page.
+    pub articles_count: i64,
+    /// Only set in cursor mode, and only if there are more articles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// An article found by a search, most relevant first.
+#[derive(Debug, Serialize)]
+pub struct ArticleSearchHit {
+    #[serde(flatten)]
+    pub article: ArticleResponseInner,
+    /// Part of the body around the first match as HTML: escaped, matches wrapped in `<mark>`
+    /// tags.
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleSearchResponse {
+    pub articles: Vec<ArticleSearchHit>,
+    pub articles_count: i64,
+}
+
+/// Sent with a `301 Moved Permanently` for a slug the article had before it was renamed.
+#[derive(Debug, Serialize)]
+pub struct ArticleRedirectResponse {
+    pub redirect: ArticleRedirect,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArticleRedirect {
+    pub slug: String,
+    pub location: String,
+}
+
+impl ArticleRedirectResponse {
+    pub fn to(slug: String) -> Self {
+        Self {
+            redirect: ArticleRedirect {
+                location: format!("/api/articles/{slug}"),
+                slug,
+            },
+        }
+    }
+
+    pub fn location(&self) -> &str {
+        &self.redirect.location
+    }
+}
+
+impl From<ArticleResponseInner> for ArticleResponse {
+    fn from(article: ArticleResponseInner) -> Self {
+        Self { article }
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{profile::ProfileResponseInner, CustomDateTime};
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct AddCommentData {
+    #[validate(length(min = 1, message = "body can't be blank"))]
+    pub body: String,
+}
+
+// ================================== JSON response objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct CommentResponse {
+    pub comment: CommentResponseInner,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentResponseInner {
+    pub id: i32,
+    pub body: String,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+    pub author: ProfileResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentListResponse {
+    pub comments: Vec<CommentResponseInner>,
+}
+
+impl From<CommentResponseInner> for CommentResponse {
+    fn from(comment: CommentResponseInner) -> Self {
+        Self { comment }
+    }
+}
+
+impl From<Vec<CommentResponseInner>> for CommentListResponse {
+    fn from(comments: Vec<CommentResponseInner>) -> Self {
+        Self { comments }
+    }
+}
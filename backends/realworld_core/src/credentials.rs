@@ -0,0 +1,349 @@
+//! Where requests carry the token they authenticate with.
+//!
+//! API clients send `Authorization: Token <token>`, or `Bearer` in its place. Browsers can keep a
+//! session in HttpOnly cookies instead, set on login once [`CookieConfig::enabled`], so scripts
+//! never get to see the token.
+//!
+//! Browsers send cookies along with requests other sites make too, so a request authenticated by
+//! cookie that may change anything also has to repeat the value of the CSRF cookie in an
+//! `X-CSRF-Token` header. Only scripts of the frontend's own site can read that cookie.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    config::TokenConfig,
+    token::{hash_token, new_token},
+    user::UserResponse,
+};
+
+/// Accepted in the `Authorization` header, in any case.
+pub const SCHEMES: [&str; 2] = ["Token", "Bearer"];
+
+pub const SESSION_COOKIE: &str = "realworld_session";
+pub const REFRESH_COOKIE: &str = "realworld_refresh";
+pub const CSRF_COOKIE: &str = "realworld_csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The refresh cookie is only sent here.
+pub const REFRESH_PATH: &str = "/api/users/refresh";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CredentialError {
+    #[error("the authorization header is malformed")]
+    Malformed,
+
+    #[error("the authorization scheme is not supported")]
+    UnsupportedScheme,
+
+    #[error("the CSRF token is missing or wrong")]
+    Csrf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CookieConfig {
+    /// Sets session cookies on login, and takes them in place of an `Authorization` header.
+    pub enabled: bool,
+    /// Only sends the cookies over HTTPS. Only turn it off for local development.
+    pub secure: bool,
+    /// `Strict`, `Lax` or `None`.
+    pub same_site: String,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            secure: true,
+            same_site: "Lax".to_string(),
+        }
+    }
+}
+
+/// The headers of a request that may carry credentials.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RequestCredentials<'a> {
+    pub authorization: Option<&'a [u8]>,
+    pub cookie: Option<&'a [u8]>,
+    /// The `X-CSRF-Token` header.
+    pub csrf_token: Option<&'a [u8]>,
+    /// `GET`, `HEAD` and `OPTIONS` requests change nothing, so they need no CSRF token.
+    pub safe_method: bool,
+}
+
+impl CookieConfig {
+    /// The token `request` authenticates with, if any. An `Authorization` header takes
+    /// precedence over a session cookie.
+    pub fn token(&self, request: &RequestCredentials) -> Result<Option<String>, CredentialError> {
+        if let Some(authorization) = request.authorization {
+            return parse_authorization(authorization).map(Some);
+        }
+
+        self.cookie_token(request, SESSION_COOKIE)
+    }
+
+    /// The refresh token of a cookie session, so refreshing it needs no request body.
+    pub fn refresh_token(
+        &self,
+        request: &RequestCredentials,
+    ) -> Result<Option<String>, CredentialError> {
+        self.cookie_token(request, REFRESH_COOKIE)
+    }
+
+    fn cookie_token(
+        &self,
+        request: &RequestCredentials,
+        name: &str,
+    ) -> Result<Option<String>, CredentialError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let Some(token) = request.cookie.and_then(|header| cookie(header, name)) else {
+            return Ok(None);
+        };
+
+        if !request.safe_method {
+            let expected = request
+                .cookie
+                .and_then(|header| cookie(header, CSRF_COOKIE));
+            let given = request
+                .csrf_token
+                .and_then(|header| std::str::from_utf8(header).ok());
+
+            // Compared as digests, so the time taken doesn't tell how much of it was right
+            match (expected, given) {
+                (Some(expected), Some(given)) if hash_token(expected) == hash_token(given) => {}
+                _ => return Err(CredentialError::Csrf),
+            }
+        }
+
+        Ok(Some(token.to_string()))
+    }
+
+    /// `Set-Cookie` values that start a cookie session with the tokens of `user`, none unless
+    /// enabled.
+    pub fn session_cookies(&self, user: &UserResponse, tokens: &TokenConfig) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut cookies = vec![
+            self.cookie(
+                SESSION_COOKIE,
+                &user.user.token,
+                "/",
+                tokens.access_ttl,
+                true,
+            ),
+            self.cookie(CSRF_COOKIE, &new_token(), "/", tokens.refresh_ttl, false),
+        ];
+        if let Some(refresh_token) = &user.user.refresh_token {
+            cookies.push(self.cookie(
+                REFRESH_COOKIE,
+                refresh_token,
+                REFRESH_PATH,
+                tokens.refresh_ttl,
+                true,
+            ));
+        }
+        cookies
+    }
+
+    /// `Set-Cookie` values that end a cookie session, none unless enabled.
+    pub fn cleared_cookies(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        vec![
+            self.cookie(SESSION_COOKIE, "", "/", 0, true),
+            self.cookie(CSRF_COOKIE, "", "/", 0, false),
+            self.cookie(REFRESH_COOKIE, "", REFRESH_PATH, 0, true),
+        ]
+    }
+
+    fn cookie(&self, name: &str, value: &str, path: &str, max_age: i64, http_only: bool) -> String {
+        let mut cookie = format!(
+            "{name}={value}; Path={path}; Max-Age={max_age}; SameSite={}",
+            self.same_site
+        );
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+/// The token of an `Authorization` header, `<scheme> <token>` with one of the [`SCHEMES`].
+pub fn parse_authorization(value: &[u8]) -> Result<String, CredentialError> {
+    let value = std::str::from_utf8(value).map_err(|_| CredentialError::Malformed)?;
+    let mut parts = value.split_whitespace();
+
+    let (Some(scheme), Some(token), None) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(CredentialError::Malformed);
+    };
+    if !SCHEMES
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(scheme))
+    {
+        return Err(CredentialError::UnsupportedScheme);
+    }
+
+    Ok(token.to_string())
+}
+
+/// The value of the cookie `name` in a `Cookie` header.
+fn cookie<'a>(header: &'a [u8], name: &str) -> Option<&'a str> {
+    std::str::from_utf8(header)
+        .ok()?
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{policy::Role, user::UserResponseInner};
+
+    fn enabled() -> CookieConfig {
+        CookieConfig {
+            enabled: true,
+            ..CookieConfig::default()
+        }
+    }
+
+    fn with_cookie(cookie: &str) -> RequestCredentials<'_> {
+        RequestCredentials {
+            cookie: Some(cookie.as_bytes()),
+            safe_method: true,
+            ..RequestCredentials::default()
+        }
+    }
+
+    #[test]
+    fn authorization_takes_either_scheme_in_any_case() {
+        for header in ["Token abc", "Bearer abc", "bearer abc", "  TOKEN   abc  "] {
+            assert_eq!(
+                parse_authorization(header.as_bytes()),
+                Ok("abc".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_authorization_is_refused() {
+        for header in ["", "Token", "abc", "Token abc def"] {
+            assert_eq!(
+                parse_authorization(header.as_bytes()),
+                Err(CredentialError::Malformed),
+                "{header:?}"
+            );
+        }
+        assert_eq!(
+            parse_authorization(b"Token \xff"),
+            Err(CredentialError::Malformed)
+        );
+        assert_eq!(
+            parse_authorization(b"Basic abc"),
+            Err(CredentialError::UnsupportedScheme)
+        );
+    }
+
+    #[test]
+    fn cookies_are_found_by_name() {
+        let header = b"other=1; realworld_session=abc;realworld_csrf=";
+        assert_eq!(cookie(header, SESSION_COOKIE), Some("abc"));
+        assert_eq!(cookie(header, CSRF_COOKIE), None);
+        assert_eq!(cookie(header, REFRESH_COOKIE), None);
+        assert_eq!(cookie(b"realworld_session_old=abc", SESSION_COOKIE), None);
+    }
+
+    #[test]
+    fn session_cookies_only_count_when_enabled() {
+        let request = with_cookie("realworld_session=abc");
+        assert_eq!(CookieConfig::default().token(&request), Ok(None));
+        assert_eq!(enabled().token(&request), Ok(Some("abc".to_string())));
+    }
+
+    #[test]
+    fn authorization_takes_precedence_over_cookies() {
+        let request = RequestCredentials {
+            authorization: Some(b"Token header"),
+            ..with_cookie("realworld_session=cookie")
+        };
+        assert_eq!(enabled().token(&request), Ok(Some("header".to_string())));
+    }
+
+    #[test]
+    fn unsafe_requests_repeat_the_csrf_cookie() {
+        let cookies = "realworld_session=abc; realworld_csrf=secret";
+        let unsafe_request = |csrf_token: Option<&'static [u8]>| RequestCredentials {
+            csrf_token,
+            safe_method: false,
+            ..with_cookie(cookies)
+        };
+
+        assert_eq!(
+            enabled().token(&unsafe_request(Some(b"secret"))),
+            Ok(Some("abc".to_string()))
+        );
+        assert_eq!(
+            enabled().token(&unsafe_request(Some(b"wrong"))),
+            Err(CredentialError::Csrf)
+        );
+        assert_eq!(
+            enabled().token(&unsafe_request(None)),
+            Err(CredentialError::Csrf)
+        );
+        let without_cookie = RequestCredentials {
+            csrf_token: Some(b"secret"),
+            safe_method: false,
+            ..with_cookie("realworld_session=abc")
+        };
+        assert_eq!(enabled().token(&without_cookie), Err(CredentialError::Csrf));
+    }
+
+    #[test]
+    fn sessions_set_and_clear_their_cookies() {
+        let user = UserResponse::from(UserResponseInner {
+            email: "jake@example.com".to_string(),
+            token: "access".to_string(),
+            username: "jake".to_string(),
+            bio: None,
+            image: None,
+            email_verified: true,
+            role: Role::User,
+            refresh_token: Some("refresh".to_string()),
+        });
+        let tokens = TokenConfig::default();
+
+        assert!(CookieConfig::default()
+            .session_cookies(&user, &tokens)
+            .is_empty());
+
+        let cookies = enabled().session_cookies(&user, &tokens);
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(
+            cookies[0],
+            format!(
+                "realworld_session=access; Path=/; Max-Age={}; SameSite=Lax; HttpOnly; Secure",
+                tokens.access_ttl
+            )
+        );
+        // Scripts read the CSRF cookie to repeat it
+        assert!(cookies[1].starts_with("realworld_csrf="));
+        assert!(!cookies[1].contains("HttpOnly"));
+        assert!(cookies[2].starts_with("realworld_refresh=refresh; Path=/api/users/refresh;"));
+
+        let cleared = enabled().cleared_cookies();
+        assert_eq!(cleared.len(), 3);
+        assert!(cleared.iter().all(|cookie| cookie.contains("Max-Age=0")));
+    }
+}
@@ -0,0 +1,27 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Serialize, Serializer};
+
+// Every timestamp in the API is rendered as UTC with millisecond precision,
+// e.g. `2023-06-18T12:04:05.123Z`, whatever the backend stores it as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CustomDateTime(pub DateTime<Utc>);
+
+impl Serialize for CustomDateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let s = self.0.format("%Y-%m-%dT%H:%M:%S.%3fZ");
+        serializer.serialize_str(&s.to_string())
+    }
+}
+
+impl From<DateTime<Utc>> for CustomDateTime {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        Self(datetime)
+    }
+}
+
+// `TIMESTAMPTZ` columns loaded as `NaiveDateTime` are already in UTC
+impl From<NaiveDateTime> for CustomDateTime {
+    fn from(datetime: NaiveDateTime) -> Self {
+        Self(Utc.from_utc_datetime(&datetime))
+    }
+}
@@ -0,0 +1,260 @@
+//! Line diffs between two versions of a text.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+/// Most lines inserted and deleted that [`lines`] looks for the shortest diff with. Its time
+/// grows with the length of the texts times the edits, and its memory with the edits squared.
+pub const MAX_EDITS: usize = 1000;
+
+/// Turns `old` into `new` with as few inserted and deleted lines as possible. Deletions come
+/// before insertions wherever a line was replaced. If more than [`MAX_EDITS`] lines changed, the
+/// changed part is deleted and inserted as a whole instead.
+pub fn lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Only the middle part that actually changed needs a search
+    let prefix = old
+        .iter()
+        .zip(&new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let line = |op, text: &str| DiffLine {
+        op,
+        text: text.to_string(),
+    };
+
+    let mut diff = old[..prefix]
+        .iter()
+        .map(|text| line(DiffOp::Equal, text))
+        .collect::<Vec<_>>();
+
+    match shortest_edits(a, b) {
+        Some(edits) => {
+            let (mut i, mut j) = (0, 0);
+            for op in edits {
+                let text = match op {
+                    DiffOp::Equal | DiffOp::Delete => a[i],
+                    DiffOp::Insert => b[j],
+                };
+                i += usize::from(op != DiffOp::Insert);
+                j += usize::from(op != DiffOp::Delete);
+                diff.push(line(op, text));
+            }
+        }
+        None => {
+            diff.extend(a.iter().map(|text| line(DiffOp::Delete, text)));
+            diff.extend(b.iter().map(|text| line(DiffOp::Insert, text)));
+        }
+    }
+
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|text| line(DiffOp::Equal, text)),
+    );
+
+    diff
+}
+
+/// The operations turning `a` into `b` with the fewest edits, by Myers' O(ND) algorithm, or
+/// `None` if that takes more than [`MAX_EDITS`].
+fn shortest_edits(a: &[&str], b: &[&str]) -> Option<Vec<DiffOp>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (a.len() + b.len()).min(MAX_EDITS) as isize;
+
+    // v[k] is how far along `a` the furthest path on diagonal k = x - y got, offset by max + 1
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The diagonals -d - 1..=d + 1 of v before each round d, to retrace the path
+    let mut trace = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(offset + k) as usize];
+            let mut x = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                true => at(k + 1),
+                false => at(k - 1) + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+
+            if x >= n && y >= m {
+                return Some(retrace(&trace, n, m));
+            }
+        }
+    }
+
+    None
+}
+
+/// Walks the path that reached (n, m) back to the start.
+fn retrace(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<DiffOp> {
+    let (mut x, mut y) = (n, m);
+    let mut edits = Vec::new();
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+
+        let (prev_x, prev_y) = match d {
+            0 => (0, 0),
+            _ => {
+                let prev_k = match k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                    true => k + 1,
+                    false => k - 1,
+                };
+                (at(prev_k), at(prev_k) - prev_k)
+            }
+        };
+
+        while x > prev_x && y > prev_y {
+            edits.push(DiffOp::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(match x == prev_x {
+                true => DiffOp::Insert,
+                false => DiffOp::Delete,
+            });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    edits.reverse();
+
+    // Deletions first within every run of changes
+    for run in edits.split_mut(|op| *op == DiffOp::Equal) {
+        run.sort_by_key(|op| *op == DiffOp::Insert);
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(diff: &[DiffLine]) -> Vec<(DiffOp, &str)> {
+        diff.iter()
+            .map(|line| (line.op, line.text.as_str()))
+            .collect()
+    }
+
+    /// The old and the new text the diff was made from.
+    fn sides(diff: &[DiffLine]) -> (Vec<&str>, Vec<&str>) {
+        let side = |skip| {
+            diff.iter()
+                .filter(|line| line.op != skip)
+                .map(|line| line.text.as_str())
+                .collect()
+        };
+        (side(DiffOp::Insert), side(DiffOp::Delete))
+    }
+
+    fn edits(diff: &[DiffLine]) -> usize {
+        diff.iter().filter(|line| line.op != DiffOp::Equal).count()
+    }
+
+    #[test]
+    fn empty_texts_have_no_lines() {
+        assert_eq!(lines("", ""), vec![]);
+    }
+
+    #[test]
+    fn new_and_removed_texts_are_pure_inserts_and_deletes() {
+        use DiffOp::*;
+        assert_eq!(ops(&lines("", "a\nb")), vec![(Insert, "a"), (Insert, "b")]);
+        assert_eq!(ops(&lines("a\nb", "")), vec![(Delete, "a"), (Delete, "b")]);
+        assert_eq!(
+            ops(&lines("a\nc", "a\nb\nc")),
+            vec![(Equal, "a"), (Insert, "b"), (Equal, "c")]
+        );
+    }
+
+    #[test]
+    fn replaced_lines_are_deleted_before_they_are_inserted() {
+        use DiffOp::*;
+        assert_eq!(
+            ops(&lines("a\nb\nc\nd", "a\nx\ny\nd")),
+            vec![
+                (Equal, "a"),
+                (Delete, "b"),
+                (Delete, "c"),
+                (Insert, "x"),
+                (Insert, "y"),
+                (Equal, "d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_are_shortest() {
+        // The example of Myers' paper, 5 edits apart
+        let (old, new) = ("a\nb\nc\na\nb\nb\na", "c\nb\na\nb\na\nc");
+        let diff = lines(old, new);
+        assert_eq!(edits(&diff), 5);
+        assert_eq!(sides(&diff), (old.lines().collect(), new.lines().collect()));
+    }
+
+    #[test]
+    fn too_many_edits_replace_the_changed_part_as_a_whole() {
+        let text = |name: &str| {
+            let middle = (0..MAX_EDITS).map(|i| format!("{name}{i}"));
+            ["first".to_string()]
+                .into_iter()
+                .chain(middle)
+                .chain(["last".to_string()])
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let (old, new) = (text("old"), text("new"));
+        let diff = lines(&old, &new);
+
+        assert_eq!(diff.len(), 2 * MAX_EDITS + 2);
+        assert_eq!(
+            ops(&diff[..2]),
+            vec![(DiffOp::Equal, "first"), (DiffOp::Delete, "old0")]
+        );
+        assert!(diff[1..=MAX_EDITS]
+            .iter()
+            .all(|line| line.op == DiffOp::Delete));
+        assert!(diff[MAX_EDITS + 1..=2 * MAX_EDITS]
+            .iter()
+            .all(|line| line.op == DiffOp::Insert));
+        assert_eq!(diff.last().map(|line| line.op), Some(DiffOp::Equal));
+        assert_eq!(sides(&diff), (old.lines().collect(), new.lines().collect()));
+    }
+}
@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use validator::{ValidationError, ValidationErrors};
+
+/// The media type of RFC 7807 problem details, which clients opt into with `Accept`.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Machine-readable error codes. Every backend maps its own error type onto one
+/// of these, which in turn decides the HTTP status that is returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    AlreadyExists,
+    ValidationFailed,
+    TooManyRequests,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::AlreadyExists => "already_exists",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::TooManyRequests => "too_many_requests",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::NotFound => "Not Found",
+            ErrorCode::AlreadyExists => "Already Exists",
+            ErrorCode::ValidationFailed => "Validation Failed",
+            ErrorCode::TooManyRequests => "Too Many Requests",
+            ErrorCode::Internal => "Internal Server Error",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::AlreadyExists | ErrorCode::ValidationFailed => 422,
+            ErrorCode::TooManyRequests => 429,
+            ErrorCode::Internal => 500,
+        }
+    }
+}
+
+/// An error as clients see it, whichever backend answered.
+///
+/// By default it is sent in the RealWorld shape, `{"errors": {"body": ["<detail>"]}}`, or
+/// `{"errors": {"<field>": ["<message>", ...]}}` when fields failed. Clients that accept
+/// [`PROBLEM_JSON`] get RFC 7807 problem details instead, with the `code` and any failed
+/// fields as extension members.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub detail: String,
+    /// Messages by field, for validation failures and taken values.
+    pub fields: BTreeMap<String, Vec<String>>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn validation(errors: &ValidationErrors) -> Self {
+        let fields = errors
+            .field_errors()
+            .into_iter()
+            .map(|(field, errors)| {
+                let messages = errors
+                    .iter()
+                    .map(|error| match error.message {
+                        Some(ref message) => message.to_string(),
+                        None => error.code.to_string(),
+                    })
+                    .collect();
+                (field.to_string(), messages)
+            })
+            .collect();
+
+        Self {
+            fields,
+            ..Self::new(
+                ErrorCode::ValidationFailed,
+                "The request has invalid fields",
+            )
+        }
+    }
+
+    /// A unique `field` someone else has already, from the name a `RepoError::Conflict`
+    /// carries.
+    pub fn conflict(field: &str) -> Self {
+        Self {
+            fields: BTreeMap::from([(field.to_string(), vec!["has already been taken".into()])]),
+            ..Self::new(
+                ErrorCode::AlreadyExists,
+                format!("{field} has already been taken"),
+            )
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        self.code.status()
+    }
+
+    /// The body in the format `accept` asks for, with its content type.
+    pub fn body(&self, accept: Option<&str>) -> (&'static str, Value) {
+        match accepts_problem(accept) {
+            true => (PROBLEM_JSON, self.problem_body()),
+            false => ("application/json", self.legacy_body()),
+        }
+    }
+
+    /// The RealWorld shape.
+    pub fn legacy_body(&self) -> Value {
+        match self.fields.is_empty() {
+            true => json!({ "errors": { "body": [self.detail] } }),
+            false => json!({ "errors": self.fields }),
+        }
+    }
+
+    /// RFC 7807 problem details.
+    pub fn problem_body(&self) -> Value {
+        let mut body = json!({
+            "type": format!("urn:realworld:error:{}", self.code.as_str()),
+            "title": self.code.title(),
+            "status": self.status(),
+            "detail": self.detail,
+            "code": self.code,
+        });
+        if !self.fields.is_empty() {
+            body["errors"] = json!(self.fields);
+        }
+        body
+    }
+}
+
+/// Why a request was refused before its handler ran, however the framework reading it calls
+/// the failure. Both backends answer each kind with the same error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformed {
+    /// The body isn't declared as `application/json`.
+    ContentType,
+    /// The body isn't JSON at all.
+    Syntax,
+    /// The body is JSON, but not the shape the endpoint expects.
+    Data,
+    /// The body is over the size limit.
+    TooLarge,
+    /// A path segment doesn't parse, such as an id that isn't a number, so nothing has it.
+    Path,
+    /// A query parameter doesn't parse.
+    Query,
+}
+
+impl Malformed {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Malformed::ContentType => "The body must be sent as application/json",
+            Malformed::Syntax => "The body is not valid JSON",
+            Malformed::Data => "The body is missing fields or has fields of the wrong type",
+            Malformed::TooLarge => "The body is too large",
+            Malformed::Path => "Record not found",
+            Malformed::Query => "The query string has invalid parameters",
+        }
+    }
+}
+
+/// Whether an `Accept` header lists [`PROBLEM_JSON`]. Quality values are not weighed.
+pub fn accepts_problem(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+        accept.split(',').any(|range| {
+            let media_type = range.split(';').next().unwrap_or_default();
+            media_type.trim().eq_ignore_ascii_case(PROBLEM_JSON)
+        })
+    })
+}
+
+/// A single failed field, for checks that can't be expressed as `#[validate]` rules.
+pub fn field_error(field: &'static str, message: &'static str) -> ValidationErrors {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+
+    let mut errors = ValidationErrors::new();
+    errors.add(field, error);
+    errors
+}
+
+/// Accepts `value` if it parses as a `T`, for `#[validate(custom)]` rules.
+///
+/// Fields naming one of a fixed set of values, such as scopes or roles, are deserialized as plain
+/// strings and checked here instead, so unknown values are reported like any other invalid field.
+/// Parsing them is only infallible once validated.
+pub fn validate_known<T: FromStr>(value: &str, code: &'static str) -> Result<(), ValidationError> {
+    match value.parse::<T>() {
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let mut error = ValidationError::new(code);
+            error.message = Some(format!("unknown {code}").into());
+            Err(error)
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Keys access tokens are signed and verified with.
+//!
+//! A [`KeyRing`] holds any number of verification keys, one of which also signs new tokens.
+//! Tokens name the key that signed them in their `kid` header, so keys can be rotated without
+//! logging anyone out: add the new key, make it the signing key, and drop the old one once the
+//! tokens it signed have expired.
+//!
+//! The public halves of RSA keys are published as a [`JwkSet`] so other services can verify
+//! tokens themselves. HMAC secrets never leave the server.
+
+use std::{collections::HashMap, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    errors::{Error as JwtError, ErrorKind as JwtErrorKind},
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use simple_asn1::ASN1Block;
+use thiserror::Error;
+
+/// `kid` of the key a server builds from its single-key settings.
+pub const DEFAULT_KID: &str = "default";
+
+/// One key of the ring. HMAC keys need a `secret`. RSA keys need a PEM encoded `public_key`
+/// (or certificate), plus the `private_key` if they sign.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub private_key: Option<String>,
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+impl KeyConfig {
+    pub fn hmac(kid: &str, secret: &str) -> Self {
+        Self {
+            kid: kid.to_string(),
+            algorithm: Algorithm::HS256,
+            secret: Some(secret.to_string()),
+            private_key: None,
+            public_key: None,
+        }
+    }
+
+    pub fn rsa(kid: &str, private_key: &str, public_key: &str) -> Self {
+        Self {
+            kid: kid.to_string(),
+            algorithm: Algorithm::RS384,
+            secret: None,
+            private_key: Some(private_key.to_string()),
+            public_key: Some(public_key.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("the signing key {0:?} is not in the key ring")]
+    UnknownSigningKey(String),
+
+    #[error("key {0:?} is configured twice")]
+    DuplicateKey(String),
+
+    #[error("key {kid:?} is missing its {field}")]
+    Missing { kid: String, field: &'static str },
+
+    #[error("key {kid:?} is invalid: {source}")]
+    Invalid {
+        kid: String,
+        #[source]
+        source: JwtError,
+    },
+
+    #[error("key {kid:?} uses {algorithm:?}, only HMAC and RSA keys are supported")]
+    Unsupported { kid: String, algorithm: Algorithm },
+}
+
+// ================================== JSON Web Key Set ================================== //
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+/// The public half of an RSA key, see RFC 7517.
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    pub kty: &'static str,
+    pub kid: String,
+    #[serde(rename = "use")]
+    pub key_use: &'static str,
+    pub alg: Algorithm,
+    /// Modulus, big endian and base64url encoded.
+    pub n: String,
+    /// Public exponent, big endian and base64url encoded.
+    pub e: String,
+}
+
+// ================================== Key Ring ================================== //
+
+/// Cheap to clone, every clone shares the same keys.
+#[derive(Clone)]
+pub struct KeyRing {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    signing_kid: String,
+    signing_algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    verifying: HashMap<String, (Algorithm, DecodingKey)>,
+    jwks: JwkSet,
+}
+
+impl KeyRing {
+    /// New tokens are signed with the key named `signing_kid`, which must be one of `keys`.
+    pub fn new(signing_kid: &str, keys: &[KeyConfig]) -> Result<Self, KeyError> {
+        let signing = keys
+            .iter()
+            .find(|key| key.kid == signing_kid)
+            .ok_or_else(|| KeyError::UnknownSigningKey(signing_kid.to_string()))?;
+
+        let mut verifying = HashMap::new();
+        let mut jwks = JwkSet::default();
+        for key in keys {
+            let decoding_key = match family(key)? {
+                Family::Hmac => DecodingKey::from_secret(secret(key)?.as_bytes()),
+                Family::Rsa => {
+                    let public_key = material(key, &key.public_key, "public_key")?;
+                    jwks.keys.push(rsa_jwk(key, public_key)?);
+                    DecodingKey::from_rsa_pem(public_key.as_bytes()).map_err(invalid(key))?
+                }
+            };
+
+            if verifying
+                .insert(key.kid.clone(), (key.algorithm, decoding_key))
+                .is_some()
+            {
+                return Err(KeyError::DuplicateKey(key.kid.clone()));
+            }
+        }
+
+        let encoding_key = match family(signing)? {
+            Family::Hmac => EncodingKey::from_secret(secret(signing)?.as_bytes()),
+            Family::Rsa => {
+                let private_key = material(signing, &signing.private_key, "private_key")?;
+                EncodingKey::from_rsa_pem(private_key.as_bytes()).map_err(invalid(signing))?
+            }
+        };
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                signing_kid: signing.kid.clone(),
+                signing_algorithm: signing.algorithm,
+                encoding_key,
+                verifying,
+                jwks,
+            }),
+        })
+    }
+
+    pub fn signing_kid(&self) -> &str {
+        &self.inner.signing_kid
+    }
+
+    /// Signs `claims` with the signing key, naming it in the `kid` header.
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+        let mut header = Header::new(self.inner.signing_algorithm);
+        header.kid = Some(self.inner.signing_kid.clone());
+        jsonwebtoken::encode(&header, claims, &self.inner.encoding_key)
+    }
+
+    /// Every key only accepts the algorithm it was configured with, whatever the token claims.
+    /// Tokens without a `kid` predate the key ring and are checked against the signing key.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, JwtError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(&self.inner.signing_kid);
+        let (algorithm, key) = self
+            .inner
+            .verifying
+            .get(kid)
+            .ok_or_else(|| JwtError::from(JwtErrorKind::InvalidToken))?;
+
+        Ok(jsonwebtoken::decode::<T>(token, key, &Validation::new(*algorithm))?.claims)
+    }
+
+    /// Public keys of the ring, for `/.well-known/jwks.json`.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.inner.jwks
+    }
+}
+
+// ================== HELPERS ================== //
+
+enum Family {
+    Hmac,
+    Rsa,
+}
+
+fn family(key: &KeyConfig) -> Result<Family, KeyError> {
+    match key.algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => Ok(Family::Hmac),
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => Ok(Family::Rsa),
+        algorithm => Err(KeyError::Unsupported {
+            kid: key.kid.clone(),
+            algorithm,
+        }),
+    }
+}
+
+fn material<'a>(
+    key: &KeyConfig,
+    value: &'a Option<String>,
+    field: &'static str,
+) -> Result<&'a str, KeyError> {
+    match value.as_deref() {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(KeyError::Missing {
+            kid: key.kid.clone(),
+            field,
+        }),
+    }
+}
+
+fn secret(key: &KeyConfig) -> Result<&str, KeyError> {
+    material(key, &key.secret, "secret")
+}
+
+fn invalid(key: &KeyConfig) -> impl FnOnce(JwtError) -> KeyError + '_ {
+    |source| KeyError::Invalid {
+        kid: key.kid.clone(),
+        source,
+    }
+}
+
+fn rsa_jwk(key: &KeyConfig, public_key: &str) -> Result<Jwk, KeyError> {
+    let Some((n, e)) = rsa_components(public_key) else {
+        return Err(invalid(key)(JwtErrorKind::InvalidKeyFormat.into()));
+    };
+
+    Ok(Jwk {
+        kty: "RSA",
+        kid: key.kid.clone(),
+        key_use: "sig",
+        alg: key.algorithm,
+        n: URL_SAFE_NO_PAD.encode(n),
+        e: URL_SAFE_NO_PAD.encode(e),
+    })
+}
+
+/// The modulus and public exponent of a PEM encoded RSA public key, or of the key a
+/// certificate is for.
+fn rsa_components(pem: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let pem = pem::parse(pem).ok()?;
+    let der = match pem.tag.as_str() {
+        "RSA PUBLIC KEY" => pem.contents,
+        // A SubjectPublicKeyInfo, on its own or as part of a certificate, wraps the key above
+        "PUBLIC KEY" | "CERTIFICATE" => {
+            first_bit_string(&simple_asn1::from_der(&pem.contents).ok()?)?.to_vec()
+        }
+        _ => return None,
+    };
+
+    match simple_asn1::from_der(&der).ok()?.as_slice() {
+        [ASN1Block::Sequence(_, fields)] => match fields.as_slice() {
+            [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => {
+                Some((n.to_bytes_be().1, e.to_bytes_be().1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn first_bit_string(blocks: &[ASN1Block]) -> Option<&[u8]> {
+    blocks.iter().find_map(|block| match block {
+        ASN1Block::Sequence(_, blocks) => first_bit_string(blocks),
+        ASN1Block::BitString(_, _, bits) => Some(bits.as_slice()),
+        _ => None,
+    })
+}
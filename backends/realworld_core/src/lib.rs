@@ -0,0 +1,34 @@
+//! Wire types, validation rules and error codes shared by every RealWorld backend.
+//!
+//! Anything that ends up in a request or response body lives here, so the JSON a client
+//! sees is the same regardless of which server produced it.
+
+pub mod access_token;
+pub mod account;
+pub mod admin;
+pub mod article;
+pub mod comment;
+pub mod config;
+pub mod credentials;
+pub mod datetime;
+pub mod diff;
+pub mod error;
+pub mod keys;
+pub mod lockout;
+pub mod mail;
+pub mod metrics;
+pub mod oidc;
+pub mod policy;
+pub mod privacy;
+pub mod profile;
+pub mod repo;
+pub mod revision;
+pub mod search;
+pub mod session;
+pub mod tag;
+pub mod telemetry;
+pub mod token;
+pub mod totp;
+pub mod user;
+
+pub use datetime::CustomDateTime;
@@ -0,0 +1,166 @@
+//! Slows down guessing passwords.
+//!
+//! Failed logins are counted per account and per client address. Once either count reaches its
+//! threshold, logins it covers are refused for a while, twice as long with every further failure
+//! up to a cap. Counts are forgotten once there was no failure or lockout for a while.
+//!
+//! A successful login only resets the account, so knowing one password doesn't let an address
+//! go on guessing others.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::repo::{LoginAttemptRepo, RepoError, RepoResult};
+
+/// Carries the client address when the server sits behind a proxy.
+pub const FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockoutConfig {
+    /// Failed logins for one account before it is locked.
+    pub max_failures: i32,
+    /// Failed logins from one address, for any account, before it is locked.
+    pub max_ip_failures: i32,
+    /// Seconds the first lockout lasts.
+    pub base_delay: i64,
+    /// Seconds no lockout lasts longer than.
+    pub max_delay: i64,
+    /// Seconds without failures or lockout after which failures are forgotten.
+    pub window: i64,
+    /// Takes the client address from `X-Forwarded-For`. Only safe behind a proxy that sets it,
+    /// otherwise clients pick their own address.
+    pub trust_proxy: bool,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            max_ip_failures: 50,
+            base_delay: 30,
+            max_delay: 60 * 60,
+            window: 15 * 60,
+            trust_proxy: false,
+        }
+    }
+}
+
+impl LockoutConfig {
+    /// The address a request came from, the last one a trusted proxy appended if there is one.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let forwarded = forwarded_for
+            .filter(|_| self.trust_proxy)
+            .and_then(|header| header.rsplit(',').next())
+            .and_then(|address| address.trim().parse().ok());
+
+        forwarded.or(peer)
+    }
+
+    /// How long `failures` lock a counter with `threshold`, if they do.
+    pub fn delay(&self, failures: i32, threshold: i32) -> Option<Duration> {
+        if failures < threshold {
+            return None;
+        }
+
+        let doublings = (failures - threshold).min(32) as u32;
+        let seconds = self.base_delay.saturating_mul(1 << doublings);
+        Some(Duration::seconds(seconds.min(self.max_delay)))
+    }
+}
+
+/// The counters a login attempt is subject to.
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    account: String,
+    address: Option<String>,
+}
+
+impl Attempt {
+    pub fn new(email: &str, ip: Option<IpAddr>) -> Self {
+        Self {
+            account: account_key(email),
+            address: ip.map(|ip| format!("ip:{ip}")),
+        }
+    }
+
+    fn counters<'a>(&'a self, config: &LockoutConfig) -> Vec<(&'a str, i32)> {
+        let mut counters = vec![(self.account.as_str(), config.max_failures)];
+        if let Some(address) = &self.address {
+            counters.push((address.as_str(), config.max_ip_failures));
+        }
+        counters
+    }
+}
+
+fn account_key(email: &str) -> String {
+    format!("account:{}", email.trim().to_lowercase())
+}
+
+/// Until when `attempt` has to wait, if any of its counters is locked.
+pub async fn locked_until(
+    repo: &dyn LoginAttemptRepo,
+    config: &LockoutConfig,
+    attempt: &Attempt,
+) -> RepoResult<Option<DateTime<Utc>>> {
+    let now = Utc::now();
+    let mut until = None;
+
+    for (key, _) in attempt.counters(config) {
+        match repo.find(key).await {
+            Ok(attempts) => {
+                until = until.max(attempts.locked_until.filter(|locked| *locked > now));
+            }
+            Err(RepoError::NotFound) => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(until)
+}
+
+/// Counts a failed `attempt`, locking the counters that reach their threshold. Answers until
+/// when it is locked now, if it is.
+pub async fn fail(
+    repo: &dyn LoginAttemptRepo,
+    config: &LockoutConfig,
+    attempt: &Attempt,
+) -> RepoResult<Option<DateTime<Utc>>> {
+    let now = Utc::now();
+    let stale_before = now - Duration::seconds(config.window);
+    let mut until = None;
+
+    for (key, threshold) in attempt.counters(config) {
+        let failures = repo.fail(key, stale_before).await?;
+
+        if let Some(delay) = config.delay(failures, threshold) {
+            let locked_until = now + delay;
+            repo.lock(key, failures, locked_until).await?;
+            tracing::warn!(%key, %locked_until, failures, "locked after failed logins");
+            until = until.max(Some(locked_until));
+        }
+    }
+
+    Ok(until)
+}
+
+/// Forgets the failures of the account `attempt` logged into.
+pub async fn succeed(repo: &dyn LoginAttemptRepo, attempt: &Attempt) -> RepoResult<()> {
+    repo.clear(&attempt.account).await
+}
+
+/// Lifts a lockout of the account with `email` and forgets its failures.
+pub async fn unlock(repo: &dyn LoginAttemptRepo, email: &str) -> RepoResult<()> {
+    let key = account_key(email);
+    repo.clear(&key).await?;
+    tracing::info!(%key, "unlocked");
+    Ok(())
+}
+
+/// Seconds until `until`, rounded up, for a `Retry-After` header.
+pub fn retry_after(until: DateTime<Utc>) -> i64 {
+    let millis = (until - Utc::now()).num_milliseconds().max(0);
+    (millis + 999) / 1000
+}
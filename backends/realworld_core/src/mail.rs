@@ -0,0 +1,276 @@
+//! Outgoing mail.
+//!
+//! Handlers never talk to a mail server. They queue mail in an outbox, in the same transaction
+//! as whatever the mail announces, and an [`Outbox`] dispatcher delivers it in the background
+//! through a [`Mailer`]. Failed deliveries are retried with exponential backoff, up to
+//! [`MAX_ATTEMPTS`] times.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{
+    message::Mailbox, transport::smtp, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+use crate::repo::OutboxRepo;
+
+/// Deliveries of a message are given up after this many failures.
+pub const MAX_ATTEMPTS: i32 = 5;
+
+/// Messages claimed per round trip to the outbox.
+const BATCH_SIZE: i64 = 20;
+
+/// How long a claimed message is hidden from other dispatchers while it is being delivered.
+const LEASE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    /// Plain text.
+    pub body: String,
+}
+
+/// A message waiting in the outbox.
+#[derive(Debug, Clone)]
+pub struct QueuedMail {
+    pub id: i64,
+    pub mail: Mail,
+    /// Including the one about to be made.
+    pub attempts: i32,
+}
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+
+    #[error("invalid message: {0}")]
+    Message(#[from] lettre::error::Error),
+
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] smtp::Error),
+
+    #[error("failed to write mail: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// ================================== Configuration ================================== //
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Writes every message to the log, e.g. for development.
+    #[default]
+    Log,
+    /// Writes every message to a file in `dir`.
+    File,
+    /// Sends every message to `smtp_url`.
+    Smtp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    pub transport: Transport,
+    pub from: String,
+    pub dir: PathBuf,
+    /// `smtp://`, `smtps://` or `smtp://...?tls=required` URL, with credentials if needed.
+    pub smtp_url: String,
+    /// The frontend links in mail point to.
+    pub app_url: String,
+    /// Seconds between looks at the outbox, in case another server queued mail.
+    pub poll_interval: u64,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::default(),
+            from: "RealWorld <noreply@realworld.local>".to_string(),
+            dir: PathBuf::from("mail"),
+            smtp_url: "smtp://localhost:25".to_string(),
+            app_url: "http://localhost:3000".to_string(),
+            poll_interval: 10,
+        }
+    }
+}
+
+impl MailConfig {
+    pub fn mailer(&self) -> Result<Arc<dyn Mailer>, MailError> {
+        let from: Mailbox = self.from.parse()?;
+
+        Ok(match self.transport {
+            Transport::Log => Arc::new(LogMailer),
+            Transport::File => Arc::new(FileMailer::new(from, &self.dir)),
+            Transport::Smtp => Arc::new(SmtpMailer::new(from, &self.smtp_url)?),
+        })
+    }
+}
+
+// ================================== Transports ================================== //
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError>;
+}
+
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        tracing::info!(to = %mail.to, subject = %mail.subject, "mail:\n{}", mail.body);
+        Ok(())
+    }
+}
+
+/// One file per message, named so that listing the directory sorts them oldest first.
+pub struct FileMailer {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(from: Mailbox, dir: &Path) -> Self {
+        Self {
+            from,
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        let name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.9f"),
+            uuid::Uuid::new_v4().simple()
+        );
+        let contents = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            self.from, mail.to, mail.subject, mail.body
+        );
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.dir.join(name), contents).await?;
+        Ok(())
+    }
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(from: Mailbox, url: &str) -> Result<Self, MailError> {
+        Ok(Self {
+            from,
+            transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &Mail) -> Result<(), MailError> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse()?)
+            .subject(&mail.subject)
+            .body(mail.body.clone())?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+// ================================== Dispatcher ================================== //
+
+/// What handlers need to send mail. Cheap to clone, every clone wakes the same dispatcher.
+#[derive(Clone)]
+pub struct Outbox {
+    app_url: Arc<str>,
+    wake: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Starts delivering the mail queued in `repo`. Must be called within a tokio runtime.
+    pub fn spawn(config: &MailConfig, repo: Arc<dyn OutboxRepo>, mailer: Arc<dyn Mailer>) -> Self {
+        let wake = Arc::new(Notify::new());
+        let poll_interval = Duration::from_secs(config.poll_interval.max(1));
+
+        tokio::spawn(dispatch(repo, mailer, wake.clone(), poll_interval));
+
+        Self {
+            app_url: config.app_url.trim_end_matches('/').into(),
+            wake,
+        }
+    }
+
+    /// Delivers newly queued mail right away instead of at the next poll.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// A link to `path` of the frontend, carrying `token`.
+    pub fn link(&self, path: &str, token: &str) -> String {
+        format!("{}{path}?token={token}", self.app_url)
+    }
+}
+
+async fn dispatch(
+    repo: Arc<dyn OutboxRepo>,
+    mailer: Arc<dyn Mailer>,
+    wake: Arc<Notify>,
+    poll_interval: Duration,
+) {
+    loop {
+        loop {
+            match deliver(repo.as_ref(), mailer.as_ref()).await {
+                Ok(0) => break,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to read the outbox");
+                    break;
+                }
+            }
+        }
+
+        tokio::select! {
+            _ = wake.notified() => {}
+            _ = tokio::time::sleep(poll_interval) => {}
+        }
+    }
+}
+
+/// Delivers one batch of due mail, returning how many messages were claimed.
+async fn deliver(repo: &dyn OutboxRepo, mailer: &dyn Mailer) -> crate::repo::RepoResult<usize> {
+    let lease = chrono::Duration::from_std(LEASE).unwrap();
+    let batch = repo.claim(BATCH_SIZE, Utc::now() + lease).await?;
+
+    for queued in &batch {
+        match mailer.send(&queued.mail).await {
+            Ok(()) => repo.delivered(queued.id).await?,
+            Err(e) => {
+                tracing::warn!(mail = %queued.id, error = %e, "failed to deliver mail");
+
+                let backoff = chrono::Duration::seconds(30 << queued.attempts.clamp(0, 10));
+                repo.failed(queued.id, &e.to_string(), Utc::now() + backoff)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(batch.len())
+}
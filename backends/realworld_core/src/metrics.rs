@@ -0,0 +1,244 @@
+//! Prometheus metrics, served in the text format at `GET /metrics`.
+//!
+//! Scrapers authenticate with `Authorization: Bearer <metrics.token>`. Without a token, the
+//! endpoint is only served on `metrics.bind`, an address of its own that can be kept private,
+//! and the API answers `404`.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::token::hash_token;
+
+pub const METRICS_PATH: &str = "/metrics";
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Bearer token scrapers must send, if any.
+    pub token: String,
+    /// Serves `/metrics` on this address too, not only next to the API.
+    pub bind: Option<SocketAddr>,
+}
+
+/// Things users did that are worth counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Registration,
+    Login,
+    ArticleCreated,
+    Favorite,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Registration => "registration",
+            Event::Login => "login",
+            Event::ArticleCreated => "article_created",
+            Event::Favorite => "favorite",
+        }
+    }
+}
+
+/// A snapshot of a connection pool, taken on every scrape.
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    pub connections: u32,
+    pub idle: u32,
+    pub max: u32,
+    /// Only known for pools that keep count.
+    pub waits: Option<PoolWaits>,
+}
+
+/// Totals since the pool was created.
+#[derive(Debug, Clone, Default)]
+pub struct PoolWaits {
+    pub count: u64,
+    pub timeouts: u64,
+    pub time: Duration,
+}
+
+type PoolSource = Arc<dyn Fn() -> PoolStats + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Metrics {
+    token: Option<String>,
+    inner: Arc<Inner>,
+    pool: Option<PoolSource>,
+}
+
+struct Inner {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    events: IntCounterVec,
+    pool: PoolMetrics,
+}
+
+struct PoolMetrics {
+    connections: IntGauge,
+    idle: IntGauge,
+    max: IntGauge,
+    waits: IntCounter,
+    timeouts: IntCounter,
+    wait_seconds: Counter,
+    /// Scrapes catch the counters up with the pool's totals one at a time.
+    sync: Mutex<()>,
+}
+
+impl Metrics {
+    pub fn new(config: &MetricsConfig) -> Self {
+        let registry = Registry::new();
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests answered"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time taken to answer"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let events = IntCounterVec::new(
+            Opts::new(
+                "realworld_events_total",
+                "Registrations, logins, articles and favorites",
+            ),
+            &["event"],
+        )
+        .unwrap();
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(latency.clone()),
+            Box::new(events.clone()),
+        ] {
+            registry.register(collector).unwrap();
+        }
+
+        let pool = PoolMetrics {
+            connections: IntGauge::new("db_pool_connections", "Open connections").unwrap(),
+            idle: IntGauge::new("db_pool_idle_connections", "Connections not in use").unwrap(),
+            max: IntGauge::new("db_pool_max_connections", "Most connections opened").unwrap(),
+            waits: IntCounter::new("db_pool_waits_total", "Checkouts that had to wait").unwrap(),
+            timeouts: IntCounter::new("db_pool_timeouts_total", "Checkouts that timed out")
+                .unwrap(),
+            wait_seconds: Counter::new(
+                "db_pool_wait_seconds_total",
+                "Time spent waiting for a connection",
+            )
+            .unwrap(),
+            sync: Mutex::new(()),
+        };
+
+        Self {
+            token: Some(config.token.clone()).filter(|token| !token.is_empty()),
+            inner: Arc::new(Inner {
+                registry,
+                requests,
+                latency,
+                events,
+                pool,
+            }),
+            pool: None,
+        }
+    }
+
+    /// Reports the database pool on every scrape, through `stats`.
+    pub fn with_pool(mut self, stats: impl Fn() -> PoolStats + Send + Sync + 'static) -> Self {
+        let pool = &self.inner.pool;
+        let mut collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(pool.connections.clone()),
+            Box::new(pool.idle.clone()),
+            Box::new(pool.max.clone()),
+        ];
+        if stats().waits.is_some() {
+            collectors.push(Box::new(pool.waits.clone()));
+            collectors.push(Box::new(pool.timeouts.clone()));
+            collectors.push(Box::new(pool.wait_seconds.clone()));
+        }
+        for collector in collectors {
+            // Registered already by an earlier pool
+            let _ = self.inner.registry.register(collector);
+        }
+
+        self.pool = Some(Arc::new(stats));
+        self
+    }
+
+    /// Whether the API serves `/metrics`, which it only does behind a token.
+    pub fn exposed(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Checks the `Authorization` header of a scrape against the token, if there is one.
+    pub fn authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let sent = authorization
+            .and_then(|header| header.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+            .map(|(_, sent)| sent.trim());
+
+        // Compared as hashes, which take as long to match whatever was sent
+        sent.is_some_and(|sent| hash_token(sent) == hash_token(token))
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.inner.requests.with_label_values(&labels).inc();
+        self.inner
+            .latency
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record(&self, event: Event) {
+        self.inner.events.with_label_values(&[event.as_str()]).inc();
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self) -> String {
+        if let Some(stats) = &self.pool {
+            self.inner.pool.update(stats());
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.inner.registry.gather(), &mut buffer)
+            .expect("metrics are valid");
+        String::from_utf8(buffer).expect("metrics are UTF-8")
+    }
+}
+
+impl PoolMetrics {
+    fn update(&self, stats: PoolStats) {
+        self.connections.set(stats.connections.into());
+        self.idle.set(stats.idle.into());
+        self.max.set(stats.max.into());
+
+        let Some(waits) = stats.waits else {
+            return;
+        };
+        let _sync = self.sync.lock().unwrap_or_else(|e| e.into_inner());
+        self.waits
+            .inc_by(waits.count.saturating_sub(self.waits.get()));
+        self.timeouts
+            .inc_by(waits.timeouts.saturating_sub(self.timeouts.get()));
+        let seconds = waits.time.as_secs_f64() - self.wait_seconds.get();
+        if seconds > 0.0 {
+            self.wait_seconds.inc_by(seconds);
+        }
+    }
+}
@@ -0,0 +1,585 @@
+//! Logging in with OpenID Connect providers, as a relying party.
+//!
+//! Logins use the authorization code flow with PKCE. Starting one stores a random state, nonce
+//! and code verifier, and sends the user to the provider. The provider sends them back to a
+//! frontend page, which posts the code and state to the callback endpoint, and the code is
+//! swapped for an ID token there.
+//!
+//! Identities are linked to users by the provider's subject. The first login links to the
+//! user with the same email, if the provider verified it, and provisions a new account
+//! otherwise.
+
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::{Mutex, OnceCell, RwLock};
+use validator::{Validate, ValidationErrors};
+
+use crate::{
+    repo::{Identifier, NewUser, OidcRepo, RepoError, Repos, User, UserChanges},
+    token::{hash_token, new_token},
+    user::{UpdateUserData, UsernameConfig},
+};
+
+/// How long users have to log in at the provider.
+const LOGIN_TTL: i64 = 10 * 60;
+
+/// Longest username derived from an identity, leaving room for a suffix.
+const MAX_USERNAME: usize = 48;
+
+/// Usernames tried for a new account before giving up, the first unsuffixed.
+const USERNAME_ATTEMPTS: usize = 5;
+
+/// Least time between two fetches of a provider's keys, so tokens naming unknown keys can't
+/// make every login fetch them.
+const KEYS_REFETCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("Unknown provider {0}")]
+    UnknownProvider(String),
+
+    /// The login can't be completed, e.g. the state is unknown or the ID token is invalid.
+    #[error("Login rejected: {0}")]
+    Rejected(String),
+
+    #[error("The provider didn't share an email address")]
+    MissingEmail,
+
+    /// The username or email the provider shared fails the rules of registering.
+    #[error("Invalid identity: {0}")]
+    Invalid(ValidationErrors),
+
+    /// The email belongs to another user, and the provider didn't verify it.
+    #[error("Email has already been taken")]
+    EmailTaken,
+
+    #[error("Provider error: {0}")]
+    Provider(String),
+
+    #[error(transparent)]
+    Repo(#[from] RepoError),
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(error: reqwest::Error) -> Self {
+        OidcError::Provider(error.to_string())
+    }
+}
+
+// ================================== Configuration ================================== //
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    pub providers: Vec<ProviderConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// Names the provider in URLs, e.g. `google`.
+    pub name: String,
+    /// Serves its discovery document at `<issuer>/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    /// Sent as `client_secret_post`. Empty for public clients.
+    #[serde(default)]
+    pub client_secret: String,
+    /// The frontend page the provider sends users back to.
+    pub redirect_url: String,
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_scopes() -> Vec<String> {
+    ["openid", "email", "profile"].map(String::from).to_vec()
+}
+
+// ================================== Records ================================== //
+
+/// A login waiting for the provider to send the user back.
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+}
+
+/// What a provider vouched for in an ID token.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+// ================================== Client ================================== //
+
+/// Cheap to clone, every clone shares the discovered provider metadata and keys.
+#[derive(Clone)]
+pub struct Oidc {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    http: reqwest::Client,
+    providers: HashMap<String, Provider>,
+}
+
+struct Provider {
+    config: ProviderConfig,
+    metadata: OnceCell<Metadata>,
+    keys: RwLock<Vec<ProviderKey>>,
+    /// When `keys` were last fetched, locked while fetching them.
+    keys_fetched_at: Mutex<Option<Instant>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderKey {
+    kid: Option<String>,
+    kty: String,
+    alg: Option<Algorithm>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderKeys {
+    keys: Vec<ProviderKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    preferred_username: Option<String>,
+}
+
+impl Oidc {
+    /// Providers are only contacted once a login needs them.
+    pub fn new(config: &OidcConfig) -> Self {
+        let providers = config
+            .providers
+            .iter()
+            .map(|config| {
+                let provider = Provider {
+                    config: config.clone(),
+                    metadata: OnceCell::new(),
+                    keys: RwLock::new(Vec::new()),
+                    keys_fetched_at: Mutex::new(None),
+                };
+                (config.name.clone(), provider)
+            })
+            .collect();
+
+        Self {
+            inner: Arc::new(Inner {
+                http: reqwest::Client::new(),
+                providers,
+            }),
+        }
+    }
+
+    pub fn provider_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.inner.providers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn provider(&self, name: &str) -> Result<&Provider, OidcError> {
+        self.inner
+            .providers
+            .get(name)
+            .ok_or_else(|| OidcError::UnknownProvider(name.to_string()))
+    }
+
+    async fn metadata<'a>(&self, provider: &'a Provider) -> Result<&'a Metadata, OidcError> {
+        provider
+            .metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    provider.config.issuer.trim_end_matches('/')
+                );
+                let metadata: Metadata = self
+                    .inner
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                if metadata.issuer != provider.config.issuer {
+                    return Err(OidcError::Provider(format!(
+                        "discovered issuer {} instead of {}",
+                        metadata.issuer, provider.config.issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// The URL to send the user to, with the state that identifies `login` when they return.
+    pub async fn authorize(&self, name: &str) -> Result<(String, String, PendingLogin), OidcError> {
+        let provider = self.provider(name)?;
+        let metadata = self.metadata(provider).await?;
+
+        let state = new_token();
+        let login = PendingLogin {
+            provider: name.to_string(),
+            code_verifier: new_token(),
+            nonce: new_token(),
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(login.code_verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &provider.config.client_id),
+                ("redirect_uri", &provider.config.redirect_url),
+                ("scope", &provider.config.scopes.join(" ")),
+                ("state", &state),
+                ("nonce", &login.nonce),
+                ("code_challenge", &challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::Provider(e.to_string()))?;
+
+        Ok((url.into(), state, login))
+    }
+
+    /// Swaps `code` for an ID token and checks it was issued for `login`.
+    pub async fn identify(&self, login: &PendingLogin, code: &str) -> Result<Identity, OidcError> {
+        let provider = self.provider(&login.provider)?;
+        let metadata = self.metadata(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &provider.config.redirect_url),
+            ("client_id", &provider.config.client_id),
+            ("code_verifier", &login.code_verifier),
+        ];
+        if !provider.config.client_secret.is_empty() {
+            form.push(("client_secret", &provider.config.client_secret));
+        }
+
+        let response = self
+            .inner
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        let status = response.status();
+        let tokens: TokenResponse = response.json().await?;
+
+        let id_token = match tokens.id_token {
+            Some(id_token) if status.is_success() => id_token,
+            _ => {
+                let error = tokens.error.unwrap_or_else(|| status.to_string());
+                return Err(OidcError::Rejected(format!(
+                    "code exchange failed: {error}"
+                )));
+            }
+        };
+
+        let claims = self.verify(provider, metadata, &id_token).await?;
+        if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+            return Err(OidcError::Rejected("nonce mismatch".to_string()));
+        }
+
+        Ok(Identity {
+            provider: login.provider.clone(),
+            subject: claims.sub,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            preferred_username: claims.preferred_username,
+        })
+    }
+
+    async fn verify(
+        &self,
+        provider: &Provider,
+        metadata: &Metadata,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, OidcError> {
+        let rejected = |e: jsonwebtoken::errors::Error| OidcError::Rejected(e.to_string());
+        let header = jsonwebtoken::decode_header(id_token).map_err(rejected)?;
+        if !matches!(
+            header.alg,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+        ) {
+            return Err(OidcError::Rejected(format!(
+                "unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        // Providers rotate keys, so an unknown one is looked up again before giving up
+        let key = match self.key(provider, &header).await {
+            Some(key) => key,
+            None => {
+                self.refetch_keys(provider, metadata).await?;
+                self.key(provider, &header)
+                    .await
+                    .ok_or_else(|| OidcError::Rejected("unknown signing key".to_string()))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.config.client_id]);
+
+        Ok(jsonwebtoken::decode(id_token, &key, &validation)
+            .map_err(rejected)?
+            .claims)
+    }
+
+    /// Fetches the keys of `provider`, unless that was done less than
+    /// [`KEYS_REFETCH_INTERVAL`] ago. Concurrent calls wait for a single fetch.
+    async fn refetch_keys(
+        &self,
+        provider: &Provider,
+        metadata: &Metadata,
+    ) -> Result<(), OidcError> {
+        let mut fetched_at = provider.keys_fetched_at.lock().await;
+        if fetched_at.is_some_and(|at| at.elapsed() < KEYS_REFETCH_INTERVAL) {
+            return Ok(());
+        }
+        // Failed fetches count too, a provider that is down isn't asked on every login
+        *fetched_at = Some(Instant::now());
+
+        let fresh: ProviderKeys = self
+            .inner
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        *provider.keys.write().await = fresh.keys;
+        Ok(())
+    }
+
+    async fn key(&self, provider: &Provider, header: &jsonwebtoken::Header) -> Option<DecodingKey> {
+        let keys = provider.keys.read().await;
+        let key = keys.iter().find(|key| {
+            key.kty == "RSA"
+                && key.alg.is_none_or(|alg| alg == header.alg)
+                && (header.kid.is_none() || key.kid == header.kid)
+        })?;
+
+        DecodingKey::from_rsa_components(key.n.as_deref()?, key.e.as_deref()?).ok()
+    }
+}
+
+// ================================== Flows ================================== //
+
+/// Starts a login at `provider`, answering with the URL to send the user to.
+pub async fn begin<I: Identifier>(
+    oidc: &Oidc,
+    repo: &dyn OidcRepo<Id = I>,
+    provider: &str,
+) -> Result<AuthorizationResponse, OidcError> {
+    let (url, state, login) = oidc.authorize(provider).await?;
+    let expires_at = Utc::now() + Duration::seconds(LOGIN_TTL);
+    repo.start_login(&hash_token(&state), login, expires_at)
+        .await?;
+
+    Ok(AuthorizationResponse {
+        authorization: AuthorizationResponseInner { url },
+    })
+}
+
+/// Completes a login at `provider`, answering with the identity it vouched for.
+pub async fn complete<I: Identifier>(
+    oidc: &Oidc,
+    repo: &dyn OidcRepo<Id = I>,
+    provider: &str,
+    callback: &OidcCallback,
+) -> Result<Identity, OidcError> {
+    let login = match repo.finish_login(&hash_token(&callback.state)).await {
+        Ok(login) if login.provider == provider => login,
+        Ok(_) | Err(RepoError::NotFound) => {
+            return Err(OidcError::Rejected("unknown or expired state".to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    oidc.identify(&login, &callback.code).await
+}
+
+/// The user `identity` is linked to. On first login, links it to the user with the same email
+/// if the provider verified it. `None` if there is no such user.
+pub async fn linked_user<I: Identifier>(
+    repos: &Repos<I>,
+    identity: &Identity,
+) -> Result<Option<User<I>>, OidcError> {
+    match repos
+        .oidc
+        .find_identity(&identity.provider, &identity.subject)
+        .await
+    {
+        Ok(user) => return Ok(Some(repos.users.find(user).await?)),
+        Err(RepoError::NotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let email = match &identity.email {
+        Some(email) if identity.email_verified => email,
+        _ => return Ok(None),
+    };
+    let user = match repos.users.find_by_email(email).await {
+        Ok(user) => user,
+        Err(RepoError::NotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    repos
+        .oidc
+        .link_identity(user.id, &identity.provider, &identity.subject)
+        .await?;
+    Ok(Some(user))
+}
+
+/// Creates an account for `identity` and links it. The username is the one the provider
+/// suggests, or the local part of the email, without control characters and suffixed if it is
+/// taken. Reserved ones are replaced by a suffixed `user`. Both have to pass the rules users
+/// change theirs by, and a conflict is given up on after [`USERNAME_ATTEMPTS`] usernames.
+pub async fn provision<I: Identifier>(
+    repos: &Repos<I>,
+    usernames: &UsernameConfig,
+    identity: &Identity,
+    password_hash: String,
+) -> Result<User<I>, OidcError> {
+    let email = identity.email.clone().ok_or(OidcError::MissingEmail)?;
+    let base: String = identity
+        .preferred_username
+        .as_deref()
+        .or_else(|| email.split('@').next())
+        .map(|name| name.chars().filter(|c| !c.is_control()).collect::<String>())
+        .filter(|name| !name.trim().is_empty())
+        .as_deref()
+        .unwrap_or("user")
+        .trim()
+        .chars()
+        .take(MAX_USERNAME)
+        .collect();
+
+    let base = match usernames.is_reserved(&base) {
+        true => "user".to_string(),
+        false => base,
+    };
+    let mut username = match usernames.is_reserved(&base) {
+        true => suffixed(&base),
+        false => base.clone(),
+    };
+    let mut created = Err(RepoError::Conflict("username".to_string()));
+    for _ in 0..USERNAME_ATTEMPTS {
+        let checked = UpdateUserData {
+            username: Some(username.clone()),
+            email: Some(email.clone()),
+            bio: None,
+            image: None,
+            password: None,
+        };
+        checked.validate().map_err(OidcError::Invalid)?;
+
+        let new_user = NewUser {
+            username: username.clone(),
+            email: email.clone(),
+            password_hash: password_hash.clone(),
+        };
+        created = repos.users.create(new_user).await;
+        match &created {
+            Err(RepoError::Conflict(field)) if field == "email" => {
+                return Err(OidcError::EmailTaken)
+            }
+            Err(RepoError::Conflict(_)) => username = suffixed(&base),
+            _ => break,
+        }
+    }
+    let mut user = created?;
+
+    if identity.email_verified {
+        let changes = UserChanges {
+            email_verified: Some(true),
+            ..Default::default()
+        };
+        user = repos.users.update(user.id, changes).await?;
+    }
+    repos
+        .oidc
+        .link_identity(user.id, &identity.provider, &identity.subject)
+        .await?;
+
+    Ok(user)
+}
+
+fn suffixed(username: &str) -> String {
+    format!(
+        "{username}-{}",
+        &uuid::Uuid::new_v4().simple().to_string()[..6]
+    )
+}
+
+// ================================== Client Messages ================================== //
+
+/// What the provider appended to the redirect URL.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct OidcCallback {
+    #[validate(length(min = 1, message = "code can't be blank"))]
+    pub code: String,
+    #[validate(length(min = 1, message = "state can't be blank"))]
+    pub state: String,
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct ProviderListResponse {
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizationResponse {
+    pub authorization: AuthorizationResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizationResponseInner {
+    /// Where to send the user to log in.
+    pub url: String,
+}
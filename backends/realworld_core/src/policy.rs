@@ -0,0 +1,124 @@
+//! Who may do what. Every mutating handler asks [`User::may`] before touching anything.
+//!
+//! Users own what they write, moderators can also delete or hide anyone's articles and comments,
+//! and admins can moderate and manage users. Roles are stored per user, admins listed in
+//! [`RoleConfig`] get theirs whenever they log in.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::repo::User;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::User, Role::Moderator, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether someone with this role may do `action` to something, which they `own` or not.
+    pub fn permits(self, action: Action, own: bool) -> bool {
+        match action {
+            Action::CreateArticle | Action::FavoriteArticle | Action::Comment | Action::Follow => {
+                true
+            }
+            Action::EditArticle => own,
+            Action::DeleteArticle | Action::DeleteComment => own || self >= Role::Moderator,
+            Action::HideArticle | Action::HideComment => self >= Role::Moderator,
+            Action::ManageUsers => self == Role::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|known| known.as_str() == role)
+            .ok_or_else(|| format!("unknown role {role:?}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    CreateArticle,
+    /// Updating, publishing and going through the history of an article.
+    EditArticle,
+    DeleteArticle,
+    HideArticle,
+    FavoriteArticle,
+    Comment,
+    DeleteComment,
+    HideComment,
+    Follow,
+    ManageUsers,
+}
+
+impl Action {
+    /// Why someone was refused.
+    pub fn denied(&self) -> &'static str {
+        match self {
+            Action::CreateArticle => "You are not authorized to write articles",
+            Action::EditArticle => "You are not authorized to edit this article",
+            Action::DeleteArticle => "You are not authorized to delete this article",
+            Action::HideArticle => "You are not authorized to hide this article",
+            Action::FavoriteArticle => "You are not authorized to favorite articles",
+            Action::Comment => "You are not authorized to comment",
+            Action::DeleteComment => "You are not authorized to delete this comment",
+            Action::HideComment => "You are not authorized to hide this comment",
+            Action::Follow => "You are not authorized to follow users",
+            Action::ManageUsers => "You are not authorized to manage users",
+        }
+    }
+}
+
+impl<I: PartialEq> User<I> {
+    /// Whether this user may do `action` to something written by `owner`, if anyone.
+    pub fn may(&self, action: Action, owner: Option<&I>) -> bool {
+        self.role.permits(action, owner == Some(&self.id))
+    }
+}
+
+/// Bootstraps the first admins, since only admins can hand out roles.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoleConfig {
+    /// Emails of users made admins when they log in.
+    pub admins: Vec<String>,
+}
+
+impl RoleConfig {
+    /// The role `user` should be given on login, unless they have it already.
+    pub fn promotion<I>(&self, user: &User<I>) -> Option<Role> {
+        let listed = self
+            .admins
+            .iter()
+            .any(|email| email.eq_ignore_ascii_case(&user.email));
+
+        (listed && user.role != Role::Admin).then_some(Role::Admin)
+    }
+}
@@ -0,0 +1,221 @@
+//! Deleting accounts and exporting what is stored about a user, for privacy requests.
+//!
+//! Deleting an account either anonymizes it or removes it along with everything it wrote, see
+//! [`ContentPolicy`]. Anonymized accounts keep their articles and comments under a random
+//! `deleted-…` username, but lose their email, password, profile, follows, favorites, drafts and
+//! every way to log in.
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use validator::ValidationErrors;
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    article::ArticleResponseInner,
+    error::field_error,
+    policy::Role,
+    repo::{Identifier, NewUser, PersonalData, RepoError, RepoResult, Repos, User},
+    CustomDateTime,
+};
+
+/// Anonymized accounts are renamed to this, followed by random characters.
+pub const DELETED_PREFIX: &str = "deleted-";
+
+/// What happens to the articles and comments of a deleted account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentPolicy {
+    /// Kept under an anonymous author.
+    #[default]
+    Anonymize,
+    /// Deleted along with the account.
+    Remove,
+}
+
+impl ContentPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentPolicy::Anonymize => "anonymize",
+            ContentPolicy::Remove => "remove",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// Unless the user asks for the other one when deleting their account.
+    pub deleted_content: ContentPolicy,
+}
+
+// ================================== Extractors ================================== //
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteUserParams {
+    /// `anonymize` or `remove`.
+    pub content: Option<String>,
+}
+
+impl DeleteUserParams {
+    pub fn policy(&self, config: &PrivacyConfig) -> Result<ContentPolicy, ValidationErrors> {
+        match self.content.as_deref() {
+            None => Ok(config.deleted_content),
+            Some("anonymize") => Ok(ContentPolicy::Anonymize),
+            Some("remove") => Ok(ContentPolicy::Remove),
+            Some(_) => Err(field_error("content", "must be anonymize or remove")),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportParams {
+    /// `json`, the default, or `zip`.
+    pub format: Option<String>,
+}
+
+impl ExportParams {
+    /// Whether a ZIP archive was asked for.
+    pub fn zip(&self) -> Result<bool, ValidationErrors> {
+        match self.format.as_deref() {
+            None | Some("json") => Ok(false),
+            Some("zip") => Ok(true),
+            Some(_) => Err(field_error("format", "must be json or zip")),
+        }
+    }
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalDataResponse {
+    pub user: ExportedUser,
+    pub articles: Vec<ArticleResponseInner>,
+    pub comments: Vec<ExportedComment>,
+    /// Slugs of the articles the user favorited.
+    pub favorites: Vec<String>,
+    /// Usernames of the users the user follows.
+    pub following: Vec<String>,
+    /// Usernames of the users following the user.
+    pub followers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedUser {
+    pub username: String,
+    pub email: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub email_verified: bool,
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedComment {
+    pub id: i32,
+    /// Slug of the article commented on.
+    pub article: String,
+    pub body: String,
+    pub created_at: CustomDateTime,
+    pub updated_at: CustomDateTime,
+}
+
+impl<I> From<User<I>> for ExportedUser {
+    fn from(user: User<I>) -> Self {
+        ExportedUser {
+            username: user.username,
+            email: user.email,
+            bio: user.bio,
+            image: user.image,
+            email_verified: user.email_verified,
+            role: user.role,
+        }
+    }
+}
+
+impl PersonalDataResponse {
+    /// A ZIP archive with a JSON file for each part of the export.
+    pub fn to_zip(&self) -> zip::result::ZipResult<Vec<u8>> {
+        let follows = serde_json::json!({
+            "following": self.following,
+            "followers": self.followers,
+        });
+        let files = [
+            ("user.json", serde_json::to_vec_pretty(&self.user)),
+            ("articles.json", serde_json::to_vec_pretty(&self.articles)),
+            ("comments.json", serde_json::to_vec_pretty(&self.comments)),
+            ("favorites.json", serde_json::to_vec_pretty(&self.favorites)),
+            ("follows.json", serde_json::to_vec_pretty(&follows)),
+        ];
+
+        let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            archive.start_file(name, FileOptions::default())?;
+            archive.write_all(&contents.map_err(std::io::Error::from)?)?;
+        }
+        Ok(archive.finish()?.into_inner())
+    }
+}
+
+/// Everything stored about `user` that is theirs, articles in full.
+pub async fn export<I: Identifier>(repos: &Repos<I>, user: I) -> RepoResult<PersonalDataResponse> {
+    let data: PersonalData<I> = repos.users.personal_data(user).await?;
+
+    let mut articles = Vec::with_capacity(data.articles.len());
+    for slug in &data.articles {
+        // Drafts and hidden articles are found for their author
+        match repos.articles.find_by_slug(slug, Some(user)).await {
+            Ok(article) => articles.push(article.into()),
+            Err(RepoError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(PersonalDataResponse {
+        user: data.user.into(),
+        articles,
+        comments: data
+            .comments
+            .into_iter()
+            .map(|comment| ExportedComment {
+                id: comment.id,
+                article: comment.article,
+                body: comment.body,
+                created_at: comment.created_at.into(),
+                updated_at: comment.updated_at.into(),
+            })
+            .collect(),
+        favorites: data.favorites,
+        following: data.following,
+        followers: data.followers,
+    })
+}
+
+/// Deletes the account of `user`, keeping or removing what they wrote according to `policy`.
+/// Anonymized accounts get `password_hash` as their password, a random one nobody knows.
+pub async fn delete_account<I: Identifier>(
+    repos: &Repos<I>,
+    user: &User<I>,
+    policy: ContentPolicy,
+    password_hash: String,
+) -> RepoResult<()> {
+    match policy {
+        ContentPolicy::Anonymize => {
+            let suffix = uuid::Uuid::new_v4().simple().to_string();
+            let username = format!("{DELETED_PREFIX}{}", &suffix[..12]);
+            let replacement = NewUser {
+                email: format!("{username}@deleted.invalid"),
+                username,
+                password_hash,
+            };
+            repos.users.anonymize(user.id, replacement).await?;
+        }
+        ContentPolicy::Remove => repos.users.delete(user.id).await?,
+    }
+
+    tracing::info!(user_id = %user.id, content = policy.as_str(), "deleted user");
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use serde::Serialize;
+
+// ================================== JSON response objects ================================== //
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileResponseInner {
+    pub username: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub following: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileResponse {
+    pub profile: ProfileResponseInner,
+}
+
+impl From<ProfileResponseInner> for ProfileResponse {
+    fn from(profile: ProfileResponseInner) -> Self {
+        Self { profile }
+    }
+}
@@ -0,0 +1,1510 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{available_slug, slug_matches};
+use super::{
+    AccessToken, AccessTokenRepo, AccountTokenRepo, Article, ArticleChanges, ArticleFilter,
+    ArticleList, ArticleRepo, AuthoredComment, Comment, CommentRepo, FollowRepo, Identifier,
+    LoginAttemptRepo, LoginAttempts, NewArticle, NewUser, OidcRepo, OutboxRepo, Page, PersonalData,
+    Profile, RepoError, RepoResult, Revision, RevisionRepo, SearchHit, SearchResults, Session,
+    SessionRepo, TagRepo, Totp, TotpRepo, User, UserChanges, UserList, UserRepo, REVISED_FIELDS,
+};
+use crate::{
+    access_token::Scope,
+    account::TokenPurpose,
+    article::ArticleStatus,
+    mail::{Mail, QueuedMail, MAX_ATTEMPTS},
+    oidc::PendingLogin,
+    policy::Role,
+    search::SearchQuery,
+};
+
+/// Keeps everything in process memory. Data is lost on restart.
+pub struct MemoryStore<I> {
+    state: RwLock<State<I>>,
+}
+
+impl<I> Default for MemoryStore<I> {
+    fn default() -> Self {
+        Self {
+            state: RwLock::new(State {
+                seq: 0,
+                users: BTreeMap::new(),
+                sessions: BTreeMap::new(),
+                refresh_tokens: HashMap::new(),
+                account_tokens: HashMap::new(),
+                access_tokens: BTreeMap::new(),
+                totp: BTreeMap::new(),
+                recovery_codes: BTreeSet::new(),
+                challenges: HashMap::new(),
+                oidc_logins: HashMap::new(),
+                identities: BTreeMap::new(),
+                login_attempts: HashMap::new(),
+                lockouts: Vec::new(),
+                outbox: BTreeMap::new(),
+                follows: BTreeSet::new(),
+                articles: BTreeMap::new(),
+                favorites: BTreeSet::new(),
+                comments: BTreeMap::new(),
+                revisions: BTreeMap::new(),
+                old_slugs: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl<I> MemoryStore<I> {
+    fn read(&self) -> RwLockReadGuard<'_, State<I>> {
+        self.state
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, State<I>> {
+        self.state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct State<I> {
+    seq: u64,
+    users: BTreeMap<I, User<I>>,
+    sessions: BTreeMap<I, SessionRow<I>>,
+    /// By hash
+    refresh_tokens: HashMap<String, RefreshTokenRow<I>>,
+    /// By hash
+    account_tokens: HashMap<String, AccountTokenRow<I>>,
+    access_tokens: BTreeMap<I, AccessTokenRow<I>>,
+    totp: BTreeMap<I, Totp>,
+    /// `(user, hash)`, used codes are dropped
+    recovery_codes: BTreeSet<(I, String)>,
+    /// Login challenges given wrong codes or spent, by id
+    challenges: HashMap<String, ChallengeRow>,
+    /// By state hash, finished logins are dropped
+    oidc_logins: HashMap<String, (PendingLogin, DateTime<Utc>)>,
+    /// `(provider, subject)` to the user
+    identities: BTreeMap<(String, String), I>,
+    login_attempts: HashMap<String, LoginAttempts>,
+    /// `(key, failures, locked until)`
+    lockouts: Vec<(String, i32, DateTime<Utc>)>,
+    outbox: BTreeMap<i64, OutboxRow>,
+    /// `(follower, followee)`
+    follows: BTreeSet<(I, I)>,
+    articles: BTreeMap<I, ArticleRow<I>>,
+    /// `(article, user)`
+    favorites: BTreeSet<(I, I)>,
+    comments: BTreeMap<i32, CommentRow<I>>,
+    /// `(article, number)`
+    revisions: BTreeMap<(I, i32), RevisionRow<I>>,
+    /// Slugs articles had before being renamed, to the article
+    old_slugs: BTreeMap<String, I>,
+}
+
+struct SessionRow<I> {
+    user_id: I,
+    created_at: DateTime<Utc>,
+    revoked: bool,
+}
+
+struct RefreshTokenRow<I> {
+    session_id: I,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+struct AccountTokenRow<I> {
+    user_id: I,
+    purpose: TokenPurpose,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+struct ChallengeRow {
+    failures: i32,
+    spent: bool,
+    expires_at: DateTime<Utc>,
+}
+
+struct AccessTokenRow<I> {
+    token: AccessToken<I>,
+    token_hash: String,
+    revoked: bool,
+}
+
+/// Delivered mail is dropped rather than kept around.
+struct OutboxRow {
+    mail: Mail,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+}
+
+struct ArticleRow<I> {
+    id: I,
+    author_id: I,
+    slug: String,
+    title: String,
+    description: String,
+    body: String,
+    tag_list: Vec<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    status: ArticleStatus,
+    published_at: Option<DateTime<Utc>>,
+    hidden: bool,
+}
+
+impl<I> ArticleRow<I> {
+    fn listed(&self) -> bool {
+        self.status == ArticleStatus::Published && !self.hidden
+    }
+
+    fn visible_to(&self, viewer: Option<I>) -> bool
+    where
+        I: PartialEq,
+    {
+        (self.status != ArticleStatus::Draft && !self.hidden)
+            || viewer.is_some_and(|viewer| viewer == self.author_id)
+    }
+}
+
+struct CommentRow<I> {
+    id: i32,
+    article_id: I,
+    author_id: I,
+    body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    hidden: bool,
+}
+
+struct RevisionRow<I> {
+    author_id: Option<I>,
+    title: String,
+    description: String,
+    body: String,
+    changed_fields: Vec<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl<I: Identifier> State<I> {
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn session(&self, id: I) -> RepoResult<Session<I>> {
+        match self.sessions.get(&id) {
+            Some(row) if !row.revoked => Ok(Session {
+                id,
+                user_id: row.user_id,
+                created_at: row.created_at,
+            }),
+            _ => Err(RepoError::NotFound),
+        }
+    }
+
+    fn user_by_name(&self, username: &str) -> Option<&User<I>> {
+        self.users.values().find(|user| user.username == username)
+    }
+
+    fn article_by_slug(&self, slug: &str) -> Option<&ArticleRow<I>> {
+        self.articles.values().find(|article| article.slug == slug)
+    }
+
+    /// Slugs starting like `base` that articles other than `except` have, or had.
+    fn taken_slugs(&self, base: &str, except: Option<I>) -> Vec<String> {
+        let current = self.articles.values().map(|row| (&row.slug, row.id));
+        let old = self.old_slugs.iter().map(|(slug, id)| (slug, *id));
+
+        current
+            .chain(old)
+            .filter(|(slug, id)| Some(*id) != except && slug_matches(slug, base))
+            .map(|(slug, _)| slug.clone())
+            .collect()
+    }
+
+    fn check_unique_user(&self, id: Option<I>, username: &str, email: &str) -> RepoResult<()> {
+        let others = self.users.values().filter(|user| Some(user.id) != id);
+
+        // Ignoring case, like the lower() indexes of the Postgres repos
+        for user in others {
+            if user.username.to_lowercase() == username.to_lowercase() {
+                return Err(RepoError::Conflict("username".into()));
+            }
+            if user.email.to_lowercase() == email.to_lowercase() {
+                return Err(RepoError::Conflict("email".into()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn profile(&self, user: &User<I>, viewer: Option<I>) -> Profile<I> {
+        Profile {
+            id: user.id,
+            username: user.username.clone(),
+            bio: user.bio.clone(),
+            image: user.image.clone(),
+            following: viewer.is_some_and(|viewer| self.follows.contains(&(viewer, user.id))),
+        }
+    }
+
+    fn article(&self, row: &ArticleRow<I>, viewer: Option<I>) -> RepoResult<Article<I>> {
+        let author = self.users.get(&row.author_id).ok_or(RepoError::NotFound)?;
+        let favorites_count = self
+            .favorites
+            .iter()
+            .filter(|(article_id, _)| *article_id == row.id)
+            .count();
+
+        Ok(Article {
+            id: row.id,
+            slug: row.slug.clone(),
+            title: row.title.clone(),
+            description: row.description.clone(),
+            body: row.body.clone(),
+            tag_list: row.tag_list.clone(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            favorited: viewer.is_some_and(|viewer| self.favorites.contains(&(row.id, viewer))),
+            favorites_count: favorites_count as i64,
+            author: self.profile(author, viewer),
+            status: row.status,
+            published_at: row.published_at,
+        })
+    }
+
+    fn comment(&self, row: &CommentRow<I>, viewer: Option<I>) -> RepoResult<Comment<I>> {
+        let author = self.users.get(&row.author_id).ok_or(RepoError::NotFound)?;
+
+        Ok(Comment {
+            id: row.id,
+            article_id: row.article_id,
+            body: row.body.clone(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            author: self.profile(author, viewer),
+        })
+    }
+
+    fn revision(
+        &self,
+        (article_id, number): (I, i32),
+        row: &RevisionRow<I>,
+        viewer: Option<I>,
+    ) -> RepoResult<Revision<I>> {
+        let author = match row.author_id {
+            Some(id) => Some(self.users.get(&id).ok_or(RepoError::NotFound)?),
+            None => None,
+        };
+
+        Ok(Revision {
+            article_id,
+            number,
+            title: row.title.clone(),
+            description: row.description.clone(),
+            body: row.body.clone(),
+            changed_fields: row.changed_fields.clone(),
+            author: author.map(|author| self.profile(author, viewer)),
+            created_at: row.created_at,
+        })
+    }
+
+    /// Snapshots the current content of article `id`.
+    fn add_revision(&mut self, id: I, author_id: I, changed_fields: Vec<String>) {
+        let number = self
+            .revisions
+            .range((id, i32::MIN)..=(id, i32::MAX))
+            .next_back()
+            .map_or(1, |((_, number), _)| number + 1);
+
+        let article = &self.articles[&id];
+        let row = RevisionRow {
+            author_id: Some(author_id),
+            title: article.title.clone(),
+            description: article.description.clone(),
+            body: article.body.clone(),
+            changed_fields,
+            created_at: article.updated_at,
+        };
+        self.revisions.insert((id, number), row);
+    }
+
+    /// Newest first; ties are broken by id so pages are stable.
+    fn page<F>(&self, page: Page<I>, viewer: Option<I>, keep: F) -> RepoResult<ArticleList<I>>
+    where
+        F: Fn(&ArticleRow<I>) -> bool,
+    {
+        let mut rows = self
+            .articles
+            .values()
+            .filter(|row| row.listed() && keep(row))
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|row| Reverse((row.created_at, row.id)));
+
+        let total = rows.len() as i64;
+        let after = page.after();
+        let articles = rows
+            .into_iter()
+            .filter(|row| after.is_none_or(|after| after.precedes(row.created_at, row.id)))
+            .skip(page.offset())
+            .take(page.fetch_limit())
+            .map(|row| self.article(row, viewer))
+            .collect::<RepoResult<Vec<_>>>()?;
+
+        Ok(ArticleList::new(articles, total, &page))
+    }
+}
+
+impl<I: Identifier> State<I> {
+    /// Removes articles along with everything attached to them.
+    fn remove_articles(&mut self, ids: &[I]) {
+        for id in ids {
+            self.articles.remove(id);
+        }
+        self.favorites
+            .retain(|(article_id, _)| !ids.contains(article_id));
+        self.comments
+            .retain(|_, comment| !ids.contains(&comment.article_id));
+        self.revisions
+            .retain(|(article_id, _), _| !ids.contains(article_id));
+        self.old_slugs
+            .retain(|_, article_id| !ids.contains(article_id));
+    }
+
+    /// Drops the follows and favorites of `user`, and every way to log in as them.
+    fn forget_user(&mut self, user: I) {
+        self.follows
+            .retain(|(follower, followee)| *follower != user && *followee != user);
+        self.favorites.retain(|(_, fan)| *fan != user);
+
+        let sessions = self
+            .sessions
+            .iter()
+            .filter(|(_, row)| row.user_id == user)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        self.sessions.retain(|_, row| row.user_id != user);
+        self.refresh_tokens
+            .retain(|_, row| !sessions.contains(&row.session_id));
+        self.account_tokens.retain(|_, row| row.user_id != user);
+        self.access_tokens
+            .retain(|_, row| row.token.user_id != user);
+        self.totp.remove(&user);
+        self.recovery_codes.retain(|(owner, _)| *owner != user);
+        self.identities.retain(|_, owner| *owner != user);
+    }
+}
+
+fn dedup_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags = tags;
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+#[async_trait]
+impl<I: Identifier> UserRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn create(&self, user: NewUser) -> RepoResult<User<I>> {
+        let mut state = self.write();
+        state.check_unique_user(None, &user.username, &user.email)?;
+
+        let id = I::generate(state.next_seq());
+        let user = User {
+            id,
+            username: user.username,
+            email: user.email,
+            password_hash: user.password_hash,
+            bio: None,
+            image: None,
+            email_verified: false,
+            role: Role::User,
+        };
+        state.users.insert(id, user.clone());
+
+        Ok(user)
+    }
+
+    async fn find(&self, id: I) -> RepoResult<User<I>> {
+        self.read()
+            .users
+            .get(&id)
+            .cloned()
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &str) -> RepoResult<User<I>> {
+        self.read()
+            .users
+            .values()
+            .find(|user| user.email.to_lowercase() == email.to_lowercase())
+            .cloned()
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn update(&self, id: I, changes: UserChanges) -> RepoResult<User<I>> {
+        let mut state = self.write();
+
+        let mut user = state.users.get(&id).cloned().ok_or(RepoError::NotFound)?;
+        if let Some(username) = changes.username {
+            user.username = username;
+        }
+        if let Some(email) = changes.email {
+            if email != user.email {
+                user.email = email;
+                user.email_verified = false;
+            }
+        }
+        if let Some(password_hash) = changes.password_hash {
+            user.password_hash = password_hash;
+        }
+        if let Some(bio) = changes.bio {
+            user.bio = Some(bio);
+        }
+        if let Some(image) = changes.image {
+            user.image = Some(image);
+        }
+        if let Some(email_verified) = changes.email_verified {
+            user.email_verified = email_verified;
+        }
+        if let Some(role) = changes.role {
+            user.role = role;
+        }
+
+        state.check_unique_user(Some(id), &user.username, &user.email)?;
+        state.users.insert(id, user.clone());
+
+        Ok(user)
+    }
+
+    async fn profile(&self, username: &str, viewer: Option<I>) -> RepoResult<Profile<I>> {
+        let state = self.read();
+        let user = state.user_by_name(username).ok_or(RepoError::NotFound)?;
+        Ok(state.profile(user, viewer))
+    }
+
+    async fn list(&self, limit: usize, offset: usize) -> RepoResult<UserList<I>> {
+        let state = self.read();
+        let mut users = state.users.values().collect::<Vec<_>>();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+
+        Ok(UserList {
+            total: users.len() as i64,
+            users: users
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn personal_data(&self, id: I) -> RepoResult<PersonalData<I>> {
+        let state = self.read();
+        let user = state.users.get(&id).cloned().ok_or(RepoError::NotFound)?;
+        let slug = |article: &I| state.articles.get(article).map(|row| row.slug.clone());
+        let username = |user: &I| state.users.get(user).map(|user| user.username.clone());
+
+        let mut articles = state
+            .articles
+            .values()
+            .filter(|row| row.author_id == id)
+            .collect::<Vec<_>>();
+        articles.sort_by_key(|row| (row.created_at, row.id));
+
+        // Sorted by slug and username, like the Postgres repos
+        let mut favorites: Vec<String> = state
+            .favorites
+            .iter()
+            .filter(|(_, user)| *user == id)
+            .filter_map(|(article, _)| slug(article))
+            .collect();
+        favorites.sort();
+        let mut following: Vec<String> = state
+            .follows
+            .iter()
+            .filter(|(follower, _)| *follower == id)
+            .filter_map(|(_, followee)| username(followee))
+            .collect();
+        following.sort();
+        let mut followers: Vec<String> = state
+            .follows
+            .iter()
+            .filter(|(_, followee)| *followee == id)
+            .filter_map(|(follower, _)| username(follower))
+            .collect();
+        followers.sort();
+
+        Ok(PersonalData {
+            user,
+            articles: articles.into_iter().map(|row| row.slug.clone()).collect(),
+            comments: state
+                .comments
+                .values()
+                .filter(|row| row.author_id == id)
+                .filter_map(|row| {
+                    Some(AuthoredComment {
+                        id: row.id,
+                        article: slug(&row.article_id)?,
+                        body: row.body.clone(),
+                        created_at: row.created_at,
+                        updated_at: row.updated_at,
+                    })
+                })
+                .collect(),
+            favorites,
+            following,
+            followers,
+        })
+    }
+
+    async fn anonymize(&self, id: I, replacement: NewUser) -> RepoResult<()> {
+        let mut state = self.write();
+        state.check_unique_user(Some(id), &replacement.username, &replacement.email)?;
+
+        let user = state.users.get_mut(&id).ok_or(RepoError::NotFound)?;
+        *user = User {
+            id,
+            username: replacement.username,
+            email: replacement.email,
+            password_hash: replacement.password_hash,
+            bio: None,
+            image: None,
+            email_verified: false,
+            role: Role::User,
+        };
+
+        let drafts = state
+            .articles
+            .values()
+            .filter(|row| row.author_id == id && row.status == ArticleStatus::Draft)
+            .map(|row| row.id)
+            .collect::<Vec<_>>();
+        state.remove_articles(&drafts);
+        state.forget_user(id);
+        Ok(())
+    }
+
+    async fn delete(&self, id: I) -> RepoResult<()> {
+        let mut state = self.write();
+        state.users.remove(&id).ok_or(RepoError::NotFound)?;
+
+        let articles = state
+            .articles
+            .values()
+            .filter(|row| row.author_id == id)
+            .map(|row| row.id)
+            .collect::<Vec<_>>();
+        state.remove_articles(&articles);
+        state.comments.retain(|_, row| row.author_id != id);
+        for row in state.revisions.values_mut() {
+            if row.author_id == Some(id) {
+                row.author_id = None;
+            }
+        }
+        state.forget_user(id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> SessionRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn create(
+        &self,
+        user: I,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<Session<I>> {
+        let mut state = self.write();
+        if !state.users.contains_key(&user) {
+            return Err(RepoError::NotFound);
+        }
+
+        let id = I::generate(state.next_seq());
+        state.sessions.insert(
+            id,
+            SessionRow {
+                user_id: user,
+                created_at: Utc::now(),
+                revoked: false,
+            },
+        );
+        state.refresh_tokens.insert(
+            token_hash.to_string(),
+            RefreshTokenRow {
+                session_id: id,
+                expires_at,
+                used: false,
+            },
+        );
+
+        state.session(id)
+    }
+
+    async fn find(&self, id: I) -> RepoResult<Session<I>> {
+        self.read().session(id)
+    }
+
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<Session<I>> {
+        let mut state = self.write();
+        let state = &mut *state;
+
+        let token = state
+            .refresh_tokens
+            .get_mut(token_hash)
+            .ok_or(RepoError::NotFound)?;
+        let session = state
+            .sessions
+            .get_mut(&token.session_id)
+            .ok_or(RepoError::NotFound)?;
+
+        if token.used {
+            session.revoked = true;
+            return Err(RepoError::NotFound);
+        }
+        if session.revoked || token.expires_at <= Utc::now() {
+            return Err(RepoError::NotFound);
+        }
+
+        token.used = true;
+        let session = Session {
+            id: token.session_id,
+            user_id: session.user_id,
+            created_at: session.created_at,
+        };
+        state.refresh_tokens.insert(
+            new_hash.to_string(),
+            RefreshTokenRow {
+                session_id: session.id,
+                expires_at,
+                used: false,
+            },
+        );
+
+        Ok(session)
+    }
+
+    async fn revoke(&self, id: I) -> RepoResult<()> {
+        if let Some(session) = self.write().sessions.get_mut(&id) {
+            session.revoked = true;
+        }
+        Ok(())
+    }
+
+    async fn revoke_all(&self, user: I) -> RepoResult<()> {
+        self.write()
+            .sessions
+            .values_mut()
+            .filter(|session| session.user_id == user)
+            .for_each(|session| session.revoked = true);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> AccountTokenRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn issue(
+        &self,
+        user: I,
+        purpose: TokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        mail: Mail,
+    ) -> RepoResult<()> {
+        let mut state = self.write();
+        if !state.users.contains_key(&user) {
+            return Err(RepoError::NotFound);
+        }
+
+        state
+            .account_tokens
+            .values_mut()
+            .filter(|token| token.user_id == user && token.purpose == purpose)
+            .for_each(|token| token.used = true);
+        state.account_tokens.insert(
+            token_hash.to_string(),
+            AccountTokenRow {
+                user_id: user,
+                purpose,
+                expires_at,
+                used: false,
+            },
+        );
+
+        let id = state.next_seq() as i64;
+        state.outbox.insert(
+            id,
+            OutboxRow {
+                mail,
+                attempts: 0,
+                next_attempt_at: Utc::now(),
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn redeem(&self, purpose: TokenPurpose, token_hash: &str) -> RepoResult<I> {
+        match self.write().account_tokens.get_mut(token_hash) {
+            Some(token)
+                if token.purpose == purpose && !token.used && token.expires_at > Utc::now() =>
+            {
+                token.used = true;
+                Ok(token.user_id)
+            }
+            _ => Err(RepoError::NotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> AccessTokenRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn create(
+        &self,
+        user: I,
+        name: &str,
+        scopes: &[Scope],
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<AccessToken<I>> {
+        let mut state = self.write();
+        if !state.users.contains_key(&user) {
+            return Err(RepoError::NotFound);
+        }
+
+        let token = AccessToken {
+            id: I::generate(state.next_seq()),
+            user_id: user,
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            created_at: Utc::now(),
+            expires_at,
+            last_used_at: None,
+        };
+        state.access_tokens.insert(
+            token.id,
+            AccessTokenRow {
+                token: token.clone(),
+                token_hash: token_hash.to_string(),
+                revoked: false,
+            },
+        );
+        Ok(token)
+    }
+
+    async fn authenticate(&self, token_hash: &str) -> RepoResult<AccessToken<I>> {
+        let now = Utc::now();
+        let mut state = self.write();
+        let row = state
+            .access_tokens
+            .values_mut()
+            .find(|row| row.token_hash == token_hash)
+            .filter(|row| !row.revoked && row.token.expires_at > now)
+            .ok_or(RepoError::NotFound)?;
+
+        row.token.last_used_at = Some(now);
+        Ok(row.token.clone())
+    }
+
+    async fn list(&self, user: I) -> RepoResult<Vec<AccessToken<I>>> {
+        let mut tokens: Vec<AccessToken<I>> = self
+            .read()
+            .access_tokens
+            .values()
+            .filter(|row| row.token.user_id == user && !row.revoked)
+            .map(|row| row.token.clone())
+            .collect();
+        tokens.sort_by_key(|token| Reverse(token.created_at));
+        Ok(tokens)
+    }
+
+    async fn revoke(&self, user: I, id: I) -> RepoResult<()> {
+        match self.write().access_tokens.get_mut(&id) {
+            Some(row) if row.token.user_id == user && !row.revoked => {
+                row.revoked = true;
+                Ok(())
+            }
+            _ => Err(RepoError::NotFound),
+        }
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> TotpRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn enroll(&self, user: I, secret: &str) -> RepoResult<()> {
+        let mut state = self.write();
+        if !state.users.contains_key(&user) {
+            return Err(RepoError::NotFound);
+        }
+        if state.totp.get(&user).is_some_and(|totp| totp.enabled) {
+            return Err(RepoError::Conflict("totp".to_string()));
+        }
+
+        state.totp.insert(
+            user,
+            Totp {
+                secret: secret.to_string(),
+                enabled: false,
+                last_step: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn find(&self, user: I) -> RepoResult<Totp> {
+        self.read()
+            .totp
+            .get(&user)
+            .cloned()
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn enable(
+        &self,
+        user: I,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> RepoResult<()> {
+        let mut state = self.write();
+        match state.totp.get_mut(&user) {
+            Some(totp) if !totp.enabled => {
+                totp.enabled = true;
+                totp.last_step = Some(step);
+            }
+            _ => return Err(RepoError::NotFound),
+        }
+
+        state.recovery_codes.retain(|(owner, _)| *owner != user);
+        state.recovery_codes.extend(
+            recovery_code_hashes
+                .into_iter()
+                .map(|code_hash| (user, code_hash)),
+        );
+        Ok(())
+    }
+
+    async fn use_step(&self, user: I, step: i64) -> RepoResult<()> {
+        match self.write().totp.get_mut(&user) {
+            Some(totp) if totp.enabled && totp.last_step.is_none_or(|last| last < step) => {
+                totp.last_step = Some(step);
+                Ok(())
+            }
+            _ => Err(RepoError::NotFound),
+        }
+    }
+
+    async fn use_recovery_code(&self, user: I, code_hash: &str) -> RepoResult<()> {
+        match self
+            .write()
+            .recovery_codes
+            .remove(&(user, code_hash.to_string()))
+        {
+            true => Ok(()),
+            false => Err(RepoError::NotFound),
+        }
+    }
+
+    async fn challenge_spent(&self, id: &str) -> RepoResult<bool> {
+        Ok(self
+            .read()
+            .challenges
+            .get(id)
+            .is_some_and(|challenge| challenge.spent))
+    }
+
+    async fn fail_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<i32> {
+        let mut state = self.write();
+        let challenge = state.challenge(id, expires_at);
+        challenge.failures += 1;
+        Ok(challenge.failures)
+    }
+
+    async fn spend_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<()> {
+        let mut state = self.write();
+        let challenge = state.challenge(id, expires_at);
+        if challenge.spent {
+            return Err(RepoError::Conflict("challenge".to_string()));
+        }
+        challenge.spent = true;
+        Ok(())
+    }
+}
+
+impl<I: Identifier> State<I> {
+    /// The row of the challenge `id`, dropping expired ones.
+    fn challenge(&mut self, id: &str, expires_at: DateTime<Utc>) -> &mut ChallengeRow {
+        let now = Utc::now();
+        self.challenges
+            .retain(|_, challenge| challenge.expires_at > now);
+        self.challenges
+            .entry(id.to_string())
+            .or_insert(ChallengeRow {
+                failures: 0,
+                spent: false,
+                expires_at,
+            })
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> OidcRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn start_login(
+        &self,
+        state_hash: &str,
+        login: PendingLogin,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<()> {
+        let mut state = self.write();
+        let now = Utc::now();
+        state
+            .oidc_logins
+            .retain(|_, (_, expires_at)| *expires_at > now);
+        state
+            .oidc_logins
+            .insert(state_hash.to_string(), (login, expires_at));
+        Ok(())
+    }
+
+    async fn finish_login(&self, state_hash: &str) -> RepoResult<PendingLogin> {
+        match self.write().oidc_logins.remove(state_hash) {
+            Some((login, expires_at)) if expires_at > Utc::now() => Ok(login),
+            _ => Err(RepoError::NotFound),
+        }
+    }
+
+    async fn find_identity(&self, provider: &str, subject: &str) -> RepoResult<I> {
+        self.read()
+            .identities
+            .get(&(provider.to_string(), subject.to_string()))
+            .copied()
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn link_identity(&self, user: I, provider: &str, subject: &str) -> RepoResult<()> {
+        let mut state = self.write();
+        if !state.users.contains_key(&user) {
+            return Err(RepoError::NotFound);
+        }
+
+        let key = (provider.to_string(), subject.to_string());
+        if state.identities.contains_key(&key) {
+            return Err(RepoError::Conflict("identity".to_string()));
+        }
+        state.identities.insert(key, user);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> LoginAttemptRepo for MemoryStore<I> {
+    async fn find(&self, key: &str) -> RepoResult<LoginAttempts> {
+        self.read()
+            .login_attempts
+            .get(key)
+            .cloned()
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn fail(&self, key: &str, stale_before: DateTime<Utc>) -> RepoResult<i32> {
+        let now = Utc::now();
+        let mut state = self.write();
+        let attempts = state
+            .login_attempts
+            .entry(key.to_string())
+            .or_insert(LoginAttempts {
+                failures: 0,
+                last_failed_at: now,
+                locked_until: None,
+            });
+
+        if attempts
+            .last_failed_at
+            .max(attempts.locked_until.unwrap_or(attempts.last_failed_at))
+            < stale_before
+        {
+            attempts.failures = 0;
+        }
+        attempts.failures += 1;
+        attempts.last_failed_at = now;
+        Ok(attempts.failures)
+    }
+
+    async fn lock(&self, key: &str, failures: i32, until: DateTime<Utc>) -> RepoResult<()> {
+        let mut state = self.write();
+        let attempts = state
+            .login_attempts
+            .entry(key.to_string())
+            .or_insert(LoginAttempts {
+                failures,
+                last_failed_at: Utc::now(),
+                locked_until: None,
+            });
+        attempts.locked_until = Some(until);
+        state.lockouts.push((key.to_string(), failures, until));
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> RepoResult<()> {
+        self.write().login_attempts.remove(key);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> OutboxRepo for MemoryStore<I> {
+    async fn claim(&self, limit: i64, lease_until: DateTime<Utc>) -> RepoResult<Vec<QueuedMail>> {
+        let now = Utc::now();
+
+        Ok(self
+            .write()
+            .outbox
+            .iter_mut()
+            .filter(|(_, row)| row.attempts < MAX_ATTEMPTS && row.next_attempt_at <= now)
+            .take(limit as usize)
+            .map(|(id, row)| {
+                row.attempts += 1;
+                row.next_attempt_at = lease_until;
+                QueuedMail {
+                    id: *id,
+                    mail: row.mail.clone(),
+                    attempts: row.attempts,
+                }
+            })
+            .collect())
+    }
+
+    async fn delivered(&self, id: i64) -> RepoResult<()> {
+        self.write().outbox.remove(&id);
+        Ok(())
+    }
+
+    async fn failed(&self, id: i64, _error: &str, retry_at: DateTime<Utc>) -> RepoResult<()> {
+        let mut state = self.write();
+        let row = state.outbox.get_mut(&id).ok_or(RepoError::NotFound)?;
+        row.next_attempt_at = retry_at;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> FollowRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn follow(&self, follower: I, followee: I) -> RepoResult<()> {
+        let mut state = self.write();
+        if !state.users.contains_key(&followee) {
+            return Err(RepoError::NotFound);
+        }
+        state.follows.insert((follower, followee));
+        Ok(())
+    }
+
+    async fn unfollow(&self, follower: I, followee: I) -> RepoResult<()> {
+        self.write().follows.remove(&(follower, followee));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> ArticleRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn create(&self, author: I, article: NewArticle) -> RepoResult<Article<I>> {
+        let mut state = self.write();
+        let slug = available_slug(&article.slug, &state.taken_slugs(&article.slug, None));
+
+        let now = Utc::now();
+        let id = I::generate(state.next_seq());
+        let row = ArticleRow {
+            id,
+            author_id: author,
+            slug,
+            title: article.title,
+            description: article.description,
+            body: article.body,
+            tag_list: dedup_tags(article.tag_list),
+            created_at: now,
+            updated_at: now,
+            status: article.status,
+            published_at: (article.status == ArticleStatus::Published).then_some(now),
+            hidden: false,
+        };
+
+        let article = state.article(&row, Some(author))?;
+        state.articles.insert(id, row);
+        state.add_revision(id, author, REVISED_FIELDS.map(String::from).to_vec());
+
+        Ok(article)
+    }
+
+    async fn find_by_slug(&self, slug: &str, viewer: Option<I>) -> RepoResult<Article<I>> {
+        let state = self.read();
+        let row = state
+            .article_by_slug(slug)
+            .filter(|row| row.visible_to(viewer))
+            .ok_or(RepoError::NotFound)?;
+        state.article(row, viewer)
+    }
+
+    async fn renamed(&self, slug: &str) -> RepoResult<String> {
+        let state = self.read();
+        state
+            .old_slugs
+            .get(slug)
+            .and_then(|id| state.articles.get(id))
+            .map(|row| row.slug.clone())
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn list(
+        &self,
+        filter: &ArticleFilter,
+        page: Page<I>,
+        viewer: Option<I>,
+    ) -> RepoResult<ArticleList<I>> {
+        let state = self.read();
+
+        let author = match filter.author {
+            Some(ref username) => match state.user_by_name(username) {
+                Some(user) => Some(user.id),
+                None => return Ok(ArticleList::new(Vec::new(), 0, &page)),
+            },
+            None => None,
+        };
+        let favorited_by = match filter.favorited {
+            Some(ref username) => match state.user_by_name(username) {
+                Some(user) => Some(user.id),
+                None => return Ok(ArticleList::new(Vec::new(), 0, &page)),
+            },
+            None => None,
+        };
+
+        state.page(page, viewer, |row| {
+            author.is_none_or(|author| row.author_id == author)
+                && favorited_by.is_none_or(|user| state.favorites.contains(&(row.id, user)))
+                && filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| row.tag_list.contains(tag))
+        })
+    }
+
+    async fn feed(&self, viewer: I, page: Page<I>) -> RepoResult<ArticleList<I>> {
+        let state = self.read();
+        state.page(page, Some(viewer), |row| {
+            state.follows.contains(&(viewer, row.author_id))
+        })
+    }
+
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        page: Page<I>,
+        viewer: Option<I>,
+    ) -> RepoResult<SearchResults<I>> {
+        let state = self.read();
+
+        // Weighted like the Postgres search vectors: title, then description, then body
+        let mut rows = state
+            .articles
+            .values()
+            .filter(|row| row.listed())
+            .filter(|row| query.matches(&format!("{} {} {}", row.title, row.description, row.body)))
+            .map(|row| {
+                let rank = 10 * query.hits(&row.title)
+                    + 4 * query.hits(&row.description)
+                    + 2 * query.hits(&row.body);
+                (rank, row)
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|(rank, row)| Reverse((*rank, row.created_at, row.id)));
+
+        let total = rows.len() as i64;
+        let hits = rows
+            .into_iter()
+            .skip(page.offset())
+            .take(page.fetch_limit())
+            .map(|(_, row)| {
+                Ok(SearchHit {
+                    article: state.article(row, viewer)?,
+                    snippet: query.snippet(&row.body),
+                })
+            })
+            .collect::<RepoResult<Vec<_>>>()?;
+
+        Ok(SearchResults { hits, total })
+    }
+
+    async fn update(
+        &self,
+        id: I,
+        changes: ArticleChanges,
+        viewer: Option<I>,
+    ) -> RepoResult<Article<I>> {
+        let mut state = self.write();
+
+        let current = state.articles.get(&id).ok_or(RepoError::NotFound)?;
+        let renamed = match changes.slug {
+            Some(ref base) if !slug_matches(&current.slug, base) => {
+                let slug = available_slug(base, &state.taken_slugs(base, Some(id)));
+                Some((current.slug.clone(), slug))
+            }
+            _ => None,
+        };
+        if let Some((ref old, ref new)) = renamed {
+            state.old_slugs.remove(new);
+            state.old_slugs.insert(old.clone(), id);
+        }
+
+        let row = state.articles.get_mut(&id).ok_or(RepoError::NotFound)?;
+        let changed_fields = changes.changed_fields(&row.title, &row.description, &row.body);
+        let editor = viewer.unwrap_or(row.author_id);
+        if let Some((_, slug)) = renamed {
+            row.slug = slug;
+        }
+        if let Some(title) = changes.title {
+            row.title = title;
+        }
+        if let Some(description) = changes.description {
+            row.description = description;
+        }
+        if let Some(body) = changes.body {
+            row.body = body;
+        }
+        if let Some(tag_list) = changes.tag_list {
+            row.tag_list = dedup_tags(tag_list);
+        }
+        let now = Utc::now();
+        if let Some(status) = changes.status {
+            match status {
+                ArticleStatus::Draft => row.published_at = None,
+                ArticleStatus::Published if row.status != status => row.published_at = Some(now),
+                _ => {}
+            }
+            row.status = status;
+        }
+        row.updated_at = now;
+
+        if !changed_fields.is_empty() {
+            state.add_revision(id, editor, changed_fields);
+        }
+
+        let row = &state.articles[&id];
+        state.article(row, viewer)
+    }
+
+    async fn delete(&self, id: I) -> RepoResult<()> {
+        let mut state = self.write();
+        state.articles.remove(&id).ok_or(RepoError::NotFound)?;
+        state.favorites.retain(|(article_id, _)| *article_id != id);
+        state.comments.retain(|_, comment| comment.article_id != id);
+        state
+            .revisions
+            .retain(|(article_id, _), _| *article_id != id);
+        Ok(())
+    }
+
+    async fn set_hidden(&self, slug: &str, hidden: bool) -> RepoResult<()> {
+        let mut state = self.write();
+        let row = state
+            .articles
+            .values_mut()
+            .find(|row| row.slug == slug)
+            .ok_or(RepoError::NotFound)?;
+        row.hidden = hidden;
+        Ok(())
+    }
+
+    async fn favorite(&self, id: I, user: I) -> RepoResult<()> {
+        let mut state = self.write();
+        if !state.articles.contains_key(&id) {
+            return Err(RepoError::NotFound);
+        }
+        state.favorites.insert((id, user));
+        Ok(())
+    }
+
+    async fn unfavorite(&self, id: I, user: I) -> RepoResult<()> {
+        self.write().favorites.remove(&(id, user));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> CommentRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn create(&self, article: I, author: I, body: String) -> RepoResult<Comment<I>> {
+        let mut state = self.write();
+        if !state.articles.contains_key(&article) {
+            return Err(RepoError::NotFound);
+        }
+
+        let now = Utc::now();
+        let id = state.comments.keys().next_back().map_or(1, |id| id + 1);
+        let row = CommentRow {
+            id,
+            article_id: article,
+            author_id: author,
+            body,
+            created_at: now,
+            updated_at: now,
+            hidden: false,
+        };
+
+        let comment = state.comment(&row, Some(author))?;
+        state.comments.insert(id, row);
+
+        Ok(comment)
+    }
+
+    async fn find(&self, id: i32) -> RepoResult<Comment<I>> {
+        let state = self.read();
+        let row = state.comments.get(&id).ok_or(RepoError::NotFound)?;
+        state.comment(row, None)
+    }
+
+    async fn list(&self, article: I, viewer: Option<I>) -> RepoResult<Vec<Comment<I>>> {
+        let state = self.read();
+        state
+            .comments
+            .values()
+            .rev()
+            .filter(|row| row.article_id == article)
+            .filter(|row| !row.hidden || Some(row.author_id) == viewer)
+            .map(|row| state.comment(row, viewer))
+            .collect()
+    }
+
+    async fn delete(&self, id: i32) -> RepoResult<()> {
+        self.write()
+            .comments
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(RepoError::NotFound)
+    }
+
+    async fn set_hidden(&self, id: i32, hidden: bool) -> RepoResult<()> {
+        let mut state = self.write();
+        let row = state.comments.get_mut(&id).ok_or(RepoError::NotFound)?;
+        row.hidden = hidden;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> RevisionRepo for MemoryStore<I> {
+    type Id = I;
+
+    async fn list(&self, article: I, viewer: Option<I>) -> RepoResult<Vec<Revision<I>>> {
+        let state = self.read();
+        state
+            .revisions
+            .range((article, i32::MIN)..=(article, i32::MAX))
+            .rev()
+            .map(|(key, row)| state.revision(*key, row, viewer))
+            .collect()
+    }
+
+    async fn find(&self, article: I, number: i32, viewer: Option<I>) -> RepoResult<Revision<I>> {
+        let state = self.read();
+        let row = state
+            .revisions
+            .get(&(article, number))
+            .ok_or(RepoError::NotFound)?;
+        state.revision((article, number), row, viewer)
+    }
+}
+
+#[async_trait]
+impl<I: Identifier> TagRepo for MemoryStore<I> {
+    async fn list(&self) -> RepoResult<Vec<String>> {
+        let state = self.read();
+
+        let mut counts = HashMap::<&str, usize>::new();
+        let listed = state.articles.values().filter(|row| row.listed());
+        for tag in listed.flat_map(|row| &row.tag_list) {
+            *counts.entry(tag).or_default() += 1;
+        }
+
+        let mut tags = counts.into_iter().collect::<Vec<_>>();
+        tags.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+        Ok(tags.into_iter().map(|(tag, _)| tag.to_string()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_user(name: &str) -> NewUser {
+        NewUser {
+            username: name.to_string(),
+            email: format!("{name}@example.com"),
+            password_hash: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn revisions_outlive_their_editor() {
+        let store = MemoryStore::<i32>::default();
+        let author = UserRepo::create(&store, new_user("author")).await.unwrap();
+        let editor = UserRepo::create(&store, new_user("editor")).await.unwrap();
+        let article = ArticleRepo::create(
+            &store,
+            author.id,
+            NewArticle {
+                slug: "shared".to_string(),
+                title: "Shared".to_string(),
+                description: "Two hands".to_string(),
+                body: "First".to_string(),
+                tag_list: Vec::new(),
+                status: ArticleStatus::Published,
+            },
+        )
+        .await
+        .unwrap();
+        let changes = ArticleChanges {
+            body: Some("Second".to_string()),
+            ..Default::default()
+        };
+        ArticleRepo::update(&store, article.id, changes, Some(editor.id))
+            .await
+            .unwrap();
+
+        UserRepo::delete(&store, editor.id).await.unwrap();
+
+        let revisions = RevisionRepo::list(&store, article.id, None).await.unwrap();
+        let authors = revisions
+            .iter()
+            .map(|revision| revision.author.as_ref().map(|author| author.id))
+            .collect::<Vec<_>>();
+        assert_eq!(authors, [None, Some(author.id)]);
+        assert_eq!(revisions[0].body, "Second");
+    }
+}
@@ -0,0 +1,1021 @@
+//! Storage-agnostic repositories.
+//!
+//! Handlers only ever talk to the traits in this module. Each backend ships a Postgres
+//! implementation next to its schema, and [`MemoryStore`] implements every trait on top
+//! of plain collections so a server can run (and be tested) without a database.
+
+mod memory;
+
+pub use memory::MemoryStore;
+
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    access_token::Scope,
+    account::TokenPurpose,
+    article::{
+        ArticleListResponse, ArticleResponse, ArticleResponseInner, ArticleSearchHit,
+        ArticleSearchResponse, ArticleStatus,
+    },
+    comment::{CommentListResponse, CommentResponse, CommentResponseInner},
+    mail::{Mail, QueuedMail},
+    oidc::PendingLogin,
+    policy::Role,
+    profile::{ProfileResponse, ProfileResponseInner},
+    revision::{RevisionListResponse, RevisionResponse, RevisionResponseInner},
+    search::SearchQuery,
+    user::{UserResponse, UserResponseInner},
+};
+
+pub type RepoResult<T> = Result<T, RepoError>;
+
+#[derive(Error, Debug)]
+pub enum RepoError {
+    #[error("Record not found")]
+    NotFound,
+
+    /// A uniqueness constraint was violated. Carries the name of the offending field.
+    #[error("{0} has already been taken")]
+    Conflict(String),
+
+    #[error("Storage error: {0}")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl RepoError {
+    pub fn backend<E>(error: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        RepoError::Backend(Box::new(error))
+    }
+}
+
+/// Primary key type of a backend. The sqlx backend uses `SERIAL` ids, the diesel one UUIDs.
+pub trait Identifier:
+    Copy + Eq + Ord + Hash + Debug + Display + FromStr + Send + Sync + 'static
+{
+    /// Produces the id for the `seq`th row created by the in-memory store.
+    fn generate(seq: u64) -> Self;
+}
+
+impl Identifier for i32 {
+    fn generate(seq: u64) -> Self {
+        seq as i32
+    }
+}
+
+impl Identifier for Uuid {
+    fn generate(_: u64) -> Self {
+        Uuid::new_v4()
+    }
+}
+
+// ================================== Records ================================== //
+
+#[derive(Debug, Clone)]
+pub struct User<I> {
+    pub id: I,
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub email_verified: bool,
+    pub role: Role,
+}
+
+impl<I> User<I> {
+    pub fn into_response(self, token: String) -> UserResponse {
+        UserResponseInner {
+            token,
+            email: self.email,
+            username: self.username,
+            bio: self.bio,
+            image: self.image,
+            email_verified: self.email_verified,
+            role: self.role,
+            refresh_token: None,
+        }
+        .into()
+    }
+}
+
+/// A second factor, see [`crate::totp`].
+#[derive(Debug, Clone)]
+pub struct Totp {
+    pub secret: String,
+    /// Only once a code generated from `secret` was confirmed.
+    pub enabled: bool,
+    /// The most recent time step a code was accepted for.
+    pub last_step: Option<i64>,
+}
+
+/// Failed logins counted under one key, see [`crate::lockout`].
+#[derive(Debug, Clone)]
+pub struct LoginAttempts {
+    pub failures: i32,
+    pub last_failed_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// A login on one device, kept alive by refreshing it.
+#[derive(Debug, Clone)]
+pub struct Session<I> {
+    pub id: I,
+    pub user_id: I,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A personal access token, see [`crate::access_token`]. Only its hash is stored.
+#[derive(Debug, Clone)]
+pub struct AccessToken<I> {
+    pub id: I,
+    pub user_id: I,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewUser {
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UserChanges {
+    pub username: Option<String>,
+    /// A new address is unverified, unless `email_verified` says otherwise.
+    pub email: Option<String>,
+    pub password_hash: Option<String>,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub email_verified: Option<bool>,
+    pub role: Option<Role>,
+}
+
+/// One page of users, by username.
+#[derive(Debug, Clone)]
+pub struct UserList<I> {
+    pub users: Vec<User<I>>,
+    /// Every user, regardless of the page.
+    pub total: i64,
+}
+
+/// Everything stored about a user that is theirs, see [`crate::privacy`].
+#[derive(Debug, Clone)]
+pub struct PersonalData<I> {
+    pub user: User<I>,
+    /// Slugs of every article they wrote, drafts and hidden ones included, oldest first.
+    pub articles: Vec<String>,
+    /// Hidden ones included, oldest first.
+    pub comments: Vec<AuthoredComment>,
+    /// Slugs of the articles they favorited.
+    pub favorites: Vec<String>,
+    /// Usernames of the users they follow.
+    pub following: Vec<String>,
+    /// Usernames of the users following them.
+    pub followers: Vec<String>,
+}
+
+/// A comment, as exported for its author.
+#[derive(Debug, Clone)]
+pub struct AuthoredComment {
+    pub id: i32,
+    /// Slug of the article commented on.
+    pub article: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A user as seen by `viewer`.
+#[derive(Debug, Clone)]
+pub struct Profile<I> {
+    pub id: I,
+    pub username: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub following: bool,
+}
+
+impl<I> From<Profile<I>> for ProfileResponseInner {
+    fn from(profile: Profile<I>) -> Self {
+        ProfileResponseInner {
+            username: profile.username,
+            bio: profile.bio,
+            image: profile.image,
+            following: profile.following,
+        }
+    }
+}
+
+impl<I> From<Profile<I>> for ProfileResponse {
+    fn from(profile: Profile<I>) -> Self {
+        ProfileResponseInner::from(profile).into()
+    }
+}
+
+/// An article as seen by `viewer`, with its author, tags and favorite state resolved.
+#[derive(Debug, Clone)]
+pub struct Article<I> {
+    pub id: I,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub tag_list: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub favorited: bool,
+    pub favorites_count: i64,
+    pub author: Profile<I>,
+    pub status: ArticleStatus,
+    /// When the article was last published. Unset while it is a draft.
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl<I> From<Article<I>> for ArticleResponseInner {
+    fn from(article: Article<I>) -> Self {
+        ArticleResponseInner {
+            slug: article.slug,
+            title: article.title,
+            description: article.description,
+            body: article.body,
+            tag_list: article.tag_list,
+            created_at: article.created_at.into(),
+            updated_at: article.updated_at.into(),
+            favorited: article.favorited,
+            favorites_count: article.favorites_count,
+            author: article.author.into(),
+            status: article.status,
+            published_at: article.published_at.map(Into::into),
+        }
+    }
+}
+
+impl<I> From<Article<I>> for ArticleResponse {
+    fn from(article: Article<I>) -> Self {
+        ArticleResponseInner::from(article).into()
+    }
+}
+
+/// One page of articles.
+#[derive(Debug, Clone)]
+pub struct ArticleList<I> {
+    pub articles: Vec<Article<I>>,
+    /// Every article matching the filters, regardless of the page.
+    pub total: i64,
+    pub next_cursor: Option<Cursor<I>>,
+}
+
+impl<I: Identifier> ArticleList<I> {
+    /// Builds a page from up to [`Page::fetch_limit`] rows, in list order. In keyset mode,
+    /// the extra row only tells that another page follows and is dropped.
+    pub fn new(mut articles: Vec<Article<I>>, total: i64, page: &Page<I>) -> Self {
+        let next_cursor = match page {
+            Page::Keyset { limit, .. } if articles.len() > *limit => {
+                articles.truncate(*limit);
+                articles.last().map(Cursor::of)
+            }
+            _ => None,
+        };
+
+        Self {
+            articles,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+impl<I: Identifier> From<ArticleList<I>> for ArticleListResponse {
+    fn from(list: ArticleList<I>) -> Self {
+        ArticleListResponse {
+            articles: list
+                .articles
+                .into_iter()
+                .map(ArticleResponseInner::from)
+                .collect(),
+            articles_count: list.total,
+            next_cursor: list.next_cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit<I> {
+    pub article: Article<I>,
+    pub snippet: String,
+}
+
+/// One page of search hits, most relevant first.
+#[derive(Debug, Clone)]
+pub struct SearchResults<I> {
+    pub hits: Vec<SearchHit<I>>,
+    /// Every matching article, regardless of the page.
+    pub total: i64,
+}
+
+impl<I> From<SearchResults<I>> for ArticleSearchResponse {
+    fn from(results: SearchResults<I>) -> Self {
+        ArticleSearchResponse {
+            articles: results
+                .hits
+                .into_iter()
+                .map(|hit| ArticleSearchHit {
+                    article: hit.article.into(),
+                    snippet: hit.snippet,
+                })
+                .collect(),
+            articles_count: results.total,
+        }
+    }
+}
+
+/// Whether an article at `slug` can keep it when its title slugifies to `base`, i.e. `slug` is
+/// `base` itself or `base` with a numeric suffix.
+pub fn slug_matches(slug: &str, base: &str) -> bool {
+    match slug.strip_prefix(base) {
+        Some("") => true,
+        Some(suffix) => suffix
+            .strip_prefix('-')
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())),
+        None => false,
+    }
+}
+
+/// `base` if nobody has it, otherwise the first of `base-2`, `base-3`... that is free.
+pub fn available_slug(base: &str, taken: &[String]) -> String {
+    let free = |slug: &String| !taken.contains(slug);
+
+    std::iter::once(base.to_string())
+        .chain((2..).map(|n| format!("{base}-{n}")))
+        .find(free)
+        .expect("there are always free slugs")
+}
+
+#[derive(Debug, Clone)]
+pub struct NewArticle {
+    /// Made unique with a numeric suffix if another article has, or had, it.
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub tag_list: Vec<String>,
+    pub status: ArticleStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArticleChanges {
+    /// The slug the new title asks for. Kept if [`slug_matches`] the current one, made unique
+    /// like [`NewArticle::slug`] otherwise. The old slug then redirects to the new one.
+    pub slug: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub body: Option<String>,
+    pub tag_list: Option<Vec<String>>,
+    /// Publishing sets `published_at` unless the article was already published, going back to
+    /// a draft clears it.
+    pub status: Option<ArticleStatus>,
+}
+
+impl ArticleChanges {
+    /// The content fields these changes actually modify, compared with the current ones.
+    pub fn changed_fields(&self, title: &str, description: &str, body: &str) -> Vec<String> {
+        let changes = [&self.title, &self.description, &self.body];
+        let current = [title, description, body];
+
+        REVISED_FIELDS
+            .iter()
+            .zip(changes.into_iter().zip(current))
+            .filter(|(_, (new, current))| new.as_deref().is_some_and(|new| new != *current))
+            .map(|(field, _)| field.to_string())
+            .collect()
+    }
+}
+
+/// The fields a revision keeps track of. A new article's first revision changes all of them.
+pub const REVISED_FIELDS: [&str; 3] = ["title", "description", "body"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ArticleFilter {
+    pub tag: Option<String>,
+    pub author: Option<String>,
+    pub favorited: Option<String>,
+}
+
+/// Articles are listed newest first, ties broken by the highest id.
+#[derive(Debug, Clone, Copy)]
+pub enum Page<I> {
+    Offset {
+        limit: usize,
+        offset: usize,
+    },
+    /// Starts right after `after`, or at the newest article.
+    Keyset {
+        limit: usize,
+        after: Option<Cursor<I>>,
+    },
+}
+
+impl<I: Identifier> Page<I> {
+    pub fn new(limit: usize, offset: usize) -> Self {
+        Page::Offset { limit, offset }
+    }
+
+    pub fn keyset(limit: usize, after: Option<Cursor<I>>) -> Self {
+        Page::Keyset { limit, after }
+    }
+
+    /// Rows to skip before the page starts.
+    pub fn offset(&self) -> usize {
+        match self {
+            Page::Offset { offset, .. } => *offset,
+            Page::Keyset { .. } => 0,
+        }
+    }
+
+    /// Rows to load, one more than the page holds in keyset mode, see [`ArticleList::new`].
+    pub fn fetch_limit(&self) -> usize {
+        match self {
+            Page::Offset { limit, .. } => *limit,
+            Page::Keyset { limit, .. } => limit + 1,
+        }
+    }
+
+    pub fn after(&self) -> Option<Cursor<I>> {
+        match self {
+            Page::Offset { .. } => None,
+            Page::Keyset { after, .. } => *after,
+        }
+    }
+}
+
+/// Position of an article in list order. Opaque to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor<I> {
+    pub created_at: DateTime<Utc>,
+    pub id: I,
+}
+
+impl<I: Identifier> Cursor<I> {
+    pub fn of(article: &Article<I>) -> Self {
+        Self {
+            created_at: article.created_at,
+            id: article.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let created_at = self.created_at.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        URL_SAFE_NO_PAD.encode(format!("{created_at} {}", self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let (created_at, id) = std::str::from_utf8(&bytes).ok()?.split_once(' ')?;
+
+        Some(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at).ok()?.into(),
+            id: id.parse().ok()?,
+        })
+    }
+
+    /// Whether `(created_at, id)` comes after this cursor in list order.
+    pub fn precedes(&self, created_at: DateTime<Utc>, id: I) -> bool {
+        (created_at, id) < (self.created_at, self.id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comment<I> {
+    pub id: i32,
+    pub article_id: I,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub author: Profile<I>,
+}
+
+impl<I> From<Comment<I>> for CommentResponseInner {
+    fn from(comment: Comment<I>) -> Self {
+        CommentResponseInner {
+            id: comment.id,
+            body: comment.body,
+            created_at: comment.created_at.into(),
+            updated_at: comment.updated_at.into(),
+            author: comment.author.into(),
+        }
+    }
+}
+
+impl<I> From<Comment<I>> for CommentResponse {
+    fn from(comment: Comment<I>) -> Self {
+        CommentResponseInner::from(comment).into()
+    }
+}
+
+impl<I> From<Vec<Comment<I>>> for CommentListResponse {
+    fn from(comments: Vec<Comment<I>>) -> Self {
+        comments
+            .into_iter()
+            .map(CommentResponseInner::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+/// An immutable snapshot of an article's content, stored whenever it is created or edited.
+/// Numbered from 1 for each article.
+#[derive(Debug, Clone)]
+pub struct Revision<I> {
+    pub article_id: I,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub changed_fields: Vec<String>,
+    /// `None` once the editor has deleted their account; the revision outlives them.
+    pub author: Option<Profile<I>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<I> From<Revision<I>> for RevisionResponseInner {
+    fn from(revision: Revision<I>) -> Self {
+        RevisionResponseInner {
+            number: revision.number,
+            title: revision.title,
+            description: revision.description,
+            body: revision.body,
+            changed_fields: revision.changed_fields,
+            author: revision.author.map(Into::into),
+            created_at: revision.created_at.into(),
+        }
+    }
+}
+
+impl<I> From<Revision<I>> for RevisionResponse {
+    fn from(revision: Revision<I>) -> Self {
+        RevisionResponseInner::from(revision).into()
+    }
+}
+
+impl<I> From<Vec<Revision<I>>> for RevisionListResponse {
+    fn from(revisions: Vec<Revision<I>>) -> Self {
+        revisions
+            .into_iter()
+            .map(RevisionResponseInner::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+// ================================== Repositories ================================== //
+
+#[async_trait]
+pub trait UserRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Fails with [`RepoError::Conflict`] when the username or email is taken, ignoring case.
+    async fn create(&self, user: NewUser) -> RepoResult<User<Self::Id>>;
+    async fn find(&self, id: Self::Id) -> RepoResult<User<Self::Id>>;
+    /// Emails are unique ignoring case, and found that way too.
+    async fn find_by_email(&self, email: &str) -> RepoResult<User<Self::Id>>;
+    async fn update(&self, id: Self::Id, changes: UserChanges) -> RepoResult<User<Self::Id>>;
+    async fn profile(
+        &self,
+        username: &str,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Profile<Self::Id>>;
+    async fn list(&self, limit: usize, offset: usize) -> RepoResult<UserList<Self::Id>>;
+    async fn personal_data(&self, id: Self::Id) -> RepoResult<PersonalData<Self::Id>>;
+    /// Replaces the name, email and password with those of `replacement`, and deletes the
+    /// profile, follows, favorites, drafts and every way to log in, in one transaction.
+    /// Published articles and comments are kept.
+    async fn anonymize(&self, id: Self::Id, replacement: NewUser) -> RepoResult<()>;
+    /// Deletes the user along with everything they wrote, follows and favorites. Their
+    /// revisions of other people's articles are kept without an author.
+    async fn delete(&self, id: Self::Id) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait SessionRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Starts a session for `user` whose first refresh token hashes to `token_hash`.
+    async fn create(
+        &self,
+        user: Self::Id,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<Session<Self::Id>>;
+    /// Revoked sessions are not found.
+    async fn find(&self, id: Self::Id) -> RepoResult<Session<Self::Id>>;
+    /// Swaps the refresh token hashing to `token_hash` for one hashing to `new_hash`. Unknown,
+    /// expired and revoked tokens are not found, and a token that was already swapped out
+    /// revokes its session.
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<Session<Self::Id>>;
+    /// Revoking twice is a no-op.
+    async fn revoke(&self, id: Self::Id) -> RepoResult<()>;
+    /// Revokes every session of `user`.
+    async fn revoke_all(&self, user: Self::Id) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait AccountTokenRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Stores a single-use token for `user` and queues the `mail` delivering it, in one
+    /// transaction. Earlier tokens of `user` for the same purpose stop working.
+    async fn issue(
+        &self,
+        user: Self::Id,
+        purpose: TokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        mail: Mail,
+    ) -> RepoResult<()>;
+    /// Uses up the token hashing to `token_hash`, returning its user. Unknown, used and expired
+    /// tokens, and tokens for another purpose, are not found.
+    async fn redeem(&self, purpose: TokenPurpose, token_hash: &str) -> RepoResult<Self::Id>;
+}
+
+#[async_trait]
+pub trait AccessTokenRepo: Send + Sync {
+    type Id: Identifier;
+
+    async fn create(
+        &self,
+        user: Self::Id,
+        name: &str,
+        scopes: &[Scope],
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<AccessToken<Self::Id>>;
+    /// The token hashing to `token_hash`, recording that it was used. Unknown, expired and
+    /// revoked tokens are not found.
+    async fn authenticate(&self, token_hash: &str) -> RepoResult<AccessToken<Self::Id>>;
+    /// Tokens of `user` that weren't revoked, newest first. Expired ones are listed too.
+    async fn list(&self, user: Self::Id) -> RepoResult<Vec<AccessToken<Self::Id>>>;
+    /// Tokens of other users, and revoked ones, are not found.
+    async fn revoke(&self, user: Self::Id, id: Self::Id) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait TotpRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Stores a secret for `user` to confirm, replacing one that wasn't. Fails with
+    /// [`RepoError::Conflict`] once a secret is enabled.
+    async fn enroll(&self, user: Self::Id, secret: &str) -> RepoResult<()>;
+    async fn find(&self, user: Self::Id) -> RepoResult<Totp>;
+    /// Enables the pending secret, confirmed with a code for `step`, and replaces the recovery
+    /// codes of `user`. Not found if nothing is pending.
+    async fn enable(
+        &self,
+        user: Self::Id,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> RepoResult<()>;
+    /// Records that a code for `step` was used. Not found if that or a later step was used
+    /// already, or nothing is enabled.
+    async fn use_step(&self, user: Self::Id, step: i64) -> RepoResult<()>;
+    /// Uses up the recovery code hashing to `code_hash`. Unknown and used codes are not found.
+    async fn use_recovery_code(&self, user: Self::Id, code_hash: &str) -> RepoResult<()>;
+    /// Whether the login challenge `id` was spent, see [`TotpRepo::spend_challenge`].
+    async fn challenge_spent(&self, id: &str) -> RepoResult<bool>;
+    /// Counts a wrong code given with the login challenge `id`, and answers how many there are.
+    async fn fail_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<i32>;
+    /// Spends the login challenge `id` for good. Fails with [`RepoError::Conflict`] if it was
+    /// spent already, so only one of concurrent redemptions gets through. Challenges that
+    /// expired are forgotten.
+    async fn spend_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<()>;
+}
+
+/// Logins with OpenID Connect providers, see [`crate::oidc`].
+#[async_trait]
+pub trait OidcRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Remembers a login sent to a provider with the state hashing to `state_hash`.
+    async fn start_login(
+        &self,
+        state_hash: &str,
+        login: PendingLogin,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<()>;
+    /// Uses up the login with the state hashing to `state_hash`. Unknown, finished and expired
+    /// logins are not found.
+    async fn finish_login(&self, state_hash: &str) -> RepoResult<PendingLogin>;
+    /// The user the identity `subject` at `provider` is linked to.
+    async fn find_identity(&self, provider: &str, subject: &str) -> RepoResult<Self::Id>;
+    /// Fails with [`RepoError::Conflict`] if the identity is linked already.
+    async fn link_identity(&self, user: Self::Id, provider: &str, subject: &str) -> RepoResult<()>;
+}
+
+/// Failed logins, by the account or address they were made for, see [`crate::lockout`].
+#[async_trait]
+pub trait LoginAttemptRepo: Send + Sync {
+    async fn find(&self, key: &str) -> RepoResult<LoginAttempts>;
+    /// Counts a failure and answers how many there are. Starts over if neither the last failure
+    /// nor a lockout was after `stale_before`.
+    async fn fail(&self, key: &str, stale_before: DateTime<Utc>) -> RepoResult<i32>;
+    /// Refuses logins for `key` until `until`, recording a lockout event. Unknown keys are
+    /// counted with `failures`.
+    async fn lock(&self, key: &str, failures: i32, until: DateTime<Utc>) -> RepoResult<()>;
+    /// Forgets failures and lifts a lockout. Clearing an unknown key is a no-op.
+    async fn clear(&self, key: &str) -> RepoResult<()>;
+}
+
+/// Mail waiting to be delivered, see [`crate::mail`].
+#[async_trait]
+pub trait OutboxRepo: Send + Sync {
+    /// Claims up to `limit` undelivered messages that are due and have attempts left, counting
+    /// an attempt for each. They are not due again before `lease_until`, so no other dispatcher
+    /// picks them up in the meantime.
+    async fn claim(&self, limit: i64, lease_until: DateTime<Utc>) -> RepoResult<Vec<QueuedMail>>;
+    async fn delivered(&self, id: i64) -> RepoResult<()>;
+    async fn failed(&self, id: i64, error: &str, retry_at: DateTime<Utc>) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait FollowRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Following someone twice is a no-op.
+    async fn follow(&self, follower: Self::Id, followee: Self::Id) -> RepoResult<()>;
+    async fn unfollow(&self, follower: Self::Id, followee: Self::Id) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait ArticleRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Stores the first revision. Fails with [`RepoError::Conflict`] only if a concurrent
+    /// request took the same slug.
+    async fn create(&self, author: Self::Id, article: NewArticle) -> RepoResult<Article<Self::Id>>;
+    /// Drafts and hidden articles are only found for their author.
+    async fn find_by_slug(
+        &self,
+        slug: &str,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Article<Self::Id>>;
+    /// The current slug of the article that used to be at `slug`.
+    async fn renamed(&self, slug: &str) -> RepoResult<String>;
+    /// Published articles only, most recent first.
+    async fn list(
+        &self,
+        filter: &ArticleFilter,
+        page: Page<Self::Id>,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<ArticleList<Self::Id>>;
+    /// Published articles written by the users `viewer` follows, most recent first.
+    async fn feed(
+        &self,
+        viewer: Self::Id,
+        page: Page<Self::Id>,
+    ) -> RepoResult<ArticleList<Self::Id>>;
+    /// Published articles only, most relevant first. See [`crate::search`] for the query syntax.
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        page: Page<Self::Id>,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<SearchResults<Self::Id>>;
+    /// A `tag_list` of `Some` replaces every tag on the article. Stores a revision authored by
+    /// `viewer` (the article's author if unset) when the title, description or body change.
+    async fn update(
+        &self,
+        id: Self::Id,
+        changes: ArticleChanges,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Article<Self::Id>>;
+    async fn delete(&self, id: Self::Id) -> RepoResult<()>;
+    /// Hides the article at `slug` from everyone but its author, lists and search included, or
+    /// shows it again. Unlike [`ArticleRepo::find_by_slug`], finds articles however hidden.
+    async fn set_hidden(&self, slug: &str, hidden: bool) -> RepoResult<()>;
+    /// Favoriting twice is a no-op.
+    async fn favorite(&self, id: Self::Id, user: Self::Id) -> RepoResult<()>;
+    async fn unfavorite(&self, id: Self::Id, user: Self::Id) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait CommentRepo: Send + Sync {
+    type Id: Identifier;
+
+    async fn create(
+        &self,
+        article: Self::Id,
+        author: Self::Id,
+        body: String,
+    ) -> RepoResult<Comment<Self::Id>>;
+    /// Finds hidden comments too.
+    async fn find(&self, id: i32) -> RepoResult<Comment<Self::Id>>;
+    /// Most recent first. Hidden comments are only listed for their author.
+    async fn list(
+        &self,
+        article: Self::Id,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Vec<Comment<Self::Id>>>;
+    async fn delete(&self, id: i32) -> RepoResult<()>;
+    /// Hides the comment from everyone but its author, or shows it again.
+    async fn set_hidden(&self, id: i32, hidden: bool) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait RevisionRepo: Send + Sync {
+    type Id: Identifier;
+
+    /// Most recent first.
+    async fn list(
+        &self,
+        article: Self::Id,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Vec<Revision<Self::Id>>>;
+    async fn find(
+        &self,
+        article: Self::Id,
+        number: i32,
+        viewer: Option<Self::Id>,
+    ) -> RepoResult<Revision<Self::Id>>;
+}
+
+#[async_trait]
+pub trait TagRepo: Send + Sync {
+    /// Every tag in use, most popular first.
+    async fn list(&self) -> RepoResult<Vec<String>>;
+}
+
+/// Every repository a server needs, behind trait objects so storage can be chosen at startup.
+pub struct Repos<I> {
+    pub users: Arc<dyn UserRepo<Id = I>>,
+    pub sessions: Arc<dyn SessionRepo<Id = I>>,
+    pub account_tokens: Arc<dyn AccountTokenRepo<Id = I>>,
+    pub access_tokens: Arc<dyn AccessTokenRepo<Id = I>>,
+    pub totp: Arc<dyn TotpRepo<Id = I>>,
+    pub oidc: Arc<dyn OidcRepo<Id = I>>,
+    pub login_attempts: Arc<dyn LoginAttemptRepo>,
+    pub outbox: Arc<dyn OutboxRepo>,
+    pub follows: Arc<dyn FollowRepo<Id = I>>,
+    pub articles: Arc<dyn ArticleRepo<Id = I>>,
+    pub comments: Arc<dyn CommentRepo<Id = I>>,
+    pub revisions: Arc<dyn RevisionRepo<Id = I>>,
+    pub tags: Arc<dyn TagRepo>,
+}
+
+impl<I> Clone for Repos<I> {
+    fn clone(&self) -> Self {
+        Self {
+            users: self.users.clone(),
+            sessions: self.sessions.clone(),
+            account_tokens: self.account_tokens.clone(),
+            access_tokens: self.access_tokens.clone(),
+            totp: self.totp.clone(),
+            oidc: self.oidc.clone(),
+            login_attempts: self.login_attempts.clone(),
+            outbox: self.outbox.clone(),
+            follows: self.follows.clone(),
+            articles: self.articles.clone(),
+            comments: self.comments.clone(),
+            revisions: self.revisions.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+impl<I: Identifier> Repos<I> {
+    /// Uses a single store for every repository.
+    pub fn from_store<S>(store: S) -> Self
+    where
+        S: UserRepo<Id = I>
+            + SessionRepo<Id = I>
+            + AccountTokenRepo<Id = I>
+            + AccessTokenRepo<Id = I>
+            + TotpRepo<Id = I>
+            + OidcRepo<Id = I>
+            + LoginAttemptRepo
+            + OutboxRepo
+            + FollowRepo<Id = I>
+            + ArticleRepo<Id = I>
+            + CommentRepo<Id = I>
+            + RevisionRepo<Id = I>
+            + TagRepo
+            + 'static,
+    {
+        let store = Arc::new(store);
+
+        Self {
+            users: store.clone(),
+            sessions: store.clone(),
+            account_tokens: store.clone(),
+            access_tokens: store.clone(),
+            totp: store.clone(),
+            oidc: store.clone(),
+            login_attempts: store.clone(),
+            outbox: store.clone(),
+            follows: store.clone(),
+            articles: store.clone(),
+            comments: store.clone(),
+            revisions: store.clone(),
+            tags: store,
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::from_store(MemoryStore::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(nanos: u32) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000, nanos).unwrap()
+    }
+
+    #[test]
+    fn cursors_survive_encoding() {
+        let cursor = Cursor {
+            created_at: at(123_456_789),
+            id: 42,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+
+        let cursor = Cursor {
+            created_at: at(0),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+    }
+
+    #[test]
+    fn malformed_cursors_are_none() {
+        let encode = |text: &str| URL_SAFE_NO_PAD.encode(text);
+        for cursor in [
+            String::new(),
+            "not base64!".to_string(),
+            encode("2023-11-14T22:13:20Z"),
+            encode("yesterday 42"),
+            encode("2023-11-14T22:13:20Z forty-two"),
+            URL_SAFE_NO_PAD.encode([0xff, 0x20, 0x34]),
+        ] {
+            assert_eq!(Cursor::<i32>::decode(&cursor), None, "{cursor:?}");
+        }
+        // A cursor of the other backend
+        let uuid = Cursor {
+            created_at: at(0),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::<i32>::decode(&uuid.encode()), None);
+    }
+
+    #[test]
+    fn cursors_precede_older_articles_and_lower_ids() {
+        let cursor = Cursor {
+            created_at: at(500),
+            id: 10,
+        };
+        assert!(cursor.precedes(at(499), 99));
+        assert!(cursor.precedes(at(500), 9));
+        assert!(!cursor.precedes(at(500), 10));
+        assert!(!cursor.precedes(at(501), 1));
+    }
+
+    #[test]
+    fn keyset_pages_fetch_one_more_row_than_they_hold() {
+        let after = Some(Cursor {
+            created_at: at(0),
+            id: 1,
+        });
+        assert_eq!(Page::<i32>::new(20, 40).fetch_limit(), 20);
+        assert_eq!(Page::<i32>::new(20, 40).offset(), 40);
+        assert_eq!(Page::<i32>::new(20, 40).after(), None);
+        assert_eq!(Page::keyset(20, after).fetch_limit(), 21);
+        assert_eq!(Page::keyset(20, after).offset(), 0);
+        assert_eq!(Page::keyset(20, after).after(), after);
+    }
+}
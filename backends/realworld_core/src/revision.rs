@@ -0,0 +1,81 @@
+use serde::Serialize;
+
+use crate::{
+    diff::{self, DiffLine},
+    profile::ProfileResponseInner,
+    repo::Revision,
+    CustomDateTime,
+};
+
+// ================================== JSON response objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct RevisionResponse {
+    pub revision: RevisionResponseInner,
+}
+
+/// The content of an article right after an edit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionResponseInner {
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    /// Which of `title`, `description` and `body` the edit changed.
+    pub changed_fields: Vec<String>,
+    /// `null` once the editor has deleted their account.
+    pub author: Option<ProfileResponseInner>,
+    pub created_at: CustomDateTime,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionListResponse {
+    pub revisions: Vec<RevisionResponseInner>,
+    pub revisions_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiffResponse {
+    pub diff: RevisionDiff,
+}
+
+/// What changed from revision `from` to revision `to`, line by line.
+#[derive(Debug, Serialize)]
+pub struct RevisionDiff {
+    pub from: i32,
+    pub to: i32,
+    pub title: Vec<DiffLine>,
+    pub description: Vec<DiffLine>,
+    pub body: Vec<DiffLine>,
+}
+
+impl From<RevisionResponseInner> for RevisionResponse {
+    fn from(revision: RevisionResponseInner) -> Self {
+        Self { revision }
+    }
+}
+
+impl From<Vec<RevisionResponseInner>> for RevisionListResponse {
+    fn from(revisions: Vec<RevisionResponseInner>) -> Self {
+        Self {
+            revisions_count: revisions.len(),
+            revisions,
+        }
+    }
+}
+
+impl RevisionDiffResponse {
+    pub fn between<I>(from: &Revision<I>, to: &Revision<I>) -> Self {
+        Self {
+            diff: RevisionDiff {
+                from: from.number,
+                to: to.number,
+                title: diff::lines(&from.title, &to.title),
+                description: diff::lines(&from.description, &to.description),
+                body: diff::lines(&from.body, &to.body),
+            },
+        }
+    }
+}
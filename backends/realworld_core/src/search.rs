@@ -0,0 +1,246 @@
+//! Article search queries.
+//!
+//! A query is a list of terms that must all match an article's title, description or body:
+//!
+//! - `dragon` matches the word. Postgres also matches other forms of it, e.g. `dragons`.
+//! - `drag*` matches every word starting with `drag`.
+//! - `"train your dragon"` matches the words next to each other, in that order.
+//!
+//! Anything but letters and digits separates words, so queries can't inject tsquery syntax.
+//! The in-memory store compares words exactly though, so only `dragon*` finds `dragons` there.
+//!
+//! Snippets are HTML: the body is escaped as by [`escape_html`], and only the `<mark>` tags
+//! around matches are markup. Postgres escapes it before `ts_headline`, whose parser keeps
+//! entities whole.
+
+/// Marks the matches in a snippet.
+pub const SNIPPET_START: &str = "<mark>";
+pub const SNIPPET_STOP: &str = "</mark>";
+/// Longest snippet, in words.
+pub const SNIPPET_WORDS: usize = 35;
+
+/// Options of `ts_headline` producing the same snippets as [`SearchQuery::snippet`].
+pub const HEADLINE_OPTIONS: &str = "StartSel=<mark>, StopSel=</mark>, MaxWords=35, MinWords=15";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    terms: Vec<Term>,
+}
+
+impl SearchQuery {
+    /// `None` if `q` holds no words to search for.
+    pub fn parse(q: &str) -> Option<Self> {
+        let mut terms = Vec::new();
+
+        // Every other part is quoted, an unterminated quote runs to the end
+        for (n, part) in q.split('"').enumerate() {
+            if n % 2 == 1 {
+                let mut phrase = words(part);
+                match phrase.len() {
+                    0 => {}
+                    1 => terms.extend(phrase.pop().map(Term::Word)),
+                    _ => terms.push(Term::Phrase(phrase)),
+                }
+                continue;
+            }
+
+            for raw in part.split_whitespace() {
+                let mut words = words(raw);
+                // Only the last word of `foo-bar*` is a prefix
+                let prefix = match raw.ends_with('*') {
+                    true => words.pop().map(Term::Prefix),
+                    false => None,
+                };
+
+                terms.extend(words.into_iter().map(Term::Word));
+                terms.extend(prefix);
+            }
+        }
+
+        (!terms.is_empty()).then_some(Self { terms })
+    }
+
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    /// The query in `to_tsquery` syntax.
+    pub fn to_tsquery(&self) -> String {
+        self.terms
+            .iter()
+            .map(|term| match term {
+                Term::Word(word) => word.clone(),
+                Term::Prefix(prefix) => format!("{prefix}:*"),
+                Term::Phrase(words) => format!("({})", words.join(" <-> ")),
+            })
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+
+    /// Whether every term occurs in `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let words = words(text);
+        self.terms.iter().all(|term| term.occurs_in(&words))
+    }
+
+    /// How many terms occur in `text`.
+    pub fn hits(&self, text: &str) -> usize {
+        let words = words(text);
+        self.terms
+            .iter()
+            .filter(|term| term.occurs_in(&words))
+            .count()
+    }
+
+    /// Up to [`SNIPPET_WORDS`] words of `text` around the first match, escaped, with every
+    /// matching word marked. Starts at the beginning of `text` if nothing matches.
+    pub fn snippet(&self, text: &str) -> String {
+        let tokens = text.split_whitespace().collect::<Vec<_>>();
+        let marked = tokens
+            .iter()
+            .map(|token| words(token).iter().any(|word| self.highlights(word)))
+            .collect::<Vec<_>>();
+
+        let first = marked.iter().position(|marked| *marked).unwrap_or(0);
+        let start = first.saturating_sub(SNIPPET_WORDS / 4);
+
+        tokens
+            .iter()
+            .zip(marked)
+            .skip(start)
+            .take(SNIPPET_WORDS)
+            .map(|(token, marked)| match marked {
+                true => format!("{SNIPPET_START}{}{SNIPPET_STOP}", escape_html(token)),
+                false => escape_html(token),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn highlights(&self, word: &str) -> bool {
+        self.terms.iter().any(|term| match term {
+            Term::Word(term) => term == word,
+            Term::Prefix(prefix) => word.starts_with(prefix.as_str()),
+            Term::Phrase(words) => words.iter().any(|term| term == word),
+        })
+    }
+}
+
+impl Term {
+    fn occurs_in(&self, words: &[String]) -> bool {
+        match self {
+            Term::Word(term) => words.contains(term),
+            Term::Prefix(prefix) => words.iter().any(|word| word.starts_with(prefix.as_str())),
+            Term::Phrase(phrase) => words.windows(phrase.len()).any(|window| window == phrase),
+        }
+    }
+}
+
+/// Escapes `text` for HTML, in elements and quoted attributes alike.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Lowercased runs of letters and digits.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_parse_into_words_prefixes_and_phrases() {
+        let query =
+            SearchQuery::parse(r#"Dragon foo-bar* "train  your dragon" "solo" "unterminated"#)
+                .unwrap();
+        assert_eq!(
+            query.terms(),
+            [
+                Term::Word("dragon".to_string()),
+                Term::Word("foo".to_string()),
+                Term::Prefix("bar".to_string()),
+                Term::Phrase(vec![
+                    "train".to_string(),
+                    "your".to_string(),
+                    "dragon".to_string()
+                ]),
+                Term::Word("solo".to_string()),
+                Term::Word("unterminated".to_string()),
+            ]
+        );
+        assert_eq!(
+            query.to_tsquery(),
+            "dragon & foo & bar:* & (train <-> your <-> dragon) & solo & unterminated"
+        );
+    }
+
+    #[test]
+    fn queries_without_words_are_none() {
+        assert_eq!(SearchQuery::parse(""), None);
+        assert_eq!(SearchQuery::parse(r#"  "" * & | ! <-> "#), None);
+    }
+
+    #[test]
+    fn tsquery_syntax_is_only_a_separator() {
+        let query = SearchQuery::parse("a&b|!c:d <-> (e)").unwrap();
+        assert_eq!(query.to_tsquery(), "a & b & c & d & e");
+    }
+
+    #[test]
+    fn every_term_must_match() {
+        let query = SearchQuery::parse(r#"drag* "your dragon""#).unwrap();
+        assert!(query.matches("How to train your Dragon"));
+        assert!(!query.matches("How to train a dragon"));
+        assert_eq!(query.hits("How to train a dragon"), 1);
+    }
+
+    #[test]
+    fn snippets_mark_matches_and_escape_the_rest() {
+        let query = SearchQuery::parse("dragon").unwrap();
+        assert_eq!(
+            query.snippet(r#"<b>Dragon</b> & "friends" aren't <script>"#),
+            "<mark>&lt;b&gt;Dragon&lt;/b&gt;</mark> &amp; &quot;friends&quot; aren&#39;t \
+             &lt;script&gt;"
+        );
+    }
+
+    #[test]
+    fn snippets_start_shortly_before_the_first_match() {
+        let text = (0..100)
+            .map(|n| format!("w{n}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let snippet = SearchQuery::parse("w50").unwrap().snippet(&text);
+        let words = snippet.split(' ').collect::<Vec<_>>();
+
+        assert_eq!(words.len(), SNIPPET_WORDS);
+        assert_eq!(words[0], format!("w{}", 50 - SNIPPET_WORDS / 4));
+        assert_eq!(words[SNIPPET_WORDS / 4], "<mark>w50</mark>");
+
+        let unmatched = SearchQuery::parse("nothing").unwrap().snippet(&text);
+        assert!(unmatched.starts_with("w0 w1 "), "{unmatched}");
+    }
+}
@@ -0,0 +1,19 @@
+//! Refresh tokens and the sessions they keep alive.
+//!
+//! Logging in starts a session and hands out a short-lived access token, a JWT naming the
+//! session, together with an opaque refresh token, see [`crate::token`].
+//! Every refresh swaps the refresh token for a new one, and presenting a token that was already
+//! swapped out revokes the whole session since the token must have leaked.
+
+use serde::Deserialize;
+use validator::Validate;
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct RefreshTokenData {
+    // Renamed on the field itself so validation errors use the same name
+    #[serde(rename = "refreshToken")]
+    #[validate(length(min = 1, message = "refresh token can't be blank"))]
+    pub refresh_token: String,
+}
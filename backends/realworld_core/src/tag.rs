@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+// ================================== JSON response objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct TagsResponse {
+    pub tags: Vec<String>,
+}
+
+impl From<Vec<String>> for TagsResponse {
+    fn from(tags: Vec<String>) -> Self {
+        Self { tags }
+    }
+}
@@ -0,0 +1,115 @@
+//! Structured logs and request spans.
+//!
+//! Every request runs in a `request` span carrying its method, matched route, correlation id
+//! and, once authenticated, the user id. Events inside the span, including the timings of
+//! repository calls, inherit those fields, so a single `request_id` finds everything one request
+//! did. The id is taken from an incoming `X-Request-Id` header or generated, and echoed on the
+//! response.
+
+use std::{fmt::Display, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::{field, Span};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Incoming ids longer than this are replaced rather than logged.
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, for log pipelines.
+    #[default]
+    Json,
+    /// Human readable lines, for development.
+    Text,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// `EnvFilter` directives, overridden by `RUST_LOG` when set.
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Json,
+            // sqlx logs every statement at info
+            filter: "info,sqlx::query=warn".to_string(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Installs the global subscriber. `log` records are forwarded to it too. Does nothing if
+    /// one is installed already, e.g. by the Shuttle runtime.
+    pub fn init(&self) {
+        let filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(&self.filter))
+            .unwrap_or_else(|_| EnvFilter::new("info"));
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE);
+
+        let _ = match self.format {
+            LogFormat::Json => builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(true)
+                .try_init(),
+            LogFormat::Text => builder.try_init(),
+        };
+    }
+}
+
+/// Keeps an incoming id if it is short and printable, so it cannot forge log lines, and
+/// generates one otherwise.
+pub fn request_id(incoming: Option<&str>) -> String {
+    match incoming {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b)) =>
+        {
+            id.to_string()
+        }
+        _ => Uuid::new_v4().to_string(),
+    }
+}
+
+/// `route` is the matched pattern, e.g. `/api/articles/:slug`, rather than the path, so
+/// requests to one endpoint can be grouped.
+pub fn request_span(method: &str, route: &str, request_id: &str) -> Span {
+    tracing::info_span!(
+        "request",
+        method,
+        route,
+        request_id,
+        user_id = field::Empty,
+        status = field::Empty,
+    )
+}
+
+/// Attaches the authenticated user to the request being handled.
+pub fn record_user(user_id: impl Display) {
+    Span::current().record("user_id", field::display(user_id));
+}
+
+/// Logs the outcome of the request `span` belongs to.
+pub fn finish_request(span: &Span, status: u16, started: Instant) {
+    span.record("status", status);
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    span.in_scope(|| match status {
+        500.. => tracing::error!(status, latency_ms, "request failed"),
+        400.. => tracing::warn!(status, latency_ms, "request rejected"),
+        _ => tracing::info!(status, latency_ms, "request finished"),
+    });
+}
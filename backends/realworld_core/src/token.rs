@@ -0,0 +1,21 @@
+//! Opaque tokens, such as refresh tokens and the single-use tokens mailed to users.
+//!
+//! They are random rather than signed, so only a hash of each is stored and a leaked database
+//! can't be used to log anyone in.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// 256 random bits, URL safe.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// What gets stored in place of `token`. Tokens are random enough that a plain, unsalted digest
+/// can't be brute forced.
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
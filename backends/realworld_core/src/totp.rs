@@ -0,0 +1,449 @@
+//! Two-factor authentication with time-based one-time passwords (RFC 6238).
+//!
+//! Enrolling stores a secret that authenticator apps import through an `otpauth://` URI, and
+//! only takes effect once a code generated from it is confirmed. Confirming hands out
+//! single-use recovery codes for when the authenticator is lost.
+//!
+//! Once enabled, a correct password no longer logs the user in. It is answered with a
+//! short-lived challenge token instead, which has to be exchanged together with a code for a
+//! session. Each time step is accepted only once, so an intercepted code can't be replayed.
+//!
+//! A challenge can be exchanged once, and is used up after a few wrong codes. Wrong codes
+//! also count as failed logins of the account and the address, see `lockout`, so guessing
+//! codes with fresh challenges is as slow as guessing passwords.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, TimeZone, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha1::Sha1;
+use thiserror::Error;
+use validator::Validate;
+
+use crate::{
+    keys::KeyRing,
+    lockout::{self, Attempt, LockoutConfig},
+    repo::{Identifier, RepoError, RepoResult, Repos, TotpRepo, User},
+    token::{hash_token, new_token},
+};
+
+/// Names the account in authenticator apps.
+pub const ISSUER: &str = "RealWorld";
+
+/// Seconds each code is valid for.
+pub const STEP: i64 = 30;
+
+pub const DIGITS: u32 = 6;
+
+/// Steps of clock drift tolerated in either direction.
+const SKEW: i64 = 1;
+
+const RECOVERY_CODES: usize = 10;
+
+/// Keeps challenge tokens from being mistaken for anything else signed by the key ring.
+const CHALLENGE_AUDIENCE: &str = "realworld:totp";
+
+/// Wrong second factors a challenge survives.
+pub const MAX_CHALLENGE_FAILURES: i32 = 3;
+
+// ================================== Codes ================================== //
+
+/// 160 random bits, base32 encoded as authenticator apps expect.
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// What authenticator apps import, usually by scanning it as a QR code.
+pub fn otpauth_uri(account: &str, secret: &str) -> String {
+    let label = format!("{ISSUER}:{account}");
+    format!(
+        "otpauth://totp/{}?secret={secret}&issuer={}&algorithm=SHA1&digits={DIGITS}&period={STEP}",
+        utf8_percent_encode(&label, NON_ALPHANUMERIC),
+        utf8_percent_encode(ISSUER, NON_ALPHANUMERIC),
+    )
+}
+
+/// The time step `at` falls into.
+pub fn step_at(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(STEP)
+}
+
+/// The code for `step`, or `None` if `secret` isn't valid base32.
+pub fn code(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Some(format!(
+        "{:0width$}",
+        value % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// The step `code` was generated for, if it is one of those around `now`.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code = code.trim();
+    let current = step_at(now);
+
+    (current - SKEW..=current + SKEW)
+        .find(|&step| self::code(secret, step).is_some_and(|expected| expected == code))
+}
+
+/// Ten codes of ten base32 characters, grouped in fives for readability.
+pub fn new_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; 7];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+/// What gets stored in place of a recovery code. Case and separators don't matter, since the
+/// code may well have been typed in by hand.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&normalized)
+}
+
+// ================================== Challenges ================================== //
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims<I> {
+    sub: I,
+    aud: String,
+    exp: i64,
+    /// Names the challenge, so it is only redeemed once.
+    jti: String,
+}
+
+/// A token proving that `user` got their password right, good until `expires_at`.
+pub fn issue_challenge<I: Serialize>(
+    keys: &KeyRing,
+    user: I,
+    expires_at: DateTime<Utc>,
+) -> Result<String, JwtError> {
+    keys.encode(&ChallengeClaims {
+        sub: user,
+        aud: CHALLENGE_AUDIENCE.to_string(),
+        exp: expires_at.timestamp(),
+        jti: new_token(),
+    })
+}
+
+/// A challenge token that was signed by the key ring and hasn't expired.
+#[derive(Debug, Clone)]
+pub struct Challenge<I> {
+    pub user: I,
+    pub expires_at: DateTime<Utc>,
+    id: String,
+}
+
+/// Decodes a challenge token. Access tokens are rejected.
+pub fn verify_challenge<I: DeserializeOwned>(
+    keys: &KeyRing,
+    token: &str,
+) -> Result<Challenge<I>, JwtError> {
+    let claims: ChallengeClaims<I> = keys.decode(token)?;
+    if claims.aud != CHALLENGE_AUDIENCE {
+        return Err(JwtErrorKind::InvalidAudience.into());
+    }
+    Ok(Challenge {
+        user: claims.sub,
+        expires_at: Utc
+            .timestamp_opt(claims.exp, 0)
+            .single()
+            .ok_or(JwtErrorKind::ExpiredSignature)?,
+        id: claims.jti,
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum ChallengeError {
+    #[error("the challenge is invalid, expired or used up")]
+    Invalid,
+
+    #[error("the second factor is wrong")]
+    Refused,
+
+    #[error("too many failed logins")]
+    LockedOut(DateTime<Utc>),
+
+    #[error(transparent)]
+    Repo(#[from] RepoError),
+}
+
+// ================================== Flows ================================== //
+
+/// Starts enrolling `user`, replacing any secret that wasn't confirmed. `None` if two-factor
+/// authentication is enabled already.
+pub async fn enroll<I: Identifier>(
+    repo: &dyn TotpRepo<Id = I>,
+    user: I,
+    account: &str,
+) -> RepoResult<Option<TotpEnrollment>> {
+    let secret = new_secret();
+    match repo.enroll(user, &secret).await {
+        Ok(()) => Ok(Some(TotpEnrollment {
+            otpauth_uri: otpauth_uri(account, &secret),
+            secret,
+        })),
+        Err(RepoError::Conflict(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Enables two-factor authentication if `code` matches the pending secret, answering with a
+/// fresh set of recovery codes. `None` if it doesn't, or nothing is pending.
+pub async fn confirm<I: Identifier>(
+    repo: &dyn TotpRepo<Id = I>,
+    user: I,
+    code: &str,
+) -> RepoResult<Option<RecoveryCodes>> {
+    let totp = match repo.find(user).await {
+        Ok(totp) if !totp.enabled => totp,
+        Ok(_) | Err(RepoError::NotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let Some(step) = verify(&totp.secret, code, Utc::now()) else {
+        return Ok(None);
+    };
+
+    let recovery_codes = new_recovery_codes();
+    let hashes = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    match repo.enable(user, step, hashes).await {
+        Ok(()) => Ok(Some(RecoveryCodes { recovery_codes })),
+        Err(RepoError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn is_enabled<I: Identifier>(repo: &dyn TotpRepo<Id = I>, user: I) -> RepoResult<bool> {
+    match repo.find(user).await {
+        Ok(totp) => Ok(totp.enabled),
+        Err(RepoError::NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `login` carries a code or recovery code `user` hasn't used before.
+pub async fn second_factor<I: Identifier>(
+    repo: &dyn TotpRepo<Id = I>,
+    user: I,
+    login: &TotpLogin,
+) -> RepoResult<bool> {
+    let used = match (&login.code, &login.recovery_code) {
+        (Some(code), _) => {
+            let totp = match repo.find(user).await {
+                Ok(totp) if totp.enabled => totp,
+                Ok(_) | Err(RepoError::NotFound) => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            match verify(&totp.secret, code, Utc::now()) {
+                Some(step) => repo.use_step(user, step).await,
+                None => return Ok(false),
+            }
+        }
+        (None, Some(recovery_code)) => {
+            repo.use_recovery_code(user, &hash_recovery_code(recovery_code))
+                .await
+        }
+        (None, None) => return Ok(false),
+    };
+
+    match used {
+        Ok(()) => Ok(true),
+        Err(RepoError::NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Exchanges a challenge and the second factor in `login` for the user the challenge was issued
+/// to, coming from `ip`. Wrong factors count against the challenge, the account and the
+/// address; passing uses the challenge up and forgets the failures of the account.
+pub async fn redeem<I>(
+    repos: &Repos<I>,
+    keys: &KeyRing,
+    limits: &LockoutConfig,
+    login: &TotpLogin,
+    ip: Option<IpAddr>,
+) -> Result<User<I>, ChallengeError>
+where
+    I: Identifier + DeserializeOwned,
+{
+    let challenge: Challenge<I> =
+        verify_challenge(keys, &login.challenge).map_err(|_| ChallengeError::Invalid)?;
+    let (totp, attempts) = (repos.totp.as_ref(), repos.login_attempts.as_ref());
+
+    // Spares the codes of spent challenges, `spend_challenge` is what keeps them single use
+    if totp.challenge_spent(&challenge.id).await? {
+        return Err(ChallengeError::Invalid);
+    }
+
+    let user = match repos.users.find(challenge.user).await {
+        Ok(user) => user,
+        Err(RepoError::NotFound) => return Err(ChallengeError::Invalid),
+        Err(e) => return Err(e.into()),
+    };
+    let attempt = Attempt::new(&user.email, ip);
+    if let Some(until) = lockout::locked_until(attempts, limits, &attempt).await? {
+        return Err(ChallengeError::LockedOut(until));
+    }
+
+    if second_factor(totp, user.id, login).await? {
+        return match totp
+            .spend_challenge(&challenge.id, challenge.expires_at)
+            .await
+        {
+            Ok(()) => {
+                lockout::succeed(attempts, &attempt).await?;
+                Ok(user)
+            }
+            Err(RepoError::Conflict(_)) => Err(ChallengeError::Invalid),
+            Err(e) => Err(e.into()),
+        };
+    }
+
+    let failures = totp
+        .fail_challenge(&challenge.id, challenge.expires_at)
+        .await?;
+    if failures >= MAX_CHALLENGE_FAILURES {
+        match totp
+            .spend_challenge(&challenge.id, challenge.expires_at)
+            .await
+        {
+            Ok(()) | Err(RepoError::Conflict(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    match lockout::fail(attempts, limits, &attempt).await? {
+        Some(until) => Err(ChallengeError::LockedOut(until)),
+        None => Err(ChallengeError::Refused),
+    }
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct TotpConfirm {
+    #[validate(length(min = 1, message = "code can't be blank"))]
+    pub code: String,
+}
+
+/// Either a code from the authenticator or one of the recovery codes.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct TotpLogin {
+    #[validate(length(min = 1, message = "challenge can't be blank"))]
+    pub challenge: String,
+
+    pub code: Option<String>,
+
+    #[serde(rename = "recoveryCode")]
+    pub recovery_code: Option<String>,
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct TotpResponse<T> {
+    pub totp: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+/// Shown once, only their hashes are kept.
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+/// What a correct password is answered with once two-factor authentication is enabled.
+#[derive(Debug, Serialize)]
+pub struct ChallengeResponse {
+    pub challenge: ChallengeResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChallengeResponseInner {
+    pub token: String,
+}
+
+impl From<String> for ChallengeResponse {
+    fn from(token: String) -> Self {
+        ChallengeResponse {
+            challenge: ChallengeResponseInner { token },
+        }
+    }
+}
+
+impl<T> From<T> for TotpResponse<T> {
+    fn from(totp: T) -> Self {
+        TotpResponse { totp }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 seed of RFC 6238 appendix B, "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        // The RFC lists 8 digits, of which the last 6 are ours
+        for (time, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1234567890, "005924"),
+        ] {
+            let at = Utc.timestamp_opt(time, 0).unwrap();
+            assert_eq!(code(RFC_SECRET, step_at(at)).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn codes_verify_one_step_around_now() {
+        let now = Utc.timestamp_opt(59, 0).unwrap();
+        assert_eq!(verify(RFC_SECRET, " 287082 ", now), Some(1));
+        assert_eq!(
+            verify(RFC_SECRET, &code(RFC_SECRET, 2).unwrap(), now),
+            Some(2)
+        );
+        assert_eq!(verify(RFC_SECRET, &code(RFC_SECRET, 3).unwrap(), now), None);
+        assert_eq!(code("not base32!", 1), None);
+    }
+}
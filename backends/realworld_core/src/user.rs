@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+use crate::{error::field_error, policy::Role, privacy::DELETED_PREFIX};
+
+/// Names that would pass for the site itself or one of its pages.
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "api",
+    "editor",
+    "help",
+    "login",
+    "logout",
+    "me",
+    "moderator",
+    "register",
+    "root",
+    "settings",
+    "support",
+    "system",
+];
+
+/// Usernames nobody can register or rename themselves to, compared ignoring case. Names
+/// starting with `deleted-` are kept for anonymized accounts on top of these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsernameConfig {
+    pub reserved: Vec<String>,
+}
+
+impl Default for UsernameConfig {
+    fn default() -> Self {
+        Self {
+            reserved: RESERVED_USERNAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl UsernameConfig {
+    pub fn is_reserved(&self, username: &str) -> bool {
+        let username = username.to_lowercase();
+        username.starts_with(DELETED_PREFIX)
+            || self
+                .reserved
+                .iter()
+                .any(|reserved| reserved.to_lowercase() == username)
+    }
+
+    /// Fails with a `username` field error if `username` is reserved.
+    pub fn check(&self, username: &str) -> Result<(), ValidationErrors> {
+        match self.is_reserved(username) {
+            true => Err(field_error("username", "is reserved")),
+            false => Ok(()),
+        }
+    }
+}
+
+// ================================== Client Messages ================================== //
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct RegistrationUser {
+    #[validate(
+        non_control_character(message = "user name can't contain non-ascii charactors"),
+        length(min = 1, message = "user name can't be blank"),
+        length(max = 64, message = "too long user name")
+    )]
+    pub username: String,
+
+    #[validate(
+        length(min = 1, message = "email can't be blank"),
+        length(max = 64, message = "too long email address"),
+        email(message = "invalid email address")
+    )]
+    pub email: String,
+
+    #[validate(
+        non_control_character(message = "password can't contain non-ascii charactors"),
+        length(min = 8, message = "password must be at least 8 characters long"),
+        length(max = 64, message = "too long password")
+    )]
+    pub password: String,
+}
+
+/// What a login with an unknown email or a wrong password is told, the same either way.
+pub const INVALID_LOGIN: &str = "email or password is invalid";
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct LoginUser {
+    #[validate(
+        email(message = "invalid email address"),
+        length(min = 1, message = "email can't be blank")
+    )]
+    pub email: String,
+    #[validate(length(min = 1, message = "password can't be blank"))]
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct UpdateUserData {
+    pub bio: Option<String>,
+    pub image: Option<String>,
+
+    #[validate(
+        length(max = 64, message = "too long email address"),
+        email(message = "invalid email address")
+    )]
+    pub email: Option<String>,
+    #[validate(
+        non_control_character(message = "user name can't contain non-ascii charactors"),
+        length(min = 1, message = "user name can't be blank"),
+        length(max = 64, message = "too long user name")
+    )]
+    pub username: Option<String>,
+    #[validate(
+        non_control_character(message = "password can't contain non-ascii charactors"),
+        length(min = 8, message = "password must be at least 8 characters long"),
+        length(max = 64, message = "too long password")
+    )]
+    pub password: Option<String>,
+}
+
+// ================================== JSON Response Objects ================================== //
+
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    pub user: UserResponseInner,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserResponseInner {
+    pub email: String,
+    pub token: String,
+    pub username: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    pub role: Role,
+    /// Only sent when a session starts or is refreshed.
+    #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+impl From<UserResponseInner> for UserResponse {
+    fn from(user: UserResponseInner) -> Self {
+        Self { user }
+    }
+}
+
+impl UserResponse {
+    pub fn with_refresh_token(mut self, refresh_token: String) -> Self {
+        self.user.refresh_token = Some(refresh_token);
+        self
+    }
+}
@@ -0,0 +1,6 @@
+-- Kept up to date by Postgres, queried with raw SQL so it stays out of `schema.rs`
+ALTER TABLE articles
+ADD COLUMN search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A') || setweight(to_tsvector('english', description), 'B') || setweight(to_tsvector('english', body), 'C')
+    ) STORED;
+CREATE INDEX articles_search_idx ON articles USING GIN (search);
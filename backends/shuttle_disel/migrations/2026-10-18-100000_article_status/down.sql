@@ -0,0 +1,3 @@
+DROP INDEX articles_published_idx;
+ALTER TABLE articles DROP COLUMN published_at,
+    DROP COLUMN status;
@@ -0,0 +1,9 @@
+ALTER TABLE articles
+ADD COLUMN status TEXT NOT NULL DEFAULT 'published' CHECK (status IN ('draft', 'published', 'unlisted')),
+    ADD COLUMN published_at TIMESTAMPTZ;
+UPDATE articles
+SET published_at = created_at
+WHERE status = 'published';
+-- Lists only ever show published articles
+CREATE INDEX articles_published_idx ON articles (created_at DESC, id DESC)
+WHERE status = 'published';
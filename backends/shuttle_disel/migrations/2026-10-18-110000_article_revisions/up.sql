@@ -0,0 +1,32 @@
+-- Snapshots of every version of an article, never updated
+CREATE TABLE article_revisions (
+    article_id UUID NOT NULL REFERENCES articles (id) ON DELETE CASCADE,
+    number INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    body TEXT NOT NULL,
+    changed_fields TEXT [] NOT NULL,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (article_id, number)
+);
+-- Existing articles start their history at their current content
+INSERT INTO article_revisions (
+        article_id,
+        number,
+        title,
+        description,
+        body,
+        changed_fields,
+        user_id,
+        created_at
+    )
+SELECT id,
+    1,
+    title,
+    description,
+    body,
+    ARRAY ['title', 'description', 'body'],
+    author_id,
+    updated_at
+FROM articles;
@@ -0,0 +1,7 @@
+-- Slugs articles had before being renamed, so old links can be redirected
+CREATE TABLE article_slugs (
+    slug VARCHAR(255) PRIMARY KEY,
+    article_id UUID NOT NULL REFERENCES articles (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX article_slugs_article_id_idx ON article_slugs (article_id);
@@ -0,0 +1,3 @@
+ALTER TABLE comments DROP COLUMN hidden_at;
+ALTER TABLE articles DROP COLUMN hidden_at;
+ALTER TABLE users DROP COLUMN role;
@@ -0,0 +1,7 @@
+-- Roles, and content hidden by moderators
+ALTER TABLE users
+ADD COLUMN role TEXT NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'moderator', 'admin'));
+ALTER TABLE articles
+ADD COLUMN hidden_at TIMESTAMPTZ;
+ALTER TABLE comments
+ADD COLUMN hidden_at TIMESTAMPTZ;
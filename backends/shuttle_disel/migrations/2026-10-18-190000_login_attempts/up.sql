@@ -0,0 +1,16 @@
+-- Failed logins by account or client address
+CREATE TABLE login_attempts (
+    key TEXT NOT NULL PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMPTZ
+);
+-- Every time failed logins locked a key
+CREATE TABLE lockout_events (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    key TEXT NOT NULL,
+    failures INTEGER NOT NULL,
+    locked_until TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX lockout_events_key_idx ON lockout_events (key);
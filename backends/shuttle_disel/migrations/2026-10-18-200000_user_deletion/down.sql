@@ -0,0 +1,15 @@
+ALTER TABLE articles
+    DROP CONSTRAINT articles_author_id_fkey,
+    ADD CONSTRAINT articles_author_id_fkey
+        FOREIGN KEY (author_id) REFERENCES users (id);
+ALTER TABLE favorite_articles
+    DROP CONSTRAINT favorite_articles_user_id_fkey,
+    ADD CONSTRAINT favorite_articles_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id);
+ALTER TABLE followers
+    DROP CONSTRAINT followers_follower_id_fkey,
+    ADD CONSTRAINT followers_follower_id_fkey
+        FOREIGN KEY (follower_id) REFERENCES users (id),
+    DROP CONSTRAINT followers_user_id_fkey,
+    ADD CONSTRAINT followers_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id);
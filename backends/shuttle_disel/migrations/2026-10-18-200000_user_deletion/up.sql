@@ -0,0 +1,16 @@
+-- Deleting a user removes what they wrote, followed and favorited
+ALTER TABLE followers
+    DROP CONSTRAINT followers_user_id_fkey,
+    ADD CONSTRAINT followers_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    DROP CONSTRAINT followers_follower_id_fkey,
+    ADD CONSTRAINT followers_follower_id_fkey
+        FOREIGN KEY (follower_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE favorite_articles
+    DROP CONSTRAINT favorite_articles_user_id_fkey,
+    ADD CONSTRAINT favorite_articles_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE articles
+    DROP CONSTRAINT articles_author_id_fkey,
+    ADD CONSTRAINT articles_author_id_fkey
+        FOREIGN KEY (author_id) REFERENCES users (id) ON DELETE CASCADE;
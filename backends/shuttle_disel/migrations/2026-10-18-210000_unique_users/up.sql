@@ -0,0 +1,32 @@
+-- Usernames and emails are unique on their own, ignoring case, not just as a pair. Users that
+-- only differ in case would make the indexes fail with an unhelpful error, so they are listed
+-- instead, to be renamed by hand
+DO $$
+DECLARE
+    clashes TEXT;
+BEGIN
+    SELECT string_agg(format('%s %L (ids %s)', field, value, ids), '; ')
+    INTO clashes
+    FROM (
+            SELECT 'username' AS field,
+                lower(username) AS value,
+                string_agg(id::TEXT, ', ' ORDER BY id) AS ids
+            FROM users
+            GROUP BY lower(username)
+            HAVING COUNT(*) > 1
+            UNION ALL
+            SELECT 'email',
+                lower(email),
+                string_agg(id::TEXT, ', ' ORDER BY id)
+            FROM users
+            GROUP BY lower(email)
+            HAVING COUNT(*) > 1
+        ) AS duplicates;
+    IF clashes IS NOT NULL THEN
+        RAISE EXCEPTION 'users differ only in case: %', clashes
+            USING HINT = 'Rename all but one user of each before creating the case-insensitive unique indexes';
+    END IF;
+END $$;
+ALTER TABLE users DROP CONSTRAINT users_username_email_key;
+CREATE UNIQUE INDEX users_username_lower_key ON users (lower(username));
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
@@ -0,0 +1,7 @@
+-- Login challenges given wrong codes or spent, until they expire
+CREATE TABLE totp_challenges (
+    id TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL DEFAULT 0,
+    spent_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,6 @@
+DELETE FROM article_revisions WHERE user_id IS NULL;
+ALTER TABLE article_revisions
+    ALTER COLUMN user_id SET NOT NULL,
+    DROP CONSTRAINT article_revisions_user_id_fkey,
+    ADD CONSTRAINT article_revisions_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
@@ -0,0 +1,6 @@
+-- Revisions outlive their editor, even on other people's articles; they just lose the author
+ALTER TABLE article_revisions
+    ALTER COLUMN user_id DROP NOT NULL,
+    DROP CONSTRAINT article_revisions_user_id_fkey,
+    ADD CONSTRAINT article_revisions_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE SET NULL;
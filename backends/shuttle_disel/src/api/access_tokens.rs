@@ -0,0 +1,72 @@
+use crate::error::{AppError, AppResult};
+use crate::utils::authenticate;
+use crate::AppState;
+use actix_web::web::{self, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::access_token::{
+    new_access_token, AccessTokenListResponse, AccessTokenResponse, NewAccessTokenData,
+};
+use realworld_core::token::hash_token;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    #[serde(rename = "accessToken")]
+    access_token: T,
+}
+
+// ================================== Handlers ================================== //
+
+/// Lists the tokens that haven't been revoked, without the tokens themselves.
+pub async fn get_access_tokens(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    let access_tokens = state.repos.access_tokens.list(auth.user.id).await?;
+
+    Ok(HttpResponse::Ok().json(AccessTokenListResponse::from(access_tokens)))
+}
+
+/// The only response that shows the token.
+pub async fn create_access_token(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    form: Json<In<NewAccessTokenData>>,
+) -> AppResult<HttpResponse> {
+    let access_token = form.into_inner().access_token;
+    access_token.validate()?;
+
+    let auth = authenticate(&state, &req).await?;
+    let token = new_access_token();
+    let created = state
+        .repos
+        .access_tokens
+        .create(
+            auth.user.id,
+            &access_token.name,
+            &access_token.scopes(),
+            &hash_token(&token),
+            access_token.expires_at(),
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(AccessTokenResponse::created(created, token)))
+}
+
+pub async fn revoke_access_token(
+    req: HttpRequest,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    let id: Uuid = id
+        .parse()
+        .map_err(|_| AppError::not_found("Record not found"))?;
+    state.repos.access_tokens.revoke(auth.user.id, id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
@@ -0,0 +1,135 @@
+use crate::error::{AppError, AppResult};
+use crate::utils::{authenticate, HASHER};
+use crate::AppState;
+use actix_web::web::{self, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::account::{
+    password_reset_mail, verification_mail, EmailVerification, PasswordResetConfirm,
+    PasswordResetRequest, TokenPurpose,
+};
+use realworld_core::repo::{RepoError, User, UserChanges};
+use realworld_core::token::{hash_token, new_token};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    user: T,
+}
+
+// ================================== Handlers ================================== //
+
+/// Mails a reset link. Answers the same whether or not the email is registered.
+pub async fn request_password_reset(
+    state: web::Data<AppState>,
+    form: Json<In<PasswordResetRequest>>,
+) -> AppResult<HttpResponse> {
+    let request = form.into_inner().user;
+    request.validate()?;
+
+    match state.repos.users.find_by_email(&request.email).await {
+        Ok(user) => {
+            let token = new_token();
+            let mail = password_reset_mail(&state.outbox, &user.email, &token);
+            state
+                .repos
+                .account_tokens
+                .issue(
+                    user.id,
+                    TokenPurpose::PasswordReset,
+                    &hash_token(&token),
+                    state.tokens.password_reset_expiry(),
+                    mail,
+                )
+                .await?;
+            state.outbox.wake();
+        }
+        Err(RepoError::NotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+/// Sets a new password and logs the user out everywhere.
+pub async fn confirm_password_reset(
+    state: web::Data<AppState>,
+    form: Json<In<PasswordResetConfirm>>,
+) -> AppResult<HttpResponse> {
+    let confirm = form.into_inner().user;
+    confirm.validate()?;
+
+    let user_id = redeem(&state, TokenPurpose::PasswordReset, &confirm.token).await?;
+    let changes = UserChanges {
+        password_hash: Some(HASHER.hash(&confirm.password)?),
+        ..Default::default()
+    };
+    state.repos.users.update(user_id, changes).await?;
+    state.repos.sessions.revoke_all(user_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+pub async fn verify_email(
+    state: web::Data<AppState>,
+    form: Json<In<EmailVerification>>,
+) -> AppResult<HttpResponse> {
+    let verification = form.into_inner().user;
+    verification.validate()?;
+
+    let user_id = redeem(&state, TokenPurpose::EmailVerification, &verification.token).await?;
+    let changes = UserChanges {
+        email_verified: Some(true),
+        ..Default::default()
+    };
+    state.repos.users.update(user_id, changes).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+/// Mails a new verification link, unless the email is verified already.
+pub async fn resend_verification(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    if !auth.user.email_verified {
+        send_verification(&state, &auth.user).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+/// Mails `user` a link confirming their current email address.
+pub async fn send_verification(state: &AppState, user: &User<Uuid>) -> AppResult<()> {
+    let token = new_token();
+    state
+        .repos
+        .account_tokens
+        .issue(
+            user.id,
+            TokenPurpose::EmailVerification,
+            &hash_token(&token),
+            state.tokens.verification_expiry(),
+            verification_mail(&state.outbox, &user.email, &token),
+        )
+        .await?;
+    state.outbox.wake();
+
+    Ok(())
+}
+
+async fn redeem(state: &AppState, purpose: TokenPurpose, token: &str) -> AppResult<Uuid> {
+    match state
+        .repos
+        .account_tokens
+        .redeem(purpose, &hash_token(token))
+        .await
+    {
+        Ok(user_id) => Ok(user_id),
+        Err(RepoError::NotFound) => Err(AppError::Unauthorized("Invalid or expired token")),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,74 @@
+use crate::error::AppResult;
+use crate::utils::{authenticate, permit};
+use crate::AppState;
+use actix_web::web::{self, Json, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::admin::{
+    AdminUserListResponse, AdminUserResponse, UpdateRoleData, UsersParams,
+};
+use realworld_core::lockout;
+use realworld_core::policy::Action;
+use realworld_core::repo::UserChanges;
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    user: T,
+}
+
+// ================================== Handlers ================================== //
+
+pub async fn get_users(
+    req: HttpRequest,
+    params: Query<UsersParams>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    permit(&auth.user, Action::ManageUsers, None)?;
+
+    let users = state
+        .repos
+        .users
+        .list(params.limit(), params.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(AdminUserListResponse::from(users)))
+}
+
+pub async fn update_role(
+    req: HttpRequest,
+    username: web::Path<String>,
+    state: web::Data<AppState>,
+    form: Json<In<UpdateRoleData>>,
+) -> AppResult<HttpResponse> {
+    let user = form.into_inner().user;
+    user.validate()?;
+
+    let auth = authenticate(&state, &req).await?;
+    permit(&auth.user, Action::ManageUsers, None)?;
+
+    let target = state.repos.users.profile(&username, None).await?;
+    let changes = UserChanges {
+        role: Some(user.role()),
+        ..Default::default()
+    };
+    let updated = state.repos.users.update(target.id, changes).await?;
+
+    Ok(HttpResponse::Ok().json(AdminUserResponse::from(updated)))
+}
+
+pub async fn unlock_user(
+    req: HttpRequest,
+    username: web::Path<String>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    permit(&auth.user, Action::ManageUsers, None)?;
+
+    let target = state.repos.users.profile(&username, None).await?;
+    let target = state.repos.users.find(target.id).await?;
+    lockout::unlock(state.repos.login_attempts.as_ref(), &target.email).await?;
+
+    Ok(HttpResponse::Ok().json(AdminUserResponse::from(target)))
+}
@@ -1,356 +1,299 @@
 use crate::error::AppResult;
-use crate::models::articles::Article;
-use crate::models::user::User;
-use crate::utils::{authenticate, Auth, CustomDateTime};
+use crate::utils::{authorize, permit};
 use crate::AppState;
-use actix::Message;
 use actix_web::web::{self, Json, Query};
-use actix_web::{HttpRequest, HttpResponse, ResponseError};
-use futures::{FutureExt, TryFutureExt};
-use serde::{Deserialize, Serialize};
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use realworld_core::access_token::Scope;
+use realworld_core::article::{
+    ArticleListResponse, ArticleRedirectResponse, ArticleResponse, ArticleSearchResponse,
+    ArticleStatus, ArticlesParams, CreateArticleData, FeedParams, SearchParams, UpdateArticleData,
+};
+use realworld_core::metrics::Event;
+use realworld_core::policy::Action;
+use realworld_core::repo::{Article, ArticleChanges, ArticleFilter, NewArticle, RepoError, User};
+use serde::Deserialize;
+use serde_json::json;
+use slug::slugify;
+use uuid::Uuid;
 use validator::Validate;
 
-use super::profile::ProfileResponseInner;
-
 #[derive(Debug, Deserialize)]
 pub struct In<T> {
     article: T,
 }
 
-// ================================== Extractors ================================== //
-
-#[derive(Debug, Deserialize)]
-pub struct ArticlesParams {
-    pub tag: Option<String>,
-    pub author: Option<String>,
-    pub favorited: Option<String>,
-    pub limit: Option<usize>,  // <- if not set, is 20
-    pub offset: Option<usize>, // <- if not set, is 0
-}
-
-#[derive(Debug, Deserialize)]
-pub struct FeedParams {
-    pub limit: Option<usize>,  // <- if not set, is 20
-    pub offset: Option<usize>, // <- if not set, is 0
-}
-
-// ================================== Client Messages ================================== //
-
-#[derive(Debug, Deserialize, Validate)]
-#[serde(rename_all = "camelCase")]
-pub struct CreateArticleData {
-    #[serde(default)]
-    pub tag_list: Vec<String>,
-    #[validate(length(min = 1, message = "title can't be blank"))]
-    pub title: String,
-    #[validate(length(min = 1, message = "description can't be blank"))]
-    pub description: String,
-    #[validate(length(min = 1, message = "body can't be blank"))]
-    pub body: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleResponse>")]
-pub struct CreateArticleOuter {
-    pub auth: Auth,
-    pub article: CreateArticleData,
-}
+// ================================== Handlers ================================== //
 
-#[derive(Debug, Deserialize, Validate)]
-#[serde(rename_all = "camelCase")]
-pub struct UpdateArticleData {
-    pub body: Option<String>,
-    pub title: Option<String>,
-    pub description: Option<String>,
-    pub tag_list: Option<Vec<String>>,
-}
+pub async fn create_article(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    form: Json<In<CreateArticleData>>,
+) -> AppResult<HttpResponse> {
+    let article = form.into_inner().article;
+    article.validate()?;
 
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleResponse>")]
-pub struct UpdateArticleOuter {
-    pub auth: Auth,
-    pub slug: String,
-    pub article: UpdateArticleData,
-}
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    permit(&user, Action::CreateArticle, None)?;
 
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleResponse>")]
-pub struct GetArticle {
-    pub slug: String,
-    pub auth: Option<Auth>,
-}
+    let new_article = NewArticle {
+        slug: slugify(&article.title),
+        title: article.title,
+        description: article.description,
+        body: article.body,
+        tag_list: article.tag_list,
+        status: article.status,
+    };
 
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleListResponse>")]
-pub struct GetArticles {
-    // auth is option in case authentication fails or isn't present
-    pub auth: Option<Auth>,
-    pub params: ArticlesParams,
-}
+    // A taken slug is a conflict, see `From<RepoError>`
+    let article = state.repos.articles.create(user.id, new_article).await?;
+    state.metrics.record(Event::ArticleCreated);
 
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleListResponse>")]
-pub struct GetFeedArticles {
-    pub auth: Auth,
-    pub params: FeedParams,
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
 }
 
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<serde_json::Value>")]
-pub struct DeleteArticle {
-    pub auth: Auth,
-    pub slug: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleResponse>")]
-pub struct FavoriteArticle {
-    pub auth: Auth,
-    pub slug: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ArticleResponse>")]
-pub struct UnFavoriteArticle {
-    pub auth: Auth,
-    pub slug: String,
+pub async fn get_article(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let viewer = authorize(&state, &req, Scope::Read)
+        .await
+        .ok()
+        .map(|user| user.id);
+
+    let slug = match state.repos.articles.find_by_slug(&slug, viewer).await {
+        Ok(article) => return Ok(HttpResponse::Ok().json(ArticleResponse::from(article))),
+        Err(RepoError::NotFound) => state.repos.articles.renamed(&slug).await?,
+        Err(e) => return Err(e.into()),
+    };
+
+    // Old slugs of drafts stay hidden like the drafts themselves
+    state.repos.articles.find_by_slug(&slug, viewer).await?;
+
+    let redirect = ArticleRedirectResponse::to(slug);
+    Ok(HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, redirect.location()))
+        .json(redirect))
 }
 
-// ================================== JSON response objects ================================== //
+pub async fn get_feed_articles(
+    req: HttpRequest,
+    params: Query<FeedParams>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::Read).await?;
 
-#[derive(Debug, Serialize)]
-pub struct ArticleResponse {
-    pub article: ArticleResponseInner,
-}
+    let page = params.page()?;
+    let articles = state.repos.articles.feed(user.id, page).await?;
 
-impl ArticleResponse {
-    pub fn new(
-        article: Article,
-        author: User,
-        tags: Vec<String>,
-        favorited: bool,
-        favorites_count: i64,
-        following: bool,
-    ) -> Self {
-        Self {
-            article: ArticleResponseInner {
-                favorited,
-                tag_list: tags,
-                favorites_count,
-                slug: article.slug,
-                body: article.body,
-                title: article.title,
-                description: article.description,
-                created_at: CustomDateTime(article.created_at),
-                updated_at: CustomDateTime(article.updated_at),
-                author: ProfileResponseInner {
-                    following,
-                    bio: author.bio,
-                    image: author.image,
-                    username: author.username,
-                },
-            },
-        }
-    }
+    Ok(HttpResponse::Ok().json(ArticleListResponse::from(articles)))
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ArticleResponseInner {
-    pub slug: String,
-    pub body: String,
-    pub title: String,
-    pub favorited: bool,
-    pub description: String,
-    pub tag_list: Vec<String>,
-    pub favorites_count: i64,
-    pub created_at: CustomDateTime,
-    pub updated_at: CustomDateTime,
-    pub author: ProfileResponseInner,
+pub async fn get_articles(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    params: Query<ArticlesParams>,
+) -> AppResult<HttpResponse> {
+    let viewer = authorize(&state, &req, Scope::Read)
+        .await
+        .ok()
+        .map(|user| user.id);
+
+    let params = params.into_inner();
+    let page = params.page()?;
+    let filter = ArticleFilter {
+        tag: params.tag,
+        author: params.author,
+        favorited: params.favorited,
+    };
+
+    let articles = state.repos.articles.list(&filter, page, viewer).await?;
+
+    Ok(HttpResponse::Ok().json(ArticleListResponse::from(articles)))
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ArticleListResponse {
-    pub articles: Vec<ArticleResponseInner>,
-    pub articles_count: usize,
+pub async fn search_articles(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    params: Query<SearchParams>,
+) -> AppResult<HttpResponse> {
+    let viewer = authorize(&state, &req, Scope::Read)
+        .await
+        .ok()
+        .map(|user| user.id);
+
+    let query = params.query()?;
+    let results = state
+        .repos
+        .articles
+        .search(&query, params.page(), viewer)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ArticleSearchResponse::from(results)))
 }
 
-// ================================== Handlers ================================== //
-
-pub async fn create_article(
+pub async fn update_article(
     req: HttpRequest,
+    slug: web::Path<String>,
     state: web::Data<AppState>,
-    form: Json<In<CreateArticleData>>,
+    form: Json<In<UpdateArticleData>>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
     let article = form.into_inner().article;
     article.validate()?;
 
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move { db.send(CreateArticleOuter { auth, article }).await? })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    let existing = find_permitted(&state, &slug, &user, Action::EditArticle).await?;
+
+    let changes = ArticleChanges {
+        slug: article.title.as_ref().map(slugify),
+        title: article.title,
+        description: article.description,
+        body: article.body,
+        tag_list: article.tag_list,
+        status: article.status,
+    };
+
+    let article = state
+        .repos
+        .articles
+        .update(existing.id, changes, Some(user.id))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
 }
 
-pub async fn get_article(
+pub async fn publish_article(
     req: HttpRequest,
     slug: web::Path<String>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .then(|auth| async move {
-            db.send(GetArticle {
-                auth: auth.ok(),
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    set_status(req, slug, state, ArticleStatus::Published).await
 }
 
-pub async fn get_feed_articles(
+/// Turns the article back into a draft.
+pub async fn unpublish_article(
     req: HttpRequest,
-    params: Query<FeedParams>,
+    slug: web::Path<String>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(GetFeedArticles {
-                auth,
-                params: params.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    set_status(req, slug, state, ArticleStatus::Draft).await
 }
 
-pub async fn get_articles(
+pub async fn delete_article(
     req: HttpRequest,
+    slug: web::Path<String>,
     state: web::Data<AppState>,
-    params: Query<ArticlesParams>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .then(|auth| async move {
-            db.send(GetArticles {
-                auth: auth.ok(),
-                params: params.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    let article = find_permitted(&state, &slug, &user, Action::DeleteArticle).await?;
+
+    state.repos.articles.delete(article.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
 }
 
-pub async fn update_article(
+pub async fn favorite_article(
     req: HttpRequest,
     slug: web::Path<String>,
     state: web::Data<AppState>,
-    form: Json<In<UpdateArticleData>>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    permit(&user, Action::FavoriteArticle, None)?;
+    let viewer = Some(user.id);
 
-    let article = form.into_inner().article;
-    article.validate()?;
+    let article = state.repos.articles.find_by_slug(&slug, viewer).await?;
+    state.repos.articles.favorite(article.id, user.id).await?;
+    state.metrics.record(Event::Favorite);
 
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(UpdateArticleOuter {
-                auth,
-                article,
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let article = state.repos.articles.find_by_slug(&slug, viewer).await?;
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
 }
 
-pub async fn delete_article(
+pub async fn unfavorite_article(
     req: HttpRequest,
     slug: web::Path<String>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(DeleteArticle {
-                auth,
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    permit(&user, Action::FavoriteArticle, None)?;
+    let viewer = Some(user.id);
+
+    let article = state.repos.articles.find_by_slug(&slug, viewer).await?;
+    state.repos.articles.unfavorite(article.id, user.id).await?;
+
+    let article = state.repos.articles.find_by_slug(&slug, viewer).await?;
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
 }
 
-pub async fn favorite_article(
+/// Hides the article from everyone but its author.
+pub async fn hide_article(
     req: HttpRequest,
     slug: web::Path<String>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(FavoriteArticle {
-                auth,
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    set_hidden(req, slug, state, true).await
 }
 
-pub async fn unfavorite_article(
+pub async fn unhide_article(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    set_hidden(req, slug, state, false).await
+}
+
+// ================== HELPERS ================== //
+
+async fn set_hidden(
     req: HttpRequest,
     slug: web::Path<String>,
     state: web::Data<AppState>,
+    hidden: bool,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(UnFavoriteArticle {
-                auth,
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    permit(&user, Action::HideArticle, None)?;
+
+    state.repos.articles.set_hidden(&slug, hidden).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+async fn set_status(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    state: web::Data<AppState>,
+    status: ArticleStatus,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    let existing = find_permitted(&state, &slug, &user, Action::EditArticle).await?;
+
+    let changes = ArticleChanges {
+        status: Some(status),
+        ..Default::default()
+    };
+
+    let article = state
+        .repos
+        .articles
+        .update(existing.id, changes, Some(user.id))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
+}
+
+/// Loads the article at `slug`, refusing `user` unless the policy lets them do `action` to it.
+pub(crate) async fn find_permitted(
+    state: &AppState,
+    slug: &str,
+    user: &User<Uuid>,
+    action: Action,
+) -> AppResult<Article<Uuid>> {
+    let article = state
+        .repos
+        .articles
+        .find_by_slug(slug, Some(user.id))
+        .await?;
+
+    permit(user, action, Some(article.author.id))?;
+    Ok(article)
 }
@@ -1,75 +1,22 @@
-use crate::error::AppResult;
-use crate::utils::{authenticate, Auth, CustomDateTime};
+use crate::error::{AppError, AppResult};
+use crate::utils::{authorize, permit};
 use crate::AppState;
-use actix::Message;
 use actix_web::web::{self, Json};
-use actix_web::{HttpRequest, HttpResponse, ResponseError};
-use futures::{FutureExt, TryFutureExt};
-use serde::{Deserialize, Serialize};
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::access_token::Scope;
+use realworld_core::comment::{AddCommentData, CommentListResponse, CommentResponse};
+use realworld_core::policy::Action;
+use realworld_core::repo::Comment;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
 use validator::Validate;
 
-use super::profile::ProfileResponseInner;
-
 #[derive(Debug, Deserialize)]
 pub struct In<T> {
     comment: T,
 }
 
-// ================================== Extractors ================================== //
-// ================================== Client Messages ================================== //
-
-#[derive(Debug, Deserialize, Validate)]
-#[serde(rename_all = "camelCase")]
-pub struct AddCommentData {
-    #[validate(length(min = 1, message = "body can't be blank"))]
-    pub body: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<CommentResponse>")]
-pub struct AddCommentOuter {
-    pub auth: Auth,
-    pub slug: String,
-    pub comment: AddCommentData,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<CommentListResponse>")]
-pub struct GetComments {
-    pub slug: String,
-    pub auth: Option<Auth>,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<serde_json::Value>")]
-pub struct DeleteComment {
-    pub auth: Auth,
-    pub slug: String,
-    pub comment_id: i32,
-}
-
-// ================================== JSON response objects ================================== //
-
-#[derive(Debug, Serialize)]
-pub struct CommentResponse {
-    pub comment: CommentResponseInner,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CommentResponseInner {
-    pub id: i32,
-    pub body: String,
-    pub created_at: CustomDateTime,
-    pub updated_at: CustomDateTime,
-    pub author: ProfileResponseInner,
-}
-
-#[derive(Debug, Serialize)]
-pub struct CommentListResponse {
-    pub comments: Vec<CommentResponseInner>,
-}
-
 // ================================== HANDLERS ================================== //
 
 pub async fn add_comment(
@@ -78,25 +25,24 @@ pub async fn add_comment(
     state: web::Data<AppState>,
     form: Json<In<AddCommentData>>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
     let comment = form.into_inner().comment;
     comment.validate()?;
 
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(AddCommentOuter {
-                auth,
-                comment,
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::CommentsWrite).await?;
+    permit(&user, Action::Comment, None)?;
+    let article = state
+        .repos
+        .articles
+        .find_by_slug(&slug, Some(user.id))
+        .await?;
+
+    let comment = state
+        .repos
+        .comments
+        .create(article.id, user.id, comment.body)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(CommentResponse::from(comment)))
 }
 
 pub async fn get_comments(
@@ -104,21 +50,15 @@ pub async fn get_comments(
     slug: web::Path<String>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .then(|auth| async move {
-            db.send(GetComments {
-                auth: auth.ok(),
-                slug: slug.into_inner(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let viewer = authorize(&state, &req, Scope::Read)
+        .await
+        .ok()
+        .map(|user| user.id);
+
+    let article = state.repos.articles.find_by_slug(&slug, viewer).await?;
+    let comments = state.repos.comments.list(article.id, viewer).await?;
+
+    Ok(HttpResponse::Ok().json(CommentListResponse::from(comments)))
 }
 
 #[derive(Debug, Deserialize)]
@@ -132,20 +72,57 @@ pub async fn delete_comment(
     path: web::Path<ArticleCommentPath>,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(DeleteComment {
-                auth,
-                slug: path.slug.to_owned(),
-                comment_id: path.comment_id.to_owned(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let user = authorize(&state, &req, Scope::CommentsWrite).await?;
+    let comment = find_comment(&state, &path).await?;
+    permit(&user, Action::DeleteComment, Some(comment.author.id))?;
+
+    state.repos.comments.delete(comment.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+/// Hides the comment from everyone but its author.
+pub async fn hide_comment(
+    req: HttpRequest,
+    path: web::Path<ArticleCommentPath>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    set_hidden(req, path, state, true).await
+}
+
+pub async fn unhide_comment(
+    req: HttpRequest,
+    path: web::Path<ArticleCommentPath>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    set_hidden(req, path, state, false).await
+}
+
+// ================== HELPERS ================== //
+
+async fn set_hidden(
+    req: HttpRequest,
+    path: web::Path<ArticleCommentPath>,
+    state: web::Data<AppState>,
+    hidden: bool,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::CommentsWrite).await?;
+    permit(&user, Action::HideComment, None)?;
+
+    let comment = find_comment(&state, &path).await?;
+    state.repos.comments.set_hidden(comment.id, hidden).await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "message": "OK" })))
+}
+
+/// Loads the comment at `path`, as long as it belongs to the article there.
+async fn find_comment(state: &AppState, path: &ArticleCommentPath) -> AppResult<Comment<Uuid>> {
+    let article = state.repos.articles.find_by_slug(&path.slug, None).await?;
+    let comment = state.repos.comments.find(path.comment_id).await?;
+
+    if comment.article_id != article.id {
+        return Err(AppError::not_found("Record not found"));
+    }
+
+    Ok(comment)
 }
@@ -1,5 +1,11 @@
+pub mod access_tokens;
+pub mod account;
+pub mod admin;
 pub mod articles;
 pub mod comments;
+pub mod oidc;
 pub mod profile;
+pub mod revisions;
 pub mod tags;
+pub mod totp;
 pub mod user;
@@ -0,0 +1,71 @@
+use crate::api::account::send_verification;
+use crate::error::AppResult;
+use crate::utils::{session_response, start_session, HASHER};
+use crate::AppState;
+use actix_web::web::{self, Json, Path};
+use actix_web::HttpResponse;
+use realworld_core::metrics::Event;
+use realworld_core::oidc::{self, OidcCallback, ProviderListResponse};
+use realworld_core::token::new_token;
+use realworld_core::totp::{self, ChallengeResponse};
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    user: T,
+}
+
+// ================================== Handlers ================================== //
+
+/// The providers users can log in with.
+pub async fn get_providers(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(ProviderListResponse {
+        providers: state.oidc.provider_names(),
+    })
+}
+
+/// Starts logging in at a provider, answering with where to send the user.
+pub async fn authorize(state: web::Data<AppState>, path: Path<String>) -> AppResult<HttpResponse> {
+    let provider = path.into_inner();
+    let authorization = oidc::begin(&state.oidc, state.repos.oidc.as_ref(), &provider).await?;
+    Ok(HttpResponse::Ok().json(authorization))
+}
+
+/// Logs in the user the provider sent back, provisioning an account on their first login.
+pub async fn callback(
+    state: web::Data<AppState>,
+    path: Path<String>,
+    form: Json<In<OidcCallback>>,
+) -> AppResult<HttpResponse> {
+    let provider = path.into_inner();
+    let callback = form.into_inner().user;
+    callback.validate()?;
+
+    let identity =
+        oidc::complete(&state.oidc, state.repos.oidc.as_ref(), &provider, &callback).await?;
+    let user = match oidc::linked_user(&state.repos, &identity).await? {
+        Some(user) => user,
+        None => {
+            // Nobody knows the password, it can only be set through a password reset
+            let password_hash = HASHER.hash(&new_token())?;
+            let user =
+                oidc::provision(&state.repos, &state.usernames, &identity, password_hash).await?;
+            if !user.email_verified {
+                send_verification(&state, &user).await?;
+            }
+            state.metrics.record(Event::Registration);
+            user
+        }
+    };
+
+    if totp::is_enabled(state.repos.totp.as_ref(), user.id).await? {
+        let challenge =
+            totp::issue_challenge(&state.keys, user.id, state.tokens.challenge_expiry())?;
+        return Ok(HttpResponse::Ok().json(ChallengeResponse::from(challenge)));
+    }
+
+    let response = start_session(&state, user).await?;
+    state.metrics.record(Event::Login);
+    Ok(session_response(&state, response))
+}
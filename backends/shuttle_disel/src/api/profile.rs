@@ -1,62 +1,11 @@
-use crate::error::AppResult;
-use crate::models::user::User;
-use crate::utils::auth::{authenticate, Auth};
+use crate::error::{AppError, AppResult};
+use crate::utils::auth::{authorize, permit};
 use crate::AppState;
-use actix::Message;
 use actix_web::web::{self};
-use actix_web::{HttpRequest, HttpResponse, ResponseError};
-use futures::{FutureExt, TryFutureExt};
-use serde::Serialize;
-
-// ================================== Client Messages ================================== //
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ProfileResponse>")]
-pub struct GetProfile {
-    // auth is option in case authentication fails or isn't present
-    pub auth: Option<Auth>,
-    pub username: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ProfileResponse>")]
-pub struct FollowProfile {
-    pub auth: Auth,
-    pub username: String,
-}
-
-#[derive(Debug, Message)]
-#[rtype(result = "AppResult<ProfileResponse>")]
-pub struct UnFollowProfile {
-    pub auth: Auth,
-    pub username: String,
-}
-
-// ================================== JSON response objects ================================== //
-#[derive(Debug, Serialize)]
-pub struct ProfileResponseInner {
-    pub following: bool,
-    pub username: String,
-    pub bio: Option<String>,
-    pub image: Option<String>,
-}
-
-#[derive(Debug, Serialize)]
-pub struct ProfileResponse {
-    pub profile: ProfileResponseInner,
-}
-
-impl ProfileResponse {
-    pub fn new(user: User, following: bool) -> Self {
-        Self {
-            profile: ProfileResponseInner {
-                following,
-                bio: user.bio,
-                image: user.image,
-                username: user.username,
-            },
-        }
-    }
-}
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::access_token::Scope;
+use realworld_core::policy::Action;
+use realworld_core::profile::ProfileResponse;
 
 // ================================== Handlers ================================== //
 
@@ -65,20 +14,13 @@ pub async fn get_profile(
     state: web::Data<AppState>,
     user_name: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
-    Ok(authenticate(&state, &req)
-        .then(|auth| async move {
-            db.send(GetProfile {
-                auth: auth.ok(),
-                username: user_name.to_owned(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let viewer = authorize(&state, &req, Scope::Read)
+        .await
+        .ok()
+        .map(|user| user.id);
+    let profile = state.repos.users.profile(&user_name, viewer).await?;
+
+    Ok(HttpResponse::Ok().json(ProfileResponse::from(profile)))
 }
 
 pub async fn follow_profile(
@@ -86,21 +28,19 @@ pub async fn follow_profile(
     state: web::Data<AppState>,
     user_name: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
+    let user = authorize(&state, &req, Scope::ProfilesWrite).await?;
+    permit(&user, Action::Follow, None)?;
+    let viewer = Some(user.id);
+
+    let profile = state.repos.users.profile(&user_name, viewer).await?;
+    if profile.id == user.id {
+        return Err(AppError::UnprocessableEntity("You cannot follow yourself"));
+    }
+
+    state.repos.follows.follow(user.id, profile.id).await?;
 
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(FollowProfile {
-                auth,
-                username: user_name.to_owned(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let profile = state.repos.users.profile(&user_name, viewer).await?;
+    Ok(HttpResponse::Ok().json(ProfileResponse::from(profile)))
 }
 
 pub async fn unfollow_profile(
@@ -108,19 +48,19 @@ pub async fn unfollow_profile(
     state: web::Data<AppState>,
     user_name: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    let db = &state.db;
+    let user = authorize(&state, &req, Scope::ProfilesWrite).await?;
+    permit(&user, Action::Follow, None)?;
+    let viewer = Some(user.id);
+
+    let profile = state.repos.users.profile(&user_name, viewer).await?;
+    if profile.id == user.id {
+        return Err(AppError::UnprocessableEntity(
+            "You cannot unfollow yourself",
+        ));
+    }
+
+    state.repos.follows.unfollow(user.id, profile.id).await?;
 
-    Ok(authenticate(&state, &req)
-        .and_then(|auth| async move {
-            db.send(UnFollowProfile {
-                auth,
-                username: user_name.to_owned(),
-            })
-            .await?
-        })
-        .map(|res| match res {
-            Err(e) => e.error_response(),
-            Ok(res) => HttpResponse::Ok().json(res),
-        })
-        .await)
+    let profile = state.repos.users.profile(&user_name, viewer).await?;
+    Ok(HttpResponse::Ok().json(ProfileResponse::from(profile)))
 }
@@ -0,0 +1,117 @@
+use crate::api::articles::find_permitted;
+use crate::error::AppResult;
+use crate::utils::authorize;
+use crate::AppState;
+use actix_web::web;
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::access_token::Scope;
+use realworld_core::article::ArticleResponse;
+use realworld_core::policy::Action;
+use realworld_core::repo::ArticleChanges;
+use realworld_core::revision::{RevisionDiffResponse, RevisionListResponse, RevisionResponse};
+use serde::Deserialize;
+use slug::slugify;
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionPath {
+    slug: String,
+    number: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffPath {
+    slug: String,
+    from: i32,
+    to: i32,
+}
+
+// ================================== HANDLERS ================================== //
+
+pub async fn get_revisions(
+    req: HttpRequest,
+    slug: web::Path<String>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::Read).await?;
+    let article = find_permitted(&state, &slug, &user, Action::EditArticle).await?;
+
+    let revisions = state
+        .repos
+        .revisions
+        .list(article.id, Some(user.id))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(RevisionListResponse::from(revisions)))
+}
+
+pub async fn get_revision(
+    req: HttpRequest,
+    path: web::Path<RevisionPath>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::Read).await?;
+    let article = find_permitted(&state, &path.slug, &user, Action::EditArticle).await?;
+
+    let revision = state
+        .repos
+        .revisions
+        .find(article.id, path.number, Some(user.id))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(RevisionResponse::from(revision)))
+}
+
+pub async fn diff_revisions(
+    req: HttpRequest,
+    path: web::Path<RevisionDiffPath>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::Read).await?;
+    let article = find_permitted(&state, &path.slug, &user, Action::EditArticle).await?;
+
+    let viewer = Some(user.id);
+    let from = state
+        .repos
+        .revisions
+        .find(article.id, path.from, viewer)
+        .await?;
+    let to = state
+        .repos
+        .revisions
+        .find(article.id, path.to, viewer)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(RevisionDiffResponse::between(&from, &to)))
+}
+
+/// Brings back the content of an old revision, which is stored as a new one.
+pub async fn restore_revision(
+    req: HttpRequest,
+    path: web::Path<RevisionPath>,
+    state: web::Data<AppState>,
+) -> AppResult<HttpResponse> {
+    let user = authorize(&state, &req, Scope::ArticlesWrite).await?;
+    let article = find_permitted(&state, &path.slug, &user, Action::EditArticle).await?;
+
+    let revision = state
+        .repos
+        .revisions
+        .find(article.id, path.number, Some(user.id))
+        .await?;
+
+    let changes = ArticleChanges {
+        slug: (revision.title != article.title).then(|| slugify(&revision.title)),
+        title: Some(revision.title),
+        description: Some(revision.description),
+        body: Some(revision.body),
+        ..Default::default()
+    };
+
+    let article = state
+        .repos
+        .articles
+        .update(article.id, changes, Some(user.id))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ArticleResponse::from(article)))
+}
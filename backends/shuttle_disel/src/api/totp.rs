@@ -0,0 +1,64 @@
+use crate::error::AppResult;
+use crate::utils::{authenticate, client_ip, session_response, start_session};
+use crate::AppState;
+use actix_web::web::{self, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use realworld_core::error::field_error;
+use realworld_core::metrics::Event;
+use realworld_core::totp::{self, TotpConfirm, TotpLogin, TotpResponse};
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct In<T> {
+    totp: T,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginIn {
+    user: TotpLogin,
+}
+
+// ================================== Handlers ================================== //
+
+/// Starts enrolling a second factor, over again until a code is confirmed.
+pub async fn enroll(req: HttpRequest, state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+
+    match totp::enroll(state.repos.totp.as_ref(), auth.user.id, &auth.user.email).await? {
+        Some(enrollment) => Ok(HttpResponse::Ok().json(TotpResponse::from(enrollment))),
+        None => Err(field_error("totp", "is already enabled").into()),
+    }
+}
+
+/// Enables the second factor, answering with the recovery codes.
+pub async fn confirm(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    form: Json<In<TotpConfirm>>,
+) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    let confirm = form.into_inner().totp;
+    confirm.validate()?;
+
+    match totp::confirm(state.repos.totp.as_ref(), auth.user.id, &confirm.code).await? {
+        Some(recovery_codes) => Ok(HttpResponse::Ok().json(TotpResponse::from(recovery_codes))),
+        None => Err(field_error("code", "is invalid").into()),
+    }
+}
+
+/// Swaps the challenge handed out on login and a second factor for a session.
+pub async fn login(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    form: Json<LoginIn>,
+) -> AppResult<HttpResponse> {
+    let login = form.into_inner().user;
+    login.validate()?;
+
+    let ip = client_ip(&state, &req);
+    let user = totp::redeem(&state.repos, &state.keys, &state.lockout, &login, ip).await?;
+    let response = start_session(&state, user).await?;
+    state.metrics.record(Event::Login);
+    Ok(session_response(&state, response))
+}
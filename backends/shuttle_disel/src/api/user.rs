@@ -1,12 +1,28 @@
-use crate::error::AppResult;
+use crate::api::account::send_verification;
+use crate::error::{AppError, AppResult};
 use crate::utils::jwt::GenerateJwt;
-use crate::utils::{authenticate, Auth};
-use crate::{models::user::User, AppState};
-use actix::Message;
-use actix_web::web::{self, Json};
-use actix_web::{HttpRequest, HttpResponse, ResponseError};
-use futures::TryFutureExt;
-use serde::{Deserialize, Serialize};
+use crate::utils::{
+    authenticate, clear_session, client_ip, refresh_cookie, session_response, start_session, Auth,
+    HASHER, PWD_SCHEME_VERSION,
+};
+use crate::AppState;
+use actix_web::http::header::ContentDisposition;
+use actix_web::web::{self, Json, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use libreauth::pass::HashBuilder;
+use realworld_core::lockout::{self, Attempt};
+use realworld_core::metrics::Event;
+use realworld_core::privacy::{self, DeleteUserParams, ExportParams};
+use realworld_core::repo::{NewUser, RepoError, User, UserChanges};
+use realworld_core::session::RefreshTokenData;
+use realworld_core::token::{hash_token, new_token};
+use realworld_core::totp::{self, ChallengeResponse};
+use realworld_core::user::{
+    LoginUser, RegistrationUser, UpdateUserData, UserResponse, UserResponseInner, INVALID_LOGIN,
+};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
 use validator::Validate;
 
 #[derive(Debug, Deserialize)]
@@ -14,98 +30,17 @@ pub struct In<U> {
     user: U,
 }
 
-// ================================== UI Messages ================================== //
-
-#[derive(Deserialize, Validate, Debug, Clone, Message)]
-#[rtype(result = "AppResult<UserResponse>")]
-pub struct RegistrationUser {
-    #[validate(
-        non_control_character(message = "user name can't contain non-ascii charactors"),
-        length(min = 1, message = "user name can't be blank"),
-        length(max = 64, message = "too long user name")
-    )]
-    pub username: String,
-
-    #[validate(
-        length(min = 1, message = "email can't be blank"),
-        length(max = 64, message = "too long email address"),
-        email(message = "invalid email address")
-    )]
-    pub email: String,
-
-    #[validate(
-        non_control_character(message = "password can't contain non-ascii charactors"),
-        length(min = 8, message = "password must be at least 8 characters long"),
-        length(max = 64, message = "too long password")
-    )]
-    pub password: String,
-}
-
-#[derive(Debug, Deserialize, Validate, Message)]
-#[rtype(result = "AppResult<UserResponse>")]
-pub struct LoginUser {
-    #[validate(
-        email(message = "invalid email address"),
-        length(min = 1, message = "email can't be blank")
-    )]
-    pub email: String,
-    #[validate(length(min = 1, message = "password can't be blank"))]
-    pub password: String,
-}
-
-#[derive(Debug, Deserialize, Validate, Message, Clone)]
-#[rtype(result = "AppResult<UserResponse>")]
-pub struct UpdateUserData {
-    pub bio: Option<String>,
-    pub image: Option<String>,
-
-    #[validate(email)]
-    pub email: Option<String>,
-    #[validate(non_control_character, length(min = 1, max = 64))]
-    pub username: Option<String>,
-    #[validate(non_control_character, length(min = 8, max = 64))]
-    pub password: Option<String>,
-}
-
-#[derive(Clone, Debug, Message)]
-#[rtype(result = "AppResult<UserResponse>")]
-pub struct UpdateUserOuter {
-    pub auth: Auth,
-    pub update_user: UpdateUserData,
+#[derive(Debug, Deserialize)]
+pub struct Refresh {
+    // Cookie sessions send their refresh token as a cookie, and `{}` as the body
+    #[serde(default)]
+    user: RefreshTokenData,
 }
 
 // ================================== JSON Response Objects ================================== //
 
-#[derive(Debug, Serialize)]
-pub struct UserResponse {
-    pub user: UserResponseInner,
-}
-
-#[derive(Debug, Serialize)]
-pub struct UserResponseInner {
-    pub email: String,
-    pub token: String,
-    pub username: String,
-    pub bio: Option<String>,
-    pub image: Option<String>,
-}
-
-impl From<User> for UserResponse {
-    fn from(user: User) -> Self {
-        UserResponse {
-            user: UserResponseInner {
-                token: user.generate_jwt().unwrap(),
-                bio: user.bio,
-                email: user.email,
-                image: user.image,
-                username: user.username,
-            },
-        }
-    }
-}
-
-impl UserResponse {
-    fn create_with_auth(auth: crate::utils::Auth) -> Self {
+impl From<Auth> for UserResponse {
+    fn from(auth: Auth) -> Self {
         UserResponse {
             user: UserResponseInner {
                 token: auth.token,
@@ -113,6 +48,9 @@ impl UserResponse {
                 username: auth.user.username,
                 bio: auth.user.bio,
                 image: auth.user.image,
+                email_verified: auth.user.email_verified,
+                role: auth.user.role,
+                refresh_token: None,
             },
         }
     }
@@ -126,57 +64,228 @@ pub async fn registration(
 ) -> AppResult<HttpResponse> {
     let register_user = form.into_inner().user;
     register_user.validate()?;
+    state.usernames.check(&register_user.username)?;
+
+    let new_user = NewUser {
+        email: register_user.email,
+        username: register_user.username,
+        password_hash: HASHER.hash(&register_user.password)?,
+    };
 
-    Ok(state.db.send(register_user).await.map(|res| match res {
-        Err(e) => e.error_response(),
-        Ok(res) => HttpResponse::Ok().json(res),
-    })?)
+    let user = state.repos.users.create(new_user).await?;
+    send_verification(&state, &user).await?;
+    state.metrics.record(Event::Registration);
+
+    let response = start_session(&state, user).await?;
+    Ok(session_response(&state, response))
 }
 
+/// Only answers with a challenge once a second factor is enabled, see [`super::totp::login`].
 pub async fn login(
+    req: HttpRequest,
     state: web::Data<AppState>,
     form: Json<In<LoginUser>>,
 ) -> AppResult<HttpResponse> {
     let login_user = form.into_inner().user;
     login_user.validate()?;
 
-    Ok(state.db.send(login_user).await.map(|res| match res {
-        Err(e) => e.error_response(),
-        Ok(res) => HttpResponse::Ok().json(res),
-    })?)
+    let attempt = Attempt::new(&login_user.email, client_ip(&state, &req));
+    let attempts = state.repos.login_attempts.as_ref();
+    if let Some(until) = lockout::locked_until(attempts, &state.lockout, &attempt).await? {
+        return Err(AppError::LockedOut(until));
+    }
+
+    let user = match check_password(&state, &login_user).await {
+        Ok(user) => user,
+        Err(error @ AppError::Unauthorized(_)) => {
+            if let Some(until) = lockout::fail(attempts, &state.lockout, &attempt).await? {
+                return Err(AppError::LockedOut(until));
+            }
+            return Err(error);
+        }
+        Err(error) => return Err(error),
+    };
+
+    // Failures are only forgotten once the second factor is right too
+    if totp::is_enabled(state.repos.totp.as_ref(), user.id).await? {
+        let challenge =
+            totp::issue_challenge(&state.keys, user.id, state.tokens.challenge_expiry())?;
+        return Ok(HttpResponse::Ok().json(ChallengeResponse::from(challenge)));
+    }
+    lockout::succeed(attempts, &attempt).await?;
+
+    let response = start_session(&state, user).await?;
+    state.metrics.record(Event::Login);
+    Ok(session_response(&state, response))
+}
+
+/// The user logging in, with the password rehashed if its scheme is outdated. Unknown emails
+/// and wrong passwords are unauthorized alike, so neither tells which emails are registered.
+async fn check_password(state: &AppState, login_user: &LoginUser) -> AppResult<User<Uuid>> {
+    let stored_user = match state.repos.users.find_by_email(&login_user.email).await {
+        Ok(user) => user,
+        Err(RepoError::NotFound) => return Err(AppError::Unauthorized(INVALID_LOGIN)),
+        Err(e) => return Err(e.into()),
+    };
+    let checker = HashBuilder::from_phc(&stored_user.password_hash)?;
+
+    if !checker.is_valid(&login_user.password) {
+        return Err(AppError::Unauthorized(INVALID_LOGIN));
+    }
+
+    match checker.needs_update(Some(PWD_SCHEME_VERSION)) {
+        true => {
+            let changes = UserChanges {
+                password_hash: Some(HASHER.hash(&login_user.password)?),
+                ..Default::default()
+            };
+            Ok(state.repos.users.update(stored_user.id, changes).await?)
+        }
+        false => Ok(stored_user),
+    }
+}
+
+/// Swaps a refresh token for a new one and a fresh access token.
+pub async fn refresh(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    form: Json<Refresh>,
+) -> AppResult<HttpResponse> {
+    let mut data = form.into_inner().user;
+    if data.refresh_token.is_empty() {
+        if let Some(refresh_token) = refresh_cookie(&state, &req)? {
+            data.refresh_token = refresh_token;
+        }
+    }
+    data.validate()?;
+
+    let refresh_token = new_token();
+    let rotated = state
+        .repos
+        .sessions
+        .rotate(
+            &hash_token(&data.refresh_token),
+            &hash_token(&refresh_token),
+            state.tokens.refresh_expiry(),
+        )
+        .await;
+
+    let session = match rotated {
+        Ok(session) => session,
+        Err(RepoError::NotFound) => return Err(AppError::Unauthorized("Invalid refresh token")),
+        Err(e) => return Err(e.into()),
+    };
+
+    let user = state.repos.users.find(session.user_id).await?;
+    let token = user.generate_jwt(session.id, state.tokens.access_expiry(), &state.keys)?;
+
+    let response = user.into_response(token).with_refresh_token(refresh_token);
+    Ok(session_response(&state, response))
+}
+
+/// Ends the session of the token used.
+pub async fn logout(req: HttpRequest, state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    state.repos.sessions.revoke(auth.session).await?;
+
+    let mut response = HttpResponse::Ok();
+    clear_session(&state, &mut response);
+    Ok(response.json(json!({ "message": "OK" })))
+}
+
+/// Ends every session of the user, on every device.
+pub async fn logout_all(req: HttpRequest, state: web::Data<AppState>) -> AppResult<HttpResponse> {
+    let auth = authenticate(&state, &req).await?;
+    state.repos.sessions.revoke_all(auth.user.id).await?;
+
+    let mut response = HttpResponse::Ok();
+    clear_session(&state, &mut response);
+    Ok(response.json(json!({ "message": "OK" })))
 }
 
 pub async fn get_current_user(
     req: HttpRequest,
     state: web::Data<AppState>,
 ) -> AppResult<HttpResponse> {
-    authenticate(&state, &req)
-        .and_then(|auth| {
-            futures::future::ok(HttpResponse::Ok().json(UserResponse::create_with_auth(auth)))
-        })
-        .await
+    let auth = authenticate(&state, &req).await?;
+
+    Ok(HttpResponse::Ok().json(UserResponse::from(auth)))
 }
 
+/// A new email address has to be verified again.
 pub async fn update_user(
     req: HttpRequest,
     state: web::Data<AppState>,
     form: Json<In<UpdateUserData>>,
 ) -> AppResult<HttpResponse> {
-    use futures::future::*;
-
     let update_user = form.into_inner().user;
     update_user.validate()?;
 
-    authenticate(&state, &req)
-        .and_then(|auth| async {
-            Ok(state
-                .db
-                .send(UpdateUserOuter { auth, update_user })
-                .await
-                .map(|res| match res {
-                    Ok(res) => HttpResponse::Ok().json(res),
-                    Err(e) => e.error_response(),
-                })?)
-        })
-        .await
+    let auth = authenticate(&state, &req).await?;
+    // Whoever got a reserved name before it was reserved keeps it
+    if let Some(ref username) = update_user.username {
+        if *username != auth.user.username {
+            state.usernames.check(username)?;
+        }
+    }
+
+    let changes = UserChanges {
+        bio: update_user.bio,
+        email: update_user.email,
+        image: update_user.image,
+        username: update_user.username,
+        password_hash: update_user
+            .password
+            .map(|password| HASHER.hash(&password))
+            .transpose()?,
+        ..Default::default()
+    };
+
+    let user = state.repos.users.update(auth.user.id, changes).await?;
+    if user.email != auth.user.email {
+        send_verification(&state, &user).await?;
+    }
+
+    let token = user.generate_jwt(auth.session, state.tokens.access_expiry(), &state.keys)?;
+
+    Ok(HttpResponse::Ok().json(user.into_response(token)))
+}
+
+/// Anonymizes or removes what the user wrote according to the privacy settings, unless
+/// `?content=` asks for the other one.
+pub async fn delete_user(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    params: Query<DeleteUserParams>,
+) -> AppResult<HttpResponse> {
+    let policy = params.policy(&state.privacy)?;
+    let auth = authenticate(&state, &req).await?;
+
+    // Anonymized accounts keep a password nobody knows
+    let password_hash = HASHER.hash(&new_token())?;
+    privacy::delete_account(&state.repos, &auth.user, policy, password_hash).await?;
+
+    let mut response = HttpResponse::Ok();
+    clear_session(&state, &mut response);
+    Ok(response.json(json!({ "message": "OK" })))
+}
+
+/// Everything stored about the user, as JSON or with `?format=zip` as a ZIP archive.
+pub async fn export_user(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    params: Query<ExportParams>,
+) -> AppResult<HttpResponse> {
+    let zip = params.zip()?;
+    let auth = authenticate(&state, &req).await?;
+    let export = privacy::export(&state.repos, auth.user.id).await?;
+
+    if !zip {
+        return Ok(HttpResponse::Ok().json(export));
+    }
+    let archive = export.to_zip().map_err(|_| AppError::InternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition::attachment("realworld-export.zip"))
+        .body(archive))
 }
@@ -0,0 +1,82 @@
+//! Runs the API without the Shuttle runtime, configured through `realworld.toml` and
+//! `REALWORLD_*` environment variables.
+
+use std::io::{Error, ErrorKind};
+
+use actix_web::{App, HttpServer};
+use realworld_core::{
+    config::{self, Storage},
+    mail::Outbox,
+    metrics::Metrics,
+    oidc::Oidc,
+};
+use shuttle_disel_server::{
+    config::Config,
+    configure, configure_metrics,
+    db::{self, PgRepo, Repos},
+    new_pool, pool_stats, AppState,
+};
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+
+    let config: Config = config::load().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    config.log.init();
+    let keys = config
+        .jwt
+        .key_ring()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+    let mut metrics = Metrics::new(&config.metrics);
+    let repos = match config.storage {
+        Storage::Memory => Repos::in_memory(),
+        Storage::Postgres => {
+            db::run_migrations(&config.database_url).map_err(Error::other)?;
+
+            let pool = new_pool(&config.database_url, &config.pool);
+            let (stats_pool, max) = (pool.clone(), config.pool.max_connections);
+            metrics = metrics.with_pool(move || pool_stats(&stats_pool, max));
+            PgRepo::new(pool).into_repos()
+        }
+    };
+
+    let mailer = config
+        .mail
+        .mailer()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let outbox = Outbox::spawn(&config.mail, repos.outbox.clone(), mailer);
+    let oidc = Oidc::new(&config.oidc);
+
+    let routes = configure(AppState::new(
+        repos,
+        keys,
+        config.tokens,
+        outbox,
+        oidc,
+        config.roles,
+        config.lockout,
+        config.cookies,
+        config.privacy,
+        config.usernames,
+        metrics.clone(),
+    ));
+    let mut server = HttpServer::new(move || App::new().configure(routes.clone()));
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+
+    let server = server.bind(config.bind)?.run();
+
+    match config.metrics.bind {
+        Some(bind) => {
+            let metrics = configure_metrics(metrics);
+            let metrics_server = HttpServer::new(move || App::new().configure(metrics.clone()))
+                .workers(1)
+                .bind(bind)?
+                .run();
+            futures::try_join!(server, metrics_server).map(|_| ())
+        }
+        None => server.await,
+    }
+}
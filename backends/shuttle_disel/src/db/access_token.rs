@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use realworld_core::{
+    access_token::{parse_scopes, Scope},
+    repo::{AccessToken as AccessTokenRecord, AccessTokenRepo, RepoError, RepoResult},
+};
+use uuid::Uuid;
+
+use crate::models::access_token::{AccessToken, NewAccessToken};
+
+use super::{repo_error, PgRepo};
+
+impl From<AccessToken> for AccessTokenRecord<Uuid> {
+    fn from(token: AccessToken) -> Self {
+        AccessTokenRecord {
+            id: token.id,
+            user_id: token.user_id,
+            name: token.name,
+            scopes: parse_scopes(&token.scopes),
+            created_at: Utc.from_utc_datetime(&token.created_at),
+            expires_at: Utc.from_utc_datetime(&token.expires_at),
+            last_used_at: token
+                .last_used_at
+                .map(|used_at| Utc.from_utc_datetime(&used_at)),
+        }
+    }
+}
+
+#[async_trait]
+impl AccessTokenRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "access_tokens.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        user: Uuid,
+        name: &str,
+        scopes: &[Scope],
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<AccessTokenRecord<Uuid>> {
+        use crate::schema::access_tokens;
+
+        let mut conn = self.conn().await?;
+        diesel::insert_into(access_tokens::table)
+            .values(NewAccessToken {
+                user_id: user,
+                name,
+                scopes: scopes.iter().map(ToString::to_string).collect(),
+                token_hash,
+                expires_at: expires_at.naive_utc(),
+            })
+            .returning(AccessToken::as_returning())
+            .get_result::<AccessToken>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "access_tokens.authenticate", level = "debug", skip_all)]
+    async fn authenticate(&self, token_hash: &str) -> RepoResult<AccessTokenRecord<Uuid>> {
+        use crate::schema::access_tokens;
+
+        let now = Utc::now().naive_utc();
+        let mut conn = self.conn().await?;
+        diesel::update(access_tokens::table)
+            .filter(access_tokens::token_hash.eq(token_hash))
+            .filter(access_tokens::revoked_at.is_null())
+            .filter(access_tokens::expires_at.gt(now))
+            .set(access_tokens::last_used_at.eq(now))
+            .returning(AccessToken::as_returning())
+            .get_result::<AccessToken>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "access_tokens.list", level = "debug", skip_all)]
+    async fn list(&self, user: Uuid) -> RepoResult<Vec<AccessTokenRecord<Uuid>>> {
+        use crate::schema::access_tokens;
+
+        let mut conn = self.conn().await?;
+        let tokens = access_tokens::table
+            .filter(access_tokens::user_id.eq(user))
+            .filter(access_tokens::revoked_at.is_null())
+            .order(access_tokens::created_at.desc())
+            .select(AccessToken::as_select())
+            .load::<AccessToken>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(tokens.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(name = "access_tokens.revoke", level = "debug", skip_all)]
+    async fn revoke(&self, user: Uuid, id: Uuid) -> RepoResult<()> {
+        use crate::schema::access_tokens;
+
+        let mut conn = self.conn().await?;
+        let revoked = diesel::update(access_tokens::table.find(id))
+            .filter(access_tokens::user_id.eq(user))
+            .filter(access_tokens::revoked_at.is_null())
+            .set(access_tokens::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match revoked {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::{
+    account::TokenPurpose,
+    mail::{Mail, QueuedMail, MAX_ATTEMPTS},
+    repo::{AccountTokenRepo, OutboxRepo, RepoError, RepoResult},
+};
+use uuid::Uuid;
+
+use crate::models::account::{NewAccountToken, NewOutboxMail, OutboxMail};
+
+use super::{repo_error, PgRepo};
+
+impl From<OutboxMail> for QueuedMail {
+    fn from(row: OutboxMail) -> Self {
+        QueuedMail {
+            id: row.id,
+            mail: Mail {
+                to: row.recipient,
+                subject: row.subject,
+                body: row.body,
+            },
+            attempts: row.attempts,
+        }
+    }
+}
+
+#[async_trait]
+impl AccountTokenRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "account_tokens.issue", level = "debug", skip_all)]
+    async fn issue(
+        &self,
+        user: Uuid,
+        purpose: TokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        mail: Mail,
+    ) -> RepoResult<()> {
+        use crate::schema::{account_tokens, outbox};
+
+        let token_hash = token_hash.to_string();
+        let mut conn = self.conn().await?;
+        conn.transaction(|conn| {
+            async move {
+                diesel::update(account_tokens::table)
+                    .filter(account_tokens::user_id.eq(user))
+                    .filter(account_tokens::purpose.eq(purpose.as_str()))
+                    .filter(account_tokens::used_at.is_null())
+                    .set(account_tokens::used_at.eq(Utc::now().naive_utc()))
+                    .execute(conn)
+                    .await?;
+
+                diesel::insert_into(account_tokens::table)
+                    .values(NewAccountToken {
+                        token_hash: &token_hash,
+                        user_id: user,
+                        purpose: purpose.as_str(),
+                        expires_at: expires_at.naive_utc(),
+                    })
+                    .execute(conn)
+                    .await?;
+
+                diesel::insert_into(outbox::table)
+                    .values(NewOutboxMail {
+                        recipient: mail.to,
+                        subject: mail.subject,
+                        body: mail.body,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "account_tokens.redeem", level = "debug", skip_all)]
+    async fn redeem(&self, purpose: TokenPurpose, token_hash: &str) -> RepoResult<Uuid> {
+        use crate::schema::account_tokens;
+
+        let now = Utc::now().naive_utc();
+        let mut conn = self.conn().await?;
+        diesel::update(account_tokens::table.find(token_hash))
+            .filter(account_tokens::purpose.eq(purpose.as_str()))
+            .filter(account_tokens::used_at.is_null())
+            .filter(account_tokens::expires_at.gt(now))
+            .set(account_tokens::used_at.eq(now))
+            .returning(account_tokens::user_id)
+            .get_result::<Uuid>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .ok_or(RepoError::NotFound)
+    }
+}
+
+#[async_trait]
+impl OutboxRepo for PgRepo {
+    #[tracing::instrument(name = "outbox.claim", level = "debug", skip_all)]
+    async fn claim(&self, limit: i64, lease_until: DateTime<Utc>) -> RepoResult<Vec<QueuedMail>> {
+        use crate::schema::outbox;
+
+        let mut conn = self.conn().await?;
+        conn.transaction(|conn| {
+            async move {
+                // Skipping locked rows lets several dispatchers share the outbox
+                let due = outbox::table
+                    .filter(outbox::delivered_at.is_null())
+                    .filter(outbox::attempts.lt(MAX_ATTEMPTS))
+                    .filter(outbox::next_attempt_at.le(Utc::now().naive_utc()))
+                    .order(outbox::id)
+                    .limit(limit)
+                    .select(outbox::id)
+                    .for_update()
+                    .skip_locked()
+                    .load::<i64>(conn)
+                    .await?;
+
+                diesel::update(outbox::table.filter(outbox::id.eq_any(due)))
+                    .set((
+                        outbox::attempts.eq(outbox::attempts + 1),
+                        outbox::next_attempt_at.eq(lease_until.naive_utc()),
+                    ))
+                    .returning(OutboxMail::as_returning())
+                    .get_results::<OutboxMail>(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+        .map(|rows| rows.into_iter().map(Into::into).collect())
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "outbox.delivered", level = "debug", skip_all)]
+    async fn delivered(&self, id: i64) -> RepoResult<()> {
+        use crate::schema::outbox;
+
+        let mut conn = self.conn().await?;
+        diesel::update(outbox::table.find(id))
+            .set(outbox::delivered_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "outbox.failed", level = "debug", skip_all)]
+    async fn failed(&self, id: i64, error: &str, retry_at: DateTime<Utc>) -> RepoResult<()> {
+        use crate::schema::outbox;
+
+        let mut conn = self.conn().await?;
+        diesel::update(outbox::table.find(id))
+            .set((
+                outbox::last_error.eq(error),
+                outbox::next_attempt_at.eq(retry_at.naive_utc()),
+            ))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+}
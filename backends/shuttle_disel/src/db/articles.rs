@@ -1,413 +1,669 @@
-use std::ops::DerefMut;
-
-use actix::prelude::*;
-use diesel::prelude::*;
-use serde_json::json;
-use slug::slugify;
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::{
+    dsl::count_star,
+    pg::Pg,
+    prelude::*,
+    result::Error as DieselError,
+    sql_types::{BigInt, Text},
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::article::ArticleStatus;
+use realworld_core::repo::{
+    available_slug, slug_matches, Article as ArticleRecord, ArticleChanges, ArticleFilter,
+    ArticleList, ArticleRepo, Cursor, NewArticle as NewArticleRecord, Page, RepoError, RepoResult,
+    SearchHit, SearchResults, REVISED_FIELDS,
+};
+use realworld_core::search::{SearchQuery, HEADLINE_OPTIONS};
 use uuid::Uuid;
 
-use crate::api::articles::{
-    ArticleListResponse, ArticleResponse, ArticleResponseInner, CreateArticleOuter, DeleteArticle,
-    FavoriteArticle, GetArticle, GetArticles, GetFeedArticles, UnFavoriteArticle,
-    UpdateArticleOuter,
-};
-use crate::db::DbExecutor;
-use crate::error::AppResult;
 use crate::models::articles::{Article, NewArticle, NewFavoriteArticle, UpdateArticle};
-use crate::models::tags::{ArticleTag, NewArticleTag};
+use crate::models::tags::NewArticleTag;
 use crate::models::user::User;
 
-impl Handler<CreateArticleOuter> for DbExecutor {
-    type Result = AppResult<ArticleResponse>;
+use super::{repo_error, revisions::add_revision, Conn, PgRepo};
 
-    fn handle(&mut self, msg: CreateArticleOuter, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::articles;
-
-        let author = msg.auth.user;
+#[async_trait]
+impl ArticleRepo for PgRepo {
+    type Id = Uuid;
 
-        // Generating the Uuid here since it will help make a unique slug
-        // This is for when some articles may have similar titles such that they generate the same slug
-        let slug = slugify(&msg.article.title);
+    #[tracing::instrument(name = "articles.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        author: Uuid,
+        article: NewArticleRecord,
+    ) -> RepoResult<ArticleRecord<Uuid>> {
+        use crate::schema::articles;
 
-        let new_article = NewArticle {
-            slug,
-            author_id: author.id,
-            body: msg.article.body,
-            title: msg.article.title,
-            description: msg.article.description,
+        let tags = article.tag_list;
+        let mut new_article = NewArticle {
+            author_id: author,
+            slug: article.slug,
+            body: article.body,
+            title: article.title,
+            description: article.description,
+            status: article.status.as_str().to_string(),
+            published_at: (article.status == ArticleStatus::Published)
+                .then(|| Utc::now().naive_utc()),
         };
 
-        let mut conn = self.0.get()?;
-
-        let article = match diesel::insert_into(articles::table)
-            .values(&new_article)
-            .get_result::<Article>(&mut conn)
-        {
-            Ok(article) => article,
-            Err(_) => {
-                return Err(crate::error::AppError::UnprocessableEntity(json!({
-                    "error": format!("Article with slug '{}' already exists", new_article.slug)
-                })))
-            }
-        };
+        let mut conn = self.conn().await?;
+        let article = conn
+            .transaction(|conn| {
+                async move {
+                    let taken = taken_slugs(conn, &new_article.slug, None).await?;
+                    new_article.slug = available_slug(&new_article.slug, &taken);
+
+                    let article = diesel::insert_into(articles::table)
+                        .values(&new_article)
+                        .get_result::<Article>(conn)
+                        .await?;
+
+                    replace_tags(conn, article.id, tags).await?;
+                    add_revision(
+                        conn,
+                        &article,
+                        author,
+                        REVISED_FIELDS.map(String::from).to_vec(),
+                    )
+                    .await?;
+                    Ok(article)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(repo_error)?;
 
-        let _ = replace_tags(conn.deref_mut(), article.id, msg.article.tag_list)?;
-        get_article_response(conn.deref_mut(), article.slug, Some(article.author_id))
+        load_article(&mut conn, article, Some(author))
+            .await
+            .map_err(repo_error)
     }
-}
 
-impl Handler<GetArticle> for DbExecutor {
-    type Result = AppResult<ArticleResponse>;
+    #[tracing::instrument(name = "articles.find_by_slug", level = "debug", skip_all)]
+    async fn find_by_slug(
+        &self,
+        slug: &str,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<ArticleRecord<Uuid>> {
+        use crate::schema::articles;
 
-    fn handle(&mut self, msg: GetArticle, _: &mut Self::Context) -> Self::Result {
-        let mut conn = self.0.get()?;
+        let mut conn = self.conn().await?;
+        let mut query = articles::table.filter(articles::slug.eq(slug)).into_boxed();
+        let public = articles::status
+            .ne(ArticleStatus::Draft.as_str())
+            .and(articles::hidden_at.is_null());
+        query = match viewer {
+            Some(viewer) => query.filter(public.or(articles::author_id.eq(viewer))),
+            None => query.filter(public),
+        };
 
-        match msg.auth {
-            Some(auth) => get_article_response(conn.deref_mut(), msg.slug, Some(auth.user.id)),
-            None => get_article_response(conn.deref_mut(), msg.slug, None),
-        }
+        let article = query
+            .first::<Article>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .ok_or(RepoError::NotFound)?;
+
+        load_article(&mut conn, article, viewer)
+            .await
+            .map_err(repo_error)
     }
-}
 
-impl Handler<UpdateArticleOuter> for DbExecutor {
-    type Result = AppResult<ArticleResponse>;
+    #[tracing::instrument(name = "articles.renamed", level = "debug", skip_all)]
+    async fn renamed(&self, slug: &str) -> RepoResult<String> {
+        use crate::schema::{article_slugs, articles};
+
+        let mut conn = self.conn().await?;
+        article_slugs::table
+            .inner_join(articles::table)
+            .filter(article_slugs::slug.eq(slug))
+            .select(articles::slug)
+            .first::<String>(&mut conn)
+            .await
+            .map_err(repo_error)
+    }
 
-    fn handle(&mut self, msg: UpdateArticleOuter, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "articles.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        filter: &ArticleFilter,
+        page: Page<Uuid>,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<ArticleList<Uuid>> {
         use crate::schema::articles;
 
-        let mut conn = self.0.get()?;
-
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(&mut conn)?;
+        let mut conn = self.conn().await?;
+        let articles = after_cursor(filtered(filter), page.after())
+            .order((articles::created_at.desc(), articles::id.desc()))
+            .limit(page.fetch_limit() as i64)
+            .offset(page.offset() as i64)
+            .load::<Article>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let total = filtered(filter)
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let articles = load_articles(&mut conn, articles, viewer)
+            .await
+            .map_err(repo_error)?;
+        Ok(ArticleList::new(articles, total, &page))
+    }
 
-        if msg.auth.user.id != article.author_id {
-            return Err(crate::error::AppError::Unauthorized(
-                "You are not authorized to update this article",
-            ));
-        }
+    #[tracing::instrument(name = "articles.feed", level = "debug", skip_all)]
+    async fn feed(&self, viewer: Uuid, page: Page<Uuid>) -> RepoResult<ArticleList<Uuid>> {
+        use crate::schema::{articles, followers};
 
-        let slug = msg.article.title.as_ref().map(slugify);
+        let feed = || {
+            let followed = followers::table
+                .filter(followers::follower_id.eq(viewer))
+                .select(followers::user_id);
 
-        let updated = UpdateArticle {
-            slug,
-            body: msg.article.body,
-            title: msg.article.title,
-            description: msg.article.description,
+            published().filter(articles::author_id.eq_any(followed))
         };
 
-        let article = diesel::update(articles::table.find(article.id))
-            .set(&updated)
-            .get_result::<Article>(&mut conn)?;
+        let mut conn = self.conn().await?;
+        let articles = after_cursor(feed(), page.after())
+            .order((articles::created_at.desc(), articles::id.desc()))
+            .limit(page.fetch_limit() as i64)
+            .offset(page.offset() as i64)
+            .load::<Article>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let total = feed()
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let articles = load_articles(&mut conn, articles, Some(viewer))
+            .await
+            .map_err(repo_error)?;
+        Ok(ArticleList::new(articles, total, &page))
+    }
 
-        if let Some(updated_tags) = msg.article.tag_list {
-            replace_tags(conn.deref_mut(), article.id, updated_tags)?;
-        }
+    #[tracing::instrument(name = "articles.search", level = "debug", skip_all)]
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        page: Page<Uuid>,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<SearchResults<Uuid>> {
+        use crate::schema::articles;
 
-        get_article_response(conn.deref_mut(), article.slug, Some(article.author_id))
+        let tsquery = query.to_tsquery();
+
+        let mut conn = self.conn().await?;
+        let hits = diesel::sql_query(SEARCH_ARTICLES)
+            .bind::<Text, _>(&tsquery)
+            .bind::<BigInt, _>(page.fetch_limit() as i64)
+            .bind::<BigInt, _>(page.offset() as i64)
+            .bind::<Text, _>(HEADLINE_OPTIONS)
+            .load::<Hit>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let total = diesel::sql_query(COUNT_SEARCH_ARTICLES)
+            .bind::<Text, _>(&tsquery)
+            .get_result::<Count>(&mut conn)
+            .await
+            .map_err(repo_error)?
+            .count;
+
+        // Back into relevance order
+        let ids = hits.iter().map(|hit| hit.id).collect::<Vec<_>>();
+        let mut found = articles::table
+            .filter(articles::id.eq_any(&ids))
+            .load::<Article>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+        found.sort_by_key(|article| ids.iter().position(|id| *id == article.id));
+
+        let articles = load_articles(&mut conn, found, viewer)
+            .await
+            .map_err(repo_error)?;
+
+        // By id, as articles gone since the search query are missing from `articles`
+        let mut snippets = hits
+            .into_iter()
+            .map(|hit| (hit.id, hit.snippet))
+            .collect::<HashMap<_, _>>();
+        Ok(SearchResults {
+            hits: articles
+                .into_iter()
+                .filter_map(|article| {
+                    let snippet = snippets.remove(&article.id)?;
+                    Some(SearchHit { article, snippet })
+                })
+                .collect(),
+            total,
+        })
     }
-}
 
-impl Handler<DeleteArticle> for DbExecutor {
-    type Result = AppResult<serde_json::Value>;
-
-    fn handle(&mut self, msg: DeleteArticle, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "articles.update", level = "debug", skip_all)]
+    async fn update(
+        &self,
+        id: Uuid,
+        changes: ArticleChanges,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<ArticleRecord<Uuid>> {
         use crate::schema::articles;
 
-        let mut conn = self.0.get()?;
+        let mut conn = self.conn().await?;
+        let article = conn
+            .transaction(|conn| {
+                async move {
+                    let current = articles::table
+                        .find(id)
+                        .for_update()
+                        .first::<Article>(conn)
+                        .await?;
+                    let changed_fields =
+                        changes.changed_fields(&current.title, &current.description, &current.body);
+
+                    let published_at = match changes.status {
+                        Some(ArticleStatus::Draft) => Some(None),
+                        Some(status @ ArticleStatus::Published)
+                            if current.status != status.as_str() =>
+                        {
+                            Some(Some(Utc::now().naive_utc()))
+                        }
+                        _ => None,
+                    };
+
+                    let slug = match changes.slug {
+                        Some(base) if !slug_matches(&current.slug, &base) => {
+                            let taken = taken_slugs(conn, &base, Some(id)).await?;
+                            let slug = available_slug(&base, &taken);
+                            rename(conn, id, &current.slug, &slug).await?;
+                            Some(slug)
+                        }
+                        _ => None,
+                    };
+
+                    let updated = UpdateArticle {
+                        slug,
+                        body: changes.body,
+                        title: changes.title,
+                        description: changes.description,
+                        status: changes.status.map(|status| status.as_str().to_string()),
+                        published_at,
+                    };
+
+                    let article = match updated.is_empty() {
+                        // diesel refuses to build an UPDATE without any columns in it
+                        true => current,
+                        false => {
+                            diesel::update(articles::table.find(id))
+                                .set(&updated)
+                                .get_result::<Article>(conn)
+                                .await?
+                        }
+                    };
+
+                    if let Some(tags) = changes.tag_list {
+                        replace_tags(conn, article.id, tags).await?;
+                    }
+
+                    if !changed_fields.is_empty() {
+                        let editor = viewer.unwrap_or(article.author_id);
+                        add_revision(conn, &article, editor, changed_fields).await?;
+                    }
+
+                    Ok(article)
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(repo_error)?;
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(&mut conn)?;
+        load_article(&mut conn, article, viewer)
+            .await
+            .map_err(repo_error)
+    }
 
-        if msg.auth.user.id != article.author_id {
-            return Err(crate::error::AppError::Unauthorized(
-                "You are not authorized to delete this article",
-            ));
-        }
+    #[tracing::instrument(name = "articles.delete", level = "debug", skip_all)]
+    async fn delete(&self, id: Uuid) -> RepoResult<()> {
+        use crate::schema::articles;
 
-        match diesel::delete(articles::table.filter(articles::id.eq(article.id))).execute(&mut conn)
+        let mut conn = self.conn().await?;
+        match diesel::delete(articles::table.find(id))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?
         {
-            Ok(_) => Ok(json!({ "message": "OK" })),
-            Err(e) => Err(e.into()),
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
         }
     }
-}
 
-impl Handler<GetArticles> for DbExecutor {
-    type Result = AppResult<ArticleListResponse>;
-
-    fn handle(&mut self, msg: GetArticles, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, users};
-
-        let mut conn = self.0.get()?;
-
-        let mut query = articles::table.into_boxed();
+    #[tracing::instrument(name = "articles.set_hidden", level = "debug", skip_all)]
+    async fn set_hidden(&self, slug: &str, hidden: bool) -> RepoResult<()> {
+        use crate::schema::articles;
 
-        // Author username
-        if let Some(ref author_name) = msg.params.author {
-            let articles_by_author = articles::table
-                .inner_join(users::table)
-                .filter(users::username.eq(author_name))
-                .select(articles::id)
-                .load::<Uuid>(&mut conn)?;
+        let hidden_at = hidden.then(|| Utc::now().naive_utc());
 
-            query = query.filter(articles::id.eq_any(articles_by_author));
+        let mut conn = self.conn().await?;
+        match diesel::update(articles::table.filter(articles::slug.eq(slug)))
+            .set(articles::hidden_at.eq(hidden_at))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?
+        {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
         }
+    }
 
-        // Favorited by user
-        if let Some(favorited_username) = msg.params.favorited {
-            let favorited_by_user = articles::table
-                .inner_join(users::table)
-                .filter(users::username.eq(favorited_username))
-                .select(articles::id)
-                .load::<Uuid>(&mut conn)?;
-
-            query = query.filter(articles::id.eq_any(favorited_by_user));
-        }
+    #[tracing::instrument(name = "articles.favorite", level = "debug", skip_all)]
+    async fn favorite(&self, id: Uuid, user: Uuid) -> RepoResult<()> {
+        use crate::schema::favorite_articles;
 
-        // Tags
-        if let Some(tag) = msg.params.tag {
-            use crate::schema::article_tags;
+        let mut conn = self.conn().await?;
+        diesel::insert_into(favorite_articles::table)
+            .values(NewFavoriteArticle {
+                article_id: id,
+                user_id: user,
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(repo_error)
+    }
 
-            let tagged_article_ids: Vec<Uuid> = article_tags::table
-                .filter(article_tags::tag_name.eq(tag))
-                .select(article_tags::article_id)
-                .load::<Uuid>(&mut conn)?;
+    #[tracing::instrument(name = "articles.unfavorite", level = "debug", skip_all)]
+    async fn unfavorite(&self, id: Uuid, user: Uuid) -> RepoResult<()> {
+        use crate::schema::favorite_articles;
 
-            query = query.filter(articles::id.eq_any(tagged_article_ids));
-        }
+        let mut conn = self.conn().await?;
+        diesel::delete(favorite_articles::table)
+            .filter(favorite_articles::user_id.eq(user))
+            .filter(favorite_articles::article_id.eq(id))
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(repo_error)
+    }
+}
 
-        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
-        let offset = msg.params.offset.unwrap_or(0) as i64;
+// ================== HELPERS ================== //
 
-        let articles = query
-            .order(articles::created_at.desc())
-            .limit(limit)
-            .offset(offset)
-            .load::<Article>(&mut conn)?;
+// The body is escaped like `search::escape_html`, so only the marks are markup
+const SEARCH_ARTICLES: &str = r#"
+    SELECT articles.id, ts_headline('english', replace(replace(replace(replace(replace(
+        articles.body, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;'),
+        query, $4) AS snippet
+    FROM articles, to_tsquery('english', $1) AS query
+    WHERE articles.search @@ query AND articles.status = 'published' AND articles.hidden_at IS NULL
+    ORDER BY ts_rank(articles.search, query) DESC, articles.created_at DESC, articles.id DESC
+    LIMIT $2 OFFSET $3
+"#;
+
+const COUNT_SEARCH_ARTICLES: &str = "
+    SELECT COUNT(*) AS count FROM articles
+    WHERE search @@ to_tsquery('english', $1) AND status = 'published' AND hidden_at IS NULL
+";
+
+#[derive(QueryableByName)]
+struct Hit {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: Uuid,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+}
 
-        match msg.auth {
-            Some(auth) => get_article_list_response(conn.deref_mut(), articles, Some(auth.user.id)),
-            None => get_article_list_response(conn.deref_mut(), articles, None),
-        }
-    }
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
 }
 
-impl Handler<GetFeedArticles> for DbExecutor {
-    type Result = AppResult<ArticleListResponse>;
+type BoxedArticles<'a> = crate::schema::articles::BoxedQuery<'a, Pg>;
 
-    fn handle(&mut self, msg: GetFeedArticles, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, followers};
+/// The articles that show up in lists.
+fn published<'a>() -> BoxedArticles<'a> {
+    use crate::schema::articles;
 
-        let mut conn = self.0.get()?;
+    articles::table
+        .filter(articles::status.eq(ArticleStatus::Published.as_str()))
+        .filter(articles::hidden_at.is_null())
+        .into_boxed()
+}
 
-        let limit = std::cmp::min(msg.params.limit.unwrap_or(20), 100) as i64;
-        let offset = msg.params.offset.unwrap_or(0) as i64;
-        let user_id = msg.auth.user.id;
+/// Published articles matching every filter that is set.
+fn filtered(filter: &ArticleFilter) -> BoxedArticles<'_> {
+    use crate::schema::{article_tags, articles, favorite_articles, users};
 
-        println!("user_id: {:?}", user_id);
+    let mut query = published();
 
-        let following_ids = followers::table
-            .filter(followers::follower_id.eq(user_id))
-            .select(followers::user_id)
-            .load::<Uuid>(&mut conn)?;
+    // Author username
+    if let Some(ref author_name) = filter.author {
+        let written_by = users::table
+            .filter(users::username.eq(author_name))
+            .select(users::id);
 
-        println!("user_id: {:?}", user_id);
+        query = query.filter(articles::author_id.eq_any(written_by));
+    }
 
-        let articles = articles::table
-            .filter(articles::author_id.eq_any(following_ids))
-            .order(articles::created_at.desc())
-            .limit(limit)
-            .offset(offset)
-            .get_results::<Article>(&mut conn)?;
+    // Favorited by user
+    if let Some(ref favorited_username) = filter.favorited {
+        let favorited_by = favorite_articles::table
+            .inner_join(users::table)
+            .filter(users::username.eq(favorited_username))
+            .select(favorite_articles::article_id);
 
-        get_article_list_response(conn.deref_mut(), articles, Some(user_id))
+        query = query.filter(articles::id.eq_any(favorited_by));
     }
-}
-
-impl Handler<FavoriteArticle> for DbExecutor {
-    type Result = AppResult<ArticleResponse>;
 
-    fn handle(&mut self, msg: FavoriteArticle, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, favorite_articles};
+    // Tags
+    if let Some(ref tag) = filter.tag {
+        let tagged = article_tags::table
+            .filter(article_tags::tag_name.eq(tag))
+            .select(article_tags::article_id);
 
-        let mut conn = self.0.get()?;
+        query = query.filter(articles::id.eq_any(tagged));
+    }
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(&mut conn)?;
+    query
+}
 
-        match diesel::insert_into(favorite_articles::table)
-            .values(NewFavoriteArticle {
-                article_id: article.id,
-                user_id: msg.auth.user.id,
-            })
-            .execute(&mut conn)
-        {
-            Ok(_) => get_article_response(conn.deref_mut(), article.slug, Some(article.author_id)),
-            Err(_) => Err(crate::error::AppError::UnprocessableEntity(
-                json!({ "errors": "Article already favorited" }),
-            )),
-        }
+/// Keeps the articles listed after `cursor`, newest first with ties broken by id.
+fn after_cursor(query: BoxedArticles<'_>, cursor: Option<Cursor<Uuid>>) -> BoxedArticles<'_> {
+    use crate::schema::articles;
+
+    match cursor {
+        Some(cursor) => query.filter(
+            articles::created_at
+                .lt(cursor.created_at)
+                .or(articles::created_at
+                    .eq(cursor.created_at)
+                    .and(articles::id.lt(cursor.id))),
+        ),
+        None => query,
     }
 }
 
-impl Handler<UnFavoriteArticle> for DbExecutor {
-    type Result = AppResult<ArticleResponse>;
-
-    fn handle(&mut self, msg: UnFavoriteArticle, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, favorite_articles};
-
-        let mut conn = self.0.get()?;
+/// Resolves the author, tags and favorite state of `article` as seen by `viewer`.
+async fn load_article(
+    conn: &mut Conn,
+    article: Article,
+    viewer: Option<Uuid>,
+) -> QueryResult<ArticleRecord<Uuid>> {
+    let mut records = load_articles(conn, vec![article], viewer).await?;
+    records.pop().ok_or(DieselError::NotFound)
+}
 
-        let article = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .get_result::<Article>(&mut conn)?;
+/// Same as [`load_article`] for a whole page, in a fixed number of queries however many
+/// articles there are. Keeps the order of `articles`.
+async fn load_articles(
+    conn: &mut Conn,
+    articles: Vec<Article>,
+    viewer: Option<Uuid>,
+) -> QueryResult<Vec<ArticleRecord<Uuid>>> {
+    use crate::schema::{article_tags, favorite_articles, followers, users};
 
-        diesel::delete(favorite_articles::table)
-            .filter(favorite_articles::user_id.eq(msg.auth.user.id))
-            .filter(favorite_articles::article_id.eq(article.id))
-            .execute(&mut conn)?;
+    if articles.is_empty() {
+        return Ok(Vec::new());
+    }
 
-        get_article_response(conn.deref_mut(), article.slug, Some(article.author_id))
+    let article_ids = articles
+        .iter()
+        .map(|article| article.id)
+        .collect::<Vec<_>>();
+    let author_ids = articles
+        .iter()
+        .map(|article| article.author_id)
+        .collect::<Vec<_>>();
+
+    let authors = users::table
+        .filter(users::id.eq_any(&author_ids))
+        .load::<User>(conn)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect::<HashMap<_, _>>();
+
+    let mut tags = HashMap::<Uuid, Vec<String>>::new();
+    for (article_id, tag_name) in article_tags::table
+        .filter(article_tags::article_id.eq_any(&article_ids))
+        .select((article_tags::article_id, article_tags::tag_name))
+        .order(article_tags::tag_name)
+        .load::<(Uuid, String)>(conn)
+        .await?
+    {
+        tags.entry(article_id).or_default().push(tag_name);
     }
-}
 
-// ================== HELPERS ================== //
-fn get_article_response(
-    conn: &mut PgConnection,
-    slug: String,
-    user_id: Option<Uuid>,
-) -> AppResult<ArticleResponse> {
-    use crate::schema::{articles, users};
-
-    let (article, author) = articles::table
-        .inner_join(users::table)
-        .filter(articles::slug.eq(slug))
-        .get_result::<(Article, User)>(conn)?;
-
-    let (favorited, following) = match user_id {
-        Some(user_id) => get_favorited_and_following(conn, article.id, author.id, user_id)?,
-        None => (false, false),
+    let favorites_counts = favorite_articles::table
+        .filter(favorite_articles::article_id.eq_any(&article_ids))
+        .group_by(favorite_articles::article_id)
+        .select((favorite_articles::article_id, count_star()))
+        .load::<(Uuid, i64)>(conn)
+        .await?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let (favorited, followed) = match viewer {
+        Some(viewer) => {
+            let favorited = favorite_articles::table
+                .filter(favorite_articles::user_id.eq(viewer))
+                .filter(favorite_articles::article_id.eq_any(&article_ids))
+                .select(favorite_articles::article_id)
+                .load::<Uuid>(conn)
+                .await?;
+
+            let followed = followers::table
+                .filter(followers::follower_id.eq(viewer))
+                .filter(followers::user_id.eq_any(&author_ids))
+                .select(followers::user_id)
+                .load::<Uuid>(conn)
+                .await?;
+
+            (
+                favorited.into_iter().collect(),
+                followed.into_iter().collect(),
+            )
+        }
+        None => (HashSet::new(), HashSet::new()),
     };
 
-    let favorites_count = get_favorites_count(conn, article.id)?;
-    let tags = select_tags_on_article(conn, article.id)?;
-
-    Ok(ArticleResponse::new(
-        article,
-        author,
-        tags,
-        favorited,
-        favorites_count,
-        following,
-    ))
-}
+    articles
+        .into_iter()
+        .map(|article| {
+            let author = authors
+                .get(&article.author_id)
+                .cloned()
+                .ok_or(DieselError::NotFound)?
+                .into_profile(followed.contains(&article.author_id));
 
-fn get_article_list_response(
-    conn: &mut PgConnection,
-    articles: Vec<Article>,
-    user_id: Option<Uuid>,
-) -> AppResult<ArticleListResponse> {
-    let article_list = articles
-        .iter()
-        .map(
-            |article| match get_article_response(conn, article.slug.to_owned(), user_id) {
-                Ok(response) => Ok(response.article),
-                Err(e) => Err(e),
-            },
-        )
-        .collect::<AppResult<Vec<ArticleResponseInner>>>()?;
+            let tag_list = tags.remove(&article.id).unwrap_or_default();
+            let favorited = favorited.contains(&article.id);
+            let favorites_count = favorites_counts.get(&article.id).copied().unwrap_or(0);
 
-    Ok(ArticleListResponse {
-        articles_count: article_list.len(),
-        articles: article_list,
-    })
+            Ok(article.into_record(author, tag_list, favorited, favorites_count))
+        })
+        .collect()
 }
 
-fn add_tag<T>(conn: &mut PgConnection, article_id: Uuid, tag_name: T) -> AppResult<ArticleTag>
-where
-    T: ToString,
-{
-    use crate::schema::article_tags;
+/// Slugs starting like `base` that articles other than `except` have, or had.
+async fn taken_slugs(
+    conn: &mut Conn,
+    base: &str,
+    except: Option<Uuid>,
+) -> QueryResult<Vec<String>> {
+    use crate::schema::{article_slugs, articles};
+
+    let suffixed = format!("{base}-%");
+    let mut current = articles::table
+        .filter(articles::slug.eq(base).or(articles::slug.like(&suffixed)))
+        .select(articles::slug)
+        .into_boxed();
+    let mut old = article_slugs::table
+        .filter(
+            article_slugs::slug
+                .eq(base)
+                .or(article_slugs::slug.like(&suffixed)),
+        )
+        .select(article_slugs::slug)
+        .into_boxed();
 
-    diesel::insert_into(article_tags::table)
-        .values(NewArticleTag {
-            article_id,
-            tag_name: tag_name.to_string(),
-        })
-        .on_conflict((article_tags::article_id, article_tags::tag_name))
-        .do_nothing()
-        .get_result::<ArticleTag>(conn)
-        .map_err(Into::into)
-}
+    if let Some(id) = except {
+        current = current.filter(articles::id.ne(id));
+        old = old.filter(article_slugs::article_id.ne(id));
+    }
 
-fn replace_tags<I>(conn: &mut PgConnection, article_id: Uuid, tags: I) -> AppResult<Vec<ArticleTag>>
-where
-    I: IntoIterator<Item = String>,
-{
-    delete_tags(article_id, conn)?;
-
-    // this may look confusing but collect can convert to this
-    // https://doc.rust-lang.org/std/iter/trait.Iterator.html#method.collect
-    tags.into_iter()
-        .map(|tag_name| add_tag(conn, article_id, &tag_name))
-        .collect::<AppResult<Vec<ArticleTag>>>()
+    let mut taken = current.load::<String>(conn).await?;
+    taken.extend(old.load::<String>(conn).await?);
+    Ok(taken)
 }
 
-fn delete_tags(article_id: Uuid, conn: &mut PgConnection) -> AppResult<usize> {
-    use crate::schema::article_tags;
+/// Keeps `old` around to redirect to the article, and drops `new` from its old slugs in case
+/// it is getting one of them back.
+async fn rename(conn: &mut Conn, article_id: Uuid, old: &str, new: &str) -> QueryResult<()> {
+    use crate::schema::article_slugs;
 
-    diesel::delete(article_tags::table.filter(article_tags::article_id.eq(article_id)))
+    diesel::delete(article_slugs::table.find(new))
         .execute(conn)
-        .map_err(Into::into)
-}
+        .await?;
 
-fn get_favorited_and_following(
-    conn: &mut PgConnection,
-    article_id: Uuid,
-    author_id: Uuid,
-    user_id: Uuid,
-) -> AppResult<(bool, bool)> {
-    use crate::schema::{favorite_articles, followers, users};
-
-    let (_, favorite_id, follow_id) = users::table
-        .left_join(
-            favorite_articles::table.on(favorite_articles::article_id
-                .eq(users::id)
-                .and(favorite_articles::article_id.eq(article_id))),
-        )
-        .left_join(
-            followers::table.on(followers::follower_id
-                .eq(users::id)
-                .and(followers::user_id.eq(author_id))),
-        )
-        .filter(users::id.eq(user_id))
-        .select((
-            users::id,
-            favorite_articles::user_id.nullable(),
-            followers::user_id.nullable(),
+    diesel::insert_into(article_slugs::table)
+        .values((
+            article_slugs::slug.eq(old),
+            article_slugs::article_id.eq(article_id),
         ))
-        .get_result::<(Uuid, Option<Uuid>, Option<Uuid>)>(conn)?;
+        .execute(conn)
+        .await?;
 
-    Ok((favorite_id.is_some(), follow_id.is_some()))
+    Ok(())
 }
 
-fn get_favorites_count(conn: &mut PgConnection, article_id: Uuid) -> AppResult<i64> {
-    use crate::schema::favorite_articles;
+async fn replace_tags(conn: &mut Conn, article_id: Uuid, tags: Vec<String>) -> QueryResult<()> {
+    use crate::schema::article_tags;
 
-    Ok(favorite_articles::table
-        .filter(favorite_articles::article_id.eq(article_id))
-        .count()
-        .get_result::<i64>(conn)?)
-}
+    diesel::delete(article_tags::table.filter(article_tags::article_id.eq(article_id)))
+        .execute(conn)
+        .await?;
 
-fn select_tags_on_article(conn: &mut PgConnection, article_id: Uuid) -> AppResult<Vec<String>> {
-    use crate::schema::article_tags;
+    let new_tags = tags
+        .into_iter()
+        .map(|tag_name| NewArticleTag {
+            article_id,
+            tag_name,
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(article_tags::table)
+        .values(&new_tags)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
 
-    Ok(article_tags::table
-        .filter(article_tags::article_id.eq(article_id))
-        .select(article_tags::tag_name)
-        .load(conn)?)
+    Ok(())
 }
@@ -1,156 +1,157 @@
-use std::ops::DerefMut;
-
-use actix::prelude::*;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
 use diesel::prelude::*;
-use serde_json::json;
+use diesel_async::RunQueryDsl;
+use realworld_core::repo::{Comment as CommentRecord, CommentRepo, RepoError, RepoResult};
 use uuid::Uuid;
 
-use crate::api::comments::{
-    AddCommentOuter, CommentListResponse, CommentResponse, CommentResponseInner, DeleteComment,
-    GetComments,
-};
-use crate::api::profile::ProfileResponseInner;
-use crate::db::DbExecutor;
-use crate::error::AppResult;
 use crate::models::comment::{Comment, NewComment};
-use crate::models::follower::Follower;
 use crate::models::user::User;
-use crate::utils::CustomDateTime;
-
-impl Handler<AddCommentOuter> for DbExecutor {
-    type Result = AppResult<CommentResponse>;
-
-    fn handle(&mut self, msg: AddCommentOuter, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, comments};
 
-        let mut conn = self.0.get()?;
+use super::{profile::into_profile, repo_error, Conn, PgRepo};
 
-        let article_id = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .select(articles::id)
-            .get_result::<Uuid>(&mut conn)?;
+#[async_trait]
+impl CommentRepo for PgRepo {
+    type Id = Uuid;
 
-        let user_id = msg.auth.user.id;
+    #[tracing::instrument(name = "comments.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        article: Uuid,
+        author: Uuid,
+        body: String,
+    ) -> RepoResult<CommentRecord<Uuid>> {
+        use crate::schema::comments;
 
         let new_comment = NewComment {
-            user_id,
-            article_id,
-            body: msg.comment.body,
+            body,
+            user_id: author,
+            article_id: article,
         };
 
+        let mut conn = self.conn().await?;
         let comment = diesel::insert_into(comments::table)
             .values(new_comment)
-            .get_result::<Comment>(&mut conn)?;
+            .get_result::<Comment>(&mut conn)
+            .await
+            .map_err(repo_error)?;
 
-        get_comment_response(conn.deref_mut(), comment.id, Some(user_id))
+        load_comment(&mut conn, comment, Some(author))
+            .await
+            .map_err(repo_error)
     }
-}
-
-impl Handler<GetComments> for DbExecutor {
-    type Result = AppResult<CommentListResponse>;
 
-    fn handle(&mut self, msg: GetComments, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::{articles, comments};
+    #[tracing::instrument(name = "comments.find", level = "debug", skip_all)]
+    async fn find(&self, id: i32) -> RepoResult<CommentRecord<Uuid>> {
+        use crate::schema::comments;
 
-        let mut conn = self.0.get()?;
+        let mut conn = self.conn().await?;
+        let comment = comments::table
+            .find(id)
+            .first::<Comment>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .ok_or(RepoError::NotFound)?;
+
+        load_comment(&mut conn, comment, None)
+            .await
+            .map_err(repo_error)
+    }
 
-        let article_id = articles::table
-            .filter(articles::slug.eq(msg.slug))
-            .select(articles::id)
-            .get_result::<Uuid>(&mut conn)?;
+    #[tracing::instrument(name = "comments.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        article: Uuid,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<Vec<CommentRecord<Uuid>>> {
+        use crate::schema::comments;
 
-        let comments = comments::table
-            .filter(comments::article_id.eq(article_id))
-            .load::<Comment>(&mut conn)?;
+        let mut conn = self.conn().await?;
+        let mut query = comments::table
+            .filter(comments::article_id.eq(article))
+            .into_boxed();
+        query = match viewer {
+            Some(viewer) => query.filter(
+                comments::hidden_at
+                    .is_null()
+                    .or(comments::user_id.eq(viewer)),
+            ),
+            None => query.filter(comments::hidden_at.is_null()),
+        };
 
-        match msg.auth {
-            Some(auth) => get_comment_list_response(conn.deref_mut(), comments, Some(auth.user.id)),
-            None => get_comment_list_response(conn.deref_mut(), comments, None),
+        let comments = query
+            .order((comments::created_at.desc(), comments::id.desc()))
+            .load::<Comment>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let mut records = Vec::with_capacity(comments.len());
+        for comment in comments {
+            records.push(
+                load_comment(&mut conn, comment, viewer)
+                    .await
+                    .map_err(repo_error)?,
+            );
         }
-    }
-}
 
-impl Handler<DeleteComment> for DbExecutor {
-    type Result = AppResult<serde_json::Value>;
+        Ok(records)
+    }
 
-    fn handle(&mut self, msg: DeleteComment, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "comments.delete", level = "debug", skip_all)]
+    async fn delete(&self, id: i32) -> RepoResult<()> {
         use crate::schema::comments;
 
-        let mut conn = self.0.get()?;
+        let mut conn = self.conn().await?;
+        match diesel::delete(comments::table.find(id))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?
+        {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
 
-        let comment = comments::table
-            .filter(comments::id.eq(msg.comment_id))
-            .get_result::<Comment>(&mut conn)?;
+    #[tracing::instrument(name = "comments.set_hidden", level = "debug", skip_all)]
+    async fn set_hidden(&self, id: i32, hidden: bool) -> RepoResult<()> {
+        use crate::schema::comments;
 
-        if msg.auth.user.id != comment.user_id {
-            return Err(crate::error::AppError::Unauthorized(
-                "You are not authorized to delete this comment",
-            ));
-        }
+        let hidden_at = hidden.then(|| Utc::now().naive_utc());
 
-        match diesel::delete(comments::table.filter(comments::id.eq(msg.comment_id)))
+        let mut conn = self.conn().await?;
+        match diesel::update(comments::table.find(id))
+            .set(comments::hidden_at.eq(hidden_at))
             .execute(&mut conn)
+            .await
+            .map_err(repo_error)?
         {
-            Ok(_) => Ok(json!({ "message": "OK" })),
-            Err(e) => Err(e.into()),
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
         }
     }
 }
 
-fn get_comment_response(
-    conn: &mut PgConnection,
-    comment_id: i32,
-    user_id: Option<Uuid>,
-) -> AppResult<CommentResponse> {
-    use crate::schema::{comments, followers, users};
-
-    let (comment, commenter) = comments::table
-        .inner_join(users::table)
-        .filter(comments::id.eq(comment_id))
-        .get_result::<(Comment, User)>(conn)?;
-
-    let following = match user_id {
-        Some(user_id) => followers::table
-            .filter(followers::user_id.eq(user_id))
-            .filter(followers::follower_id.eq(commenter.id))
-            .first::<Follower>(conn)
-            .optional()?
-            .is_some(),
-        None => false,
-    };
-
-    Ok(CommentResponse {
-        comment: CommentResponseInner {
-            id: comment.id,
-            body: comment.body,
-            created_at: CustomDateTime(comment.created_at),
-            updated_at: CustomDateTime(comment.updated_at),
-            author: ProfileResponseInner {
-                following,
-                bio: commenter.bio,
-                image: commenter.image,
-                username: commenter.username,
-            },
-        },
-    })
-}
-
-fn get_comment_list_response(
-    conn: &mut PgConnection,
-    comments: Vec<Comment>,
-    user_id: Option<Uuid>,
-) -> AppResult<CommentListResponse> {
-    let comment_list = comments
-        .iter()
-        .map(
-            |comment| match get_comment_response(conn, comment.id.to_owned(), user_id) {
-                Ok(response) => Ok(response.comment),
-                Err(e) => Err(e),
-            },
-        )
-        .collect::<AppResult<Vec<CommentResponseInner>>>()?;
-
-    Ok(CommentListResponse {
-        comments: comment_list,
+// ================== HELPERS ================== //
+
+async fn load_comment(
+    conn: &mut Conn,
+    comment: Comment,
+    viewer: Option<Uuid>,
+) -> QueryResult<CommentRecord<Uuid>> {
+    use crate::schema::users;
+
+    let commenter = users::table
+        .find(comment.user_id)
+        .first::<User>(conn)
+        .await?;
+
+    Ok(CommentRecord {
+        id: comment.id,
+        body: comment.body,
+        article_id: comment.article_id,
+        created_at: Utc.from_utc_datetime(&comment.created_at),
+        updated_at: Utc.from_utc_datetime(&comment.updated_at),
+        author: into_profile(conn, commenter, viewer).await?,
     })
 }
@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{Int4, Text, Timestamptz},
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::repo::{LoginAttemptRepo, LoginAttempts, RepoError, RepoResult};
+
+use crate::models::login_attempt::{LoginAttempt, NewLockoutEvent};
+
+use super::{repo_error, PgRepo};
+
+impl From<LoginAttempt> for LoginAttempts {
+    fn from(row: LoginAttempt) -> Self {
+        LoginAttempts {
+            failures: row.failures,
+            last_failed_at: Utc.from_utc_datetime(&row.last_failed_at),
+            locked_until: row.locked_until.map(|until| Utc.from_utc_datetime(&until)),
+        }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptRepo for PgRepo {
+    #[tracing::instrument(name = "login_attempts.find", level = "debug", skip_all)]
+    async fn find(&self, key: &str) -> RepoResult<LoginAttempts> {
+        use crate::schema::login_attempts;
+
+        let mut conn = self.conn().await?;
+        login_attempts::table
+            .find(key)
+            .select(LoginAttempt::as_select())
+            .first::<LoginAttempt>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .map(Into::into)
+            .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "login_attempts.fail", level = "debug", skip_all)]
+    async fn fail(&self, key: &str, stale_before: DateTime<Utc>) -> RepoResult<i32> {
+        let mut conn = self.conn().await?;
+        diesel::sql_query(FAIL_LOGIN)
+            .bind::<Text, _>(key)
+            .bind::<Timestamptz, _>(stale_before.naive_utc())
+            .get_result::<Failures>(&mut conn)
+            .await
+            .map(|row| row.failures)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "login_attempts.lock", level = "debug", skip_all)]
+    async fn lock(&self, key: &str, failures: i32, until: DateTime<Utc>) -> RepoResult<()> {
+        use crate::schema::{lockout_events, login_attempts};
+
+        let key = key.to_string();
+        let until = until.naive_utc();
+        let mut conn = self.conn().await?;
+        conn.transaction(|conn| {
+            async move {
+                diesel::insert_into(login_attempts::table)
+                    .values((
+                        login_attempts::key.eq(&key),
+                        login_attempts::failures.eq(failures),
+                        login_attempts::locked_until.eq(until),
+                    ))
+                    .on_conflict(login_attempts::key)
+                    .do_update()
+                    .set(login_attempts::locked_until.eq(until))
+                    .execute(conn)
+                    .await?;
+
+                diesel::insert_into(lockout_events::table)
+                    .values(NewLockoutEvent {
+                        key: &key,
+                        failures,
+                        locked_until: until,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "login_attempts.clear", level = "debug", skip_all)]
+    async fn clear(&self, key: &str) -> RepoResult<()> {
+        use crate::schema::login_attempts;
+
+        let mut conn = self.conn().await?;
+        diesel::delete(login_attempts::table.find(key))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+}
+
+/// Starts over once neither the last failure nor a lockout is recent, `GREATEST` ignores a
+/// missing lockout.
+const FAIL_LOGIN: &str = "
+    INSERT INTO login_attempts (key, failures) VALUES ($1, 1)
+    ON CONFLICT (key) DO UPDATE
+    SET failures = CASE
+            WHEN GREATEST(login_attempts.last_failed_at, login_attempts.locked_until) < $2
+            THEN 1
+            ELSE login_attempts.failures + 1
+        END,
+        last_failed_at = NOW()
+    RETURNING failures
+";
+
+#[derive(QueryableByName)]
+struct Failures {
+    #[diesel(sql_type = Int4)]
+    failures: i32,
+}
@@ -1,28 +1,78 @@
-pub mod auth;
-pub use auth::*;
-pub mod comments;
-pub use comments::*;
-pub mod articles;
-pub use articles::*;
-pub mod profile;
-pub use profile::*;
+mod access_token;
+mod account;
+mod articles;
+mod comments;
+mod login_attempt;
+mod oidc;
+mod profile;
+mod revisions;
+mod sessions;
 mod tags;
-pub use tags::*;
+mod totp;
 mod user;
-pub use user::*;
 
-use actix::prelude::{Actor, SyncContext};
+use std::error::Error;
+
 use diesel::{
-    r2d2::{ConnectionManager, Pool},
-    PgConnection,
+    result::{DatabaseErrorKind, Error as DieselError},
+    Connection, PgConnection,
 };
+use diesel_async::{pooled_connection::bb8, AsyncPgConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use realworld_core::repo::{RepoError, RepoResult};
+use uuid::Uuid;
+
+pub type Conn = AsyncPgConnection;
+pub type PgPool = bb8::Pool<Conn>;
+pub type PooledConn<'a> = bb8::PooledConnection<'a, Conn>;
+
+pub type Repos = realworld_core::repo::Repos<Uuid>;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Brings the schema up to date before the server starts taking requests. Migrations run
+/// on a blocking connection of their own, outside of the pool.
+pub fn run_migrations(database_url: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut conn = PgConnection::establish(database_url)?;
+    conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(())
+}
 
-pub type Conn = PgConnection;
-pub type PgPool = Pool<ConnectionManager<Conn>>;
-// pub type PooledConn = PooledConnection<ConnectionManager<Conn>>;
+// ================================== Repository ================================== //
 
-pub struct DbExecutor(pub PgPool);
+/// Postgres implementation of every repository in `realworld_core::repo`.
+#[derive(Clone)]
+pub struct PgRepo {
+    pool: PgPool,
+}
+
+impl PgRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn into_repos(self) -> Repos {
+        Repos::from_store(self)
+    }
+
+    /// Waits for a free connection, so concurrent queries are bounded by the pool size.
+    async fn conn(&self) -> RepoResult<PooledConn<'_>> {
+        self.pool.get().await.map_err(RepoError::backend)
+    }
+}
 
-impl Actor for DbExecutor {
-    type Context = SyncContext<Self>;
+/// Maps diesel errors onto repository errors. Unique violations become a conflict on the
+/// column named by the constraint, e.g. `articles_slug_key` becomes `slug`.
+pub(crate) fn repo_error(error: DieselError) -> RepoError {
+    match error {
+        DieselError::NotFound => RepoError::NotFound,
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) => {
+            let field = info
+                .constraint_name()
+                .and_then(|constraint| constraint.split('_').nth(1))
+                .unwrap_or("record");
+            RepoError::Conflict(field.to_string())
+        }
+        error => RepoError::backend(error),
+    }
 }
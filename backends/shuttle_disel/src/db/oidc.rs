@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use realworld_core::{
+    oidc::PendingLogin,
+    repo::{OidcRepo, RepoResult},
+};
+use uuid::Uuid;
+
+use crate::models::oidc::{NewOidcLogin, NewUserIdentity, OidcLogin};
+
+use super::{repo_error, PgRepo};
+
+impl From<OidcLogin> for PendingLogin {
+    fn from(row: OidcLogin) -> Self {
+        PendingLogin {
+            provider: row.provider,
+            code_verifier: row.code_verifier,
+            nonce: row.nonce,
+        }
+    }
+}
+
+#[async_trait]
+impl OidcRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "oidc.start_login", level = "debug", skip_all)]
+    async fn start_login(
+        &self,
+        state_hash: &str,
+        login: PendingLogin,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<()> {
+        use crate::schema::oidc_logins;
+
+        let mut conn = self.conn().await?;
+
+        // Abandoned logins are cleaned up as new ones come in
+        diesel::delete(oidc_logins::table)
+            .filter(oidc_logins::expires_at.le(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        diesel::insert_into(oidc_logins::table)
+            .values(NewOidcLogin {
+                state_hash,
+                provider: login.provider,
+                code_verifier: login.code_verifier,
+                nonce: login.nonce,
+                expires_at: expires_at.naive_utc(),
+            })
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "oidc.finish_login", level = "debug", skip_all)]
+    async fn finish_login(&self, state_hash: &str) -> RepoResult<PendingLogin> {
+        use crate::schema::oidc_logins;
+
+        let mut conn = self.conn().await?;
+        diesel::delete(oidc_logins::table.find(state_hash))
+            .filter(oidc_logins::expires_at.gt(Utc::now().naive_utc()))
+            .returning(OidcLogin::as_returning())
+            .get_result::<OidcLogin>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "oidc.find_identity", level = "debug", skip_all)]
+    async fn find_identity(&self, provider: &str, subject: &str) -> RepoResult<Uuid> {
+        use crate::schema::user_identities;
+
+        let mut conn = self.conn().await?;
+        user_identities::table
+            .find((provider, subject))
+            .select(user_identities::user_id)
+            .first::<Uuid>(&mut conn)
+            .await
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "oidc.link_identity", level = "debug", skip_all)]
+    async fn link_identity(&self, user: Uuid, provider: &str, subject: &str) -> RepoResult<()> {
+        use crate::schema::user_identities;
+
+        let mut conn = self.conn().await?;
+        diesel::insert_into(user_identities::table)
+            .values(NewUserIdentity {
+                provider,
+                subject,
+                user_id: user,
+            })
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+}
@@ -1,120 +1,78 @@
-use actix::prelude::*;
+use async_trait::async_trait;
 use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use realworld_core::repo::{FollowRepo, Profile, RepoResult};
+use uuid::Uuid;
 
-use crate::api::profile::{FollowProfile, GetProfile, ProfileResponse, UnFollowProfile};
-use crate::db::DbExecutor;
-use crate::error::{AppError, AppResult};
-use crate::models::follower::{Follower, NewFollower};
+use crate::models::follower::NewFollower;
 use crate::models::user::User;
 
-impl Handler<GetProfile> for DbExecutor {
-    type Result = AppResult<ProfileResponse>;
+use super::{repo_error, Conn, PgRepo};
 
-    fn handle(&mut self, msg: GetProfile, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::followers::dsl::*;
-        use crate::schema::users::dsl::*;
+#[async_trait]
+impl FollowRepo for PgRepo {
+    type Id = Uuid;
 
-        let mut conn = self.0.get()?;
-        let user: User = {
-            match users
-                .filter(username.eq(msg.username))
-                .first(&mut conn)
-                .optional()?
-            {
-                Some(user) => user,
-                None => return Err(crate::error::AppError::not_found("User Profile not found")),
-            }
-        };
+    #[tracing::instrument(name = "follows.follow", level = "debug", skip_all)]
+    async fn follow(&self, follower: Uuid, followee: Uuid) -> RepoResult<()> {
+        use crate::schema::followers;
 
-        let following = match msg.auth {
-            Some(auth) => followers
-                .filter(user_id.eq(user.id))
-                .filter(follower_id.eq(auth.user.id))
-                .first::<Follower>(&mut conn)
-                .optional()?
-                .is_some(),
-            None => false,
-        };
-
-        Ok(ProfileResponse::new(user, following))
-    }
-}
-
-impl Handler<FollowProfile> for DbExecutor {
-    type Result = AppResult<ProfileResponse>;
-
-    fn handle(&mut self, msg: FollowProfile, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::followers::dsl::*;
-        use crate::schema::users::dsl::*;
-
-        let mut conn = self.0.get()?;
-
-        let user: User = {
-            match users
-                .filter(username.eq(msg.username))
-                .first(&mut conn)
-                .optional()?
-            {
-                Some(user) => user,
-                None => return Err(AppError::not_found("User Profile not found")),
-            }
-        };
-
-        let followee: User = msg.auth.user;
-        if user.id == followee.id {
-            return Err(AppError::UnprocessableEntity(
-                serde_json::json!({"error": "You cannot follow yourself"}),
-            ));
-        }
-
-        match diesel::insert_into(followers)
+        let mut conn = self.conn().await?;
+        diesel::insert_into(followers::table)
             .values(&NewFollower {
-                user_id: user.id,
-                follower_id: followee.id,
+                user_id: followee,
+                follower_id: follower,
             })
+            .on_conflict_do_nothing()
             .execute(&mut conn)
-        {
-            Ok(_) => Ok(ProfileResponse::new(user, true)),
-            Err(_) => Err(AppError::UnprocessableEntity(
-                serde_json::json!({"error": "You are already following this user"}),
-            )),
-        }
+            .await
+            .map(|_| ())
+            .map_err(repo_error)
     }
-}
-
-impl Handler<UnFollowProfile> for DbExecutor {
-    type Result = AppResult<ProfileResponse>;
-
-    fn handle(&mut self, msg: UnFollowProfile, _: &mut Self::Context) -> Self::Result {
-        use crate::schema::followers::dsl::*;
-        use crate::schema::users::dsl::*;
 
-        let mut conn = self.0.get()?;
+    #[tracing::instrument(name = "follows.unfollow", level = "debug", skip_all)]
+    async fn unfollow(&self, follower: Uuid, followee: Uuid) -> RepoResult<()> {
+        use crate::schema::followers;
 
-        let user: User = {
-            match users
-                .filter(username.eq(msg.username))
-                .first(&mut conn)
-                .optional()?
-            {
-                Some(user) => user,
-                None => return Err(AppError::not_found("User Profile not found")),
-            }
-        };
-
-        let followee: User = msg.auth.user;
-        if user.id == followee.id {
-            return Err(AppError::UnprocessableEntity(
-                serde_json::json!({"error": "You cannot unfollow yourself"}),
-            ));
-        }
+        let mut conn = self.conn().await?;
+        diesel::delete(followers::table)
+            .filter(followers::user_id.eq(followee))
+            .filter(followers::follower_id.eq(follower))
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(repo_error)
+    }
+}
 
-        diesel::delete(followers)
-            .filter(user_id.eq(user.id))
-            .filter(follower_id.eq(followee.id))
-            .returning(Follower::as_returning())
-            .execute(&mut conn)?;
+// ================== HELPERS ================== //
+
+/// Whether `viewer` follows `user_id`. Anonymous viewers follow nobody.
+pub(super) async fn is_following(
+    conn: &mut Conn,
+    user_id: Uuid,
+    viewer: Option<Uuid>,
+) -> QueryResult<bool> {
+    use crate::schema::followers;
+
+    let Some(viewer) = viewer else {
+        return Ok(false);
+    };
+
+    diesel::select(diesel::dsl::exists(
+        followers::table
+            .filter(followers::user_id.eq(user_id))
+            .filter(followers::follower_id.eq(viewer)),
+    ))
+    .get_result(conn)
+    .await
+}
 
-        Ok(ProfileResponse::new(user, false))
-    }
+pub(super) async fn into_profile(
+    conn: &mut Conn,
+    user: User,
+    viewer: Option<Uuid>,
+) -> QueryResult<Profile<Uuid>> {
+    let following = is_following(conn, user.id, viewer).await?;
+    Ok(user.into_profile(following))
 }
@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use realworld_core::repo::{RepoError, RepoResult, Revision as RevisionRecord, RevisionRepo};
+use uuid::Uuid;
+
+use crate::models::articles::Article;
+use crate::models::revision::Revision;
+use crate::models::user::User;
+
+use super::{profile::into_profile, repo_error, Conn, PgRepo};
+
+#[async_trait]
+impl RevisionRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "revisions.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        article: Uuid,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<Vec<RevisionRecord<Uuid>>> {
+        use crate::schema::article_revisions;
+
+        let mut conn = self.conn().await?;
+        let revisions = article_revisions::table
+            .filter(article_revisions::article_id.eq(article))
+            .order(article_revisions::number.desc())
+            .load::<Revision>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let mut records = Vec::with_capacity(revisions.len());
+        for revision in revisions {
+            records.push(
+                load_revision(&mut conn, revision, viewer)
+                    .await
+                    .map_err(repo_error)?,
+            );
+        }
+
+        Ok(records)
+    }
+
+    #[tracing::instrument(name = "revisions.find", level = "debug", skip_all)]
+    async fn find(
+        &self,
+        article: Uuid,
+        number: i32,
+        viewer: Option<Uuid>,
+    ) -> RepoResult<RevisionRecord<Uuid>> {
+        use crate::schema::article_revisions;
+
+        let mut conn = self.conn().await?;
+        let revision = article_revisions::table
+            .find((article, number))
+            .first::<Revision>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .ok_or(RepoError::NotFound)?;
+
+        load_revision(&mut conn, revision, viewer)
+            .await
+            .map_err(repo_error)
+    }
+}
+
+// ================== HELPERS ================== //
+
+async fn load_revision(
+    conn: &mut Conn,
+    revision: Revision,
+    viewer: Option<Uuid>,
+) -> QueryResult<RevisionRecord<Uuid>> {
+    use crate::schema::users;
+
+    let author = match revision.user_id {
+        Some(editor) => {
+            let editor = users::table.find(editor).first::<User>(conn).await?;
+            Some(into_profile(conn, editor, viewer).await?)
+        }
+        None => None,
+    };
+
+    Ok(RevisionRecord {
+        article_id: revision.article_id,
+        number: revision.number,
+        title: revision.title,
+        description: revision.description,
+        body: revision.body,
+        changed_fields: revision.changed_fields,
+        created_at: Utc.from_utc_datetime(&revision.created_at),
+        author,
+    })
+}
+
+/// Snapshots the current content of `article` as its next revision.
+pub(super) async fn add_revision(
+    conn: &mut Conn,
+    article: &Article,
+    user_id: Uuid,
+    changed_fields: Vec<String>,
+) -> QueryResult<()> {
+    use crate::schema::article_revisions;
+
+    let last = article_revisions::table
+        .filter(article_revisions::article_id.eq(article.id))
+        .select(diesel::dsl::max(article_revisions::number))
+        .first::<Option<i32>>(conn)
+        .await?;
+
+    diesel::insert_into(article_revisions::table)
+        .values(Revision {
+            article_id: article.id,
+            number: last.unwrap_or(0) + 1,
+            title: article.title.clone(),
+            description: article.description.clone(),
+            body: article.body.clone(),
+            changed_fields,
+            user_id: Some(user_id),
+            created_at: article.updated_at,
+        })
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
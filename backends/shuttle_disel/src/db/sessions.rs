@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::prelude::*;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::repo::{RepoError, RepoResult, Session as SessionRecord, SessionRepo};
+use uuid::Uuid;
+
+use crate::models::session::{NewSession, RefreshToken, Session};
+
+use super::{repo_error, PgRepo};
+
+impl From<Session> for SessionRecord<Uuid> {
+    fn from(session: Session) -> Self {
+        SessionRecord {
+            id: session.id,
+            user_id: session.user_id,
+            created_at: Utc.from_utc_datetime(&session.created_at),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "sessions.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        user: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<SessionRecord<Uuid>> {
+        use crate::schema::{refresh_tokens, sessions};
+
+        let token_hash = token_hash.to_string();
+        let mut conn = self.conn().await?;
+        conn.transaction(|conn| {
+            async move {
+                let session = diesel::insert_into(sessions::table)
+                    .values(NewSession { user_id: user })
+                    .get_result::<Session>(conn)
+                    .await?;
+
+                diesel::insert_into(refresh_tokens::table)
+                    .values(RefreshToken {
+                        token_hash,
+                        session_id: session.id,
+                        expires_at: expires_at.naive_utc(),
+                        used_at: None,
+                    })
+                    .execute(conn)
+                    .await?;
+
+                Ok(session)
+            }
+            .scope_boxed()
+        })
+        .await
+        .map(Into::into)
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "sessions.find", level = "debug", skip_all)]
+    async fn find(&self, id: Uuid) -> RepoResult<SessionRecord<Uuid>> {
+        use crate::schema::sessions;
+
+        let mut conn = self.conn().await?;
+        sessions::table
+            .find(id)
+            .filter(sessions::revoked_at.is_null())
+            .first::<Session>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "sessions.rotate", level = "debug", skip_all)]
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<SessionRecord<Uuid>> {
+        use crate::schema::{refresh_tokens, sessions};
+
+        let (token_hash, new_hash) = (token_hash.to_string(), new_hash.to_string());
+        let mut conn = self.conn().await?;
+
+        // A replayed token revokes its session, so that part has to be committed even though
+        // the refresh itself fails
+        let session = conn
+            .transaction(|conn| {
+                async move {
+                    let Some((token, session)) = refresh_tokens::table
+                        .inner_join(sessions::table)
+                        .filter(refresh_tokens::token_hash.eq(&token_hash))
+                        .select((RefreshToken::as_select(), Session::as_select()))
+                        .for_update()
+                        .first::<(RefreshToken, Session)>(conn)
+                        .await
+                        .optional()?
+                    else {
+                        return Ok(None);
+                    };
+
+                    let now = Utc::now().naive_utc();
+                    if token.used_at.is_some() {
+                        diesel::update(sessions::table.find(session.id))
+                            .filter(sessions::revoked_at.is_null())
+                            .set(sessions::revoked_at.eq(now))
+                            .execute(conn)
+                            .await?;
+                        return Ok(None);
+                    }
+                    if session.revoked_at.is_some() || token.expires_at <= now {
+                        return Ok(None);
+                    }
+
+                    diesel::update(refresh_tokens::table.find(&token_hash))
+                        .set(refresh_tokens::used_at.eq(now))
+                        .execute(conn)
+                        .await?;
+                    diesel::insert_into(refresh_tokens::table)
+                        .values(RefreshToken {
+                            token_hash: new_hash,
+                            session_id: session.id,
+                            expires_at: expires_at.naive_utc(),
+                            used_at: None,
+                        })
+                        .execute(conn)
+                        .await?;
+
+                    Ok(Some(session))
+                }
+                .scope_boxed()
+            })
+            .await
+            .map_err(repo_error)?;
+
+        session.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "sessions.revoke", level = "debug", skip_all)]
+    async fn revoke(&self, id: Uuid) -> RepoResult<()> {
+        use crate::schema::sessions;
+
+        let mut conn = self.conn().await?;
+        diesel::update(sessions::table.find(id))
+            .filter(sessions::revoked_at.is_null())
+            .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "sessions.revoke_all", level = "debug", skip_all)]
+    async fn revoke_all(&self, user: Uuid) -> RepoResult<()> {
+        use crate::schema::sessions;
+
+        let mut conn = self.conn().await?;
+        diesel::update(sessions::table)
+            .filter(sessions::user_id.eq(user))
+            .filter(sessions::revoked_at.is_null())
+            .set(sessions::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(())
+    }
+}
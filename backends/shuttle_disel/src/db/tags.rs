@@ -1,30 +1,28 @@
-use actix::prelude::*;
-use diesel::prelude::*;
-
-use crate::api::tags::{GetTags, TagsResponse};
-use crate::db::DbExecutor;
-use crate::error::AppResult;
-use crate::models::tags::ArticleTag;
-
-impl Handler<GetTags> for DbExecutor {
-    type Result = AppResult<TagsResponse>;
-
-    fn handle(&mut self, _: GetTags, _: &mut Self::Context) -> Self::Result {
+use async_trait::async_trait;
+use diesel::{dsl::count_star, prelude::*};
+use diesel_async::RunQueryDsl;
+use realworld_core::article::ArticleStatus;
+use realworld_core::repo::{RepoResult, TagRepo};
+
+use super::{repo_error, PgRepo};
+
+#[async_trait]
+impl TagRepo for PgRepo {
+    #[tracing::instrument(name = "tags.list", level = "debug", skip_all)]
+    async fn list(&self) -> RepoResult<Vec<String>> {
         use crate::schema::article_tags::dsl::*;
-
-        let mut conn = self.0.get()?;
-
-        println!("Getting tags");
-
-        let tags = article_tags
-            .distinct_on(tag_name)
-            .load::<ArticleTag>(&mut conn)?;
-
-        let tag_list = tags
-            .iter()
-            .map(|tag| tag.tag_name.to_owned())
-            .collect::<Vec<String>>();
-
-        Ok(TagsResponse { tags: tag_list })
+        use crate::schema::articles;
+
+        let mut conn = self.conn().await?;
+        article_tags
+            .inner_join(articles::table)
+            .filter(articles::status.eq(ArticleStatus::Published.as_str()))
+            .filter(articles::hidden_at.is_null())
+            .group_by(tag_name)
+            .select(tag_name)
+            .order((count_star().desc(), tag_name.asc()))
+            .load::<String>(&mut conn)
+            .await
+            .map_err(repo_error)
     }
 }
@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::repo::{RepoError, RepoResult, Totp, TotpRepo};
+use uuid::Uuid;
+
+use crate::models::totp::{NewRecoveryCode, NewTotpChallenge, NewUserTotp, UserTotp};
+
+use super::{repo_error, Conn, PgRepo};
+
+impl From<UserTotp> for Totp {
+    fn from(row: UserTotp) -> Self {
+        Totp {
+            secret: row.secret,
+            enabled: row.enabled_at.is_some(),
+            last_step: row.last_step,
+        }
+    }
+}
+
+#[async_trait]
+impl TotpRepo for PgRepo {
+    type Id = Uuid;
+
+    #[tracing::instrument(name = "totp.enroll", level = "debug", skip_all)]
+    async fn enroll(&self, user: Uuid, secret: &str) -> RepoResult<()> {
+        use crate::schema::user_totp;
+        // Filters the conflict action rather than the statement
+        use diesel::query_dsl::methods::FilterDsl;
+
+        // An enabled secret is left alone, which shows as no row being written
+        let mut conn = self.conn().await?;
+        let written = diesel::insert_into(user_totp::table)
+            .values(NewUserTotp {
+                user_id: user,
+                secret,
+            })
+            .on_conflict(user_totp::user_id)
+            .do_update()
+            .set((
+                user_totp::secret.eq(excluded(user_totp::secret)),
+                user_totp::last_step.eq(None::<i64>),
+                user_totp::created_at.eq(Utc::now().naive_utc()),
+            ))
+            .filter(user_totp::enabled_at.is_null())
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match written {
+            0 => Err(RepoError::Conflict("totp".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.find", level = "debug", skip_all)]
+    async fn find(&self, user: Uuid) -> RepoResult<Totp> {
+        use crate::schema::user_totp;
+
+        let mut conn = self.conn().await?;
+        user_totp::table
+            .find(user)
+            .select(UserTotp::as_select())
+            .first::<UserTotp>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "totp.enable", level = "debug", skip_all)]
+    async fn enable(
+        &self,
+        user: Uuid,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> RepoResult<()> {
+        use crate::schema::{totp_recovery_codes, user_totp};
+
+        let mut conn = self.conn().await?;
+        conn.transaction(|conn| {
+            async move {
+                let enabled = diesel::update(user_totp::table.find(user))
+                    .filter(user_totp::enabled_at.is_null())
+                    .set((
+                        user_totp::enabled_at.eq(Utc::now().naive_utc()),
+                        user_totp::last_step.eq(step),
+                    ))
+                    .execute(conn)
+                    .await?;
+
+                if enabled == 0 {
+                    return Err(diesel::result::Error::NotFound);
+                }
+
+                diesel::delete(totp_recovery_codes::table)
+                    .filter(totp_recovery_codes::user_id.eq(user))
+                    .execute(conn)
+                    .await?;
+
+                let codes: Vec<NewRecoveryCode> = recovery_code_hashes
+                    .into_iter()
+                    .map(|code_hash| NewRecoveryCode {
+                        user_id: user,
+                        code_hash,
+                    })
+                    .collect();
+                diesel::insert_into(totp_recovery_codes::table)
+                    .values(&codes)
+                    .execute(conn)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "totp.use_step", level = "debug", skip_all)]
+    async fn use_step(&self, user: Uuid, step: i64) -> RepoResult<()> {
+        use crate::schema::user_totp;
+
+        let mut conn = self.conn().await?;
+        let used = diesel::update(user_totp::table.find(user))
+            .filter(user_totp::enabled_at.is_not_null())
+            .filter(
+                user_totp::last_step
+                    .is_null()
+                    .or(user_totp::last_step.lt(step)),
+            )
+            .set(user_totp::last_step.eq(step))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match used {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.use_recovery_code", level = "debug", skip_all)]
+    async fn use_recovery_code(&self, user: Uuid, code_hash: &str) -> RepoResult<()> {
+        use crate::schema::totp_recovery_codes;
+
+        let mut conn = self.conn().await?;
+        let used = diesel::update(totp_recovery_codes::table.find((user, code_hash)))
+            .filter(totp_recovery_codes::used_at.is_null())
+            .set(totp_recovery_codes::used_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match used {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.challenge_spent", level = "debug", skip_all)]
+    async fn challenge_spent(&self, id: &str) -> RepoResult<bool> {
+        use crate::schema::totp_challenges;
+
+        let mut conn = self.conn().await?;
+        let spent = totp_challenges::table
+            .find(id)
+            .select(totp_challenges::spent_at.is_not_null())
+            .first::<bool>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?;
+
+        Ok(spent.unwrap_or(false))
+    }
+
+    #[tracing::instrument(name = "totp.fail_challenge", level = "debug", skip_all)]
+    async fn fail_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<i32> {
+        use crate::schema::totp_challenges;
+
+        let mut conn = self.conn().await?;
+        forget_expired_challenges(&mut conn).await?;
+
+        diesel::insert_into(totp_challenges::table)
+            .values(NewTotpChallenge {
+                id,
+                failures: 1,
+                spent_at: None,
+                expires_at: expires_at.naive_utc(),
+            })
+            .on_conflict(totp_challenges::id)
+            .do_update()
+            .set(totp_challenges::failures.eq(totp_challenges::failures + 1))
+            .returning(totp_challenges::failures)
+            .get_result::<i32>(&mut conn)
+            .await
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "totp.spend_challenge", level = "debug", skip_all)]
+    async fn spend_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<()> {
+        use crate::schema::totp_challenges;
+        // Filters the conflict action rather than the statement
+        use diesel::query_dsl::methods::FilterDsl;
+
+        let mut conn = self.conn().await?;
+        forget_expired_challenges(&mut conn).await?;
+
+        // A spent challenge is left alone, which shows as no row being written
+        let now = Utc::now().naive_utc();
+        let spent = diesel::insert_into(totp_challenges::table)
+            .values(NewTotpChallenge {
+                id,
+                failures: 0,
+                spent_at: Some(now),
+                expires_at: expires_at.naive_utc(),
+            })
+            .on_conflict(totp_challenges::id)
+            .do_update()
+            .set(totp_challenges::spent_at.eq(now))
+            .filter(totp_challenges::spent_at.is_null())
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match spent {
+            0 => Err(RepoError::Conflict("challenge".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Challenges are cleaned up as new ones are written.
+async fn forget_expired_challenges(conn: &mut Conn) -> RepoResult<()> {
+    use crate::schema::totp_challenges;
+
+    diesel::delete(totp_challenges::table)
+        .filter(totp_challenges::expires_at.le(Utc::now().naive_utc()))
+        .execute(conn)
+        .await
+        .map_err(repo_error)?;
+    Ok(())
+}
@@ -1,101 +1,354 @@
-use actix::prelude::*;
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, TimeZone, Utc};
 use diesel::prelude::*;
-use libreauth::pass::HashBuilder;
-
-use crate::{
-    api::user::{LoginUser, RegistrationUser, UpdateUserOuter, UserResponse},
-    error::{AppError, AppResult},
-    models::user::{NewUser, User, UserChange},
-    utils::HASHER,
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use realworld_core::repo::{
+    AuthoredComment, NewUser as NewUserRecord, PersonalData, Profile, RepoError, RepoResult,
+    User as UserRecord, UserChanges, UserList, UserRepo,
 };
+use uuid::Uuid;
+
+use crate::models::user::{NewUser, User, UserChange};
+
+use super::{profile::into_profile, repo_error, PgRepo};
+
+define_sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
 
-use super::DbExecutor;
+impl From<User> for UserRecord<Uuid> {
+    fn from(user: User) -> Self {
+        UserRecord {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            password_hash: user.password,
+            bio: user.bio,
+            image: user.image,
+            email_verified: user.email_verified_at.is_some(),
+            // The column is checked against the known roles
+            role: user.role.parse().unwrap_or_default(),
+        }
+    }
+}
 
-impl Handler<RegistrationUser> for DbExecutor {
-    type Result = AppResult<UserResponse>;
+#[async_trait]
+impl UserRepo for PgRepo {
+    type Id = Uuid;
 
-    fn handle(&mut self, msg: RegistrationUser, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "users.create", level = "debug", skip_all)]
+    async fn create(&self, user: NewUserRecord) -> RepoResult<UserRecord<Uuid>> {
         use crate::schema::users::dsl::*;
 
         let new_user = NewUser {
             bio: None,
             image: None,
-            email: msg.email.clone(),
-            username: msg.username.clone(),
-            password: HASHER.hash(&msg.password)?,
+            email: user.email,
+            username: user.username,
+            password: user.password_hash,
         };
 
-        let mut conn = self.0.get()?;
-
-        match diesel::insert_into(users)
+        let mut conn = self.conn().await?;
+        diesel::insert_into(users)
             .values(new_user)
             .get_result::<User>(&mut conn)
-        {
-            Ok(user) => Ok(user.into()),
-            Err(e) => Err(e.into()),
-        }
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
     }
-}
-
-impl Handler<LoginUser> for DbExecutor {
-    type Result = AppResult<UserResponse>;
 
-    fn handle(&mut self, msg: LoginUser, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "users.find", level = "debug", skip_all)]
+    async fn find(&self, user_id: Uuid) -> RepoResult<UserRecord<Uuid>> {
         use crate::schema::users::dsl::*;
-        let mut conn = self.0.get()?;
-
-        let stored_user: User = users.filter(email.eq(msg.email)).first(&mut conn)?;
-        let checker = HashBuilder::from_phc(&stored_user.password)?;
-
-        let provided_password_raw = &msg.password;
-        if checker.is_valid(provided_password_raw) {
-            if checker.needs_update(Some(crate::utils::PWD_SCHEME_VERSION)) {
-                let new_password = HASHER.hash(provided_password_raw)?;
-                return match diesel::update(users.find(stored_user.id))
-                    .set(password.eq(new_password))
-                    .get_result::<User>(&mut conn)
-                {
-                    Ok(user) => Ok(user.into()),
-                    Err(e) => Err(e.into()),
-                };
-            }
-            Ok(stored_user.into())
-        } else {
-            Err(AppError::Unauthorized("Wrong password"))
-        }
+
+        let mut conn = self.conn().await?;
+        users
+            .find(user_id)
+            .first::<User>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
     }
-}
 
-impl Handler<UpdateUserOuter> for DbExecutor {
-    type Result = AppResult<UserResponse>;
+    #[tracing::instrument(name = "users.find_by_email", level = "debug", skip_all)]
+    async fn find_by_email(&self, user_email: &str) -> RepoResult<UserRecord<Uuid>> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.conn().await?;
+        // Unique ignoring case, see the unique_users migration
+        users
+            .filter(lower(email).eq(user_email.to_lowercase()))
+            .first::<User>(&mut conn)
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
 
-    fn handle(&mut self, msg: UpdateUserOuter, _: &mut Self::Context) -> Self::Result {
+    #[tracing::instrument(name = "users.update", level = "debug", skip_all)]
+    async fn update(&self, user_id: Uuid, changes: UserChanges) -> RepoResult<UserRecord<Uuid>> {
         use crate::schema::users::dsl::*;
 
-        let auth = msg.auth;
-        let update_user = msg.update_user;
+        let mut conn = self.conn().await?;
+        let current_user = users
+            .find(user_id)
+            .first::<User>(&mut conn)
+            .await
+            .map_err(repo_error)?;
 
-        let updated_password = match update_user.password {
-            Some(updated_password) => Some(HASHER.hash(&updated_password)?),
+        let email_changed = changes
+            .email
+            .as_ref()
+            .is_some_and(|new_email| *new_email != current_user.email);
+        let verified_at = match changes.email_verified {
+            Some(true) => Some(Some(
+                current_user
+                    .email_verified_at
+                    .unwrap_or_else(|| Utc::now().naive_utc()),
+            )),
+            Some(false) => Some(None),
+            None if email_changed => Some(None),
             None => None,
         };
 
         let updated_user = UserChange {
-            bio: update_user.bio,
-            email: update_user.email,
-            image: update_user.image,
-            password: updated_password,
-            username: update_user.username,
+            bio: changes.bio,
+            email: changes.email,
+            image: changes.image,
+            password: changes.password_hash,
+            username: changes.username,
+            email_verified_at: verified_at,
+            role: changes.role.map(|new_role| new_role.as_str().to_string()),
         };
 
-        let mut conn = self.0.get()?;
-        let current_user = users.find(auth.user.id).first::<User>(&mut conn)?;
-        match diesel::update(&current_user)
+        // diesel refuses to build an UPDATE without any columns in it
+        if updated_user.is_empty() {
+            return Ok(current_user.into());
+        }
+
+        diesel::update(&current_user)
             .set(updated_user)
             .get_result::<User>(&mut conn)
-        {
-            Err(e) => Err(e.into()),
-            Ok(user) => Ok(user.into()),
+            .await
+            .map(Into::into)
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "users.profile", level = "debug", skip_all)]
+    async fn profile(&self, name: &str, viewer: Option<Uuid>) -> RepoResult<Profile<Uuid>> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.conn().await?;
+        let user = users
+            .filter(username.eq(name))
+            .first::<User>(&mut conn)
+            .await
+            .optional()
+            .map_err(repo_error)?
+            .ok_or(RepoError::NotFound)?;
+
+        into_profile(&mut conn, user, viewer)
+            .await
+            .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "users.list", level = "debug", skip_all)]
+    async fn list(&self, limit: usize, offset: usize) -> RepoResult<UserList<Uuid>> {
+        use crate::schema::users::dsl::*;
+
+        let mut conn = self.conn().await?;
+        let page = users
+            .order(username.asc())
+            .limit(limit as i64)
+            .offset(offset as i64)
+            .load::<User>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let total = users
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(UserList {
+            users: page.into_iter().map(Into::into).collect(),
+            total,
+        })
+    }
+
+    #[tracing::instrument(name = "users.personal_data", level = "debug", skip_all)]
+    async fn personal_data(&self, user_id: Uuid) -> RepoResult<PersonalData<Uuid>> {
+        use crate::schema::{articles, comments, favorite_articles, followers, users};
+
+        let mut conn = self.conn().await?;
+        let user = users::table
+            .find(user_id)
+            .first::<User>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let slugs = articles::table
+            .filter(articles::author_id.eq(user_id))
+            .order((articles::created_at.asc(), articles::id.asc()))
+            .select(articles::slug)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let written = comments::table
+            .inner_join(articles::table)
+            .filter(comments::user_id.eq(user_id))
+            .order(comments::id.asc())
+            .select((
+                comments::id,
+                articles::slug,
+                comments::body,
+                comments::created_at,
+                comments::updated_at,
+            ))
+            .load::<(i32, String, String, NaiveDateTime, NaiveDateTime)>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let favorites = favorite_articles::table
+            .inner_join(articles::table)
+            .filter(favorite_articles::user_id.eq(user_id))
+            .order(articles::slug.asc())
+            .select(articles::slug)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        // `user_id` is followed by `follower_id`
+        let following = followers::table
+            .inner_join(users::table.on(users::id.eq(followers::user_id)))
+            .filter(followers::follower_id.eq(user_id))
+            .order(users::username.asc())
+            .select(users::username)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        let followed_by = followers::table
+            .inner_join(users::table.on(users::id.eq(followers::follower_id)))
+            .filter(followers::user_id.eq(user_id))
+            .order(users::username.asc())
+            .select(users::username)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        Ok(PersonalData {
+            user: user.into(),
+            articles: slugs,
+            comments: written
+                .into_iter()
+                .map(
+                    |(id, article, body, created_at, updated_at)| AuthoredComment {
+                        id,
+                        article,
+                        body,
+                        created_at: Utc.from_utc_datetime(&created_at),
+                        updated_at: Utc.from_utc_datetime(&updated_at),
+                    },
+                )
+                .collect(),
+            favorites,
+            following,
+            followers: followed_by,
+        })
+    }
+
+    #[tracing::instrument(name = "users.anonymize", level = "debug", skip_all)]
+    async fn anonymize(&self, user_id: Uuid, replacement: NewUserRecord) -> RepoResult<()> {
+        use crate::schema::{
+            access_tokens, account_tokens, articles, favorite_articles, followers, sessions,
+            totp_recovery_codes, user_identities, user_totp, users,
+        };
+
+        let mut conn = self.conn().await?;
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let anonymized = diesel::update(users::table.find(user_id))
+                    .set((
+                        users::username.eq(replacement.username),
+                        users::email.eq(replacement.email),
+                        users::password.eq(replacement.password_hash),
+                        users::bio.eq(None::<String>),
+                        users::image.eq(None::<String>),
+                        users::email_verified_at.eq(None::<NaiveDateTime>),
+                        users::role.eq("user"),
+                    ))
+                    .execute(conn)
+                    .await?;
+                if anonymized == 0 {
+                    return Err(diesel::result::Error::NotFound);
+                }
+
+                diesel::delete(
+                    followers::table.filter(
+                        followers::user_id
+                            .eq(user_id)
+                            .or(followers::follower_id.eq(user_id)),
+                    ),
+                )
+                .execute(conn)
+                .await?;
+                diesel::delete(favorite_articles::table)
+                    .filter(favorite_articles::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(articles::table)
+                    .filter(articles::author_id.eq(user_id))
+                    .filter(articles::status.eq("draft"))
+                    .execute(conn)
+                    .await?;
+
+                // Refresh tokens go with their sessions
+                diesel::delete(sessions::table)
+                    .filter(sessions::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(account_tokens::table)
+                    .filter(account_tokens::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(access_tokens::table)
+                    .filter(access_tokens::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(user_totp::table.find(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(totp_recovery_codes::table)
+                    .filter(totp_recovery_codes::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+                diesel::delete(user_identities::table)
+                    .filter(user_identities::user_id.eq(user_id))
+                    .execute(conn)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(repo_error)
+    }
+
+    #[tracing::instrument(name = "users.delete", level = "debug", skip_all)]
+    async fn delete(&self, user_id: Uuid) -> RepoResult<()> {
+        use crate::schema::users::dsl::*;
+
+        // Everything else that is theirs goes with them, see the user_deletion migration
+        let mut conn = self.conn().await?;
+        let deleted = diesel::delete(users.find(user_id))
+            .execute(&mut conn)
+            .await
+            .map_err(repo_error)?;
+
+        match deleted {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
         }
     }
 }
@@ -1,14 +1,27 @@
 use std::future::IntoFuture;
 
-use actix::MailboxError;
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
-use diesel::{
-    r2d2::PoolError,
-    result::{DatabaseErrorKind, Error as DieselError},
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::{
+        header::{HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
+        StatusCode,
+    },
+    middleware::Next,
+    HttpResponse, ResponseError,
 };
+use chrono::{DateTime, Utc};
 use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
 use libreauth::pass::Error as PassError;
-use serde_json::{json, Map};
+use realworld_core::{
+    credentials::CredentialError,
+    error::{accepts_problem, field_error, ApiError, ErrorCode, Malformed, PROBLEM_JSON},
+    lockout,
+    oidc::OidcError,
+    repo::RepoError,
+    totp::ChallengeError,
+};
 use thiserror::Error;
 use validator::ValidationErrors;
 
@@ -38,7 +51,19 @@ pub enum AppError {
 
     // 422
     #[error("Unprocessable Entity: {0:?}")]
-    UnprocessableEntity(serde_json::Value),
+    UnprocessableEntity(&'static str),
+
+    // 422, by field
+    #[error("Invalid request: {0:?}")]
+    Validation(ValidationErrors),
+
+    // 422, carries the name of the field that is taken
+    #[error("{0} has already been taken")]
+    Conflict(String),
+
+    // 429
+    #[error("Too many failed logins")]
+    LockedOut(DateTime<Utc>),
 
     // 500
     #[error("Internal Server Error")]
@@ -49,52 +74,139 @@ impl AppError {
     pub fn not_found(message: &'static str) -> Self {
         AppError::NotFound(Some(message))
     }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Unauthorized(_) => ErrorCode::Unauthorized,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::UnprocessableEntity(_) | AppError::Validation(_) => {
+                ErrorCode::ValidationFailed
+            }
+            AppError::Conflict(_) => ErrorCode::AlreadyExists,
+            AppError::LockedOut(_) => ErrorCode::TooManyRequests,
+            AppError::InternalServerError => ErrorCode::Internal,
+        }
+    }
+
+    /// What clients are told.
+    pub fn api_error(&self) -> ApiError {
+        match self {
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::UnprocessableEntity(message) => ApiError::new(self.code(), *message),
+            AppError::NotFound(message) => {
+                ApiError::new(self.code(), message.unwrap_or(self.code().title()))
+            }
+            AppError::Validation(errors) => ApiError::validation(errors),
+            AppError::Conflict(field) => ApiError::conflict(field),
+            AppError::LockedOut(_) => ApiError::new(self.code(), self.to_string()),
+            AppError::InternalServerError => ApiError::new(self.code(), self.code().title()),
+        }
+    }
 }
 
 // the ResponseError trait lets us convert errors to http responses with appropriate data
 // https://actix.rs/docs/errors/
 impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let coerce_error = move |message: &'static str| json!({ "error": message });
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.code().status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
 
-        println!("Error: {:?}", self);
+    /// In the RealWorld shape, `problem_details` swaps in problem details when they are
+    /// accepted.
+    fn error_response(&self) -> HttpResponse {
+        match self.status_code().is_server_error() {
+            true => tracing::error!(error = ?self, code = self.code().as_str(), "internal error"),
+            false => tracing::debug!(error = %self, code = self.code().as_str(), "request refused"),
+        }
 
-        match *self {
-            AppError::NotFound(message) => {
-                HttpResponse::NotFound().json(coerce_error(message.unwrap_or("Not Found")))
-            }
-            AppError::Forbidden(message) => HttpResponse::Forbidden().json(coerce_error(message)),
-            AppError::UnprocessableEntity(ref message) => {
-                HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY).json(message)
-            }
-            AppError::Unauthorized(message) => {
-                HttpResponse::Unauthorized().json(coerce_error(message))
-            }
-            _ => HttpResponse::InternalServerError().json("Internal Server Error"),
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::LockedOut(until) = *self {
+            response.insert_header((RETRY_AFTER, lockout::retry_after(until)));
         }
+        response.json(self.api_error().legacy_body())
     }
 }
 
-impl From<DieselError> for AppError {
-    fn from(error: DieselError) -> Self {
-        println!("DiselError: {:?}", error);
+/// Answers with RFC 7807 problem details instead of the RealWorld shape when the request
+/// accepts them, see [`ApiError`].
+pub async fn problem_details(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let wants_problem = accepts_problem(
+        request
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok()),
+    );
+
+    let response = next.call(request).await?.map_into_boxed_body();
+    let error = match response.response().error() {
+        Some(error) if wants_problem => error.as_error::<AppError>().map(AppError::api_error),
+        _ => None,
+    };
+    let Some(error) = error else {
+        return Ok(response);
+    };
+
+    Ok(response.map_body(|head, _| {
+        head.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        head.headers_mut().remove(CONTENT_LENGTH);
+        BoxBody::new(error.problem_body().to_string())
+    }))
+}
+
+impl From<RepoError> for AppError {
+    fn from(error: RepoError) -> Self {
         match error {
-            DieselError::DatabaseError(kind, info) => {
-                if let DatabaseErrorKind::UniqueViolation = kind {
-                    let message = info.details().unwrap_or_else(|| info.message()).to_string();
-                    return AppError::UnprocessableEntity(json!({ "error": message }));
-                }
+            RepoError::NotFound => AppError::not_found("Record not found"),
+            RepoError::Conflict(field) => AppError::Conflict(field),
+            RepoError::Backend(error) => {
+                // The response drops the cause; refusals are already logged with their response
+                tracing::error!(error = %error, "storage error");
                 AppError::InternalServerError
             }
-            DieselError::NotFound => AppError::not_found("Record not found"),
-            _ => AppError::InternalServerError,
         }
     }
 }
 
-impl From<MailboxError> for AppError {
-    fn from(_error: MailboxError) -> Self {
-        AppError::InternalServerError
+impl From<OidcError> for AppError {
+    fn from(error: OidcError) -> Self {
+        match error {
+            OidcError::UnknownProvider(_) => AppError::not_found("Provider not found"),
+            OidcError::Rejected(_) => AppError::Unauthorized("Login was rejected"),
+            OidcError::MissingEmail => field_error("email", "is required").into(),
+            OidcError::Invalid(errors) => errors.into(),
+            OidcError::EmailTaken => AppError::Conflict("email".to_string()),
+            OidcError::Provider(_) => AppError::InternalServerError,
+            OidcError::Repo(error) => error.into(),
+        }
+    }
+}
+
+impl From<ChallengeError> for AppError {
+    fn from(error: ChallengeError) -> Self {
+        match error {
+            ChallengeError::Invalid => AppError::Unauthorized("Invalid challenge"),
+            ChallengeError::Refused => AppError::Unauthorized("Invalid code"),
+            ChallengeError::LockedOut(until) => AppError::LockedOut(until),
+            ChallengeError::Repo(error) => error.into(),
+        }
+    }
+}
+
+impl From<CredentialError> for AppError {
+    fn from(error: CredentialError) -> Self {
+        match error {
+            CredentialError::Malformed => AppError::Unauthorized("Invalid authorization header"),
+            CredentialError::UnsupportedScheme => {
+                AppError::Unauthorized("Invalid authorization method")
+            }
+            CredentialError::Csrf => AppError::Forbidden("the CSRF token is missing or wrong"),
+        }
     }
 }
 
@@ -108,12 +220,6 @@ impl From<JwtError> for AppError {
     }
 }
 
-impl From<PoolError> for AppError {
-    fn from(_error: PoolError) -> Self {
-        AppError::InternalServerError
-    }
-}
-
 impl From<PassError> for AppError {
     fn from(_error: PassError) -> Self {
         AppError::InternalServerError
@@ -122,20 +228,41 @@ impl From<PassError> for AppError {
 
 impl From<ValidationErrors> for AppError {
     fn from(errors: ValidationErrors) -> Self {
-        let mut err_map = Map::new();
-
-        // transforms errors into objects that err_map can take
-        for (field, errors) in errors.field_errors().iter() {
-            let errors: Vec<serde_json::Value> = errors
-                .iter()
-                .map(|error| {
-                    dbg!(error); // <- Uncomment this if you want to see what error looks like
-                    json!(error.message)
-                })
-                .collect();
-            err_map.insert(field.to_string(), json!(errors));
+        AppError::Validation(errors)
+    }
+}
+
+impl From<Malformed> for AppError {
+    fn from(malformed: Malformed) -> Self {
+        match malformed {
+            Malformed::Path => AppError::not_found(malformed.message()),
+            _ => AppError::UnprocessableEntity(malformed.message()),
         }
+    }
+}
+
+impl From<JsonPayloadError> for AppError {
+    fn from(error: JsonPayloadError) -> Self {
+        let malformed = match error {
+            JsonPayloadError::ContentType => Malformed::ContentType,
+            JsonPayloadError::Deserialize(error) if error.is_data() => Malformed::Data,
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                Malformed::TooLarge
+            }
+            _ => Malformed::Syntax,
+        };
+        malformed.into()
+    }
+}
+
+impl From<PathError> for AppError {
+    fn from(_error: PathError) -> Self {
+        Malformed::Path.into()
+    }
+}
 
-        AppError::UnprocessableEntity(json!({ "errors": err_map }))
+impl From<QueryPayloadError> for AppError {
+    fn from(_error: QueryPayloadError) -> Self {
+        Malformed::Query.into()
     }
 }
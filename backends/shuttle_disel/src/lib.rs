@@ -0,0 +1,332 @@
+mod api;
+pub mod config;
+pub mod db;
+mod error;
+mod models;
+mod schema;
+mod telemetry;
+mod utils;
+
+use actix_web::{
+    get,
+    http::header::AUTHORIZATION,
+    middleware::from_fn,
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use diesel_async::pooled_connection::{bb8::Pool, AsyncDieselConnectionManager};
+use realworld_core::{
+    config::{PoolConfig, TokenConfig},
+    credentials::CookieConfig,
+    keys::KeyRing,
+    lockout::LockoutConfig,
+    mail::Outbox,
+    metrics::{self, Metrics, PoolStats, PoolWaits, METRICS_PATH},
+    oidc::Oidc,
+    policy::RoleConfig,
+    privacy::PrivacyConfig,
+    user::UsernameConfig,
+};
+
+use crate::api::{
+    access_tokens, account, admin, articles, comments, oidc, profile, revisions, tags, totp, user,
+};
+use db::{Conn, PgPool, Repos};
+use error::{problem_details, AppError, AppResult};
+use telemetry::trace_requests;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub repos: Repos,
+    keys: KeyRing,
+    tokens: TokenConfig,
+    outbox: Outbox,
+    oidc: Oidc,
+    roles: RoleConfig,
+    lockout: LockoutConfig,
+    cookies: CookieConfig,
+    privacy: PrivacyConfig,
+    usernames: UsernameConfig,
+    metrics: Metrics,
+}
+
+impl AppState {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        repos: Repos,
+        keys: KeyRing,
+        tokens: TokenConfig,
+        outbox: Outbox,
+        oidc: Oidc,
+        roles: RoleConfig,
+        lockout: LockoutConfig,
+        cookies: CookieConfig,
+        privacy: PrivacyConfig,
+        usernames: UsernameConfig,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            repos,
+            keys,
+            tokens,
+            outbox,
+            oidc,
+            roles,
+            lockout,
+            cookies,
+            privacy,
+            usernames,
+            metrics,
+        }
+    }
+}
+
+#[get("/")]
+async fn hello_world() -> &'static str {
+    "Hello World!"
+}
+
+/// Public keys tokens can be verified with. HMAC secrets are never listed.
+#[get("/.well-known/jwks.json")]
+async fn jwks(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.keys.jwks())
+}
+
+/// Prometheus metrics next to the API, only served behind `metrics.token`.
+async fn get_metrics(state: web::Data<AppState>, req: HttpRequest) -> AppResult<HttpResponse> {
+    if !state.metrics.exposed() {
+        return Err(AppError::NotFound(None));
+    }
+    scrape(web::Data::new(state.metrics.clone()), req).await
+}
+
+/// Prometheus metrics on `metrics.bind`.
+async fn scrape(metrics: web::Data<Metrics>, req: HttpRequest) -> AppResult<HttpResponse> {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    if !metrics.authorized(authorization) {
+        return Err(AppError::Unauthorized("Invalid metrics token"));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(metrics::CONTENT_TYPE)
+        .body(metrics.render()))
+}
+
+async fn not_found() -> AppResult<HttpResponse> {
+    Err(AppError::NotFound(None))
+}
+
+/// Connections are only opened once a query needs one. Must be called within a tokio runtime.
+pub fn new_pool<S: Into<String>>(database_url: S, config: &PoolConfig) -> PgPool {
+    let manager = AsyncDieselConnectionManager::<Conn>::new(database_url);
+    Pool::builder()
+        .max_size(config.max_connections)
+        .min_idle(Some(config.min_connections))
+        .build_unchecked(manager)
+}
+
+/// What `/metrics` reports about the pool, which was built with `max` connections at most.
+pub fn pool_stats(pool: &PgPool, max: u32) -> PoolStats {
+    let state = pool.state();
+    PoolStats {
+        connections: state.connections,
+        idle: state.idle_connections,
+        max,
+        waits: Some(PoolWaits {
+            count: state.statistics.get_waited,
+            timeouts: state.statistics.get_timed_out,
+            time: state.statistics.get_wait_time,
+        }),
+    }
+}
+
+/// Serves nothing but `/metrics`, on `metrics.bind`.
+pub fn configure_metrics(metrics: Metrics) -> impl Fn(&mut ServiceConfig) + Send + Sync + Clone {
+    let metrics = web::Data::new(metrics);
+
+    move |cfg: &mut ServiceConfig| {
+        cfg.app_data(metrics.clone())
+            .route(METRICS_PATH, web::get().to(scrape));
+    }
+}
+
+/// Registers every route of the API. Each call to the returned closure configures one worker.
+pub fn configure(state: AppState) -> impl Fn(&mut ServiceConfig) + Send + Sync + Clone + 'static {
+    let state = web::Data::new(state);
+
+    move |cfg: &mut ServiceConfig| {
+        cfg.app_data(state.clone())
+            // Requests that can't be read are answered like any other error
+            .app_data(
+                web::JsonConfig::default().error_handler(|error, _| AppError::from(error).into()),
+            )
+            .app_data(
+                web::PathConfig::default().error_handler(|error, _| AppError::from(error).into()),
+            )
+            .app_data(
+                web::QueryConfig::default().error_handler(|error, _| AppError::from(error).into()),
+            )
+            .service(hello_world)
+            .service(jwks)
+            .route(METRICS_PATH, web::get().to(get_metrics))
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(problem_details))
+                    .wrap(from_fn(trace_requests))
+                    // User routes ↓
+                    .service(web::resource("users").route(web::post().to(user::registration)))
+                    .service(web::resource("users/login").route(web::post().to(user::login)))
+                    .service(web::resource("users/login/totp").route(web::post().to(totp::login)))
+                    .service(web::resource("users/refresh").route(web::post().to(user::refresh)))
+                    .service(web::resource("users/logout").route(web::post().to(user::logout)))
+                    .service(
+                        web::resource("users/logout-all").route(web::post().to(user::logout_all)),
+                    )
+                    .service(
+                        web::resource("users/password-reset")
+                            .route(web::post().to(account::request_password_reset)),
+                    )
+                    .service(
+                        web::resource("users/password-reset/confirm")
+                            .route(web::post().to(account::confirm_password_reset)),
+                    )
+                    .service(
+                        web::resource("users/verify-email")
+                            .route(web::post().to(account::verify_email)),
+                    )
+                    .service(web::resource("users/oidc").route(web::get().to(oidc::get_providers)))
+                    .service(
+                        web::resource("users/oidc/{provider}/authorize")
+                            .route(web::post().to(oidc::authorize)),
+                    )
+                    .service(
+                        web::resource("users/oidc/{provider}/callback")
+                            .route(web::post().to(oidc::callback)),
+                    )
+                    .service(
+                        web::resource("user")
+                            .route(web::get().to(user::get_current_user))
+                            .route(web::put().to(user::update_user))
+                            .route(web::delete().to(user::delete_user)),
+                    )
+                    .service(web::resource("user/export").route(web::get().to(user::export_user)))
+                    .service(
+                        web::resource("user/verify-email")
+                            .route(web::post().to(account::resend_verification)),
+                    )
+                    .service(
+                        web::resource("user/tokens")
+                            .route(web::get().to(access_tokens::get_access_tokens))
+                            .route(web::post().to(access_tokens::create_access_token)),
+                    )
+                    .service(
+                        web::resource("user/tokens/{id}")
+                            .route(web::delete().to(access_tokens::revoke_access_token)),
+                    )
+                    .service(web::resource("user/totp").route(web::post().to(totp::enroll)))
+                    .service(
+                        web::resource("user/totp/confirm").route(web::post().to(totp::confirm)),
+                    )
+                    // Profile routes ↓
+                    .service(
+                        web::resource("profiles/{username}")
+                            .route(web::get().to(profile::get_profile)),
+                    )
+                    .service(
+                        web::resource("profiles/{username}/follow")
+                            .route(web::post().to(profile::follow_profile))
+                            .route(web::delete().to(profile::unfollow_profile)),
+                    )
+                    // Article routes ↓
+                    .service(
+                        web::resource("articles")
+                            .route(web::get().to(articles::get_articles))
+                            .route(web::post().to(articles::create_article)),
+                    )
+                    .service(
+                        web::resource("articles/feed")
+                            .route(web::get().to(articles::get_feed_articles)),
+                    )
+                    .service(
+                        web::resource("articles/search")
+                            .route(web::get().to(articles::search_articles)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}")
+                            .route(web::get().to(articles::get_article))
+                            .route(web::put().to(articles::update_article))
+                            .route(web::delete().to(articles::delete_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/favorite")
+                            .route(web::post().to(articles::favorite_article))
+                            .route(web::delete().to(articles::unfavorite_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/publish")
+                            .route(web::post().to(articles::publish_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/unpublish")
+                            .route(web::post().to(articles::unpublish_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/hide")
+                            .route(web::post().to(articles::hide_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/unhide")
+                            .route(web::post().to(articles::unhide_article)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/revisions")
+                            .route(web::get().to(revisions::get_revisions)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/revisions/{number}")
+                            .route(web::get().to(revisions::get_revision)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/revisions/{from}/diff/{to}")
+                            .route(web::get().to(revisions::diff_revisions)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/revisions/{number}/restore")
+                            .route(web::post().to(revisions::restore_revision)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/comments")
+                            .route(web::get().to(comments::get_comments))
+                            .route(web::post().to(comments::add_comment)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/comments/{comment_id}")
+                            .route(web::delete().to(comments::delete_comment)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/comments/{comment_id}/hide")
+                            .route(web::post().to(comments::hide_comment)),
+                    )
+                    .service(
+                        web::resource("articles/{slug}/comments/{comment_id}/unhide")
+                            .route(web::post().to(comments::unhide_comment)),
+                    )
+                    // Admin routes ↓
+                    .service(web::resource("admin/users").route(web::get().to(admin::get_users)))
+                    .service(
+                        web::resource("admin/users/{username}/role")
+                            .route(web::put().to(admin::update_role)),
+                    )
+                    .service(
+                        web::resource("admin/users/{username}/unlock")
+                            .route(web::post().to(admin::unlock_user)),
+                    )
+                    // Tags routes ↓
+                    .service(web::resource("tags").route(web::get().to(tags::get_tags)))
+                    .default_service(web::to(not_found)),
+            );
+    }
+}
@@ -0,0 +1,32 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+use crate::schema::{account_tokens, outbox};
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = account_tokens)]
+pub struct NewAccountToken<'a> {
+    pub token_hash: &'a str,
+    pub user_id: Uuid,
+    pub purpose: &'static str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = outbox)]
+pub struct NewOutboxMail {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = outbox)]
+pub struct OutboxMail {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub attempts: i32,
+}
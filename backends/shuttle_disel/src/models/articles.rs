@@ -1,5 +1,6 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, TimeZone, Utc};
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable, Selectable};
+use realworld_core::repo::{Article as ArticleRecord, Profile};
 use serde::Deserialize;
 use uuid::Uuid;
 
@@ -15,6 +16,38 @@ pub struct Article {
     pub author_id: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub status: String,
+    pub published_at: Option<NaiveDateTime>,
+    pub hidden_at: Option<NaiveDateTime>,
+}
+
+impl Article {
+    pub fn into_record(
+        self,
+        author: Profile<Uuid>,
+        tag_list: Vec<String>,
+        favorited: bool,
+        favorites_count: i64,
+    ) -> ArticleRecord<Uuid> {
+        ArticleRecord {
+            author,
+            tag_list,
+            favorited,
+            favorites_count,
+            id: self.id,
+            slug: self.slug,
+            body: self.body,
+            title: self.title,
+            description: self.description,
+            created_at: Utc.from_utc_datetime(&self.created_at),
+            updated_at: Utc.from_utc_datetime(&self.updated_at),
+            // The column is checked against the known statuses
+            status: self.status.parse().unwrap_or_default(),
+            published_at: self
+                .published_at
+                .map(|published_at| Utc.from_utc_datetime(&published_at)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Insertable)]
@@ -25,6 +58,8 @@ pub struct NewArticle {
     pub title: String,
     pub author_id: Uuid,
     pub description: String,
+    pub status: String,
+    pub published_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Deserialize, AsChangeset)]
@@ -34,6 +69,20 @@ pub struct UpdateArticle {
     pub body: Option<String>,
     pub title: Option<String>,
     pub description: Option<String>,
+    pub status: Option<String>,
+    /// `Some(None)` clears it.
+    pub published_at: Option<Option<NaiveDateTime>>,
+}
+
+impl UpdateArticle {
+    pub fn is_empty(&self) -> bool {
+        self.slug.is_none()
+            && self.body.is_none()
+            && self.title.is_none()
+            && self.description.is_none()
+            && self.status.is_none()
+            && self.published_at.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Insertable)]
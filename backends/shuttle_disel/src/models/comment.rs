@@ -12,6 +12,7 @@ pub struct Comment {
     pub body: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub hidden_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Insertable)]
@@ -0,0 +1,20 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+
+use crate::schema::{lockout_events, login_attempts};
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = login_attempts)]
+pub struct LoginAttempt {
+    pub failures: i32,
+    pub last_failed_at: NaiveDateTime,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = lockout_events)]
+pub struct NewLockoutEvent<'a> {
+    pub key: &'a str,
+    pub failures: i32,
+    pub locked_until: NaiveDateTime,
+}
@@ -1,5 +1,12 @@
+pub mod access_token;
+pub mod account;
 pub mod articles;
 pub mod comment;
 pub mod follower;
+pub mod login_attempt;
+pub mod oidc;
+pub mod revision;
+pub mod session;
 pub mod tags;
+pub mod totp;
 pub mod user;
@@ -0,0 +1,31 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+use crate::schema::{oidc_logins, user_identities};
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = oidc_logins)]
+pub struct OidcLogin {
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = oidc_logins)]
+pub struct NewOidcLogin<'a> {
+    pub state_hash: &'a str,
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_identities)]
+pub struct NewUserIdentity<'a> {
+    pub provider: &'a str,
+    pub subject: &'a str,
+    pub user_id: Uuid,
+}
@@ -0,0 +1,18 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+use crate::schema::article_revisions;
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = article_revisions)]
+pub struct Revision {
+    pub article_id: Uuid,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub changed_fields: Vec<String>,
+    pub user_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
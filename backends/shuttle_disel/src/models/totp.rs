@@ -0,0 +1,37 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+use crate::schema::{totp_challenges, totp_recovery_codes, user_totp};
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = user_totp)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_step: Option<i64>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_totp)]
+pub struct NewUserTotp<'a> {
+    pub user_id: Uuid,
+    pub secret: &'a str,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = totp_recovery_codes)]
+pub struct NewRecoveryCode {
+    pub user_id: Uuid,
+    pub code_hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = totp_challenges)]
+pub struct NewTotpChallenge<'a> {
+    pub id: &'a str,
+    pub failures: i32,
+    pub spent_at: Option<NaiveDateTime>,
+    pub expires_at: NaiveDateTime,
+}
@@ -2,19 +2,34 @@ use crate::schema::users;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::Queryable;
+use realworld_core::repo::Profile;
 use serde::Deserialize;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Deserialize, Queryable, Identifiable, Selectable)]
 pub struct User {
     pub id: Uuid,
-    pub email: String,
     pub username: String,
+    pub email: String,
     pub password: String,
     pub bio: Option<String>,
     pub image: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub role: String,
+}
+
+impl User {
+    pub fn into_profile(self, following: bool) -> Profile<Uuid> {
+        Profile {
+            following,
+            id: self.id,
+            bio: self.bio,
+            image: self.image,
+            username: self.username,
+        }
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -35,4 +50,19 @@ pub struct UserChange {
     pub image: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// `Some(None)` marks the email unverified.
+    pub email_verified_at: Option<Option<NaiveDateTime>>,
+    pub role: Option<String>,
+}
+
+impl UserChange {
+    pub fn is_empty(&self) -> bool {
+        self.bio.is_none()
+            && self.email.is_none()
+            && self.image.is_none()
+            && self.username.is_none()
+            && self.password.is_none()
+            && self.email_verified_at.is_none()
+            && self.role.is_none()
+    }
 }
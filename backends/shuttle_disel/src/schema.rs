@@ -1,5 +1,51 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    access_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        scopes -> Array<Text>,
+        token_hash -> Text,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    account_tokens (token_hash) {
+        token_hash -> Text,
+        user_id -> Uuid,
+        purpose -> Text,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    article_revisions (article_id, number) {
+        article_id -> Uuid,
+        number -> Int4,
+        title -> Text,
+        description -> Text,
+        body -> Text,
+        changed_fields -> Array<Text>,
+        user_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    article_slugs (slug) {
+        #[max_length = 255]
+        slug -> Varchar,
+        article_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     article_tags (article_id, tag_name) {
         article_id -> Uuid,
@@ -21,6 +67,9 @@ diesel::table! {
         author_id -> Uuid,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        status -> Text,
+        published_at -> Nullable<Timestamptz>,
+        hidden_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -32,6 +81,7 @@ diesel::table! {
         body -> Text,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        hidden_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -53,6 +103,103 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    lockout_events (id) {
+        id -> Int8,
+        key -> Text,
+        failures -> Int4,
+        locked_until -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    login_attempts (key) {
+        key -> Text,
+        failures -> Int4,
+        last_failed_at -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    oidc_logins (state_hash) {
+        state_hash -> Text,
+        provider -> Text,
+        code_verifier -> Text,
+        nonce -> Text,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    outbox (id) {
+        id -> Int8,
+        recipient -> Text,
+        subject -> Text,
+        body -> Text,
+        created_at -> Timestamptz,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (token_hash) {
+        token_hash -> Text,
+        session_id -> Uuid,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    totp_challenges (id) {
+        id -> Text,
+        failures -> Int4,
+        spent_at -> Nullable<Timestamptz>,
+        expires_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    totp_recovery_codes (user_id, code_hash) {
+        user_id -> Uuid,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    user_identities (provider, subject) {
+        provider -> Text,
+        subject -> Text,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_totp (user_id) {
+        user_id -> Uuid,
+        secret -> Text,
+        enabled_at -> Nullable<Timestamptz>,
+        last_step -> Nullable<Int8>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -64,21 +211,47 @@ diesel::table! {
         image -> Nullable<Text>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        email_verified_at -> Nullable<Timestamptz>,
+        role -> Text,
     }
 }
 
+diesel::joinable!(access_tokens -> users (user_id));
+diesel::joinable!(account_tokens -> users (user_id));
+diesel::joinable!(article_revisions -> articles (article_id));
+diesel::joinable!(article_revisions -> users (user_id));
+diesel::joinable!(article_slugs -> articles (article_id));
 diesel::joinable!(article_tags -> articles (article_id));
 diesel::joinable!(articles -> users (author_id));
 diesel::joinable!(comments -> articles (article_id));
 diesel::joinable!(comments -> users (user_id));
 diesel::joinable!(favorite_articles -> articles (article_id));
 diesel::joinable!(favorite_articles -> users (user_id));
+diesel::joinable!(refresh_tokens -> sessions (session_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(totp_recovery_codes -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(user_totp -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    access_tokens,
+    account_tokens,
+    article_revisions,
+    article_slugs,
     article_tags,
     articles,
     comments,
     favorite_articles,
     followers,
+    lockout_events,
+    login_attempts,
+    oidc_logins,
+    outbox,
+    refresh_tokens,
+    sessions,
+    totp_challenges,
+    totp_recovery_codes,
+    user_identities,
+    user_totp,
     users,
 );
@@ -0,0 +1,54 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web,
+};
+use realworld_core::telemetry::{self, REQUEST_ID_HEADER};
+use tracing::Instrument;
+
+use crate::AppState;
+
+/// Runs every request in a span, see `realworld_core::telemetry`, echoes its `X-Request-Id`
+/// and counts it in the metrics.
+pub async fn trace_requests(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let request_id = telemetry::request_id(
+        request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok()),
+    );
+    let route = request
+        .match_pattern()
+        .unwrap_or_else(|| "fallback".to_string());
+    let method = request.method().clone();
+    let metrics = request
+        .app_data::<web::Data<AppState>>()
+        .map(|state| state.metrics.clone());
+    let span = telemetry::request_span(method.as_str(), &route, &request_id);
+
+    let result = next.call(request).instrument(span.clone()).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(error) => error.as_response_error().status_code(),
+    };
+    telemetry::finish_request(&span, status.as_u16(), started);
+    if let Some(metrics) = metrics {
+        metrics.observe_request(method.as_str(), &route, status.as_u16(), started.elapsed());
+    }
+
+    let mut response = result?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
@@ -1,91 +1,195 @@
+use std::net::IpAddr;
+
 use crate::{
-    db::GenerateAuth,
     error::{AppError, AppResult},
-    models::user::User,
+    utils::{DecodeJwt, GenerateJwt},
     AppState,
 };
 use actix_web::{
-    http::header::{HeaderValue, AUTHORIZATION},
-    web::{self, Data},
-    HttpRequest,
+    http::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, SET_COOKIE},
+    web::Data,
+    HttpRequest, HttpResponse, HttpResponseBuilder,
 };
-use futures::{future::ready, Future};
-use serde::Deserialize;
-use std::pin::Pin;
-
-const SCHEME: &str = "Token";
+use realworld_core::{
+    access_token::{self, Scope},
+    credentials::{RequestCredentials, CSRF_HEADER},
+    lockout::FORWARDED_FOR,
+    policy::Action,
+    repo::{RepoError, User, UserChanges},
+    telemetry,
+    token::{hash_token, new_token},
+    user::UserResponse,
+};
+use uuid::Uuid;
 
 // expand this as needed
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct Auth {
-    pub user: User,
+    pub user: User<Uuid>,
     pub token: String,
+    pub session: Uuid,
 }
 
+/// Resolves a login token. Personal access tokens are refused, they can't manage the account
+/// they belong to.
 pub async fn authenticate(state: &Data<AppState>, req: &HttpRequest) -> AppResult<Auth> {
-    let db = state.db.clone();
+    let token = required_token(state, req)?;
+    if access_token::is_access_token(&token) {
+        return Err(AppError::Forbidden("a login is required"));
+    }
+
+    let claims = token.decode_jwt(&state.keys)?;
+
+    // Logging out revokes the session before its access tokens expire
+    match state.repos.sessions.find(claims.sid).await {
+        Ok(session) if session.user_id == claims.id => telemetry::record_user(claims.id),
+        Ok(_) | Err(RepoError::NotFound) => return Err(AppError::Unauthorized("Invalid Token")),
+        Err(e) => return Err(e.into()),
+    }
 
-    let token = preprocess_authz_token(req.headers().get(AUTHORIZATION))?;
-    db.send(GenerateAuth { token }).await?
+    match state.repos.users.find(claims.id).await {
+        Ok(user) => Ok(Auth {
+            user,
+            token,
+            session: claims.sid,
+        }),
+        Err(RepoError::NotFound) => Err(AppError::Unauthorized("Invalid Token")),
+        Err(e) => Err(e.into()),
+    }
 }
 
-fn preprocess_authz_token(token: Option<&HeaderValue>) -> AppResult<String> {
-    let mut it = match token {
-        Some(token) => token.to_str().ok().unwrap().split_whitespace(),
-        None => return Err(AppError::Unauthorized("No authorization was provided")),
+/// Resolves a login token or a personal access token. Login tokens may be used for anything,
+/// access tokens only for the scopes they were created with.
+pub async fn authorize(
+    state: &Data<AppState>,
+    req: &HttpRequest,
+    scope: Scope,
+) -> AppResult<User<Uuid>> {
+    let token = required_token(state, req)?;
+    if !access_token::is_access_token(&token) {
+        return Ok(authenticate(state, req).await?.user);
+    }
+
+    let access_token = match state
+        .repos
+        .access_tokens
+        .authenticate(&hash_token(&token))
+        .await
+    {
+        Ok(access_token) => access_token,
+        Err(RepoError::NotFound) => return Err(AppError::Unauthorized("Invalid Token")),
+        Err(e) => return Err(e.into()),
     };
+    if !access_token.allows(scope) {
+        return Err(AppError::Forbidden("the token lacks the required scope"));
+    }
 
-    let scheme = it.next().unwrap();
-    let token = it.next().unwrap();
+    telemetry::record_user(access_token.user_id);
+    match state.repos.users.find(access_token.user_id).await {
+        Ok(user) => Ok(user),
+        Err(RepoError::NotFound) => Err(AppError::Unauthorized("Invalid Token")),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    if scheme != SCHEME {
-        return Err(AppError::Unauthorized("Invalid authorization method"));
+/// Consults the policy on whether `user` may do `action` to something written by `owner`, if
+/// anyone.
+pub fn permit(user: &User<Uuid>, action: Action, owner: Option<Uuid>) -> AppResult<()> {
+    match user.may(action, owner.as_ref()) {
+        true => Ok(()),
+        false => Err(AppError::Forbidden(action.denied())),
     }
+}
 
-    Ok(token.to_string())
+/// The address `req` came from, see [`LockoutConfig::client_ip`].
+///
+/// [`LockoutConfig::client_ip`]: realworld_core::lockout::LockoutConfig::client_ip
+pub fn client_ip(state: &AppState, req: &HttpRequest) -> Option<IpAddr> {
+    let forwarded_for = req
+        .headers()
+        .get(FORWARDED_FOR)
+        .and_then(|header| header.to_str().ok());
+
+    state
+        .lockout
+        .client_ip(req.peer_addr().map(|addr| addr.ip()), forwarded_for)
 }
 
-// =================== EXTRACTOR =================== //
+/// Logs `user` in on a new session, answering with both of its tokens. Admins listed in the
+/// role config are promoted first.
+pub async fn start_session(state: &AppState, mut user: User<Uuid>) -> AppResult<UserResponse> {
+    if let Some(role) = state.roles.promotion(&user) {
+        let changes = UserChanges {
+            role: Some(role),
+            ..Default::default()
+        };
+        user = state.repos.users.update(user.id, changes).await?;
+    }
 
-#[derive(Debug)]
-pub struct AuthExtractor(pub AppResult<Auth>);
+    let refresh_token = new_token();
+    let session = state
+        .repos
+        .sessions
+        .create(
+            user.id,
+            &hash_token(&refresh_token),
+            state.tokens.refresh_expiry(),
+        )
+        .await?;
+
+    let token = user.generate_jwt(session.id, state.tokens.access_expiry(), &state.keys)?;
+    Ok(user.into_response(token).with_refresh_token(refresh_token))
+}
 
-impl std::ops::Deref for AuthExtractor {
-    type Target = AppResult<Auth>;
+/// Answers with the user of a new or refreshed session, setting the cookies of a cookie
+/// session if they are enabled.
+pub fn session_response(state: &AppState, response: UserResponse) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    set_cookies(
+        &mut builder,
+        state.cookies.session_cookies(&response, &state.tokens),
+    );
+    builder.json(response)
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
+/// Ends a cookie session, if they are enabled.
+pub fn clear_session(state: &AppState, builder: &mut HttpResponseBuilder) {
+    set_cookies(builder, state.cookies.cleared_cookies());
 }
 
-impl AuthExtractor {
-    fn auth(auth: Auth) -> AppResult<Self> {
-        Ok(AuthExtractor(Ok(auth)))
+fn set_cookies(builder: &mut HttpResponseBuilder, cookies: Vec<String>) {
+    for cookie in cookies {
+        if let Ok(cookie) = HeaderValue::try_from(cookie) {
+            builder.append_header((SET_COOKIE, cookie));
+        }
     }
+}
 
-    fn error(error: AppError) -> AppResult<Self> {
-        Ok(AuthExtractor(Err(error)))
-    }
+/// The refresh token of a cookie session, if `req` carries one.
+pub fn refresh_cookie(state: &AppState, req: &HttpRequest) -> AppResult<Option<String>> {
+    let headers = req.headers();
+    Ok(state
+        .cookies
+        .refresh_token(&request_credentials(headers, req))?)
 }
 
-impl actix_web::FromRequest for AuthExtractor {
-    type Error = AppError;
-    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+/// The token `req` authenticates with, from its `Authorization` header or a cookie session.
+fn request_token(state: &AppState, req: &HttpRequest) -> AppResult<Option<String>> {
+    let headers = req.headers();
+    Ok(state.cookies.token(&request_credentials(headers, req))?)
+}
 
-    fn from_request(req: &HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
-        let state = req.app_data::<web::Data<AppState>>().unwrap();
-        let db = state.db.clone();
+fn required_token(state: &AppState, req: &HttpRequest) -> AppResult<String> {
+    request_token(state, req)?.ok_or(AppError::Unauthorized("No authorization was provided"))
+}
 
-        let token = match preprocess_authz_token(req.headers().get(AUTHORIZATION)) {
-            Ok(token) => token,
-            Err(e) => return Box::pin(ready(AuthExtractor::error(e))),
-        };
+fn request_credentials<'a>(headers: &'a HeaderMap, req: &HttpRequest) -> RequestCredentials<'a> {
+    let header = |name| headers.get(name).map(HeaderValue::as_bytes);
 
-        Box::pin(async move {
-            match db.send(GenerateAuth { token }).await? {
-                Err(e) => AuthExtractor::error(e),
-                Ok(auth) => AuthExtractor::auth(auth),
-            }
-        })
+    RequestCredentials {
+        authorization: header(AUTHORIZATION.as_str()),
+        cookie: header(COOKIE.as_str()),
+        csrf_token: header(CSRF_HEADER),
+        safe_method: req.method().is_safe(),
     }
 }
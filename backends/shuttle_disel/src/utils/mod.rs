@@ -1,7 +1,5 @@
 pub mod auth;
 pub use auth::*;
-pub mod custom_type;
-pub use custom_type::*;
 pub mod hasher;
 pub use hasher::*;
 pub mod jwt;
@@ -1,5 +1,12 @@
+pub mod access_tokens;
+pub mod account;
+pub mod admin;
 pub mod articles;
 pub mod auth;
 pub mod comments;
+pub mod metrics;
+pub mod oidc;
+pub mod revisions;
 pub mod tags;
+pub mod totp;
 pub mod user;
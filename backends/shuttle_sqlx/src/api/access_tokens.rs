@@ -0,0 +1,78 @@
+use axum::{extract::State, response::IntoResponse};
+use realworld_core::{
+    access_token::{
+        new_access_token, AccessTokenListResponse, AccessTokenResponse, NewAccessTokenData,
+    },
+    keys::KeyRing,
+    token::hash_token,
+};
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+
+use crate::{
+    db::Repos,
+    error::{AppError, AppResult},
+    utils::{
+        auth::{self, Token},
+        extract::{Json, Path},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct CreateAccessToken {
+    #[serde(rename = "accessToken")]
+    access_token: NewAccessTokenData,
+}
+
+// GET /api/user/tokens, without the tokens themselves
+pub async fn get_access_tokens(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    let access_tokens = repos.access_tokens.list(user_id).await?;
+    Ok(Json(AccessTokenListResponse::from(access_tokens)))
+}
+
+// POST /api/user/tokens, the only response that shows the token
+pub async fn create_access_token(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
+    Json(CreateAccessToken { access_token }): Json<CreateAccessToken>,
+) -> AppResult<impl IntoResponse> {
+    access_token.validate()?;
+
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    let token = new_access_token();
+    let created = repos
+        .access_tokens
+        .create(
+            user_id,
+            &access_token.name,
+            &access_token.scopes(),
+            &hash_token(&token),
+            access_token.expires_at(),
+        )
+        .await?;
+
+    Ok(Json(AccessTokenResponse::created(created, token)))
+}
+
+// DELETE /api/user/tokens/:id
+pub async fn revoke_access_token(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(id): Path<String>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    let id = id
+        .parse()
+        .map_err(|_| AppError::not_found("Record not found"))?;
+    repos.access_tokens.revoke(user_id, id).await?;
+
+    Ok(Json(json!({ "message": "OK" })))
+}
@@ -0,0 +1,152 @@
+use axum::{extract::State, response::IntoResponse};
+use realworld_core::{
+    account::{
+        password_reset_mail, verification_mail, EmailVerification, PasswordResetConfirm,
+        PasswordResetRequest, TokenPurpose,
+    },
+    config::TokenConfig,
+    keys::KeyRing,
+    mail::Outbox,
+    repo::{RepoError, User, UserChanges},
+    token::{hash_token, new_token},
+};
+use serde::Deserialize;
+use serde_json::json;
+use validator::Validate;
+
+use crate::{
+    db::Repos,
+    error::{AppError, AppResult},
+    utils::{
+        auth::{auth_user, Token, UserId},
+        extract::Json,
+        hasher,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct Envelope<T> {
+    user: T,
+}
+
+// ================================================= PASSWORD RESET ================================================= //
+
+// POST /api/users/password-reset, answers the same whether or not the email is registered
+pub async fn request_password_reset(
+    State(repos): State<Repos>,
+    State(tokens): State<TokenConfig>,
+    State(outbox): State<Outbox>,
+    Json(Envelope { user }): Json<Envelope<PasswordResetRequest>>,
+) -> AppResult<impl IntoResponse> {
+    user.validate()?;
+
+    match repos.users.find_by_email(&user.email).await {
+        Ok(user) => {
+            let token = new_token();
+            let mail = password_reset_mail(&outbox, &user.email, &token);
+            repos
+                .account_tokens
+                .issue(
+                    user.id,
+                    TokenPurpose::PasswordReset,
+                    &hash_token(&token),
+                    tokens.password_reset_expiry(),
+                    mail,
+                )
+                .await?;
+            outbox.wake();
+        }
+        Err(RepoError::NotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    Ok(Json(json!({ "message": "OK" })))
+}
+
+// POST /api/users/password-reset/confirm, logs the user out everywhere
+pub async fn confirm_password_reset(
+    State(repos): State<Repos>,
+    Json(Envelope { user }): Json<Envelope<PasswordResetConfirm>>,
+) -> AppResult<impl IntoResponse> {
+    user.validate()?;
+
+    let user_id = redeem(&repos, TokenPurpose::PasswordReset, &user.token).await?;
+    let changes = UserChanges {
+        password_hash: Some(hasher::hash_password(user.password)?),
+        ..Default::default()
+    };
+    repos.users.update(user_id, changes).await?;
+    repos.sessions.revoke_all(user_id).await?;
+
+    Ok(Json(json!({ "message": "OK" })))
+}
+
+// ================================================= EMAIL VERIFICATION ================================================= //
+
+// POST /api/users/verify-email
+pub async fn verify_email(
+    State(repos): State<Repos>,
+    Json(Envelope { user }): Json<Envelope<EmailVerification>>,
+) -> AppResult<impl IntoResponse> {
+    user.validate()?;
+
+    let user_id = redeem(&repos, TokenPurpose::EmailVerification, &user.token).await?;
+    let changes = UserChanges {
+        email_verified: Some(true),
+        ..Default::default()
+    };
+    repos.users.update(user_id, changes).await?;
+
+    Ok(Json(json!({ "message": "OK" })))
+}
+
+// POST /api/user/verify-email, mails a new link unless the email is verified already
+pub async fn resend_verification(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(outbox): State<Outbox>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user = auth_user(&repos, &token, &keys).await?;
+    if !user.email_verified {
+        send_verification(&repos, &tokens, &outbox, &user).await?;
+    }
+
+    Ok(Json(json!({ "message": "OK" })))
+}
+
+/// Mails `user` a link confirming their current email address.
+pub async fn send_verification(
+    repos: &Repos,
+    tokens: &TokenConfig,
+    outbox: &Outbox,
+    user: &User<UserId>,
+) -> AppResult<()> {
+    let token = new_token();
+    repos
+        .account_tokens
+        .issue(
+            user.id,
+            TokenPurpose::EmailVerification,
+            &hash_token(&token),
+            tokens.verification_expiry(),
+            verification_mail(outbox, &user.email, &token),
+        )
+        .await?;
+    outbox.wake();
+
+    Ok(())
+}
+
+async fn redeem(repos: &Repos, purpose: TokenPurpose, token: &str) -> AppResult<UserId> {
+    match repos
+        .account_tokens
+        .redeem(purpose, &hash_token(token))
+        .await
+    {
+        Ok(user_id) => Ok(user_id),
+        Err(RepoError::NotFound) => Err(AppError::Unauthorized("Invalid or expired token")),
+        Err(err) => Err(err.into()),
+    }
+}
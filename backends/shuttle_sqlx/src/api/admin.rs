@@ -0,0 +1,78 @@
+use axum::{extract::State, response::IntoResponse};
+use realworld_core::{
+    admin::{AdminUserListResponse, AdminUserResponse, UpdateRoleData, UsersParams},
+    keys::KeyRing,
+    lockout,
+    policy::Action,
+    repo::UserChanges,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{self, Token},
+        extract::{Json, Path, Query},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRole {
+    user: UpdateRoleData,
+}
+
+// GET /api/admin/users?limit=20&offset=0
+pub async fn get_users(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Query(params): Query<UsersParams>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    auth::permit(&repos, user_id, Action::ManageUsers, None).await?;
+
+    let users = repos.users.list(params.limit(), params.offset()).await?;
+    Ok(Json(AdminUserListResponse::from(users)))
+}
+
+// PUT /api/admin/users/:username/role
+pub async fn update_role(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(username): Path<String>,
+    Token(token): Token,
+    Json(UpdateRole { user }): Json<UpdateRole>,
+) -> AppResult<impl IntoResponse> {
+    user.validate()?;
+
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    auth::permit(&repos, user_id, Action::ManageUsers, None).await?;
+
+    let target = repos.users.profile(&username, None).await?;
+    let changes = UserChanges {
+        role: Some(user.role()),
+        ..Default::default()
+    };
+    let updated = repos.users.update(target.id, changes).await?;
+
+    Ok(Json(AdminUserResponse::from(updated)))
+}
+
+// POST /api/admin/users/:username/unlock
+pub async fn unlock_user(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(username): Path<String>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    auth::permit(&repos, user_id, Action::ManageUsers, None).await?;
+
+    let target = repos.users.profile(&username, None).await?;
+    let target = repos.users.find(target.id).await?;
+    lockout::unlock(repos.login_attempts.as_ref(), &target.email).await?;
+
+    Ok(Json(AdminUserResponse::from(target)))
+}
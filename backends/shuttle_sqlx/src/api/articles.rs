@@ -1,19 +1,31 @@
 use axum::{
-    extract::{Path, Query, State},
-    headers::Authorization,
-    response::IntoResponse,
-    Json, TypedHeader,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use realworld_core::{
+    access_token::Scope,
+    article::{
+        ArticleListResponse, ArticleRedirectResponse, ArticleResponse, ArticleSearchResponse,
+        ArticleStatus, ArticlesParams, CreateArticleData, FeedParams, SearchParams,
+        UpdateArticleData,
+    },
+    keys::KeyRing,
+    metrics::{Event, Metrics},
+    policy::Action,
+    repo::{ArticleChanges, ArticleFilter, NewArticle, RepoError},
 };
-use jsonwebtoken::DecodingKey;
 use serde::Deserialize;
 use serde_json::json;
-use sqlx::PgPool;
 use validator::Validate;
 
 use crate::{
-    db::{retrieve_article, Article, UserProfile},
-    error::{AppError, AppResult, DBError},
-    utils::jwt::{self, JWTToken},
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{self, MaybeToken, Token},
+        extract::{Json, Path, Query},
+    },
 };
 
 #[derive(Deserialize)]
@@ -21,310 +33,299 @@ pub struct CreateArticle {
     article: CreateArticleData,
 }
 
-#[derive(Deserialize, Validate)]
-#[serde(rename_all = "camelCase")]
-struct CreateArticleData {
-    #[serde(default)]
-    tag_list: Vec<String>,
-    #[validate(length(min = 1, message = "title can't be blank"))]
-    title: String,
-    #[validate(length(min = 1, message = "description can't be blank"))]
-    description: String,
-    #[validate(length(min = 1, message = "body can't be blank"))]
-    body: String,
-}
-
 // GET /api/articles
 pub async fn create_article(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(metrics): State<Metrics>,
+    Token(token): Token,
     Json(CreateArticle { article }): Json<CreateArticle>,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
-
     article.validate()?;
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+    auth::permit(&repos, user_id, Action::CreateArticle, None).await?;
 
-    let slug = slug::slugify(&article.title);
-    let tags = article.tag_list;
-
-    let article = sqlx::query_file_as!(
-        Article,
-        "src/sql/articles/create_article.sql",
-        slug,
-        article.title,
-        article.description,
-        article.body,
-        user_id
-    )
-    .fetch_one(&mut pool.acquire().await.unwrap())
-    .await;
-
-    let mut article = match article {
-        Ok(article) => article,
-        Err(_) => return Err(DBError::ArticleAlreadyCreated.into()),
+    let new_article = NewArticle {
+        slug: slug::slugify(&article.title),
+        title: article.title,
+        description: article.description,
+        body: article.body,
+        tag_list: article.tag_list,
+        status: article.status,
     };
 
-    sqlx::query!(
-        "
-        INSERT INTO tags (name)
-        SELECT * FROM UNNEST($1::TEXT[])
-        ON CONFLICT DO NOTHING
-        ",
-        &tags[..]
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
-
-    sqlx::query!(
-        "
-        INSERT INTO article_tags (article_id, tag_id)
-        SELECT $1, tags.id FROM tags WHERE tags.name = ANY($2)
-        ",
-        article.id,
-        &tags[..],
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
-
-    article.tag_list = tags;
+    // A taken slug is a conflict, see `From<RepoError>`
+    let article = repos.articles.create(user_id, new_article).await?;
+    metrics.record(Event::ArticleCreated);
 
-    Ok(Json(json!({ "article": article })))
-}
-
-#[derive(Debug, Deserialize)]
-pub struct ListArticlesQuery {
-    #[serde(default)]
-    tag: Option<String>,
-    #[serde(default)]
-    author: Option<String>,
-    #[serde(default)]
-    favorited: Option<String>,
-    #[serde(default)]
-    limit: Option<usize>,
-    #[serde(default)]
-    offset: Option<usize>,
+    Ok(Json(ArticleResponse::from(article)))
 }
 
 // GET /api/articles
 pub async fn get_articles(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
-    Query(params): Query<ListArticlesQuery>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Query(params): Query<ArticlesParams>,
+    token: MaybeToken,
 ) -> AppResult<impl IntoResponse> {
-    let user_id = token
-        .map(|TypedHeader(Authorization(token))| jwt::verify_token(&token.0, &key))
-        .transpose()?;
-
-    let articles = sqlx::query_file_as!(
-        Article,
-        "src/sql/articles/list_articles.sql",
-        params.author,
-        params.favorited,
-        params.tag,
-        params.limit.unwrap_or(20) as i64,
-        params.offset.unwrap_or(0) as i64,
-        user_id,
-    )
-    .fetch_all(&mut pool.acquire().await.unwrap())
-    .await?;
+    let user_id = auth::verify_optional(&repos, token, &keys, Scope::Read).await?;
 
-    let count = articles.len();
-    Ok(Json(
-        json!({ "articles": articles, "articlesCount": count }),
-    ))
+    let page = params.page()?;
+    let filter = ArticleFilter {
+        tag: params.tag,
+        author: params.author,
+        favorited: params.favorited,
+    };
+
+    let articles = repos.articles.list(&filter, page, user_id).await?;
+    Ok(Json(ArticleListResponse::from(articles)))
 }
 
-#[derive(Deserialize)]
-pub struct ListFeedArticlesQuery {
-    #[serde(default)]
-    limit: Option<usize>,
-    #[serde(default)]
-    offset: Option<usize>,
+// GET /api/articles/search
+pub async fn search_articles(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Query(params): Query<SearchParams>,
+    token: MaybeToken,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_optional(&repos, token, &keys, Scope::Read).await?;
+
+    let query = params.query()?;
+    let results = repos
+        .articles
+        .search(&query, params.page(), user_id)
+        .await?;
+    Ok(Json(ArticleSearchResponse::from(results)))
 }
 
 // /api/articles/feed
 pub async fn get_feed_articles(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
-    Query(params): Query<ListFeedArticlesQuery>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Query(params): Query<FeedParams>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
-
-    let user_id = jwt::verify_token(&token.0, &key)?;
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::Read).await?;
 
-    let articles = sqlx::query_file_as!(
-        Article,
-        "src/sql/articles/feed_articles.sql",
-        user_id,
-        params.limit.unwrap_or(20) as i64,
-        params.offset.unwrap_or(0) as i64,
-    )
-    .fetch_all(&mut pool.acquire().await.unwrap())
-    .await?;
-
-    let count = articles.len();
-    Ok(Json(
-        json!({ "articles": articles, "articlesCount": count }),
-    ))
+    let page = params.page()?;
+    let articles = repos.articles.feed(user_id, page).await?;
+    Ok(Json(ArticleListResponse::from(articles)))
 }
 
 // GET /api/articles/:slug
 pub async fn get_article(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
-) -> AppResult<impl IntoResponse> {
-    let user_id = token
-        .map(|TypedHeader(Authorization(token))| jwt::verify_token(&token.0, &key))
-        .transpose()?;
+    token: MaybeToken,
+) -> AppResult<Response> {
+    let user_id = auth::verify_optional(&repos, token, &keys, Scope::Read).await?;
+
+    let slug = match repos.articles.find_by_slug(&slug, user_id).await {
+        Ok(article) => return Ok(Json(ArticleResponse::from(article)).into_response()),
+        Err(RepoError::NotFound) => repos.articles.renamed(&slug).await?,
+        Err(err) => return Err(err.into()),
+    };
+
+    // Old slugs of drafts stay hidden like the drafts themselves
+    repos.articles.find_by_slug(&slug, user_id).await?;
 
-    let article = retrieve_article(&pool, slug, user_id).await?;
-    Ok(Json(json!({ "article": article })))
+    let redirect = ArticleRedirectResponse::to(slug);
+    let location = redirect.location().to_string();
+    Ok((
+        StatusCode::MOVED_PERMANENTLY,
+        [(header::LOCATION, location)],
+        Json(redirect),
+    )
+        .into_response())
 }
 
 // /api/articles/:slug
 pub async fn delete_article(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-    let deleted = sqlx::query!(
-        r#"
-        DELETE FROM articles
-        WHERE slug = $1 AND author_id = $2
-        RETURNING *
-        "#,
-        slug,
+    let article = match repos.articles.find_by_slug(&slug, Some(user_id)).await {
+        Ok(article) => article,
+        Err(RepoError::NotFound) => {
+            return Ok(Json(json!({ "message": "Article not found.", "code": 1 })))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    auth::permit(
+        &repos,
         user_id,
+        Action::DeleteArticle,
+        Some(article.author.id),
     )
-    .fetch_all(&mut pool.acquire().await.unwrap())
     .await?;
 
-    match deleted.len() {
-        0 => Ok(Json(json!({ "message": "Article not found.", "code": 1 }))),
-        _ => Ok(Json(json!({ "message": "OK" }))),
-    }
+    repos.articles.delete(article.id).await?;
+    Ok(Json(json!({ "message": "OK" })))
 }
 
 #[derive(Debug, Deserialize)]
-pub struct UpdateArticleData {
-    #[serde(default)]
-    title: Option<String>,
-    #[serde(default)]
-    description: Option<String>,
-    #[serde(default)]
-    body: Option<String>,
-}
-
-#[derive(Debug, serde::Deserialize)]
 pub struct UpdateArticle {
     article: UpdateArticleData,
 }
 
 pub async fn update_article(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
     Json(UpdateArticle { article }): Json<UpdateArticle>,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
+    article.validate()?;
+
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+
+    let existing = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    auth::permit(
+        &repos,
+        user_id,
+        Action::EditArticle,
+        Some(existing.author.id),
+    )
+    .await?;
+
+    let changes = ArticleChanges {
+        slug: article.title.as_ref().map(slug::slugify),
+        title: article.title,
+        description: article.description,
+        body: article.body,
+        tag_list: article.tag_list,
+        status: article.status,
     };
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-    let new_slug = article.title.as_ref().map(slug::slugify);
-
-    let article = sqlx::query_file_as!(
-        Article,
-        "src/sql/articles/update_article.sql",
-        article.title,
-        article.description,
-        article.body,
-        new_slug,
-        slug,
+    let article = repos
+        .articles
+        .update(existing.id, changes, Some(user_id))
+        .await?;
+    Ok(Json(ArticleResponse::from(article)))
+}
+
+// POST /api/articles/:slug/publish
+pub async fn publish_article(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(slug): Path<String>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_status(repos, keys, slug, token, ArticleStatus::Published).await
+}
+
+// POST /api/articles/:slug/unpublish, back to a draft
+pub async fn unpublish_article(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(slug): Path<String>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_status(repos, keys, slug, token, ArticleStatus::Draft).await
+}
+
+async fn set_status(
+    repos: Repos,
+    keys: KeyRing,
+    slug: String,
+    Token(token): Token,
+    status: ArticleStatus,
+) -> AppResult<Json<ArticleResponse>> {
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+
+    let existing = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    auth::permit(
+        &repos,
         user_id,
+        Action::EditArticle,
+        Some(existing.author.id),
     )
-    .fetch_one(&mut pool.acquire().await.unwrap())
     .await?;
 
-    Ok(Json(json!({ "article": article })))
+    let changes = ArticleChanges {
+        status: Some(status),
+        ..Default::default()
+    };
+
+    let article = repos
+        .articles
+        .update(existing.id, changes, Some(user_id))
+        .await?;
+    Ok(Json(ArticleResponse::from(article)))
 }
 
 // POST /api/articles/:slug/favorite
 pub async fn favorite_article(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(metrics): State<Metrics>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+    auth::permit(&repos, user_id, Action::FavoriteArticle, None).await?;
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-
-    sqlx::query!(
-        "
-        INSERT INTO article_favs (article_id, user_id)
-        SELECT articles.id, $2
-            FROM articles
-            WHERE articles.slug = $1
-        ",
-        slug,
-        user_id
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
+    let article = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    repos.articles.favorite(article.id, user_id).await?;
+    metrics.record(Event::Favorite);
 
-    let article = retrieve_article(&pool, slug, Some(user_id)).await?;
-    Ok(Json(json!({ "article": article })))
+    let article = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    Ok(Json(ArticleResponse::from(article)))
 }
 
 // DELETE /api/articles/:slug/favorite
 pub async fn un_favorite(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+    auth::permit(&repos, user_id, Action::FavoriteArticle, None).await?;
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-
-    sqlx::query!(
-        "
-        DELETE FROM article_favs
-        WHERE article_id = (
-            SELECT articles.id
-            FROM articles
-            WHERE articles.slug = $1
-        ) AND article_favs.user_id = $2
-        ",
-        slug,
-        user_id
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
+    let article = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    repos.articles.unfavorite(article.id, user_id).await?;
+
+    let article = repos.articles.find_by_slug(&slug, Some(user_id)).await?;
+    Ok(Json(ArticleResponse::from(article)))
+}
+
+// POST /api/articles/:slug/hide, from everyone but its author
+pub async fn hide_article(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(slug): Path<String>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_hidden(repos, keys, slug, token, true).await
+}
+
+// POST /api/articles/:slug/unhide
+pub async fn unhide_article(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(slug): Path<String>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_hidden(repos, keys, slug, token, false).await
+}
 
-    let article = retrieve_article(&pool, slug, Some(user_id)).await?;
-    Ok(Json(json!({ "article": article })))
+async fn set_hidden(
+    repos: Repos,
+    keys: KeyRing,
+    slug: String,
+    Token(token): Token,
+    hidden: bool,
+) -> AppResult<Json<serde_json::Value>> {
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::ArticlesWrite).await?;
+    auth::permit(&repos, user_id, Action::HideArticle, None).await?;
+
+    repos.articles.set_hidden(&slug, hidden).await?;
+    Ok(Json(json!({ "message": "OK" })))
 }
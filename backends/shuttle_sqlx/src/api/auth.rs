@@ -1,13 +1,37 @@
-use axum::{extract::State, response::IntoResponse, Json};
-use jsonwebtoken::EncodingKey;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use realworld_core::{
+    config::TokenConfig,
+    credentials::CookieConfig,
+    keys::KeyRing,
+    lockout::{self, Attempt, LockoutConfig},
+    mail::Outbox,
+    metrics::{Event, Metrics},
+    policy::RoleConfig,
+    repo::{NewUser, RepoError, User},
+    session::RefreshTokenData,
+    token::{hash_token, new_token},
+    totp::{self, ChallengeResponse},
+    user::{LoginUser, RegistrationUser, UsernameConfig, INVALID_LOGIN},
+};
 use serde::Deserialize;
 use serde_json::json;
-use sqlx::PgPool;
 use validator::Validate;
 
 use crate::{
-    error::{AppError, AppResult, DBError},
-    utils::{auth::UserAuth, hasher},
+    api::account::send_verification,
+    db::Repos,
+    error::{AppError, AppResult},
+    utils::{
+        auth::{
+            self, clear_session, session_response, start_session, ClientIp, RefreshCookie, Token,
+            UserId,
+        },
+        extract::Json,
+        hasher, jwt,
+    },
 };
 
 // ================================================= LOGIN ================================================= //
@@ -17,112 +41,181 @@ pub struct Login {
     user: LoginUser,
 }
 
-#[derive(Debug, Deserialize, Validate)]
-struct LoginUser {
-    #[validate(
-        email(message = "invalid email address"),
-        length(min = 1, message = "email can't be blank")
-    )]
-    email: String,
-    #[validate(length(min = 1, message = "password can't be blank"))]
-    password: String,
-}
-
+// POST /api/users/login, only answers with a challenge once a second factor is enabled
+#[allow(clippy::too_many_arguments)]
 pub async fn login(
-    State(pool): State<PgPool>,
-    State(key): State<EncodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(roles): State<RoleConfig>,
+    State(limits): State<LockoutConfig>,
+    State(cookies): State<CookieConfig>,
+    State(metrics): State<Metrics>,
+    ClientIp(ip): ClientIp,
     Json(Login { user }): Json<Login>,
-) -> AppResult<impl IntoResponse> {
+) -> AppResult<Response> {
     user.validate()?;
 
-    let mut conn = pool.acquire().await.unwrap();
+    let attempt = Attempt::new(&user.email, ip);
+    let attempts = repos.login_attempts.as_ref();
+    if let Some(until) = lockout::locked_until(attempts, &limits, &attempt).await? {
+        return Err(AppError::LockedOut(until));
+    }
 
-    let user_auth = sqlx::query_as!(
-        UserAuth,
-        "SELECT *, NULL AS token FROM users WHERE email = $1",
-        user.email
-    )
-    .fetch_optional(&mut conn)
-    .await?;
+    let user_auth = match check_password(&repos, &user).await {
+        Ok(user_auth) => user_auth,
+        Err(err @ AppError::Unauthorized(_)) => {
+            if let Some(until) = lockout::fail(attempts, &limits, &attempt).await? {
+                return Err(AppError::LockedOut(until));
+            }
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+
+    // Failures are only forgotten once the second factor is right too
+    if totp::is_enabled(repos.totp.as_ref(), user_auth.id).await? {
+        let challenge = totp::issue_challenge(&keys, user_auth.id, tokens.challenge_expiry())?;
+        return Ok(Json(ChallengeResponse::from(challenge)).into_response());
+    }
+    lockout::succeed(attempts, &attempt).await?;
+
+    let response = start_session(&repos, &tokens, &keys, &roles, user_auth).await?;
+    metrics.record(Event::Login);
+    Ok(session_response(&cookies, &tokens, response))
+}
 
-    let Some(mut user_auth) = user_auth else {
-        Err(AppError::Forbidden("Invalid User"))?
+/// The user `user` logs in as. Unknown emails and wrong passwords are unauthorized alike, so
+/// neither tells which emails are registered.
+async fn check_password(repos: &Repos, user: &LoginUser) -> AppResult<User<UserId>> {
+    let user_auth = match repos.users.find_by_email(&user.email).await {
+        Ok(user_auth) => user_auth,
+        Err(RepoError::NotFound) => Err(AppError::Unauthorized(INVALID_LOGIN))?,
+        Err(err) => Err(err)?,
     };
 
-    let hash =
-        password_hash::PasswordHash::new(&user_auth.hash).map_err(|err| anyhow::anyhow!(err))?;
+    let hash = password_hash::PasswordHash::new(&user_auth.password_hash)?;
 
     hash.verify_password(&[&argon2::Argon2::default()], &user.password)
         .map_err(|err| {
-            log::error!("err: {:?}", err);
-            AppError::Forbidden("email or password is invalid")
+            tracing::debug!(error = %err, "password mismatch");
+            AppError::Unauthorized(INVALID_LOGIN)
         })?;
 
-    user_auth.generate_jwt(&key)?;
-    Ok(Json(json!({ "user": user_auth })))
+    Ok(user_auth)
 }
 
 // ================================================= REGISTRATION ================================================= //
 
-#[derive(Deserialize, Validate)]
-struct RegistrationUser {
-    #[validate(
-        non_control_character(message = "user name can't contain non-ascii charactors"),
-        length(min = 1, message = "user name can't be blank"),
-        length(max = 64, message = "too long user name")
-    )]
-    username: String,
-
-    #[validate(
-        length(min = 1, message = "email can't be blank"),
-        length(max = 64, message = "too long email address"),
-        email(message = "invalid email address")
-    )]
-    email: String,
-
-    #[validate(
-        non_control_character(message = "password can't contain non-ascii charactors"),
-        length(min = 8, message = "password must be at least 8 characters long"),
-        length(max = 64, message = "too long password")
-    )]
-    password: String,
-}
-
 #[derive(Deserialize)]
 pub struct Registration {
     user: RegistrationUser,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn registration(
-    State(pool): State<PgPool>,
-    State(key): State<EncodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(roles): State<RoleConfig>,
+    State(outbox): State<Outbox>,
+    State(cookies): State<CookieConfig>,
+    State(usernames): State<UsernameConfig>,
+    State(metrics): State<Metrics>,
     Json(Registration { user }): Json<Registration>,
-) -> AppResult<impl IntoResponse> {
+) -> AppResult<Response> {
     user.validate()?;
+    usernames.check(&user.username)?;
 
-    let hash = hasher::hash_password(&user.password)?;
-    let mut conn = pool.acquire().await.unwrap();
-
-    let user_auth = sqlx::query_as!(
-        UserAuth,
-        r#"
-        INSERT INTO users (username, email, hash)
-        VALUES ($1, $2, $3)
-        RETURNING *, NULL AS token
-        "#,
-        user.username,
-        user.email,
-        hash
-    )
-    .fetch_optional(&mut conn)
-    .await;
-
-    let user_auth = match user_auth {
-        Ok(user_auth) => user_auth,
-        Err(_) => return Err(DBError::AlreadyRegistered.into()),
+    let new_user = NewUser {
+        password_hash: hasher::hash_password(&user.password)?,
+        username: user.username,
+        email: user.email,
     };
 
-    let mut user_auth = user_auth.unwrap();
-    user_auth.generate_jwt(&key)?;
-    Ok(Json(json!({ "user": user_auth })))
+    // A taken username or email is a field error, see `From<RepoError>`
+    let user_auth = repos.users.create(new_user).await?;
+    send_verification(&repos, &tokens, &outbox, &user_auth).await?;
+    metrics.record(Event::Registration);
+
+    let response = start_session(&repos, &tokens, &keys, &roles, user_auth).await?;
+    Ok(session_response(&cookies, &tokens, response))
+}
+
+// ================================================= KEYS ================================================= //
+
+// GET /.well-known/jwks.json, HMAC secrets are never listed
+pub async fn jwks(State(keys): State<KeyRing>) -> impl IntoResponse {
+    Json(keys.jwks().clone())
+}
+
+// ================================================= SESSIONS ================================================= //
+
+#[derive(Deserialize)]
+pub struct Refresh {
+    // Cookie sessions send their refresh token as a cookie, and `{}` as the body
+    #[serde(default)]
+    user: RefreshTokenData,
+}
+
+// POST /api/users/refresh, swaps a refresh token for a new one and a fresh access token
+pub async fn refresh(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(cookies): State<CookieConfig>,
+    RefreshCookie(refresh_cookie): RefreshCookie,
+    Json(Refresh { mut user }): Json<Refresh>,
+) -> AppResult<Response> {
+    if let (true, Some(refresh_token)) = (user.refresh_token.is_empty(), refresh_cookie) {
+        user.refresh_token = refresh_token;
+    }
+    user.validate()?;
+
+    let refresh_token = new_token();
+    let rotated = repos
+        .sessions
+        .rotate(
+            &hash_token(&user.refresh_token),
+            &hash_token(&refresh_token),
+            tokens.refresh_expiry(),
+        )
+        .await;
+
+    let session = match rotated {
+        Ok(session) => session,
+        Err(RepoError::NotFound) => return Err(AppError::Unauthorized("Invalid refresh token")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let user = repos.users.find(session.user_id).await?;
+    let token = jwt::generate_token(user.id, session.id, tokens.access_expiry(), &keys)?;
+    let response = user.into_response(token).with_refresh_token(refresh_token);
+    Ok(session_response(&cookies, &tokens, response))
+}
+
+// POST /api/users/logout, ends the session of the token used
+pub async fn logout(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(cookies): State<CookieConfig>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let claims = auth::verify_session(&repos, &token, &keys).await?;
+    repos.sessions.revoke(claims.sid).await?;
+
+    Ok((clear_session(&cookies), Json(json!({ "message": "OK" }))))
+}
+
+// POST /api/users/logout-all, ends every session of the user
+pub async fn logout_all(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(cookies): State<CookieConfig>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user_id = auth::verify_session(&repos, &token, &keys).await?.user_id;
+    repos.sessions.revoke_all(user_id).await?;
+
+    Ok((clear_session(&cookies), Json(json!({ "message": "OK" }))))
 }
@@ -1,161 +1,137 @@
-use axum::{
-    extract::{Path, State},
-    headers::Authorization,
-    response::IntoResponse,
-    Json, TypedHeader,
+use axum::{extract::State, response::IntoResponse};
+use realworld_core::{
+    access_token::Scope,
+    comment::{AddCommentData, CommentListResponse, CommentResponse},
+    keys::KeyRing,
+    policy::Action,
+    repo::RepoError,
 };
-use jsonwebtoken::DecodingKey;
 use serde::Deserialize;
 use serde_json::json;
-use sqlx::PgPool;
 use validator::Validate;
 
 use crate::{
-    error::{AppError, AppResult},
-    utils::jwt::{self, JWTToken},
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{self, MaybeToken, Token},
+        extract::{Json, Path},
+    },
 };
 
-use crate::db::Comment;
-use crate::db::UserProfile;
-
 #[derive(Deserialize)]
 pub struct AddComment {
     comment: AddCommentData,
 }
 
-#[derive(Deserialize, Validate)]
-#[serde(rename_all = "camelCase")]
-struct AddCommentData {
-    #[validate(length(min = 1, message = "body can't be blank"))]
-    body: String,
-}
-
 // POST /api/articles/:slug/comments
 pub async fn create_comment(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
     Json(AddComment { comment }): Json<AddComment>,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
+    comment.validate()?;
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-
-    let comment = sqlx::query_as!(
-        Comment,
-        r#"
-        WITH comment AS (
-            INSERT INTO comments (body, article_id, author_id)
-            VALUES ($1, (SELECT id FROM articles WHERE slug = $2), $3)
-            RETURNING *
-        )
-        SELECT
-            comment.id,
-            comment.created_at,
-            comment.updated_at,
-            comment.body,
-            (
-                users.id,
-                users.username,
-                users.bio,
-                users.image,
-                EXISTS (
-                    SELECT 1
-                    FROM follows
-                    WHERE follows.follower_id = $3
-                        AND follows.followee_id = users.id
-                )
-            ) AS "author!: UserProfile"
-        FROM comment INNER JOIN users ON users.id = comment.author_id
-        "#,
-        comment.body,
-        slug,
-        user_id,
-    )
-    .fetch_one(&pool)
-    .await?;
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::CommentsWrite).await?;
+    auth::permit(&repos, user_id, Action::Comment, None).await?;
 
-    Ok(Json(json!({ "comment": comment })))
+    let article = repos.articles.find_by_slug(&slug, None).await?;
+    let comment = repos
+        .comments
+        .create(article.id, user_id, comment.body)
+        .await?;
+
+    Ok(Json(CommentResponse::from(comment)))
 }
 
 // GET /api/articles/:slug/comments
 pub async fn get_comments(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(slug): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    token: MaybeToken,
 ) -> AppResult<impl IntoResponse> {
-    let user_id = token
-        .map(|TypedHeader(Authorization(token))| jwt::verify_token(&token.0, &key))
-        .transpose()?;
-
-    let comments = sqlx::query_as!(
-        Comment,
-        r#"
-        SELECT
-            comments.id,
-            comments.created_at,
-            comments.updated_at,
-            comments.body,
-            (
-                users.id,
-                users.username,
-                users.bio,
-                users.image,
-                EXISTS (
-                    SELECT 1
-                    FROM follows
-                    WHERE follows.follower_id = $2
-                        AND follows.followee_id = users.id
-                )
-            ) AS "author!: UserProfile"
-        FROM comments 
-        INNER JOIN users ON users.id = comments.author_id
-        WHERE comments.article_id = (SELECT id FROM articles WHERE slug = $1)
-        ORDER BY comments.created_at DESC
-        "#,
-        slug,
-        user_id,
-    )
-    .fetch_all(&pool)
-    .await?;
+    let user_id = auth::verify_optional(&repos, token, &keys, Scope::Read).await?;
 
-    Ok(Json(json!({ "comments": comments })))
+    let article = repos.articles.find_by_slug(&slug, None).await?;
+    let comments = repos.comments.list(article.id, user_id).await?;
+
+    Ok(Json(CommentListResponse::from(comments)))
 }
 
 // DELETE /api/articles/:slug/comments/:id
 pub async fn delete_comment(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path((slug, comment_id)): Path<(String, i32)>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::CommentsWrite).await?;
+
+    let not_found = Json(json!({ "message": "Comment not found.", "code": 1 }));
+
+    let article = match repos.articles.find_by_slug(&slug, None).await {
+        Ok(article) => article,
+        Err(RepoError::NotFound) => return Ok(not_found),
+        Err(err) => return Err(err.into()),
     };
 
-    let user_id = jwt::verify_token(&token.0, &key)?;
-
-    let deleted = sqlx::query!(
-        r#"
-        DELETE FROM comments
-        WHERE id = $1
-            AND author_id = $2
-            AND article_id = (SELECT id FROM articles WHERE slug = $3)
-        RETURNING *
-        "#,
-        comment_id,
+    let comment = match repos.comments.find(comment_id).await {
+        Ok(comment) if comment.article_id == article.id => comment,
+        Ok(_) | Err(RepoError::NotFound) => return Ok(not_found),
+        Err(err) => return Err(err.into()),
+    };
+    auth::permit(
+        &repos,
         user_id,
-        slug,
+        Action::DeleteComment,
+        Some(comment.author.id),
     )
-    .fetch_all(&pool)
     .await?;
 
-    match deleted.len() {
-        0 => Ok(Json(json!({ "message": "Comment not found.", "code": 1 }))),
-        _ => Ok(Json(json!({ "message": "OK" }))),
+    repos.comments.delete(comment_id).await?;
+    Ok(Json(json!({ "message": "OK" })))
+}
+
+// POST /api/articles/:slug/comments/:id/hide, from everyone but its author
+pub async fn hide_comment(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path((slug, comment_id)): Path<(String, i32)>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_hidden(repos, keys, slug, comment_id, token, true).await
+}
+
+// POST /api/articles/:slug/comments/:id/unhide
+pub async fn unhide_comment(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path((slug, comment_id)): Path<(String, i32)>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    set_hidden(repos, keys, slug, comment_id, token, false).await
+}
+
+async fn set_hidden(
+    repos: Repos,
+    keys: KeyRing,
+    slug: String,
+    comment_id: i32,
+    Token(token): Token,
+    hidden: bool,
+) -> AppResult<Json<serde_json::Value>> {
+    let user_id = auth::verify_token(&repos, &token, &keys, Scope::CommentsWrite).await?;
+    auth::permit(&repos, user_id, Action::HideComment, None).await?;
+
+    let article = repos.articles.find_by_slug(&slug, None).await?;
+    let comment = repos.comments.find(comment_id).await?;
+    if comment.article_id != article.id {
+        return Err(RepoError::NotFound.into());
     }
+
+    repos.comments.set_hidden(comment_id, hidden).await?;
+    Ok(Json(json!({ "message": "OK" })))
 }
@@ -0,0 +1,34 @@
+use axum::{
+    extract::State,
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap,
+    },
+    response::{IntoResponse, Response},
+};
+use realworld_core::metrics::{self, Metrics};
+
+use crate::error::{AppError, AppResult};
+
+/// Prometheus metrics next to the API, only served behind `metrics.token`.
+pub async fn get_metrics(
+    State(metrics): State<Metrics>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    if !metrics.exposed() {
+        return Err(AppError::NotFound(None));
+    }
+    scrape(State(metrics), headers).await
+}
+
+/// Prometheus metrics on `metrics.bind`.
+pub async fn scrape(State(metrics): State<Metrics>, headers: HeaderMap) -> AppResult<Response> {
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+    if !metrics.authorized(authorization) {
+        return Err(AppError::Unauthorized("Invalid metrics token"));
+    }
+
+    Ok(([(CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics.render()).into_response())
+}
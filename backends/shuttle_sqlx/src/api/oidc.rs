@@ -0,0 +1,93 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use realworld_core::{
+    config::TokenConfig,
+    credentials::CookieConfig,
+    keys::KeyRing,
+    mail::Outbox,
+    metrics::{Event, Metrics},
+    oidc::{self, Oidc, OidcCallback, ProviderListResponse},
+    policy::RoleConfig,
+    token::new_token,
+    totp::{self, ChallengeResponse},
+    user::UsernameConfig,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    api::account::send_verification,
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{session_response, start_session},
+        extract::{Json, Path},
+        hasher,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct Callback {
+    user: OidcCallback,
+}
+
+// GET /api/users/oidc, the providers users can log in with
+pub async fn get_providers(State(oidc): State<Oidc>) -> impl IntoResponse {
+    Json(ProviderListResponse {
+        providers: oidc.provider_names(),
+    })
+}
+
+// POST /api/users/oidc/:provider/authorize, answers with where to send the user
+pub async fn authorize(
+    State(repos): State<Repos>,
+    State(oidc): State<Oidc>,
+    Path(provider): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let authorization = oidc::begin(&oidc, repos.oidc.as_ref(), &provider).await?;
+    Ok(Json(authorization))
+}
+
+// POST /api/users/oidc/:provider/callback, logs in the user the provider sent back
+#[allow(clippy::too_many_arguments)]
+pub async fn callback(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(roles): State<RoleConfig>,
+    State(outbox): State<Outbox>,
+    State(oidc): State<Oidc>,
+    State(cookies): State<CookieConfig>,
+    State(usernames): State<UsernameConfig>,
+    State(metrics): State<Metrics>,
+    Path(provider): Path<String>,
+    Json(Callback { user: callback }): Json<Callback>,
+) -> AppResult<Response> {
+    callback.validate()?;
+
+    let identity = oidc::complete(&oidc, repos.oidc.as_ref(), &provider, &callback).await?;
+    let user = match oidc::linked_user(&repos, &identity).await? {
+        Some(user) => user,
+        None => {
+            // Nobody knows the password, it can only be set through a password reset
+            let password_hash = hasher::hash_password(new_token())?;
+            let user = oidc::provision(&repos, &usernames, &identity, password_hash).await?;
+            if !user.email_verified {
+                send_verification(&repos, &tokens, &outbox, &user).await?;
+            }
+            metrics.record(Event::Registration);
+            user
+        }
+    };
+
+    if totp::is_enabled(repos.totp.as_ref(), user.id).await? {
+        let challenge = totp::issue_challenge(&keys, user.id, tokens.challenge_expiry())?;
+        return Ok(Json(ChallengeResponse::from(challenge)).into_response());
+    }
+
+    let response = start_session(&repos, &tokens, &keys, &roles, user).await?;
+    metrics.record(Event::Login);
+    Ok(session_response(&cookies, &tokens, response))
+}
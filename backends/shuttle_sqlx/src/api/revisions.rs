@@ -0,0 +1,110 @@
+use axum::{extract::State, response::IntoResponse};
+use realworld_core::{
+    access_token::Scope,
+    article::ArticleResponse,
+    keys::KeyRing,
+    policy::Action,
+    repo::{Article, ArticleChanges},
+    revision::{RevisionDiffResponse, RevisionListResponse, RevisionResponse},
+};
+
+use crate::{
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{self, Token, UserId},
+        extract::{Json, Path},
+    },
+};
+
+// GET /api/articles/:slug/revisions
+pub async fn get_revisions(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path(slug): Path<String>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    let (article, user_id) = find_editable(&repos, &keys, &slug, token, Scope::Read).await?;
+
+    let revisions = repos.revisions.list(article.id, Some(user_id)).await?;
+    Ok(Json(RevisionListResponse::from(revisions)))
+}
+
+// GET /api/articles/:slug/revisions/:number
+pub async fn get_revision(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path((slug, number)): Path<(String, i32)>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    let (article, user_id) = find_editable(&repos, &keys, &slug, token, Scope::Read).await?;
+
+    let revision = repos
+        .revisions
+        .find(article.id, number, Some(user_id))
+        .await?;
+    Ok(Json(RevisionResponse::from(revision)))
+}
+
+// GET /api/articles/:slug/revisions/:from/diff/:to
+pub async fn diff_revisions(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path((slug, from, to)): Path<(String, i32, i32)>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    let (article, user_id) = find_editable(&repos, &keys, &slug, token, Scope::Read).await?;
+
+    let from = repos
+        .revisions
+        .find(article.id, from, Some(user_id))
+        .await?;
+    let to = repos.revisions.find(article.id, to, Some(user_id)).await?;
+    Ok(Json(RevisionDiffResponse::between(&from, &to)))
+}
+
+// POST /api/articles/:slug/revisions/:number/restore, stored as a new revision
+pub async fn restore_revision(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Path((slug, number)): Path<(String, i32)>,
+    token: Token,
+) -> AppResult<impl IntoResponse> {
+    let (article, user_id) =
+        find_editable(&repos, &keys, &slug, token, Scope::ArticlesWrite).await?;
+
+    let revision = repos
+        .revisions
+        .find(article.id, number, Some(user_id))
+        .await?;
+
+    let changes = ArticleChanges {
+        slug: (revision.title != article.title).then(|| slug::slugify(&revision.title)),
+        title: Some(revision.title),
+        description: Some(revision.description),
+        body: Some(revision.body),
+        ..Default::default()
+    };
+
+    let article = repos
+        .articles
+        .update(article.id, changes, Some(user_id))
+        .await?;
+    Ok(Json(ArticleResponse::from(article)))
+}
+
+/// Only those who may edit an article get to see its history.
+async fn find_editable(
+    repos: &Repos,
+    keys: &KeyRing,
+    slug: &str,
+    Token(token): Token,
+    scope: Scope,
+) -> AppResult<(Article<UserId>, UserId)> {
+    let user_id = auth::verify_token(repos, &token, keys, scope).await?;
+
+    let article = repos.articles.find_by_slug(slug, Some(user_id)).await?;
+    auth::permit(repos, user_id, Action::EditArticle, Some(article.author.id)).await?;
+
+    Ok((article, user_id))
+}
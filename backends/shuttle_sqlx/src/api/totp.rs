@@ -0,0 +1,85 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use realworld_core::{
+    config::TokenConfig,
+    credentials::CookieConfig,
+    error::field_error,
+    keys::KeyRing,
+    lockout::LockoutConfig,
+    metrics::{Event, Metrics},
+    policy::RoleConfig,
+    totp::{self, TotpConfirm, TotpLogin, TotpResponse},
+};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    db::Repos,
+    error::AppResult,
+    utils::{
+        auth::{auth_user, session_response, start_session, ClientIp, Token},
+        extract::Json,
+    },
+};
+
+#[derive(Deserialize)]
+pub struct Confirm {
+    totp: TotpConfirm,
+}
+
+#[derive(Deserialize)]
+pub struct Login {
+    user: TotpLogin,
+}
+
+// POST /api/user/totp, starts over until a code is confirmed
+pub async fn enroll(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
+) -> AppResult<impl IntoResponse> {
+    let user = auth_user(&repos, &token, &keys).await?;
+    match totp::enroll(repos.totp.as_ref(), user.id, &user.email).await? {
+        Some(enrollment) => Ok(Json(TotpResponse::from(enrollment))),
+        None => Err(field_error("totp", "is already enabled").into()),
+    }
+}
+
+// POST /api/user/totp/confirm, answers with the recovery codes
+pub async fn confirm(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
+    Json(Confirm { totp: confirm }): Json<Confirm>,
+) -> AppResult<impl IntoResponse> {
+    confirm.validate()?;
+
+    let user = auth_user(&repos, &token, &keys).await?;
+    match totp::confirm(repos.totp.as_ref(), user.id, &confirm.code).await? {
+        Some(recovery_codes) => Ok(Json(TotpResponse::from(recovery_codes))),
+        None => Err(field_error("code", "is invalid").into()),
+    }
+}
+
+// POST /api/users/login/totp, swaps a challenge and a second factor for a session
+#[allow(clippy::too_many_arguments)]
+pub async fn login(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(roles): State<RoleConfig>,
+    State(limits): State<LockoutConfig>,
+    State(cookies): State<CookieConfig>,
+    State(metrics): State<Metrics>,
+    ClientIp(ip): ClientIp,
+    Json(Login { user: login }): Json<Login>,
+) -> AppResult<Response> {
+    login.validate()?;
+
+    let user = totp::redeem(&repos, &keys, &limits, &login, ip).await?;
+    let response = start_session(&repos, &tokens, &keys, &roles, user).await?;
+    metrics.record(Event::Login);
+    Ok(session_response(&cookies, &tokens, response))
+}
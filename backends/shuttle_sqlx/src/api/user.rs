@@ -1,171 +1,188 @@
 use axum::{
-    extract::{Path, State},
-    headers::Authorization,
-    response::IntoResponse,
-    Json, TypedHeader,
+    extract::State,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use realworld_core::{
+    access_token::Scope,
+    config::TokenConfig,
+    credentials::CookieConfig,
+    keys::KeyRing,
+    mail::Outbox,
+    policy::Action,
+    privacy::{self, DeleteUserParams, ExportParams, PrivacyConfig},
+    profile::ProfileResponse,
+    repo::UserChanges,
+    token::new_token,
+    user::{UpdateUserData, UsernameConfig},
 };
-use jsonwebtoken::DecodingKey;
 use serde::Deserialize;
 use serde_json::json;
-use sqlx::PgPool;
 use validator::Validate;
 
 use crate::{
-    db::{auth_user, get_user_profile},
+    api::account::send_verification,
+    db::Repos,
     error::{AppError, AppResult},
     utils::{
-        auth::UserAuth,
+        auth::{self, auth_user, clear_session, MaybeToken, Token},
+        extract::{Json, Path, Query},
         hasher,
-        jwt::{self, JWTToken},
     },
 };
 
-#[derive(Debug, Deserialize, Validate)]
-pub struct UpdateUserData {
-    pub bio: Option<String>,
-    pub image: Option<String>,
-
-    #[validate(email)]
-    pub email: Option<String>,
-    #[validate(non_control_character, length(min = 1, max = 64))]
-    pub username: Option<String>,
-    #[validate(non_control_character, length(min = 8, max = 64))]
-    pub password: Option<String>,
-}
-
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct UpdateUser {
     user: UpdateUserData,
 }
 
 // GET /api/user
 pub async fn get_current_user(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
-    TypedHeader(Authorization(token)): TypedHeader<Authorization<JWTToken>>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let user = auth_user(&pool, &token.0, &key).await?;
-    Ok(Json(json!({ "user": user })))
+    let user = auth_user(&repos, &token, &keys).await?;
+    Ok(Json(user.into_response(token)))
 }
 
-// PUT /api/user
+// PUT /api/user, a new email address has to be verified again
 pub async fn update_user(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
-    header: Option<TypedHeader<Authorization<JWTToken>>>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(tokens): State<TokenConfig>,
+    State(outbox): State<Outbox>,
+    State(usernames): State<UsernameConfig>,
+    Token(token): Token,
     Json(UpdateUser { user: updated_user }): Json<UpdateUser>,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = header else{
-        return Err(AppError::Unauthorized);
-    };
-
-    let user = auth_user(&pool, &token.0, &key).await?;
+    updated_user.validate()?;
+
+    let user = auth_user(&repos, &token, &keys).await?;
+    // Whoever got a reserved name before it was reserved keeps it
+    if let Some(ref username) = updated_user.username {
+        if *username != user.username {
+            usernames.check(username)?;
+        }
+    }
     let hash = updated_user
         .password
         .map(hasher::hash_password)
         .transpose()?;
 
-    let mut updated_user = sqlx::query_as!(
-        UserAuth,
-        "UPDATE users
-            SET (email, username, bio, image, hash) =
-                (
-                    COALESCE($1, email),
-                    COALESCE($2, username),
-                    COALESCE($3, bio),
-                    COALESCE($4, image),
-                    COALESCE($5, hash)
-                )
-            WHERE id = $6
-        RETURNING *, NULL AS token",
-        updated_user.email,
-        updated_user.username,
-        updated_user.bio,
-        updated_user.image,
-        hash,
-        user.id
-    )
-    .fetch_one(&mut pool.acquire().await.unwrap())
-    .await?;
-
-    updated_user.token = Some(token.0);
-
-    Ok(Json(json!({ "user": updated_user })))
+    let changes = UserChanges {
+        email: updated_user.email,
+        username: updated_user.username,
+        bio: updated_user.bio,
+        image: updated_user.image,
+        password_hash: hash,
+        ..Default::default()
+    };
+
+    let updated_user = repos.users.update(user.id, changes).await?;
+    if updated_user.email != user.email {
+        send_verification(&repos, &tokens, &outbox, &updated_user).await?;
+    }
+
+    Ok(Json(updated_user.into_response(token)))
+}
+
+// DELETE /api/user, `?content=anonymize|remove` overrides what happens to articles and comments
+pub async fn delete_user(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    State(privacy): State<PrivacyConfig>,
+    State(cookies): State<CookieConfig>,
+    Token(token): Token,
+    Query(params): Query<DeleteUserParams>,
+) -> AppResult<impl IntoResponse> {
+    let policy = params.policy(&privacy)?;
+    let user = auth_user(&repos, &token, &keys).await?;
+
+    // Anonymized accounts keep a password nobody knows
+    let password_hash = hasher::hash_password(new_token())?;
+    privacy::delete_account(&repos, &user, policy, password_hash).await?;
+
+    Ok((clear_session(&cookies), Json(json!({ "message": "OK" }))))
+}
+
+// GET /api/user/export, `?format=zip` for a ZIP archive instead of JSON
+pub async fn export_user(
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
+    Token(token): Token,
+    Query(params): Query<ExportParams>,
+) -> AppResult<Response> {
+    let zip = params.zip()?;
+    let user = auth_user(&repos, &token, &keys).await?;
+    let export = privacy::export(&repos, user.id).await?;
+
+    if !zip {
+        return Ok(Json(export).into_response());
+    }
+    let archive = export.to_zip().map_err(|_| AppError::InternalServerError)?;
+    let headers = [
+        (CONTENT_TYPE, "application/zip"),
+        (
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"realworld-export.zip\"",
+        ),
+    ];
+    Ok((headers, archive).into_response())
 }
 
 // GET /api/user/:username
 pub async fn get_profile(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(username): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    token: MaybeToken,
 ) -> AppResult<impl IntoResponse> {
-    let user_id = token
-        .map(|TypedHeader(Authorization(token))| jwt::verify_token(&token.0, &key))
-        .transpose()?;
+    let user_id = auth::verify_optional(&repos, token, &keys, Scope::Read).await?;
 
-    let profile = get_user_profile(&pool, &username, user_id).await?;
-    Ok(Json(json!({ "profile": profile })))
+    let profile = repos.users.profile(&username, user_id).await?;
+    Ok(Json(ProfileResponse::from(profile)))
 }
 
 // POST /api/user/:username/follow
 pub async fn follow_profile(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(username): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
+    let follower_id = auth::verify_token(&repos, &token, &keys, Scope::ProfilesWrite).await?;
+    auth::permit(&repos, follower_id, Action::Follow, None).await?;
+    let mut followee = repos.users.profile(&username, Some(follower_id)).await?;
+    if followee.id == follower_id {
+        return Err(AppError::UnprocessableEntity("You cannot follow yourself"));
+    }
 
-    let follower_id = jwt::verify_token(&token.0, &key)?;
-    let mut followee = get_user_profile(&pool, &username, Some(follower_id)).await?;
-
-    sqlx::query!(
-        "
-        INSERT INTO follows (follower_id, followee_id)
-        VALUES ($1, $2)
-        ON CONFLICT DO NOTHING
-        ",
-        follower_id,
-        followee.id
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
+    repos.follows.follow(follower_id, followee.id).await?;
 
     followee.following = true;
-    Ok(Json(json!({ "profile": followee })))
+    Ok(Json(ProfileResponse::from(followee)))
 }
 
 pub async fn unfollow_profile(
-    State(pool): State<PgPool>,
-    State(key): State<DecodingKey>,
+    State(repos): State<Repos>,
+    State(keys): State<KeyRing>,
     Path(username): Path<String>,
-    token: Option<TypedHeader<Authorization<JWTToken>>>,
+    Token(token): Token,
 ) -> AppResult<impl IntoResponse> {
-    let Some(TypedHeader(Authorization(token))) = token else{
-        return Err(AppError::Unauthorized);
-    };
-
-    let follower_id = jwt::verify_token(&token.0, &key)?;
-    let mut followee = get_user_profile(&pool, &username, Some(follower_id)).await?;
-
-    if !followee.following {
-        return Ok(Json(json!({ "profile": followee })));
+    let follower_id = auth::verify_token(&repos, &token, &keys, Scope::ProfilesWrite).await?;
+    auth::permit(&repos, follower_id, Action::Follow, None).await?;
+    let mut followee = repos.users.profile(&username, Some(follower_id)).await?;
+    if followee.id == follower_id {
+        return Err(AppError::UnprocessableEntity(
+            "You cannot unfollow yourself",
+        ));
     }
 
-    sqlx::query!(
-        "
-        DELETE FROM follows
-        WHERE follower_id = $1 AND followee_id = $2
-        ",
-        follower_id,
-        followee.id
-    )
-    .execute(&mut pool.acquire().await.unwrap())
-    .await?;
+    if followee.following {
+        repos.follows.unfollow(follower_id, followee.id).await?;
+    }
 
     followee.following = false;
-    Ok(Json(json!({ "profile": followee })))
+    Ok(Json(ProfileResponse::from(followee)))
 }
@@ -0,0 +1,72 @@
+//! Runs the API without the Shuttle runtime, configured through `realworld.toml` and
+//! `REALWORLD_*` environment variables.
+
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use realworld_core::{
+    config::{self, Storage},
+    mail::Outbox,
+    metrics::Metrics,
+    oidc::Oidc,
+};
+use static_next_server::{config::Config, db, routes};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let config: Config = config::load().context("invalid configuration")?;
+    config.log.init();
+    let keys = config.jwt.key_ring().context("invalid jwt keys")?;
+
+    let pool = config.pool().context("invalid database url")?;
+    let mut metrics = Metrics::new(&config.metrics);
+    let repos = match config.storage {
+        Storage::Memory => db::Repos::in_memory(),
+        Storage::Postgres => {
+            db::prepare_db(&pool)
+                .await
+                .context("failed to prepare the database")?;
+            let (stats_pool, max) = (pool.clone(), config.pool.max_connections);
+            metrics = metrics.with_pool(move || db::pool_stats(&stats_pool, max));
+            db::PgRepo::new(pool.clone()).into_repos()
+        }
+    };
+
+    let mailer = config.mail.mailer().context("invalid mail settings")?;
+    let outbox = Outbox::spawn(&config.mail, repos.outbox.clone(), mailer);
+
+    let oidc = Oidc::new(&config.oidc);
+
+    let app = routes::generate_routes(
+        pool,
+        repos,
+        keys,
+        config.tokens,
+        outbox,
+        oidc,
+        config.roles,
+        config.lockout,
+        config.cookies,
+        config.privacy,
+        config.usernames,
+        metrics.clone(),
+    );
+
+    if let Some(bind) = config.metrics.bind {
+        let server =
+            axum::Server::bind(&bind).serve(routes::metrics_routes(metrics).into_make_service());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::error!(error = %e, "metrics server error");
+            }
+        });
+    }
+
+    // Connect info lets failed logins be counted per client address
+    axum::Server::bind(&config.bind)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .context("server error")
+}
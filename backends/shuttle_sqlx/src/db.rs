@@ -1,32 +1,66 @@
-mod user;
-pub use user::*;
+mod access_token;
+mod account;
 mod article;
-pub use article::*;
 mod comment;
-pub use comment::*;
+mod login_attempt;
+mod oidc;
+mod revision;
+mod session;
 mod tag;
-pub use tag::*;
+mod totp;
+mod user;
 
-use axum::{extract::State, response::IntoResponse, Json};
-use serde_json::json;
+use realworld_core::{metrics::PoolStats, repo::RepoError};
 use sqlx::{Executor, PgPool};
 
-use crate::error::AppResult;
+use crate::utils::auth::UserId;
 
-pub async fn prepare_db(pool: &PgPool) -> Result<(), sqlx::Error> {
-    pool.execute(include_str!("sql/schema.sql")).await?;
-    Ok(())
+pub type Repos = realworld_core::repo::Repos<UserId>;
+
+/// Postgres implementation of every repository in `realworld_core::repo`.
+#[derive(Clone)]
+pub struct PgRepo {
+    pool: PgPool,
 }
 
-#[allow(dead_code)]
-pub async fn initialize_db(pool: &PgPool) -> Result<(), sqlx::Error> {
-    pool.execute(include_str!("sql/down.sql")).await?;
-    pool.execute(include_str!("sql/schema.sql")).await?;
-    Ok(())
+impl PgRepo {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn into_repos(self) -> Repos {
+        Repos::from_store(self)
+    }
 }
 
-#[allow(dead_code)]
-pub async fn initialize(State(pool): State<PgPool>) -> AppResult<impl IntoResponse> {
-    initialize_db(&pool).await?;
-    Ok(Json(json!({ "message": "ok" })))
+/// Turns a unique violation into a conflict on the column named by the constraint,
+/// e.g. `users_email_key` becomes `email`.
+pub(crate) fn map_unique_violation(error: sqlx::Error) -> RepoError {
+    if let Some(db_error) = error.as_database_error() {
+        if db_error.code().as_deref() == Some("23505") {
+            let field = db_error
+                .constraint()
+                .and_then(|constraint| constraint.split('_').nth(1))
+                .unwrap_or("record");
+            return RepoError::Conflict(field.to_string());
+        }
+    }
+
+    RepoError::backend(error)
+}
+
+/// What `/metrics` reports about the pool, which was opened with `max` connections at most.
+/// sqlx keeps no count of waits.
+pub fn pool_stats(pool: &PgPool, max: u32) -> PoolStats {
+    PoolStats {
+        connections: pool.size(),
+        idle: pool.num_idle() as u32,
+        max,
+        waits: None,
+    }
+}
+
+pub async fn prepare_db(pool: &PgPool) -> Result<(), sqlx::Error> {
+    pool.execute(include_str!("sql/schema.sql")).await?;
+    Ok(())
 }
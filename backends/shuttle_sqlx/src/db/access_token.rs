@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::{
+    access_token::{parse_scopes, Scope},
+    repo::{AccessToken as AccessTokenRecord, AccessTokenRepo, RepoError, RepoResult},
+};
+use sqlx::FromRow;
+
+use crate::utils::auth::UserId;
+
+use super::PgRepo;
+
+#[derive(Debug, FromRow)]
+pub struct AccessToken {
+    pub id: i32,
+    pub user_id: UserId,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<AccessToken> for AccessTokenRecord<UserId> {
+    fn from(token: AccessToken) -> Self {
+        AccessTokenRecord {
+            id: token.id,
+            user_id: token.user_id,
+            name: token.name,
+            scopes: parse_scopes(&token.scopes),
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+        }
+    }
+}
+
+#[async_trait]
+impl AccessTokenRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "access_tokens.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        user: UserId,
+        name: &str,
+        scopes: &[Scope],
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<AccessTokenRecord<UserId>> {
+        let scopes: Vec<String> = scopes.iter().map(ToString::to_string).collect();
+
+        sqlx::query_as!(
+            AccessToken,
+            "
+            INSERT INTO access_tokens (user_id, name, scopes, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, name, scopes, created_at, expires_at, last_used_at
+            ",
+            user,
+            name,
+            &scopes,
+            token_hash,
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(Into::into)
+        .map_err(RepoError::backend)
+    }
+
+    #[tracing::instrument(name = "access_tokens.authenticate", level = "debug", skip_all)]
+    async fn authenticate(&self, token_hash: &str) -> RepoResult<AccessTokenRecord<UserId>> {
+        sqlx::query_as!(
+            AccessToken,
+            "
+            UPDATE access_tokens SET last_used_at = NOW()
+            WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            RETURNING id, user_id, name, scopes, created_at, expires_at, last_used_at
+            ",
+            token_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .map(Into::into)
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "access_tokens.list", level = "debug", skip_all)]
+    async fn list(&self, user: UserId) -> RepoResult<Vec<AccessTokenRecord<UserId>>> {
+        let tokens = sqlx::query_as!(
+            AccessToken,
+            "
+            SELECT id, user_id, name, scopes, created_at, expires_at, last_used_at
+            FROM access_tokens WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC, id DESC
+            ",
+            user,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(tokens.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(name = "access_tokens.revoke", level = "debug", skip_all)]
+    async fn revoke(&self, user: UserId, id: UserId) -> RepoResult<()> {
+        let revoked = sqlx::query!(
+            "
+            UPDATE access_tokens SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            ",
+            id,
+            user,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match revoked.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::{
+    account::TokenPurpose,
+    mail::{Mail, QueuedMail, MAX_ATTEMPTS},
+    repo::{AccountTokenRepo, OutboxRepo, RepoError, RepoResult},
+};
+
+use crate::utils::auth::UserId;
+
+use super::PgRepo;
+
+#[async_trait]
+impl AccountTokenRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "account_tokens.issue", level = "debug", skip_all)]
+    async fn issue(
+        &self,
+        user: UserId,
+        purpose: TokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        mail: Mail,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "
+            UPDATE account_tokens SET used_at = NOW()
+            WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL
+            ",
+            user,
+            purpose.as_str(),
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "
+            INSERT INTO account_tokens (token_hash, user_id, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ",
+            token_hash,
+            user,
+            purpose.as_str(),
+            expires_at,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "INSERT INTO outbox (recipient, subject, body) VALUES ($1, $2, $3)",
+            mail.to,
+            mail.subject,
+            mail.body,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        tx.commit().await.map_err(RepoError::backend)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "account_tokens.redeem", level = "debug", skip_all)]
+    async fn redeem(&self, purpose: TokenPurpose, token_hash: &str) -> RepoResult<UserId> {
+        let user = sqlx::query_scalar!(
+            "
+            UPDATE account_tokens SET used_at = NOW()
+            WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            ",
+            token_hash,
+            purpose.as_str(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        user.ok_or(RepoError::NotFound)
+    }
+}
+
+#[async_trait]
+impl OutboxRepo for PgRepo {
+    #[tracing::instrument(name = "outbox.claim", level = "debug", skip_all)]
+    async fn claim(&self, limit: i64, lease_until: DateTime<Utc>) -> RepoResult<Vec<QueuedMail>> {
+        let rows = sqlx::query!(
+            "
+            UPDATE outbox SET attempts = attempts + 1, next_attempt_at = $3
+            WHERE id IN (
+                SELECT id FROM outbox
+                WHERE delivered_at IS NULL AND attempts < $2 AND next_attempt_at <= NOW()
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, recipient, subject, body, attempts
+            ",
+            limit,
+            MAX_ATTEMPTS,
+            lease_until,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| QueuedMail {
+                id: row.id,
+                mail: Mail {
+                    to: row.recipient,
+                    subject: row.subject,
+                    body: row.body,
+                },
+                attempts: row.attempts,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(name = "outbox.delivered", level = "debug", skip_all)]
+    async fn delivered(&self, id: i64) -> RepoResult<()> {
+        sqlx::query!("UPDATE outbox SET delivered_at = NOW() WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "outbox.failed", level = "debug", skip_all)]
+    async fn failed(&self, id: i64, error: &str, retry_at: DateTime<Utc>) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE outbox SET last_error = $2, next_attempt_at = $3 WHERE id = $1",
+            id,
+            error,
+            retry_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+}
@@ -1,15 +1,21 @@
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
-use sqlx::{FromRow, PgPool};
+use realworld_core::{
+    repo::{
+        available_slug, slug_matches, Article as ArticleRecord, ArticleChanges, ArticleFilter,
+        ArticleList, ArticleRepo, NewArticle, Page, RepoError, RepoResult, SearchHit,
+        SearchResults, REVISED_FIELDS,
+    },
+    search::{SearchQuery, HEADLINE_OPTIONS},
+};
+use sqlx::{FromRow, Postgres, Transaction};
 
-use crate::error::AppResult;
+use crate::utils::auth::UserId;
 
-use super::UserProfile;
+use super::{map_unique_violation, revision::add_revision, user::UserProfile, PgRepo};
 
-#[derive(Debug, Serialize, FromRow)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, FromRow)]
 pub struct Article {
-    #[serde(skip)]
     pub(crate) id: i32,
     pub(crate) slug: String,
     pub(crate) body: String,
@@ -21,16 +27,496 @@ pub struct Article {
     pub(crate) tag_list: Vec<String>,
     pub(crate) created_at: DateTime<Utc>,
     pub(crate) updated_at: DateTime<Utc>,
+    pub(crate) status: String,
+    pub(crate) published_at: Option<DateTime<Utc>>,
 }
 
-pub async fn retrieve_article(
-    pool: &PgPool,
-    slug: String,
-    user_id: Option<i32>,
-) -> AppResult<Article> {
-    Ok(
-        sqlx::query_file_as!(Article, "src/sql/articles/get_article.sql", slug, user_id,)
-            .fetch_one(&mut pool.acquire().await.unwrap())
-            .await?,
+#[derive(Debug, FromRow)]
+pub struct SearchedArticle {
+    pub(crate) id: i32,
+    pub(crate) slug: String,
+    pub(crate) body: String,
+    pub(crate) title: String,
+    pub(crate) favorited: bool,
+    pub(crate) description: String,
+    pub(crate) author: UserProfile,
+    pub(crate) favorites_count: i64,
+    pub(crate) tag_list: Vec<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) updated_at: DateTime<Utc>,
+    pub(crate) status: String,
+    pub(crate) published_at: Option<DateTime<Utc>>,
+    pub(crate) snippet: String,
+}
+
+impl From<SearchedArticle> for SearchHit<UserId> {
+    fn from(article: SearchedArticle) -> Self {
+        SearchHit {
+            snippet: article.snippet,
+            article: ArticleRecord {
+                id: article.id,
+                slug: article.slug,
+                body: article.body,
+                title: article.title,
+                favorited: article.favorited,
+                description: article.description,
+                author: article.author.into(),
+                favorites_count: article.favorites_count,
+                tag_list: article.tag_list,
+                created_at: article.created_at,
+                updated_at: article.updated_at,
+                status: article.status.parse().unwrap_or_default(),
+                published_at: article.published_at,
+            },
+        }
+    }
+}
+
+impl From<Article> for ArticleRecord<UserId> {
+    fn from(article: Article) -> Self {
+        ArticleRecord {
+            id: article.id,
+            slug: article.slug,
+            body: article.body,
+            title: article.title,
+            favorited: article.favorited,
+            description: article.description,
+            author: article.author.into(),
+            favorites_count: article.favorites_count,
+            tag_list: article.tag_list,
+            created_at: article.created_at,
+            updated_at: article.updated_at,
+            // The column is checked against the known statuses
+            status: article.status.parse().unwrap_or_default(),
+            published_at: article.published_at,
+        }
+    }
+}
+
+/// Slugs starting like `base` that articles other than `except` have, or had.
+async fn taken_slugs(
+    tx: &mut Transaction<'_, Postgres>,
+    base: &str,
+    except: Option<i32>,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT slug AS "slug!"
+        FROM articles
+        WHERE (slug = $1 OR slug LIKE $1 || '-%')
+            AND ($2::INT4 IS NULL OR id <> $2)
+        UNION ALL
+        SELECT slug
+        FROM article_slugs
+        WHERE (slug = $1 OR slug LIKE $1 || '-%')
+            AND ($2::INT4 IS NULL OR article_id <> $2)
+        "#,
+        base,
+        except,
+    )
+    .fetch_all(&mut *tx)
+    .await
+}
+
+/// Keeps `old` around to redirect to the article, and drops `new` from its old slugs in case
+/// it is getting one of them back.
+async fn rename(
+    tx: &mut Transaction<'_, Postgres>,
+    article_id: i32,
+    old: &str,
+    new: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM article_slugs WHERE slug = $1", new)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO article_slugs (slug, article_id) VALUES ($1, $2)",
+        old,
+        article_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn replace_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    article_id: i32,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM article_tags WHERE article_id = $1", article_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO tags (name)
+        SELECT * FROM UNNEST($1::TEXT[])
+        ON CONFLICT DO NOTHING
+        ",
+        tags
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO article_tags (article_id, tag_id)
+        SELECT $1, tags.id FROM tags WHERE tags.name = ANY($2)
+        ",
+        article_id,
+        tags,
     )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl ArticleRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "articles.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        author: UserId,
+        article: NewArticle,
+    ) -> RepoResult<ArticleRecord<UserId>> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        let taken = taken_slugs(&mut tx, &article.slug, None)
+            .await
+            .map_err(RepoError::backend)?;
+        let slug = available_slug(&article.slug, &taken);
+
+        let id = sqlx::query_scalar!(
+            "
+            INSERT INTO articles (slug, title, description, body, author_id, status, published_at)
+            VALUES ($1, $2, $3, $4, $5, $6, CASE WHEN $6 = 'published' THEN NOW() END)
+            RETURNING id
+            ",
+            slug,
+            article.title,
+            article.description,
+            article.body,
+            author,
+            article.status.as_str(),
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(map_unique_violation)?;
+
+        replace_tags(&mut tx, id, &article.tag_list)
+            .await
+            .map_err(RepoError::backend)?;
+        add_revision(&mut tx, id, author, &REVISED_FIELDS.map(String::from))
+            .await
+            .map_err(RepoError::backend)?;
+        tx.commit().await.map_err(RepoError::backend)?;
+
+        self.find_by_slug(&slug, Some(author)).await
+    }
+
+    #[tracing::instrument(name = "articles.find_by_slug", level = "debug", skip_all)]
+    async fn find_by_slug(
+        &self,
+        slug: &str,
+        viewer: Option<UserId>,
+    ) -> RepoResult<ArticleRecord<UserId>> {
+        let article =
+            sqlx::query_file_as!(Article, "src/sql/articles/get_article.sql", slug, viewer)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(RepoError::backend)?;
+
+        article.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "articles.renamed", level = "debug", skip_all)]
+    async fn renamed(&self, slug: &str) -> RepoResult<String> {
+        sqlx::query_scalar!(
+            "
+            SELECT articles.slug
+            FROM article_slugs
+                INNER JOIN articles ON articles.id = article_slugs.article_id
+            WHERE article_slugs.slug = $1
+            ",
+            slug
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "articles.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        filter: &ArticleFilter,
+        page: Page<UserId>,
+        viewer: Option<UserId>,
+    ) -> RepoResult<ArticleList<UserId>> {
+        let after = page.after();
+        let articles = sqlx::query_file_as!(
+            Article,
+            "src/sql/articles/list_articles.sql",
+            filter.author,
+            filter.favorited,
+            filter.tag,
+            page.fetch_limit() as i64,
+            page.offset() as i64,
+            viewer,
+            after.map(|cursor| cursor.created_at),
+            after.map(|cursor| cursor.id),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let total = sqlx::query_file_scalar!(
+            "src/sql/articles/count_articles.sql",
+            filter.author,
+            filter.favorited,
+            filter.tag,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let articles = articles.into_iter().map(Into::into).collect();
+        Ok(ArticleList::new(articles, total, &page))
+    }
+
+    #[tracing::instrument(name = "articles.feed", level = "debug", skip_all)]
+    async fn feed(&self, viewer: UserId, page: Page<UserId>) -> RepoResult<ArticleList<UserId>> {
+        let after = page.after();
+        let articles = sqlx::query_file_as!(
+            Article,
+            "src/sql/articles/feed_articles.sql",
+            viewer,
+            page.fetch_limit() as i64,
+            page.offset() as i64,
+            after.map(|cursor| cursor.created_at),
+            after.map(|cursor| cursor.id),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM articles
+                INNER JOIN follows ON follows.followee_id = articles.author_id
+            WHERE follows.follower_id = $1
+                AND articles.status = 'published'
+                AND articles.hidden_at IS NULL
+            "#,
+            viewer
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let articles = articles.into_iter().map(Into::into).collect();
+        Ok(ArticleList::new(articles, total, &page))
+    }
+
+    #[tracing::instrument(name = "articles.search", level = "debug", skip_all)]
+    async fn search(
+        &self,
+        query: &SearchQuery,
+        page: Page<UserId>,
+        viewer: Option<UserId>,
+    ) -> RepoResult<SearchResults<UserId>> {
+        let tsquery = query.to_tsquery();
+
+        let hits = sqlx::query_file_as!(
+            SearchedArticle,
+            "src/sql/articles/search_articles.sql",
+            tsquery,
+            page.fetch_limit() as i64,
+            page.offset() as i64,
+            viewer,
+            HEADLINE_OPTIONS,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM articles
+            WHERE search @@ to_tsquery('english', $1)
+                AND status = 'published'
+                AND hidden_at IS NULL
+            "#,
+            tsquery
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(SearchResults {
+            hits: hits.into_iter().map(Into::into).collect(),
+            total,
+        })
+    }
+
+    #[tracing::instrument(name = "articles.update", level = "debug", skip_all)]
+    async fn update(
+        &self,
+        id: i32,
+        changes: ArticleChanges,
+        viewer: Option<UserId>,
+    ) -> RepoResult<ArticleRecord<UserId>> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        let current = sqlx::query!(
+            "
+            SELECT slug, title, description, body, author_id
+            FROM articles
+            WHERE id = $1
+            FOR UPDATE
+            ",
+            id
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)?;
+        let changed_fields =
+            changes.changed_fields(&current.title, &current.description, &current.body);
+
+        let slug = match changes.slug {
+            Some(ref base) if !slug_matches(&current.slug, base) => {
+                let taken = taken_slugs(&mut tx, base, Some(id))
+                    .await
+                    .map_err(RepoError::backend)?;
+                let slug = available_slug(base, &taken);
+                rename(&mut tx, id, &current.slug, &slug)
+                    .await
+                    .map_err(RepoError::backend)?;
+                Some(slug)
+            }
+            _ => None,
+        };
+
+        let slug = sqlx::query_scalar!(
+            "
+            UPDATE articles
+            SET title = COALESCE($1, title),
+                description = COALESCE($2, description),
+                body = COALESCE($3, body),
+                slug = COALESCE($4, slug),
+                status = COALESCE($6, status),
+                published_at = CASE
+                    WHEN $6 = 'draft' THEN NULL
+                    WHEN $6 = 'published' AND status <> 'published' THEN NOW()
+                    ELSE published_at
+                END,
+                updated_at = NOW()
+            WHERE id = $5
+            RETURNING slug
+            ",
+            changes.title,
+            changes.description,
+            changes.body,
+            slug,
+            id,
+            changes.status.map(|status| status.as_str()),
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(map_unique_violation)?
+        .ok_or(RepoError::NotFound)?;
+
+        if let Some(ref tags) = changes.tag_list {
+            replace_tags(&mut tx, id, tags)
+                .await
+                .map_err(RepoError::backend)?;
+        }
+        if !changed_fields.is_empty() {
+            let editor = viewer.unwrap_or(current.author_id);
+            add_revision(&mut tx, id, editor, &changed_fields)
+                .await
+                .map_err(RepoError::backend)?;
+        }
+        tx.commit().await.map_err(RepoError::backend)?;
+
+        self.find_by_slug(&slug, viewer).await
+    }
+
+    #[tracing::instrument(name = "articles.delete", level = "debug", skip_all)]
+    async fn delete(&self, id: i32) -> RepoResult<()> {
+        let deleted = sqlx::query!("DELETE FROM articles WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        match deleted.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "articles.set_hidden", level = "debug", skip_all)]
+    async fn set_hidden(&self, slug: &str, hidden: bool) -> RepoResult<()> {
+        let updated = sqlx::query!(
+            "
+            UPDATE articles
+                SET hidden_at = CASE WHEN $2 THEN COALESCE(hidden_at, NOW()) END
+                WHERE slug = $1
+            ",
+            slug,
+            hidden,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match updated.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "articles.favorite", level = "debug", skip_all)]
+    async fn favorite(&self, id: i32, user: UserId) -> RepoResult<()> {
+        sqlx::query!(
+            "
+            INSERT INTO article_favs (article_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            ",
+            id,
+            user
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "articles.unfavorite", level = "debug", skip_all)]
+    async fn unfavorite(&self, id: i32, user: UserId) -> RepoResult<()> {
+        sqlx::query!(
+            "
+            DELETE FROM article_favs
+            WHERE article_id = $1 AND user_id = $2
+            ",
+            id,
+            user
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
 }
@@ -1,16 +1,190 @@
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use realworld_core::repo::{Comment as CommentRecord, CommentRepo, RepoError, RepoResult};
 use sqlx::FromRow;
 
-use super::UserProfile;
+use crate::utils::auth::UserId;
 
-#[derive(Debug, Serialize, FromRow)]
-#[serde(rename_all = "camelCase")]
+use super::{user::UserProfile, PgRepo};
+
+#[derive(Debug, FromRow)]
 pub struct Comment {
-    #[serde(skip)]
     pub id: i32,
+    pub article_id: i32,
     pub body: String,
     pub author: UserProfile,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+impl From<Comment> for CommentRecord<UserId> {
+    fn from(comment: Comment) -> Self {
+        CommentRecord {
+            id: comment.id,
+            article_id: comment.article_id,
+            body: comment.body,
+            author: comment.author.into(),
+            created_at: comment.created_at,
+            updated_at: comment.updated_at,
+        }
+    }
+}
+
+#[async_trait]
+impl CommentRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "comments.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        article: i32,
+        author: UserId,
+        body: String,
+    ) -> RepoResult<CommentRecord<UserId>> {
+        let comment = sqlx::query_as!(
+            Comment,
+            r#"
+            WITH comment AS (
+                INSERT INTO comments (body, article_id, author_id)
+                VALUES ($1, $2, $3)
+                RETURNING *
+            )
+            SELECT
+                comment.id,
+                comment.article_id,
+                comment.created_at,
+                comment.updated_at,
+                comment.body,
+                (
+                    users.id,
+                    users.username,
+                    users.bio,
+                    users.image,
+                    EXISTS (
+                        SELECT 1
+                        FROM follows
+                        WHERE follows.follower_id = $3
+                            AND follows.followee_id = users.id
+                    )
+                ) AS "author!: UserProfile"
+            FROM comment INNER JOIN users ON users.id = comment.author_id
+            "#,
+            body,
+            article,
+            author,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(comment.into())
+    }
+
+    #[tracing::instrument(name = "comments.find", level = "debug", skip_all)]
+    async fn find(&self, id: i32) -> RepoResult<CommentRecord<UserId>> {
+        let comment = sqlx::query_as!(
+            Comment,
+            r#"
+            SELECT
+                comments.id,
+                comments.article_id,
+                comments.created_at,
+                comments.updated_at,
+                comments.body,
+                (
+                    users.id,
+                    users.username,
+                    users.bio,
+                    users.image,
+                    FALSE
+                ) AS "author!: UserProfile"
+            FROM comments
+            INNER JOIN users ON users.id = comments.author_id
+            WHERE comments.id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        comment.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "comments.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        article: i32,
+        viewer: Option<UserId>,
+    ) -> RepoResult<Vec<CommentRecord<UserId>>> {
+        let comments = sqlx::query_as!(
+            Comment,
+            r#"
+            SELECT
+                comments.id,
+                comments.article_id,
+                comments.created_at,
+                comments.updated_at,
+                comments.body,
+                (
+                    users.id,
+                    users.username,
+                    users.bio,
+                    users.image,
+                    EXISTS (
+                        SELECT 1
+                        FROM follows
+                        WHERE follows.follower_id = $2
+                            AND follows.followee_id = users.id
+                    )
+                ) AS "author!: UserProfile"
+            FROM comments
+            INNER JOIN users ON users.id = comments.author_id
+            WHERE comments.article_id = $1
+                AND (comments.hidden_at IS NULL OR comments.author_id = $2)
+            ORDER BY comments.created_at DESC
+            "#,
+            article,
+            viewer,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(comments.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(name = "comments.delete", level = "debug", skip_all)]
+    async fn delete(&self, id: i32) -> RepoResult<()> {
+        let deleted = sqlx::query!("DELETE FROM comments WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        match deleted.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "comments.set_hidden", level = "debug", skip_all)]
+    async fn set_hidden(&self, id: i32, hidden: bool) -> RepoResult<()> {
+        let updated = sqlx::query!(
+            "
+            UPDATE comments
+                SET hidden_at = CASE WHEN $2 THEN COALESCE(hidden_at, NOW()) END
+                WHERE id = $1
+            ",
+            id,
+            hidden,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match updated.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
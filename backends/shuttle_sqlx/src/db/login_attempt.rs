@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::repo::{LoginAttemptRepo, LoginAttempts, RepoError, RepoResult};
+
+use super::PgRepo;
+
+#[async_trait]
+impl LoginAttemptRepo for PgRepo {
+    #[tracing::instrument(name = "login_attempts.find", level = "debug", skip_all)]
+    async fn find(&self, key: &str) -> RepoResult<LoginAttempts> {
+        sqlx::query_as!(
+            LoginAttempts,
+            "SELECT failures, last_failed_at, locked_until FROM login_attempts WHERE key = $1",
+            key,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "login_attempts.fail", level = "debug", skip_all)]
+    async fn fail(&self, key: &str, stale_before: DateTime<Utc>) -> RepoResult<i32> {
+        // `GREATEST` ignores a missing lockout
+        sqlx::query_scalar!(
+            "
+            INSERT INTO login_attempts (key, failures) VALUES ($1, 1)
+            ON CONFLICT (key) DO UPDATE
+            SET failures = CASE
+                    WHEN GREATEST(login_attempts.last_failed_at, login_attempts.locked_until) < $2
+                    THEN 1
+                    ELSE login_attempts.failures + 1
+                END,
+                last_failed_at = NOW()
+            RETURNING failures
+            ",
+            key,
+            stale_before,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)
+    }
+
+    #[tracing::instrument(name = "login_attempts.lock", level = "debug", skip_all)]
+    async fn lock(&self, key: &str, failures: i32, until: DateTime<Utc>) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "
+            INSERT INTO login_attempts (key, failures, locked_until) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET locked_until = EXCLUDED.locked_until
+            ",
+            key,
+            failures,
+            until,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "INSERT INTO lockout_events (key, failures, locked_until) VALUES ($1, $2, $3)",
+            key,
+            failures,
+            until,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        tx.commit().await.map_err(RepoError::backend)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "login_attempts.clear", level = "debug", skip_all)]
+    async fn clear(&self, key: &str) -> RepoResult<()> {
+        sqlx::query!("DELETE FROM login_attempts WHERE key = $1", key)
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+}
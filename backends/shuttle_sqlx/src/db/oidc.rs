@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::{
+    oidc::PendingLogin,
+    repo::{OidcRepo, RepoError, RepoResult},
+};
+
+use crate::utils::auth::UserId;
+
+use super::{map_unique_violation, PgRepo};
+
+#[async_trait]
+impl OidcRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "oidc.start_login", level = "debug", skip_all)]
+    async fn start_login(
+        &self,
+        state_hash: &str,
+        login: PendingLogin,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<()> {
+        // Abandoned logins are cleaned up as new ones come in
+        sqlx::query!("DELETE FROM oidc_logins WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "
+            INSERT INTO oidc_logins (state_hash, provider, code_verifier, nonce, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ",
+            state_hash,
+            login.provider,
+            login.code_verifier,
+            login.nonce,
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "oidc.finish_login", level = "debug", skip_all)]
+    async fn finish_login(&self, state_hash: &str) -> RepoResult<PendingLogin> {
+        sqlx::query_as!(
+            PendingLogin,
+            "
+            DELETE FROM oidc_logins WHERE state_hash = $1 AND expires_at > NOW()
+            RETURNING provider, code_verifier, nonce
+            ",
+            state_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "oidc.find_identity", level = "debug", skip_all)]
+    async fn find_identity(&self, provider: &str, subject: &str) -> RepoResult<UserId> {
+        sqlx::query_scalar!(
+            "SELECT user_id FROM user_identities WHERE provider = $1 AND subject = $2",
+            provider,
+            subject,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "oidc.link_identity", level = "debug", skip_all)]
+    async fn link_identity(&self, user: UserId, provider: &str, subject: &str) -> RepoResult<()> {
+        sqlx::query!(
+            "INSERT INTO user_identities (provider, subject, user_id) VALUES ($1, $2, $3)",
+            provider,
+            subject,
+            user,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+        Ok(())
+    }
+}
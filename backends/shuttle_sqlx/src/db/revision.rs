@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::repo::{RepoError, RepoResult, Revision as RevisionRecord, RevisionRepo};
+use sqlx::{FromRow, Postgres, Transaction};
+
+use crate::utils::auth::UserId;
+
+use super::{user::UserProfile, PgRepo};
+
+#[derive(Debug, FromRow)]
+pub struct Revision {
+    pub article_id: i32,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub changed_fields: Vec<String>,
+    pub author: Option<UserProfile>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Revision> for RevisionRecord<UserId> {
+    fn from(revision: Revision) -> Self {
+        RevisionRecord {
+            article_id: revision.article_id,
+            number: revision.number,
+            title: revision.title,
+            description: revision.description,
+            body: revision.body,
+            changed_fields: revision.changed_fields,
+            author: revision.author.map(Into::into),
+            created_at: revision.created_at,
+        }
+    }
+}
+
+/// Snapshots the current content of the article as its next revision.
+pub(super) async fn add_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    article_id: i32,
+    author: UserId,
+    changed_fields: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "
+        INSERT INTO article_revisions (
+            article_id, number, title, description, body, changed_fields, author_id, created_at
+        )
+        SELECT
+            articles.id,
+            COALESCE(
+                (SELECT MAX(number) FROM article_revisions WHERE article_id = articles.id),
+                0
+            ) + 1,
+            articles.title,
+            articles.description,
+            articles.body,
+            $2,
+            $3,
+            articles.updated_at
+        FROM articles
+        WHERE articles.id = $1
+        ",
+        article_id,
+        changed_fields,
+        author,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl RevisionRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "revisions.list", level = "debug", skip_all)]
+    async fn list(
+        &self,
+        article: i32,
+        viewer: Option<UserId>,
+    ) -> RepoResult<Vec<RevisionRecord<UserId>>> {
+        let revisions = sqlx::query_as!(
+            Revision,
+            r#"
+            SELECT
+                article_revisions.article_id,
+                article_revisions.number,
+                article_revisions.title,
+                article_revisions.description,
+                article_revisions.body,
+                article_revisions.changed_fields,
+                article_revisions.created_at,
+                CASE WHEN users.id IS NOT NULL THEN (
+                    users.id,
+                    users.username,
+                    users.bio,
+                    users.image,
+                    EXISTS (
+                        SELECT 1
+                        FROM follows
+                        WHERE follows.follower_id = $2
+                            AND follows.followee_id = users.id
+                    )
+                ) END AS "author: UserProfile"
+            FROM article_revisions
+            LEFT JOIN users ON users.id = article_revisions.author_id
+            WHERE article_revisions.article_id = $1
+            ORDER BY article_revisions.number DESC
+            "#,
+            article,
+            viewer,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(revisions.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(name = "revisions.find", level = "debug", skip_all)]
+    async fn find(
+        &self,
+        article: i32,
+        number: i32,
+        viewer: Option<UserId>,
+    ) -> RepoResult<RevisionRecord<UserId>> {
+        let revision = sqlx::query_as!(
+            Revision,
+            r#"
+            SELECT
+                article_revisions.article_id,
+                article_revisions.number,
+                article_revisions.title,
+                article_revisions.description,
+                article_revisions.body,
+                article_revisions.changed_fields,
+                article_revisions.created_at,
+                CASE WHEN users.id IS NOT NULL THEN (
+                    users.id,
+                    users.username,
+                    users.bio,
+                    users.image,
+                    EXISTS (
+                        SELECT 1
+                        FROM follows
+                        WHERE follows.follower_id = $3
+                            AND follows.followee_id = users.id
+                    )
+                ) END AS "author: UserProfile"
+            FROM article_revisions
+            LEFT JOIN users ON users.id = article_revisions.author_id
+            WHERE article_revisions.article_id = $1
+                AND article_revisions.number = $2
+            "#,
+            article,
+            number,
+            viewer,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        revision.map(Into::into).ok_or(RepoError::NotFound)
+    }
+}
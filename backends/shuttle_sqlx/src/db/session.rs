@@ -0,0 +1,175 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::repo::{RepoError, RepoResult, Session as SessionRecord, SessionRepo};
+use sqlx::FromRow;
+
+use crate::utils::auth::UserId;
+
+use super::PgRepo;
+
+#[derive(Debug, FromRow)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: UserId,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Session> for SessionRecord<UserId> {
+    fn from(session: Session) -> Self {
+        SessionRecord {
+            id: session.id,
+            user_id: session.user_id,
+            created_at: session.created_at,
+        }
+    }
+}
+
+#[async_trait]
+impl SessionRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "sessions.create", level = "debug", skip_all)]
+    async fn create(
+        &self,
+        user: UserId,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<SessionRecord<UserId>> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        let session = sqlx::query_as!(
+            Session,
+            "INSERT INTO sessions (user_id) VALUES ($1) RETURNING id, user_id, created_at",
+            user,
+        )
+        .fetch_one(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "INSERT INTO refresh_tokens (token_hash, session_id, expires_at) VALUES ($1, $2, $3)",
+            token_hash,
+            session.id,
+            expires_at,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        tx.commit().await.map_err(RepoError::backend)?;
+        Ok(session.into())
+    }
+
+    #[tracing::instrument(name = "sessions.find", level = "debug", skip_all)]
+    async fn find(&self, id: i32) -> RepoResult<SessionRecord<UserId>> {
+        let session = sqlx::query_as!(
+            Session,
+            "SELECT id, user_id, created_at FROM sessions WHERE id = $1 AND revoked_at IS NULL",
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        session.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "sessions.rotate", level = "debug", skip_all)]
+    async fn rotate(
+        &self,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> RepoResult<SessionRecord<UserId>> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        let token = sqlx::query!(
+            "
+            SELECT
+                refresh_tokens.expires_at,
+                refresh_tokens.used_at,
+                sessions.id,
+                sessions.user_id,
+                sessions.created_at,
+                sessions.revoked_at
+            FROM refresh_tokens
+            INNER JOIN sessions ON sessions.id = refresh_tokens.session_id
+            WHERE refresh_tokens.token_hash = $1
+            FOR UPDATE
+            ",
+            token_hash,
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)?;
+
+        // A replayed token revokes its session, which has to stick even though the refresh fails
+        if token.used_at.is_some() {
+            sqlx::query!(
+                "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+                token.id,
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(RepoError::backend)?;
+
+            tx.commit().await.map_err(RepoError::backend)?;
+            return Err(RepoError::NotFound);
+        }
+        if token.revoked_at.is_some() || token.expires_at <= Utc::now() {
+            return Err(RepoError::NotFound);
+        }
+
+        sqlx::query!(
+            "UPDATE refresh_tokens SET used_at = NOW() WHERE token_hash = $1",
+            token_hash,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "INSERT INTO refresh_tokens (token_hash, session_id, expires_at) VALUES ($1, $2, $3)",
+            new_hash,
+            token.id,
+            expires_at,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        tx.commit().await.map_err(RepoError::backend)?;
+        Ok(SessionRecord {
+            id: token.id,
+            user_id: token.user_id,
+            created_at: token.created_at,
+        })
+    }
+
+    #[tracing::instrument(name = "sessions.revoke", level = "debug", skip_all)]
+    async fn revoke(&self, id: i32) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL",
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "sessions.revoke_all", level = "debug", skip_all)]
+    async fn revoke_all(&self, user: UserId) -> RepoResult<()> {
+        sqlx::query!(
+            "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+            user,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+}
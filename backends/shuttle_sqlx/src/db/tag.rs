@@ -1,8 +1,37 @@
+use async_trait::async_trait;
+use realworld_core::repo::{RepoError, RepoResult, TagRepo};
 use serde::Serialize;
 use sqlx::FromRow;
 
+use super::PgRepo;
+
 #[derive(Debug, Serialize, FromRow)]
 #[serde(rename_all = "camelCase")]
 pub struct Tag {
     pub name: String,
 }
+
+#[async_trait]
+impl TagRepo for PgRepo {
+    #[tracing::instrument(name = "tags.list", level = "debug", skip_all)]
+    async fn list(&self) -> RepoResult<Vec<String>> {
+        let tags = sqlx::query_as!(
+            Tag,
+            r#"
+            SELECT tags.name
+            FROM tags
+            INNER JOIN article_tags ON article_tags.tag_id = tags.id
+            INNER JOIN articles ON articles.id = article_tags.article_id
+            WHERE articles.status = 'published'
+                AND articles.hidden_at IS NULL
+            GROUP BY tags.name
+            ORDER BY COUNT(article_tags.tag_id) DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(tags.into_iter().map(|tag| tag.name).collect())
+    }
+}
@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use realworld_core::repo::{RepoError, RepoResult, Totp, TotpRepo};
+
+use crate::utils::auth::UserId;
+
+use super::PgRepo;
+
+#[async_trait]
+impl TotpRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "totp.enroll", level = "debug", skip_all)]
+    async fn enroll(&self, user: UserId, secret: &str) -> RepoResult<()> {
+        // An enabled secret is left alone, which shows as no row being written
+        let written = sqlx::query!(
+            "
+            INSERT INTO user_totp (user_id, secret) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_step = NULL, created_at = NOW()
+            WHERE user_totp.enabled_at IS NULL
+            ",
+            user,
+            secret,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match written.rows_affected() {
+            0 => Err(RepoError::Conflict("totp".to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.find", level = "debug", skip_all)]
+    async fn find(&self, user: UserId) -> RepoResult<Totp> {
+        sqlx::query_as!(
+            Totp,
+            r#"
+            SELECT secret, enabled_at IS NOT NULL AS "enabled!", last_step
+            FROM user_totp WHERE user_id = $1
+            "#,
+            user,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?
+        .ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "totp.enable", level = "debug", skip_all)]
+    async fn enable(
+        &self,
+        user: UserId,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await.map_err(RepoError::backend)?;
+
+        let enabled = sqlx::query!(
+            "
+            UPDATE user_totp SET enabled_at = NOW(), last_step = $2
+            WHERE user_id = $1 AND enabled_at IS NULL
+            ",
+            user,
+            step,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        if enabled.rows_affected() == 0 {
+            return Err(RepoError::NotFound);
+        }
+
+        sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_id = $1", user)
+            .execute(&mut tx)
+            .await
+            .map_err(RepoError::backend)?;
+
+        sqlx::query!(
+            "
+            INSERT INTO totp_recovery_codes (user_id, code_hash)
+            SELECT $1, * FROM UNNEST($2::TEXT[])
+            ",
+            user,
+            &recovery_code_hashes,
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(RepoError::backend)?;
+
+        tx.commit().await.map_err(RepoError::backend)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "totp.use_step", level = "debug", skip_all)]
+    async fn use_step(&self, user: UserId, step: i64) -> RepoResult<()> {
+        let used = sqlx::query!(
+            "
+            UPDATE user_totp SET last_step = $2
+            WHERE user_id = $1 AND enabled_at IS NOT NULL
+                AND (last_step IS NULL OR last_step < $2)
+            ",
+            user,
+            step,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match used.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.use_recovery_code", level = "debug", skip_all)]
+    async fn use_recovery_code(&self, user: UserId, code_hash: &str) -> RepoResult<()> {
+        let used = sqlx::query!(
+            "
+            UPDATE totp_recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            ",
+            user,
+            code_hash,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match used.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "totp.challenge_spent", level = "debug", skip_all)]
+    async fn challenge_spent(&self, id: &str) -> RepoResult<bool> {
+        let spent = sqlx::query_scalar!(
+            r#"SELECT spent_at IS NOT NULL AS "spent!" FROM totp_challenges WHERE id = $1"#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(spent.unwrap_or(false))
+    }
+
+    #[tracing::instrument(name = "totp.fail_challenge", level = "debug", skip_all)]
+    async fn fail_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<i32> {
+        self.forget_expired_challenges().await?;
+
+        sqlx::query_scalar!(
+            "
+            INSERT INTO totp_challenges (id, failures, expires_at) VALUES ($1, 1, $2)
+            ON CONFLICT (id) DO UPDATE SET failures = totp_challenges.failures + 1
+            RETURNING failures
+            ",
+            id,
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(RepoError::backend)
+    }
+
+    #[tracing::instrument(name = "totp.spend_challenge", level = "debug", skip_all)]
+    async fn spend_challenge(&self, id: &str, expires_at: DateTime<Utc>) -> RepoResult<()> {
+        self.forget_expired_challenges().await?;
+
+        // A spent challenge is left alone, which shows as no row being written
+        let spent = sqlx::query!(
+            "
+            INSERT INTO totp_challenges (id, spent_at, expires_at) VALUES ($1, NOW(), $2)
+            ON CONFLICT (id) DO UPDATE SET spent_at = NOW()
+            WHERE totp_challenges.spent_at IS NULL
+            ",
+            id,
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        match spent.rows_affected() {
+            0 => Err(RepoError::Conflict("challenge".to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl PgRepo {
+    /// Challenges are cleaned up as new ones are written.
+    async fn forget_expired_challenges(&self) -> RepoResult<()> {
+        sqlx::query!("DELETE FROM totp_challenges WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+        Ok(())
+    }
+}
@@ -1,18 +1,44 @@
-use jsonwebtoken::DecodingKey;
-use serde::Serialize;
-use sqlx::PgPool;
-
-use crate::{
-    error::{AppError, AppResult, DBError},
-    utils::{
-        auth::{UserAuth, UserId},
-        jwt,
-    },
+use async_trait::async_trait;
+use realworld_core::repo::{
+    AuthoredComment, FollowRepo, NewUser, PersonalData, Profile, RepoError, RepoResult, User,
+    UserChanges, UserList, UserRepo,
 };
+use sqlx::FromRow;
 
-#[derive(Debug, Default, Serialize, sqlx::Type)]
+use crate::utils::auth::UserId;
+
+use super::{map_unique_violation, PgRepo};
+
+#[derive(Debug, FromRow)]
+pub struct UserRow {
+    pub id: UserId,
+    pub username: String,
+    pub email: String,
+    pub hash: String,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    pub email_verified: bool,
+    pub role: String,
+}
+
+impl From<UserRow> for User<UserId> {
+    fn from(row: UserRow) -> Self {
+        User {
+            id: row.id,
+            username: row.username,
+            email: row.email,
+            password_hash: row.hash,
+            bio: row.bio,
+            image: row.image,
+            email_verified: row.email_verified,
+            // The column is checked against the known roles
+            role: row.role.parse().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, sqlx::Type)]
 pub struct UserProfile {
-    #[serde(skip)]
     pub id: UserId,
     pub username: Option<String>, // This is non-null. Workaround for deriving sqlx::Type.
     pub bio: Option<String>,
@@ -20,40 +46,327 @@ pub struct UserProfile {
     pub following: bool,
 }
 
-pub async fn auth_user(pool: &PgPool, token: &str, key: &DecodingKey) -> AppResult<UserAuth> {
-    let user_id = jwt::verify_token(token, key)?;
-    let mut user = get_user(user_id, pool).await?;
-    user.token = Some(token.to_string());
-    Ok(user)
+impl From<UserProfile> for Profile<UserId> {
+    fn from(profile: UserProfile) -> Self {
+        Profile {
+            id: profile.id,
+            username: profile.username.unwrap_or_default(),
+            bio: profile.bio,
+            image: profile.image,
+            following: profile.following,
+        }
+    }
 }
 
-pub async fn get_user(user_id: UserId, pool: &PgPool) -> AppResult<UserAuth> {
-    let mut conn = pool.acquire().await.unwrap();
+#[async_trait]
+impl UserRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "users.create", level = "debug", skip_all)]
+    async fn create(&self, user: NewUser) -> RepoResult<User<UserId>> {
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+            INSERT INTO users (username, email, hash)
+            VALUES ($1, $2, $3)
+            RETURNING id, username, email, hash, bio, image, FALSE AS "email_verified!", role
+            "#,
+            user.username,
+            user.email,
+            user.password_hash
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+        Ok(user.into())
+    }
+
+    #[tracing::instrument(name = "users.find", level = "debug", skip_all)]
+    async fn find(&self, id: UserId) -> RepoResult<User<UserId>> {
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, username, email, hash, bio, image, email_verified_at IS NOT NULL AS "email_verified!", role
+            FROM users
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        user.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "users.find_by_email", level = "debug", skip_all)]
+    async fn find_by_email(&self, email: &str) -> RepoResult<User<UserId>> {
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, username, email, hash, bio, image, email_verified_at IS NOT NULL AS "email_verified!", role
+            FROM users
+            WHERE lower(email) = lower($1)
+            "#,
+            email
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        user.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "users.update", level = "debug", skip_all)]
+    async fn update(&self, id: UserId, changes: UserChanges) -> RepoResult<User<UserId>> {
+        let user = sqlx::query_as!(
+            UserRow,
+            r#"
+            UPDATE users
+                SET (email, username, bio, image, hash, email_verified_at, role) =
+                    (
+                        COALESCE($1, email),
+                        COALESCE($2, username),
+                        COALESCE($3, bio),
+                        COALESCE($4, image),
+                        COALESCE($5, hash),
+                        CASE
+                            WHEN $6 THEN COALESCE(email_verified_at, NOW())
+                            WHEN NOT $6 OR $1 <> email THEN NULL
+                            ELSE email_verified_at
+                        END,
+                        COALESCE($8, role)
+                    )
+                WHERE id = $7
+            RETURNING id, username, email, hash, bio, image, email_verified_at IS NOT NULL AS "email_verified!", role
+            "#,
+            changes.email,
+            changes.username,
+            changes.bio,
+            changes.image,
+            changes.password_hash,
+            changes.email_verified,
+            id,
+            changes.role.map(|role| role.as_str()),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+        user.map(Into::into).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "users.profile", level = "debug", skip_all)]
+    async fn profile(&self, username: &str, viewer: Option<UserId>) -> RepoResult<Profile<UserId>> {
+        let profile =
+            sqlx::query_file_as!(UserProfile, "src/sql/user_profile.sql", username, viewer)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(RepoError::backend)?;
 
-    let user = sqlx::query_as!(
-        UserAuth,
-        "SELECT *, NULL AS token FROM users WHERE id = $1",
-        user_id
-    )
-    .fetch_optional(&mut conn)
-    .await?;
+        profile.map(Into::into).ok_or(RepoError::NotFound)
+    }
 
-    user.ok_or(AppError::DBError(DBError::NotFound))
+    #[tracing::instrument(name = "users.list", level = "debug", skip_all)]
+    async fn list(&self, limit: usize, offset: usize) -> RepoResult<UserList<UserId>> {
+        let users = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, username, email, hash, bio, image, email_verified_at IS NOT NULL AS "email_verified!", role
+            FROM users
+            ORDER BY username
+            LIMIT $1 OFFSET $2
+            "#,
+            limit as i64,
+            offset as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let total = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        Ok(UserList {
+            users: users.into_iter().map(Into::into).collect(),
+            total,
+        })
+    }
+
+    #[tracing::instrument(name = "users.personal_data", level = "debug", skip_all)]
+    async fn personal_data(&self, id: UserId) -> RepoResult<PersonalData<UserId>> {
+        let user = self.find(id).await?;
+
+        let articles = sqlx::query_scalar!(
+            "SELECT slug FROM articles WHERE author_id = $1 ORDER BY created_at, id",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let comments = sqlx::query_as!(
+            AuthoredComment,
+            "
+            SELECT comments.id, articles.slug AS article, comments.body, comments.created_at, comments.updated_at
+            FROM comments
+            INNER JOIN articles ON articles.id = comments.article_id
+            WHERE comments.author_id = $1
+            ORDER BY comments.created_at, comments.id
+            ",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let favorites = sqlx::query_scalar!(
+            "
+            SELECT articles.slug
+            FROM article_favs
+            INNER JOIN articles ON articles.id = article_favs.article_id
+            WHERE article_favs.user_id = $1
+            ORDER BY articles.slug
+            ",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let following = sqlx::query_scalar!(
+            "
+            SELECT users.username
+            FROM follows
+            INNER JOIN users ON users.id = follows.followee_id
+            WHERE follows.follower_id = $1
+            ORDER BY users.username
+            ",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        let followers = sqlx::query_scalar!(
+            "
+            SELECT users.username
+            FROM follows
+            INNER JOIN users ON users.id = follows.follower_id
+            WHERE follows.followee_id = $1
+            ORDER BY users.username
+            ",
+            id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(PersonalData {
+            user,
+            articles,
+            comments,
+            favorites,
+            following,
+            followers,
+        })
+    }
+
+    #[tracing::instrument(name = "users.anonymize", level = "debug", skip_all)]
+    async fn anonymize(&self, id: UserId, replacement: NewUser) -> RepoResult<()> {
+        // Every statement of a query sees the same snapshot and commits together
+        let anonymized = sqlx::query_scalar!(
+            "
+            WITH deleted_follows AS (
+                DELETE FROM follows WHERE follower_id = $1 OR followee_id = $1
+            ), deleted_favorites AS (
+                DELETE FROM article_favs WHERE user_id = $1
+            ), deleted_drafts AS (
+                DELETE FROM articles WHERE author_id = $1 AND status = 'draft'
+            ), deleted_sessions AS (
+                DELETE FROM sessions WHERE user_id = $1
+            ), deleted_account_tokens AS (
+                DELETE FROM account_tokens WHERE user_id = $1
+            ), deleted_access_tokens AS (
+                DELETE FROM access_tokens WHERE user_id = $1
+            ), deleted_totp AS (
+                DELETE FROM user_totp WHERE user_id = $1
+            ), deleted_recovery_codes AS (
+                DELETE FROM totp_recovery_codes WHERE user_id = $1
+            ), deleted_identities AS (
+                DELETE FROM user_identities WHERE user_id = $1
+            )
+            UPDATE users
+                SET (username, email, hash, bio, image, email_verified_at, role) =
+                    ($2, $3, $4, NULL, NULL, NULL, 'user')
+                WHERE id = $1
+            RETURNING id
+            ",
+            id,
+            replacement.username,
+            replacement.email,
+            replacement.password_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_unique_violation)?;
+
+        anonymized.map(|_| ()).ok_or(RepoError::NotFound)
+    }
+
+    #[tracing::instrument(name = "users.delete", level = "debug", skip_all)]
+    async fn delete(&self, id: UserId) -> RepoResult<()> {
+        let deleted = sqlx::query!("DELETE FROM users WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(RepoError::backend)?;
+
+        match deleted.rows_affected() {
+            0 => Err(RepoError::NotFound),
+            _ => Ok(()),
+        }
+    }
 }
 
-pub async fn get_user_profile(
-    pool: &PgPool,
-    username: &str,
-    req_user_id: Option<UserId>,
-) -> AppResult<UserProfile> {
-    let user = sqlx::query_file_as!(
-        UserProfile,
-        "src/sql/user_profile.sql",
-        username,
-        req_user_id
-    )
-    .fetch_one(&mut pool.acquire().await.unwrap())
-    .await?;
-
-    Ok(user)
+#[async_trait]
+impl FollowRepo for PgRepo {
+    type Id = UserId;
+
+    #[tracing::instrument(name = "follows.follow", level = "debug", skip_all)]
+    async fn follow(&self, follower: UserId, followee: UserId) -> RepoResult<()> {
+        sqlx::query!(
+            "
+            INSERT INTO follows (follower_id, followee_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            ",
+            follower,
+            followee
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "follows.unfollow", level = "debug", skip_all)]
+    async fn unfollow(&self, follower: UserId, followee: UserId) -> RepoResult<()> {
+        sqlx::query!(
+            "
+            DELETE FROM follows
+            WHERE follower_id = $1 AND followee_id = $2
+            ",
+            follower,
+            followee
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(RepoError::backend)?;
+
+        Ok(())
+    }
 }
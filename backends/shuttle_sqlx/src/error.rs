@@ -1,74 +1,257 @@
 use axum::{
-    http::StatusCode,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{header, Request, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+use realworld_core::{
+    credentials::CredentialError,
+    error::{accepts_problem, field_error, ApiError, ErrorCode, Malformed, PROBLEM_JSON},
+    lockout,
+    oidc::OidcError,
+    repo::RepoError,
+    totp::ChallengeError,
+};
+use validator::ValidationErrors;
 
 pub type AppResult<T> = std::result::Result<T, AppError>;
 
 #[derive(thiserror::Error, Debug)]
-pub enum DBError {
-    #[error("User is already registered")]
-    AlreadyRegistered,
+pub enum AppError {
+    // 401
+    #[error("Unauthorized")]
+    Unauthorized(&'static str),
+
+    // 403
+    #[error("Forbidden: {0:?}")]
+    Forbidden(&'static str),
+
+    // 404
+    #[error("Not Found: {0:?}")]
+    NotFound(Option<&'static str>),
 
-    #[error("Article is already created")]
-    ArticleAlreadyCreated,
+    // 422
+    #[error("Unprocessable Entity: {0:?}")]
+    UnprocessableEntity(&'static str),
+
+    // 422, by field
+    #[error("Invalid request: {0:?}")]
+    Validation(ValidationErrors),
 
-    #[error("Not Found")]
-    NotFound,
+    // 422, carries the name of the field that is taken
+    #[error("{0} has already been taken")]
+    Conflict(String),
+
+    // 429
+    #[error("Too many failed logins")]
+    LockedOut(DateTime<Utc>),
+
+    // 500
+    #[error("Internal Server Error")]
+    InternalServerError,
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum AppError {
-    #[error("Any error: {0:?}")]
-    Anyhow(#[from] anyhow::Error),
+impl AppError {
+    pub fn not_found(message: &'static str) -> Self {
+        AppError::NotFound(Some(message))
+    }
 
-    #[error("DB Error: {0:?}")]
-    DBError(#[from] DBError),
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Unauthorized(_) => ErrorCode::Unauthorized,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::UnprocessableEntity(_) | AppError::Validation(_) => {
+                ErrorCode::ValidationFailed
+            }
+            AppError::Conflict(_) => ErrorCode::AlreadyExists,
+            AppError::LockedOut(_) => ErrorCode::TooManyRequests,
+            AppError::InternalServerError => ErrorCode::Internal,
+        }
+    }
 
-    #[error("Forbidden request")]
-    Forbidden(&'static str),
+    /// What clients are told.
+    pub fn api_error(&self) -> ApiError {
+        match self {
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::UnprocessableEntity(message) => ApiError::new(self.code(), *message),
+            AppError::NotFound(message) => {
+                ApiError::new(self.code(), message.unwrap_or(self.code().title()))
+            }
+            AppError::Validation(errors) => ApiError::validation(errors),
+            AppError::Conflict(field) => ApiError::conflict(field),
+            AppError::LockedOut(_) => ApiError::new(self.code(), self.to_string()),
+            AppError::InternalServerError => ApiError::new(self.code(), self.code().title()),
+        }
+    }
+}
 
-    #[error("Unauthorized")]
-    Unauthorized,
+impl From<RepoError> for AppError {
+    fn from(error: RepoError) -> Self {
+        match error {
+            RepoError::NotFound => AppError::not_found("Record not found"),
+            RepoError::Conflict(field) => AppError::Conflict(field),
+            RepoError::Backend(error) => {
+                // The response drops the cause; refusals are already logged with their response
+                tracing::error!(error = %error, "storage error");
+                AppError::InternalServerError
+            }
+        }
+    }
+}
 
-    #[error("SQL failed: {0:?}")]
-    Sqlx(#[from] sqlx::Error),
+impl From<OidcError> for AppError {
+    fn from(error: OidcError) -> Self {
+        match error {
+            OidcError::UnknownProvider(_) => AppError::not_found("Provider not found"),
+            OidcError::Rejected(_) => AppError::Unauthorized("Login was rejected"),
+            OidcError::MissingEmail => field_error("email", "is required").into(),
+            OidcError::Invalid(errors) => errors.into(),
+            OidcError::EmailTaken => AppError::Conflict("email".to_string()),
+            OidcError::Provider(_) => AppError::InternalServerError,
+            OidcError::Repo(error) => error.into(),
+        }
+    }
+}
 
-    #[error("JWT error: {0:?}")]
-    JwtError(#[from] jsonwebtoken::errors::Error),
+impl From<ChallengeError> for AppError {
+    fn from(error: ChallengeError) -> Self {
+        match error {
+            ChallengeError::Invalid => AppError::Unauthorized("Invalid challenge"),
+            ChallengeError::Refused => AppError::Unauthorized("Invalid code"),
+            ChallengeError::LockedOut(until) => AppError::LockedOut(until),
+            ChallengeError::Repo(error) => error.into(),
+        }
+    }
+}
 
-    #[error("Invalid request: {0:?}")]
-    Validation(#[from] validator::ValidationErrors),
+impl From<CredentialError> for AppError {
+    fn from(error: CredentialError) -> Self {
+        match error {
+            CredentialError::Malformed => AppError::Unauthorized("Invalid authorization header"),
+            CredentialError::UnsupportedScheme => {
+                AppError::Unauthorized("Invalid authorization method")
+            }
+            CredentialError::Csrf => AppError::Forbidden("the CSRF token is missing or wrong"),
+        }
+    }
+}
+
+impl From<JwtError> for AppError {
+    fn from(error: JwtError) -> Self {
+        match error.kind() {
+            JwtErrorKind::InvalidToken => AppError::Unauthorized("Token is invalid"),
+            JwtErrorKind::InvalidIssuer => AppError::Unauthorized("Issuer is invalid"),
+            _ => AppError::Unauthorized("An issue was found with the token provided"),
+        }
+    }
+}
+
+impl From<password_hash::Error> for AppError {
+    fn from(_error: password_hash::Error) -> Self {
+        AppError::InternalServerError
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+impl From<Malformed> for AppError {
+    fn from(malformed: Malformed) -> Self {
+        match malformed {
+            Malformed::Path => AppError::not_found(malformed.message()),
+            _ => AppError::UnprocessableEntity(malformed.message()),
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        let malformed = match rejection {
+            JsonRejection::MissingJsonContentType(_) => Malformed::ContentType,
+            JsonRejection::JsonDataError(_) => Malformed::Data,
+            JsonRejection::BytesRejection(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                Malformed::TooLarge
+            }
+            _ => Malformed::Syntax,
+        };
+        malformed.into()
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(_rejection: PathRejection) -> Self {
+        Malformed::Path.into()
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(_rejection: QueryRejection) -> Self {
+        Malformed::Query.into()
+    }
 }
 
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        println!("Error: {:?}", self);
-
-        let (status, error_message) = match self {
-            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, None),
-            AppError::JwtError(_) => (StatusCode::UNAUTHORIZED, None),
-            AppError::Sqlx(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
-            AppError::Anyhow(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
-            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, Some(self.to_string())),
-            AppError::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, Some(self.to_string())),
-            AppError::DBError(db_error) => {
-                let message = db_error.to_string();
-
-                match db_error {
-                    DBError::NotFound => (StatusCode::NOT_FOUND, Some(message)),
-                    _ => (StatusCode::OK, Some(message)),
-                }
+        let error = self.api_error();
+        let status =
+            StatusCode::from_u16(error.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        match status.is_server_error() {
+            true => tracing::error!(error = ?self, code = error.code.as_str(), "internal error"),
+            false => tracing::debug!(error = %self, code = error.code.as_str(), "request refused"),
+        }
+        let mut response = (status, Json(error.legacy_body())).into_response();
+
+        if let AppError::LockedOut(until) = self {
+            let retry_after = lockout::retry_after(until).to_string();
+            if let Ok(value) = retry_after.parse() {
+                response.headers_mut().insert(header::RETRY_AFTER, value);
             }
-        };
+        }
 
-        let body = Json(json!({
-            "error": error_message.unwrap_or_else(|| status.canonical_reason().unwrap().to_string()),
-        }));
+        // For `problem_details` to find
+        response.extensions_mut().insert(error);
+        response
+    }
+}
+
+/// Answers with RFC 7807 problem details instead of the RealWorld shape when the request
+/// accepts them, see [`ApiError`].
+pub async fn problem_details<B>(request: Request<B>, next: Next<B>) -> Response {
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(str::to_string);
+
+    let mut response = next.run(request).await;
+    if !accepts_problem(accept.as_deref()) {
+        return response;
+    }
+    let Some(error) = response.extensions_mut().remove::<ApiError>() else {
+        return response;
+    };
 
-        (status, body).into_response()
+    let mut problem = (
+        response.status(),
+        [(header::CONTENT_TYPE, PROBLEM_JSON)],
+        Json(error.problem_body()),
+    )
+        .into_response();
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            problem.headers_mut().append(name, value.clone());
+        }
     }
+    problem
 }
@@ -0,0 +1,103 @@
+pub mod api;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod routes;
+pub mod telemetry;
+pub mod utils;
+
+use axum::extract::FromRef;
+use realworld_core::{
+    config::TokenConfig, credentials::CookieConfig, keys::KeyRing, lockout::LockoutConfig,
+    mail::Outbox, metrics::Metrics, oidc::Oidc, policy::RoleConfig, privacy::PrivacyConfig,
+    user::UsernameConfig,
+};
+use sqlx::PgPool;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub repos: db::Repos,
+    keys: KeyRing,
+    tokens: TokenConfig,
+    outbox: Outbox,
+    oidc: Oidc,
+    roles: RoleConfig,
+    lockout: LockoutConfig,
+    cookies: CookieConfig,
+    privacy: PrivacyConfig,
+    usernames: UsernameConfig,
+    metrics: Metrics,
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(app_state: &AppState) -> PgPool {
+        app_state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for db::Repos {
+    fn from_ref(app_state: &AppState) -> db::Repos {
+        app_state.repos.clone()
+    }
+}
+
+impl FromRef<AppState> for KeyRing {
+    fn from_ref(app_state: &AppState) -> KeyRing {
+        app_state.keys.clone()
+    }
+}
+
+impl FromRef<AppState> for TokenConfig {
+    fn from_ref(app_state: &AppState) -> TokenConfig {
+        app_state.tokens.clone()
+    }
+}
+
+impl FromRef<AppState> for Outbox {
+    fn from_ref(app_state: &AppState) -> Outbox {
+        app_state.outbox.clone()
+    }
+}
+
+impl FromRef<AppState> for Oidc {
+    fn from_ref(app_state: &AppState) -> Oidc {
+        app_state.oidc.clone()
+    }
+}
+
+impl FromRef<AppState> for RoleConfig {
+    fn from_ref(app_state: &AppState) -> RoleConfig {
+        app_state.roles.clone()
+    }
+}
+
+impl FromRef<AppState> for LockoutConfig {
+    fn from_ref(app_state: &AppState) -> LockoutConfig {
+        app_state.lockout.clone()
+    }
+}
+
+impl FromRef<AppState> for CookieConfig {
+    fn from_ref(app_state: &AppState) -> CookieConfig {
+        app_state.cookies.clone()
+    }
+}
+
+impl FromRef<AppState> for PrivacyConfig {
+    fn from_ref(app_state: &AppState) -> PrivacyConfig {
+        app_state.privacy.clone()
+    }
+}
+
+impl FromRef<AppState> for UsernameConfig {
+    fn from_ref(app_state: &AppState) -> UsernameConfig {
+        app_state.usernames.clone()
+    }
+}
+
+impl FromRef<AppState> for Metrics {
+    fn from_ref(app_state: &AppState) -> Metrics {
+        app_state.metrics.clone()
+    }
+}
@@ -1,50 +1,89 @@
-mod api;
-mod db;
-mod error;
-mod routes;
-mod utils;
-
-use axum::extract::FromRef;
-use jsonwebtoken::{DecodingKey, EncodingKey};
+use realworld_core::{
+    config::{self, Storage},
+    mail::{Outbox, Transport},
+    metrics::Metrics,
+    oidc::Oidc,
+};
 use shuttle_runtime::CustomError;
 use shuttle_secrets::SecretStore;
 use sqlx::PgPool;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub pool: PgPool,
-    encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
-}
-
-impl FromRef<AppState> for PgPool {
-    fn from_ref(app_state: &AppState) -> PgPool {
-        app_state.pool.clone()
-    }
-}
-
-impl FromRef<AppState> for EncodingKey {
-    fn from_ref(app_state: &AppState) -> EncodingKey {
-        app_state.encoding_key.clone()
-    }
-}
-
-impl FromRef<AppState> for DecodingKey {
-    fn from_ref(app_state: &AppState) -> DecodingKey {
-        app_state.decoding_key.clone()
-    }
-}
+use static_next_server::{config::Config, db, routes};
 
 #[shuttle_runtime::main]
 async fn axum(
     #[shuttle_secrets::Secrets] secret_store: SecretStore,
     // #[shuttle_static_folder::StaticFolder] static_folder: PathBuf,
-    #[shuttle_aws_rds::Postgres] pool: PgPool,
+    #[shuttle_aws_rds::Postgres] provisioned: PgPool,
 ) -> shuttle_axum::ShuttleAxum {
-    db::prepare_db(&pool).await.map_err(CustomError::new)?;
+    // Same settings as the standalone `sqlx-server` binary, but on the provisioned database.
+    let mut config: Config = config::load().map_err(CustomError::new)?;
+
+    // Secrets take precedence over the settings they stand for.
+    if let (Some(private_key), Some(public_key)) = (
+        secret_store.get("PRIVATE_KEY"),
+        secret_store.get("PUBLIC_KEY"),
+    ) {
+        config.jwt.private_key = private_key;
+        config.jwt.public_key = public_key;
+    }
+    if let Some(smtp_url) = secret_store.get("SMTP_URL") {
+        config.mail.transport = Transport::Smtp;
+        config.mail.smtp_url = smtp_url;
+    }
+    // Emails of admins, separated by commas
+    if let Some(admins) = secret_store.get("ADMINS") {
+        config.roles.admins = admins
+            .split(',')
+            .map(|email| email.trim().to_string())
+            .collect();
+    }
+    if let Some(token) = secret_store.get("METRICS_TOKEN") {
+        config.metrics.token = token;
+    }
+    // Only safe while the server can't be reached but through Shuttle's proxy
+    if let Some(trust_proxy) = secret_store.get("TRUST_PROXY") {
+        config.lockout.trust_proxy = trust_proxy.parse().map_err(CustomError::new)?;
+    }
+    if let Some(max_connections) = secret_store.get("MAX_CONNECTIONS") {
+        config.pool.max_connections = max_connections.parse().map_err(CustomError::new)?;
+    }
+
+    let keys = config.jwt.key_ring().map_err(CustomError::new)?;
+
+    // `shuttle_aws_rds` opens at most 5 connections, so reconnect with the configured pool size
+    let pool = config
+        .pool_options()
+        .connect_lazy_with(provisioned.connect_options().clone());
+    provisioned.close().await;
+
+    let mut metrics = Metrics::new(&config.metrics);
+    let repos = match config.storage {
+        Storage::Memory => db::Repos::in_memory(),
+        Storage::Postgres => {
+            db::prepare_db(&pool).await.map_err(CustomError::new)?;
+            let (stats_pool, max) = (pool.clone(), config.pool.max_connections);
+            metrics = metrics.with_pool(move || db::pool_stats(&stats_pool, max));
+            db::PgRepo::new(pool.clone()).into_repos()
+        }
+    };
 
-    let private_key = secret_store.get("PRIVATE_KEY").unwrap();
-    let public_key = secret_store.get("PUBLIC_KEY").unwrap();
+    let mailer = config.mail.mailer().map_err(CustomError::new)?;
+    let outbox = Outbox::spawn(&config.mail, repos.outbox.clone(), mailer);
+    let oidc = Oidc::new(&config.oidc);
 
-    Ok(routes::generate_routes(pool, public_key, private_key))
+    Ok(routes::generate_routes(
+        pool,
+        repos,
+        keys,
+        config.tokens,
+        outbox,
+        oidc,
+        config.roles,
+        config.lockout,
+        config.cookies,
+        config.privacy,
+        config.usernames,
+        metrics,
+    )
+    .into())
 }
@@ -1,35 +1,108 @@
 use axum::{
-    error_handling::HandleErrorLayer,
-    http::StatusCode,
-    response::IntoResponse,
+    middleware,
     routing::{delete, get, post, put},
-    BoxError, Router,
+    Router,
+};
+use realworld_core::{
+    config::TokenConfig,
+    credentials::CookieConfig,
+    keys::KeyRing,
+    lockout::LockoutConfig,
+    mail::Outbox,
+    metrics::{Metrics, METRICS_PATH},
+    oidc::Oidc,
+    policy::RoleConfig,
+    privacy::PrivacyConfig,
+    user::UsernameConfig,
 };
-use jsonwebtoken::{DecodingKey, EncodingKey};
-use shuttle_axum::AxumService;
 use sqlx::PgPool;
-use std::time::Duration;
-use tower::{buffer::BufferLayer, limit::RateLimitLayer, ServiceBuilder};
 use tower_http::compression::CompressionLayer;
 
-use crate::{api, db, AppState};
-
-pub fn generate_routes(pool: PgPool, public_key: String, private_key: String) -> AxumService {
-    let encoding_key = EncodingKey::from_rsa_pem(private_key.as_bytes()).unwrap();
-    let decoding_key = DecodingKey::from_rsa_pem(public_key.as_bytes()).unwrap();
+use crate::{
+    api, db,
+    error::{problem_details, AppError},
+    telemetry::trace_requests,
+    AppState,
+};
 
+#[allow(clippy::too_many_arguments)]
+pub fn generate_routes(
+    pool: PgPool,
+    repos: db::Repos,
+    keys: KeyRing,
+    tokens: TokenConfig,
+    outbox: Outbox,
+    oidc: Oidc,
+    roles: RoleConfig,
+    lockout: LockoutConfig,
+    cookies: CookieConfig,
+    privacy: PrivacyConfig,
+    usernames: UsernameConfig,
+    metrics: Metrics,
+) -> Router {
     let state = AppState {
         pool,
-        encoding_key,
-        decoding_key,
+        repos,
+        keys,
+        tokens,
+        outbox,
+        oidc,
+        roles,
+        lockout,
+        cookies,
+        privacy,
+        usernames,
+        metrics: metrics.clone(),
     };
 
     Router::new()
         // ==== USERS ==== //
         .route("/api/users/login", post(api::auth::login)) // login
+        .route("/api/users/login/totp", post(api::totp::login)) // second factor
         .route("/api/users", post(api::auth::registration)) // register
+        .route("/api/users/refresh", post(api::auth::refresh)) // refresh tokens
+        .route("/api/users/logout", post(api::auth::logout)) // end this session
+        .route("/api/users/logout-all", post(api::auth::logout_all)) // end every session
+        .route(
+            "/api/users/password-reset",
+            post(api::account::request_password_reset),
+        ) // mail a password reset link
+        .route(
+            "/api/users/password-reset/confirm",
+            post(api::account::confirm_password_reset),
+        ) // set a new password
+        .route("/api/users/verify-email", post(api::account::verify_email)) // confirm an email
+        .route("/api/users/oidc", get(api::oidc::get_providers)) // list login providers
+        .route(
+            "/api/users/oidc/:provider/authorize",
+            post(api::oidc::authorize),
+        ) // start logging in at a provider
+        .route(
+            "/api/users/oidc/:provider/callback",
+            post(api::oidc::callback),
+        ) // finish logging in at a provider
         .route("/api/user", get(api::user::get_current_user)) // get user
         .route("/api/user", put(api::user::update_user)) // update user
+        .route("/api/user", delete(api::user::delete_user)) // delete account
+        .route("/api/user/export", get(api::user::export_user)) // export personal data
+        .route(
+            "/api/user/verify-email",
+            post(api::account::resend_verification),
+        ) // mail a new verification link
+        .route("/api/user/totp", post(api::totp::enroll)) // start two-factor enrollment
+        .route("/api/user/totp/confirm", post(api::totp::confirm)) // enable two-factor
+        .route(
+            "/api/user/tokens",
+            get(api::access_tokens::get_access_tokens),
+        ) // list personal access tokens
+        .route(
+            "/api/user/tokens",
+            post(api::access_tokens::create_access_token),
+        ) // create a personal access token
+        .route(
+            "/api/user/tokens/:id",
+            delete(api::access_tokens::revoke_access_token),
+        ) // revoke a personal access token
         // ==== PROFILES ==== //
         .route("/api/profiles/:username", get(api::user::get_profile))
         .route(
@@ -45,6 +118,7 @@ pub fn generate_routes(pool: PgPool, public_key: String, private_key: String) ->
         .route("/api/articles", get(api::articles::get_articles)) // get articles
         .route("/api/articles/:slug", get(api::articles::get_article)) // get articles
         .route("/api/articles/feed", get(api::articles::get_feed_articles)) // get articles feed
+        .route("/api/articles/search", get(api::articles::search_articles)) // search articles
         .route("/api/articles/:slug", put(api::articles::update_article)) // update article
         .route("/api/articles/:slug", delete(api::articles::delete_article)) // delete article
         .route(
@@ -55,6 +129,39 @@ pub fn generate_routes(pool: PgPool, public_key: String, private_key: String) ->
             "/api/articles/:slug/favorite",
             delete(api::articles::un_favorite),
         )
+        .route(
+            "/api/articles/:slug/publish",
+            post(api::articles::publish_article),
+        )
+        .route(
+            "/api/articles/:slug/unpublish",
+            post(api::articles::unpublish_article),
+        )
+        .route(
+            "/api/articles/:slug/hide",
+            post(api::articles::hide_article),
+        ) // moderators only
+        .route(
+            "/api/articles/:slug/unhide",
+            post(api::articles::unhide_article),
+        )
+        // ==== REVISIONS ==== //
+        .route(
+            "/api/articles/:slug/revisions",
+            get(api::revisions::get_revisions),
+        )
+        .route(
+            "/api/articles/:slug/revisions/:number",
+            get(api::revisions::get_revision),
+        )
+        .route(
+            "/api/articles/:slug/revisions/:number/diff/:to",
+            get(api::revisions::diff_revisions),
+        )
+        .route(
+            "/api/articles/:slug/revisions/:number/restore",
+            post(api::revisions::restore_revision),
+        )
         // ==== COMMENTS ==== //
         // create comment
         .route(
@@ -71,27 +178,45 @@ pub fn generate_routes(pool: PgPool, public_key: String, private_key: String) ->
             "/api/articles/:slug/comments/:id",
             delete(api::comments::delete_comment),
         )
+        // hide comments, moderators only
+        .route(
+            "/api/articles/:slug/comments/:id/hide",
+            post(api::comments::hide_comment),
+        )
+        .route(
+            "/api/articles/:slug/comments/:id/unhide",
+            post(api::comments::unhide_comment),
+        )
+        // ==== ADMIN ==== //
+        .route("/api/admin/users", get(api::admin::get_users)) // list users
+        .route(
+            "/api/admin/users/:username/role",
+            put(api::admin::update_role),
+        ) // grant a role
+        .route(
+            "/api/admin/users/:username/unlock",
+            post(api::admin::unlock_user),
+        ) // lift a login lockout
         // ==== TAGS ==== //
         .route("/api/tags", get(api::tags::get_tags)) // get tags
-        // ==== DB ==== //
-        .route("/api/initialize", post(db::initialize))
+        // ==== KEYS ==== //
+        .route("/.well-known/jwks.json", get(api::auth::jwks)) // public keys of the ring
+        // ==== METRICS ==== //
+        .route(METRICS_PATH, get(api::metrics::get_metrics))
         .fallback(handler_404)
         .with_state(state)
         .layer(CompressionLayer::new())
-        .layer(
-            ServiceBuilder::new()
-                .layer(HandleErrorLayer::new(|err: BoxError| async move {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Unhandled error: {}", err),
-                    )
-                }))
-                .layer(BufferLayer::new(1024))
-                .layer(RateLimitLayer::new(5, Duration::from_secs(1))),
-        )
-        .into()
+        .layer(middleware::from_fn(problem_details))
+        .layer(middleware::from_fn_with_state(metrics, trace_requests))
+}
+
+/// Serves nothing but `/metrics`, on `metrics.bind`.
+pub fn metrics_routes(metrics: Metrics) -> Router {
+    Router::new()
+        .route(METRICS_PATH, get(api::metrics::scrape))
+        .with_state(metrics)
 }
 
-async fn handler_404() -> impl IntoResponse {
-    (StatusCode::NOT_FOUND, "nothing to see here")
+async fn handler_404() -> AppError {
+    AppError::NotFound(None)
 }
@@ -0,0 +1,29 @@
+SELECT COUNT(*) AS "count!"
+FROM articles
+    INNER JOIN users ON articles.author_id = users.id
+WHERE articles.status = 'published'
+    AND articles.hidden_at IS NULL
+    AND (
+        $1::VARCHAR IS NULL
+        OR users.username = $1
+    )
+    AND (
+        $2::VARCHAR IS NULL
+        OR EXISTS (
+            SELECT 1
+            FROM article_favs
+                INNER JOIN users ON article_favs.user_id = users.id
+            WHERE article_favs.article_id = articles.id
+                AND users.username = $2
+        )
+    )
+    AND (
+        $3::VARCHAR IS NULL
+        OR EXISTS (
+            SELECT 1
+            FROM article_tags
+                INNER JOIN tags ON article_tags.tag_id = tags.id
+            WHERE article_tags.article_id = articles.id
+                AND tags.name = $3
+        )
+    )
\ No newline at end of file
@@ -5,6 +5,8 @@ SELECT articles.id,
     articles.body,
     articles.created_at,
     articles.updated_at,
+    articles.status,
+    articles.published_at,
     COALESCE(
         (
             SELECT array_agg(
@@ -40,12 +42,19 @@ SELECT articles.id,
     ) AS "author!: UserProfile"
 FROM articles
     INNER JOIN users ON articles.author_id = users.id
-WHERE EXISTS (
+WHERE articles.status = 'published'
+    AND articles.hidden_at IS NULL
+    AND EXISTS (
         SELECT 1
         FROM follows
             INNER JOIN users ON follows.followee_id = users.id
         WHERE follows.follower_id = $1
             AND follows.followee_id = articles.author_id
     )
-ORDER BY created_at DESC
+    AND (
+        $4::TIMESTAMPTZ IS NULL
+        OR (articles.created_at, articles.id) < ($4, $5::INT4)
+    )
+ORDER BY articles.created_at DESC,
+    articles.id DESC
 LIMIT $2 OFFSET $3
\ No newline at end of file
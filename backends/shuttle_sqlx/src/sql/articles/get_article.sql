@@ -5,6 +5,8 @@ SELECT articles.id,
     articles.body,
     articles.created_at,
     articles.updated_at,
+    articles.status,
+    articles.published_at,
     COALESCE(
         (
             SELECT array_agg(
@@ -46,4 +48,11 @@ SELECT articles.id,
 FROM articles
     INNER JOIN users ON articles.author_id = users.id
 WHERE articles.slug = $1
+    AND (
+        (
+            articles.status <> 'draft'
+            AND articles.hidden_at IS NULL
+        )
+        OR articles.author_id = $2
+    )
 ORDER BY created_at DESC
\ No newline at end of file
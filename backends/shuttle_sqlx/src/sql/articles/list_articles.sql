@@ -5,6 +5,8 @@ SELECT articles.id,
     articles.body,
     articles.created_at,
     articles.updated_at,
+    articles.status,
+    articles.published_at,
     COALESCE(
         (
             SELECT array_agg(
@@ -48,7 +50,9 @@ SELECT articles.id,
     ) AS "author!: UserProfile"
 FROM articles
     INNER JOIN users ON articles.author_id = users.id
-WHERE (
+WHERE articles.status = 'published'
+    AND articles.hidden_at IS NULL
+    AND (
         $1::VARCHAR IS NULL
         OR users.username = $1
     )
@@ -72,5 +76,10 @@ WHERE (
                 AND tags.name = $3
         )
     )
-ORDER BY created_at DESC
+    AND (
+        $7::TIMESTAMPTZ IS NULL
+        OR (articles.created_at, articles.id) < ($7, $8::INT4)
+    )
+ORDER BY articles.created_at DESC,
+    articles.id DESC
 LIMIT $4 OFFSET $5
\ No newline at end of file
@@ -0,0 +1,78 @@
+SELECT articles.id,
+    articles.slug,
+    articles.title,
+    articles.description,
+    articles.body,
+    articles.created_at,
+    articles.updated_at,
+    articles.status,
+    articles.published_at,
+    COALESCE(
+        (
+            SELECT array_agg(
+                    tags.name
+                    ORDER BY tags.name ASC
+                )
+            FROM article_tags
+                INNER JOIN tags ON article_tags.tag_id = tags.id
+            WHERE article_tags.article_id = articles.id
+        ),
+        '{}'::VARCHAR []
+    ) AS "tag_list!",
+    (
+        $4::INT4 IS NOT NULL
+        AND EXISTS (
+            SELECT 1
+            FROM article_favs
+            WHERE article_favs.article_id = articles.id
+                AND article_favs.user_id = $4
+        )
+    ) AS "favorited!",
+    (
+        SELECT COUNT(*)
+        FROM article_favs
+        WHERE article_favs.article_id = articles.id
+    ) AS "favorites_count!",
+    (
+        users.id,
+        users.username,
+        users.bio,
+        users.image,
+        (
+            $4 IS NOT NULL
+            AND EXISTS (
+                SELECT 1
+                FROM follows
+                WHERE follows.follower_id = $4
+                    AND follows.followee_id = users.id
+            )
+        )
+    ) AS "author!: UserProfile",
+    ts_headline(
+        'english',
+        replace(
+            replace(
+                replace(
+                    replace(replace(articles.body, '&', '&amp;'), '<', '&lt;'),
+                    '>',
+                    '&gt;'
+                ),
+                '"',
+                '&quot;'
+            ),
+            '''',
+            '&#39;'
+        ),
+        query,
+        $5
+    ) AS "snippet!"
+FROM articles
+    INNER JOIN users ON articles.author_id = users.id,
+    to_tsquery('english', $1) AS query
+WHERE articles.search @@ query
+    AND articles.status = 'published'
+    AND articles.hidden_at IS NULL
+ORDER BY ts_rank(articles.search, query) DESC,
+    articles.created_at DESC,
+    articles.id DESC
+LIMIT $2 OFFSET $3
\ No newline at end of file
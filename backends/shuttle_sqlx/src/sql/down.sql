@@ -4,17 +4,41 @@ articles,
 tags,
 article_tags,
 article_favs,
-comments;
+comments,
+article_revisions,
+article_slugs,
+sessions,
+refresh_tokens,
+account_tokens,
+outbox,
+user_totp,
+totp_recovery_codes,
+totp_challenges,
+oidc_logins,
+user_identities,
+access_tokens,
+login_attempts,
+lockout_events;
 DROP INDEX IF EXISTS users_username_idx,
 users_email_idx,
 follows_follower_id_idx,
 follows_followee_id_idx,
 articles_slug_idx,
 articles_author_id_idx,
+articles_search_idx,
+articles_published_idx,
 tags_name_idx,
 article_tags_article_id_idx,
 article_tags_tag_id_idx,
 article_favs_article_id_idx,
 article_favs_user_id_idx,
 comments_author_id_idx,
-comments_article_id_idx;
\ No newline at end of file
+comments_article_id_idx,
+article_slugs_article_id_idx,
+sessions_user_id_idx,
+refresh_tokens_session_id_idx,
+account_tokens_user_id_idx,
+outbox_pending_idx,
+user_identities_user_id_idx,
+access_tokens_user_id_idx,
+lockout_events_key_idx;
\ No newline at end of file
@@ -9,6 +9,41 @@ CREATE TABLE IF NOT EXISTS users (
 );
 CREATE INDEX IF NOT EXISTS users_username_idx ON users (username);
 CREATE INDEX IF NOT EXISTS users_email_idx ON users (email);
+-- Usernames and emails are unique ignoring case too. Databases from before that may have users
+-- that only differ in case, which the indexes would refuse with an unhelpful error, so they are
+-- listed instead, to be renamed by hand --
+DO $$
+DECLARE
+    clashes TEXT;
+BEGIN
+    IF to_regclass('users_username_lower_key') IS NOT NULL
+        AND to_regclass('users_email_lower_key') IS NOT NULL THEN
+        RETURN;
+    END IF;
+    SELECT string_agg(format('%s %L (ids %s)', field, value, ids), '; ')
+    INTO clashes
+    FROM (
+            SELECT 'username' AS field,
+                lower(username) AS value,
+                string_agg(id::TEXT, ', ' ORDER BY id) AS ids
+            FROM users
+            GROUP BY lower(username)
+            HAVING COUNT(*) > 1
+            UNION ALL
+            SELECT 'email',
+                lower(email),
+                string_agg(id::TEXT, ', ' ORDER BY id)
+            FROM users
+            GROUP BY lower(email)
+            HAVING COUNT(*) > 1
+        ) AS duplicates;
+    IF clashes IS NOT NULL THEN
+        RAISE EXCEPTION 'users differ only in case: %', clashes
+            USING HINT = 'Rename all but one user of each before creating the case-insensitive unique indexes';
+    END IF;
+END $$;
+CREATE UNIQUE INDEX IF NOT EXISTS users_username_lower_key ON users (lower(username));
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_key ON users (lower(email));
 -- Follows --
 CREATE TABLE IF NOT EXISTS follows (
     follower_id INTEGER NOT NULL,
@@ -33,6 +68,20 @@ CREATE TABLE IF NOT EXISTS articles (
 );
 CREATE INDEX IF NOT EXISTS articles_slug_idx ON articles (slug);
 CREATE INDEX IF NOT EXISTS articles_author_id_idx ON articles (author_id);
+ALTER TABLE articles
+ADD COLUMN IF NOT EXISTS search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A') || setweight(to_tsvector('english', description), 'B') || setweight(to_tsvector('english', body), 'C')
+    ) STORED;
+CREATE INDEX IF NOT EXISTS articles_search_idx ON articles USING GIN (search);
+ALTER TABLE articles
+ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'published' CHECK (status IN ('draft', 'published', 'unlisted')),
+    ADD COLUMN IF NOT EXISTS published_at TIMESTAMPTZ;
+UPDATE articles
+SET published_at = created_at
+WHERE status = 'published'
+    AND published_at IS NULL;
+CREATE INDEX IF NOT EXISTS articles_published_idx ON articles (created_at DESC, id DESC)
+WHERE status = 'published';
 -- Tags --
 CREATE TABLE IF NOT EXISTS tags (
     id SERIAL NOT NULL PRIMARY KEY,
@@ -71,4 +120,190 @@ CREATE TABLE IF NOT EXISTS comments (
     FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE
 );
 CREATE INDEX IF NOT EXISTS comments_author_id_idx ON comments (author_id);
-CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id);
+-- Article Slugs, the ones articles had before being renamed --
+CREATE TABLE IF NOT EXISTS article_slugs (
+    slug VARCHAR(255) NOT NULL PRIMARY KEY,
+    article_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS article_slugs_article_id_idx ON article_slugs (article_id);
+-- Article Revisions --
+CREATE TABLE IF NOT EXISTS article_revisions (
+    article_id INTEGER NOT NULL,
+    number INTEGER NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    description TEXT NOT NULL,
+    body TEXT NOT NULL,
+    changed_fields TEXT [] NOT NULL,
+    author_id INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (article_id, number),
+    FOREIGN KEY (article_id) REFERENCES articles(id) ON DELETE CASCADE,
+    FOREIGN KEY (author_id) REFERENCES users(id)
+);
+-- Articles written before revisions existed start their history at their current content
+INSERT INTO article_revisions (
+        article_id,
+        number,
+        title,
+        description,
+        body,
+        changed_fields,
+        author_id,
+        created_at
+    )
+SELECT articles.id,
+    1,
+    articles.title,
+    articles.description,
+    articles.body,
+    ARRAY ['title', 'description', 'body'],
+    articles.author_id,
+    articles.updated_at
+FROM articles
+WHERE NOT EXISTS (
+        SELECT 1
+        FROM article_revisions
+        WHERE article_revisions.article_id = articles.id
+    );
+-- Sessions --
+CREATE TABLE IF NOT EXISTS sessions (
+    id SERIAL NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
+-- Refresh Tokens, hashed. Used ones are kept so a replayed token can be told apart --
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    session_id INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_id_idx ON refresh_tokens (session_id);
+-- Email Verification --
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ;
+-- Account Tokens, hashed and single use, mailed for password resets and email verification --
+CREATE TABLE IF NOT EXISTS account_tokens (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    purpose TEXT NOT NULL CHECK (purpose IN ('password_reset', 'email_verification')),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS account_tokens_user_id_idx ON account_tokens (user_id);
+-- Outbox, mail waiting to be delivered --
+CREATE TABLE IF NOT EXISTS outbox (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS outbox_pending_idx ON outbox (next_attempt_at)
+WHERE delivered_at IS NULL;
+-- TOTP, a second factor. Only enabled once a code was confirmed --
+CREATE TABLE IF NOT EXISTS user_totp (
+    user_id INTEGER NOT NULL PRIMARY KEY,
+    secret TEXT NOT NULL,
+    enabled_at TIMESTAMPTZ,
+    last_step BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+-- TOTP Recovery Codes, hashed and single use --
+CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMPTZ,
+    PRIMARY KEY (user_id, code_hash),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+-- TOTP Challenges, given wrong codes or spent, until they expire --
+CREATE TABLE IF NOT EXISTS totp_challenges (
+    id TEXT NOT NULL PRIMARY KEY,
+    failures INTEGER NOT NULL DEFAULT 0,
+    spent_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+-- OIDC Logins, waiting for the provider to send the user back --
+CREATE TABLE IF NOT EXISTS oidc_logins (
+    state_hash TEXT NOT NULL PRIMARY KEY,
+    provider TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+-- User Identities, accounts at OIDC providers linked to users --
+CREATE TABLE IF NOT EXISTS user_identities (
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, subject),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS user_identities_user_id_idx ON user_identities (user_id);
+-- Personal Access Tokens, hashed and limited to their scopes --
+CREATE TABLE IF NOT EXISTS access_tokens (
+    id SERIAL NOT NULL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    scopes TEXT [] NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS access_tokens_user_id_idx ON access_tokens (user_id);
+-- Roles, and content hidden by moderators --
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user' CHECK (role IN ('user', 'moderator', 'admin'));
+ALTER TABLE articles
+ADD COLUMN IF NOT EXISTS hidden_at TIMESTAMPTZ;
+ALTER TABLE comments
+ADD COLUMN IF NOT EXISTS hidden_at TIMESTAMPTZ;
+-- Login Attempts, failed logins by account or client address --
+CREATE TABLE IF NOT EXISTS login_attempts (
+    key TEXT NOT NULL PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMPTZ
+);
+-- Lockout Events, every time failed logins locked a key --
+CREATE TABLE IF NOT EXISTS lockout_events (
+    id BIGSERIAL NOT NULL PRIMARY KEY,
+    key TEXT NOT NULL,
+    failures INTEGER NOT NULL,
+    locked_until TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS lockout_events_key_idx ON lockout_events (key);
+-- Cascades, so deleting a user removes what they wrote, their follows and favorites --
+ALTER TABLE follows DROP CONSTRAINT IF EXISTS follows_follower_id_fkey,
+    DROP CONSTRAINT IF EXISTS follows_followee_id_fkey,
+    ADD CONSTRAINT follows_follower_id_fkey FOREIGN KEY (follower_id) REFERENCES users(id) ON DELETE CASCADE,
+    ADD CONSTRAINT follows_followee_id_fkey FOREIGN KEY (followee_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE article_favs DROP CONSTRAINT IF EXISTS article_favs_user_id_fkey,
+    ADD CONSTRAINT article_favs_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_author_id_fkey,
+    ADD CONSTRAINT articles_author_id_fkey FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE;
+ALTER TABLE comments DROP CONSTRAINT IF EXISTS comments_author_id_fkey,
+    ADD CONSTRAINT comments_author_id_fkey FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE CASCADE;
+-- Revisions outlive their editor, even on other people's articles; they just lose the author --
+ALTER TABLE article_revisions ALTER COLUMN author_id DROP NOT NULL,
+    DROP CONSTRAINT IF EXISTS article_revisions_author_id_fkey,
+    ADD CONSTRAINT article_revisions_author_id_fkey FOREIGN KEY (author_id) REFERENCES users(id) ON DELETE SET NULL;
\ No newline at end of file
@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use realworld_core::{
+    metrics::Metrics,
+    telemetry::{self, REQUEST_ID_HEADER},
+};
+use tracing::Instrument;
+
+/// Runs every request in a span, see `realworld_core::telemetry`, echoes its `X-Request-Id`
+/// and counts it in the metrics.
+pub async fn trace_requests<B>(
+    State(metrics): State<Metrics>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let started = Instant::now();
+    let request_id = telemetry::request_id(
+        request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok()),
+    );
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("fallback", MatchedPath::as_str)
+        .to_string();
+    let method = request.method().clone();
+    let span = telemetry::request_span(method.as_str(), &route, &request_id);
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+    let status = response.status().as_u16();
+    telemetry::finish_request(&span, status, started);
+    metrics.observe_request(method.as_str(), &route, status, started.elapsed());
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -1,3 +1,4 @@
 pub mod auth;
+pub mod extract;
 pub mod hasher;
 pub mod jwt;
@@ -1,55 +1,269 @@
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
-use serde::Serialize;
-use sqlx::FromRow;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{
+        header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+        request::Parts,
+        HeaderMap, HeaderValue,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use realworld_core::{
+    access_token::{self, Scope},
+    config::TokenConfig,
+    credentials::{CookieConfig, RequestCredentials, CSRF_HEADER},
+    keys::KeyRing,
+    lockout::{LockoutConfig, FORWARDED_FOR},
+    policy::{Action, RoleConfig},
+    repo::{RepoError, User, UserChanges},
+    telemetry,
+    token::{hash_token, new_token},
+    user::UserResponse,
+};
 
 use crate::{
+    db::Repos,
     error::{AppError, AppResult},
-    utils::jwt::Claims,
 };
 
+use super::jwt::{self, Claims};
+
 pub type UserId = i32;
 
-#[derive(Debug, Default, Serialize, FromRow)]
-pub struct UserAuth {
-    #[serde(skip)]
-    pub id: UserId,
-    #[serde(skip)]
-    pub hash: String,
-    pub email: String,
-    pub username: String,
-    pub bio: Option<String>,
-    pub image: Option<String>,
-    pub token: Option<String>,
-}
-
-impl UserAuth {
-    pub fn generate_jwt(&mut self, key: &EncodingKey) -> AppResult<()> {
-        let exp = (chrono::Utc::now() + chrono::Duration::days(30)).timestamp();
-        let claims = Claims {
-            exp,
-            user_id: self.id,
-        };
-        let token = jsonwebtoken::encode(&Header::new(Algorithm::RS384), &claims, key)?;
-        self.token = Some(token);
-        Ok(())
+/// The claims of a token whose session hasn't been revoked, since logging out ends a session
+/// before its access tokens expire. Personal access tokens are refused, they can't manage the
+/// account they belong to.
+pub async fn verify_session(repos: &Repos, token: &str, keys: &KeyRing) -> AppResult<Claims> {
+    if access_token::is_access_token(token) {
+        return Err(AppError::Forbidden("a login is required"));
+    }
+
+    let claims = jwt::verify_jwt(token, keys)?;
+
+    match repos.sessions.find(claims.sid).await {
+        Ok(session) if session.user_id == claims.user_id => {
+            telemetry::record_user(claims.user_id);
+            Ok(claims)
+        }
+        Ok(_) | Err(RepoError::NotFound) => Err(AppError::Unauthorized("Invalid Token")),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The user a login token or a personal access token was issued to. Login tokens may be used
+/// for anything, access tokens only for the scopes they were created with.
+pub async fn verify_token(
+    repos: &Repos,
+    token: &str,
+    keys: &KeyRing,
+    scope: Scope,
+) -> AppResult<UserId> {
+    if !access_token::is_access_token(token) {
+        return Ok(verify_session(repos, token, keys).await?.user_id);
+    }
+
+    let access_token = match repos.access_tokens.authenticate(&hash_token(token)).await {
+        Ok(access_token) => access_token,
+        Err(RepoError::NotFound) => return Err(AppError::Unauthorized("Invalid Token")),
+        Err(err) => return Err(err.into()),
+    };
+    if !access_token.allows(scope) {
+        return Err(AppError::Forbidden("the token lacks the required scope"));
     }
 
-    pub fn decode_jwt(&self, key: &DecodingKey) -> AppResult<Claims> {
-        let Some(token) = self.token.as_ref() else{
-            return Err(AppError::Unauthorized)
+    telemetry::record_user(access_token.user_id);
+    Ok(access_token.user_id)
+}
+
+/// Like [`verify_token`], for endpoints that anonymous users can call too.
+pub async fn verify_optional(
+    repos: &Repos,
+    token: MaybeToken,
+    keys: &KeyRing,
+    scope: Scope,
+) -> AppResult<Option<UserId>> {
+    match token {
+        MaybeToken(Some(token)) => Ok(Some(verify_token(repos, &token, keys, scope).await?)),
+        MaybeToken(None) => Ok(None),
+    }
+}
+
+/// Resolves the user a login token was issued to.
+pub async fn auth_user(repos: &Repos, token: &str, keys: &KeyRing) -> AppResult<User<UserId>> {
+    let user_id = verify_session(repos, token, keys).await?.user_id;
+    Ok(repos.users.find(user_id).await?)
+}
+
+/// Consults the policy on whether `user_id` may do `action` to something written by `owner`,
+/// if anyone.
+pub async fn permit(
+    repos: &Repos,
+    user_id: UserId,
+    action: Action,
+    owner: Option<UserId>,
+) -> AppResult<()> {
+    let user = repos.users.find(user_id).await?;
+    match user.may(action, owner.as_ref()) {
+        true => Ok(()),
+        false => Err(AppError::Forbidden(action.denied())),
+    }
+}
+
+/// Logs `user` in on a new session, answering with both of its tokens. Admins listed in
+/// `roles` are promoted first.
+pub async fn start_session(
+    repos: &Repos,
+    tokens: &TokenConfig,
+    keys: &KeyRing,
+    roles: &RoleConfig,
+    mut user: User<UserId>,
+) -> AppResult<UserResponse> {
+    if let Some(role) = roles.promotion(&user) {
+        let changes = UserChanges {
+            role: Some(role),
+            ..Default::default()
         };
+        user = repos.users.update(user.id, changes).await?;
+    }
+
+    let refresh_token = new_token();
+    let session = repos
+        .sessions
+        .create(
+            user.id,
+            &hash_token(&refresh_token),
+            tokens.refresh_expiry(),
+        )
+        .await?;
+
+    let token = jwt::generate_token(user.id, session.id, tokens.access_expiry(), keys)?;
+    Ok(user.into_response(token).with_refresh_token(refresh_token))
+}
 
-        jsonwebtoken::decode_header(token)
-            .map(|header| {
-                jsonwebtoken::decode::<Claims>(
-                    token,
-                    key,
-                    &jsonwebtoken::Validation::new(header.alg),
-                )
-            })
-            .map(|data| match data {
-                Ok(data) => Ok(data.claims),
-                Err(err) => Err(err.into()),
-            })?
+/// The address a request came from, see [`LockoutConfig::client_ip`]. Unknown unless the
+/// server was started with connect info, or trusts a proxy that names it.
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    LockoutConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let forwarded_for = parts
+            .headers
+            .get(FORWARDED_FOR)
+            .and_then(|header| header.to_str().ok());
+
+        Ok(ClientIp(
+            LockoutConfig::from_ref(state).client_ip(peer, forwarded_for),
+        ))
+    }
+}
+
+/// The token a request authenticates with, from its `Authorization` header or a cookie session.
+/// Unauthorized without one.
+pub struct Token(pub String);
+
+/// Like [`Token`], for endpoints that anonymous users can call too. Malformed credentials are
+/// still refused rather than taken for anonymous.
+pub struct MaybeToken(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MaybeToken
+where
+    CookieConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let credentials = request_credentials(parts);
+        Ok(MaybeToken(
+            CookieConfig::from_ref(state).token(&credentials)?,
+        ))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Token
+where
+    CookieConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match MaybeToken::from_request_parts(parts, state).await? {
+            MaybeToken(Some(token)) => Ok(Token(token)),
+            MaybeToken(None) => Err(AppError::Unauthorized("No authorization was provided")),
+        }
+    }
+}
+
+/// The refresh token of a cookie session, if the request carries one.
+pub struct RefreshCookie(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RefreshCookie
+where
+    CookieConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let credentials = request_credentials(parts);
+        Ok(RefreshCookie(
+            CookieConfig::from_ref(state).refresh_token(&credentials)?,
+        ))
+    }
+}
+
+fn request_credentials(parts: &Parts) -> RequestCredentials<'_> {
+    let header = |name| parts.headers.get(name).map(HeaderValue::as_bytes);
+
+    RequestCredentials {
+        authorization: header(AUTHORIZATION.as_str()),
+        cookie: header(COOKIE.as_str()),
+        csrf_token: header(CSRF_HEADER),
+        safe_method: parts.method.is_safe(),
     }
 }
+
+/// Answers with the user of a new or refreshed session, setting the cookies of a cookie
+/// session if they are enabled.
+pub fn session_response(
+    cookies: &CookieConfig,
+    tokens: &TokenConfig,
+    response: UserResponse,
+) -> Response {
+    let headers = set_cookies(cookies.session_cookies(&response, tokens));
+    (headers, Json(response)).into_response()
+}
+
+/// `Set-Cookie` headers ending a cookie session, none unless they are enabled.
+pub fn clear_session(cookies: &CookieConfig) -> HeaderMap {
+    set_cookies(cookies.cleared_cookies())
+}
+
+fn set_cookies(cookies: Vec<String>) -> HeaderMap {
+    cookies
+        .into_iter()
+        .filter_map(|cookie| HeaderValue::try_from(cookie).ok())
+        .map(|cookie| (SET_COOKIE, cookie))
+        .collect()
+}
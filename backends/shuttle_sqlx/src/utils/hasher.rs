@@ -2,8 +2,7 @@ pub fn hash_password(password: impl AsRef<[u8]>) -> crate::error::AppResult<Stri
     let salt = password_hash::SaltString::generate(&mut rand::thread_rng());
 
     let hash =
-        password_hash::PasswordHash::generate(argon2::Argon2::default(), password.as_ref(), &salt)
-            .map_err(|err| anyhow::anyhow!(err))?
+        password_hash::PasswordHash::generate(argon2::Argon2::default(), password.as_ref(), &salt)?
             .to_string();
     Ok(hash)
 }
@@ -1,5 +1,5 @@
-use axum::headers::authorization::Credentials;
-use jsonwebtoken::DecodingKey;
+use chrono::{DateTime, Utc};
+use realworld_core::keys::KeyRing;
 use serde::{Deserialize, Serialize};
 
 use crate::error::AppResult;
@@ -10,40 +10,25 @@ use super::auth::UserId;
 pub struct Claims {
     pub exp: i64,
     pub user_id: UserId,
+    /// The session the token was issued for.
+    pub sid: i32,
 }
 
-#[derive(Debug)]
-pub struct JWTToken(pub String);
-impl Credentials for JWTToken {
-    const SCHEME: &'static str = "Token";
-
-    fn decode(value: &axum::http::HeaderValue) -> Option<Self> {
-        let mut it = value.to_str().ok()?.split_whitespace();
-        let scheme = it.next()?;
-        let token = it.next()?;
-
-        if scheme != Self::SCHEME || it.next().is_some() {
-            None?
-        }
-
-        Some(Self(token.to_string()))
-    }
-
-    fn encode(&self) -> axum::http::HeaderValue {
-        unreachable!()
-    }
+/// An access token for `session`.
+pub fn generate_token(
+    user_id: UserId,
+    session: i32,
+    expires_at: DateTime<Utc>,
+    keys: &KeyRing,
+) -> AppResult<String> {
+    let claims = Claims {
+        exp: expires_at.timestamp(),
+        user_id,
+        sid: session,
+    };
+    Ok(keys.encode(&claims)?)
 }
 
-pub fn verify_token(token: &str, key: &DecodingKey) -> AppResult<UserId> {
-    let claim = verify_jwt(token, key)?;
-    Ok(claim.user_id)
-}
-
-pub fn verify_jwt(token: &str, key: &DecodingKey) -> AppResult<Claims> {
-    let header = jsonwebtoken::decode_header(token)?;
-
-    let claims =
-        jsonwebtoken::decode::<Claims>(token, key, &jsonwebtoken::Validation::new(header.alg))?
-            .claims;
-    Ok(claims)
+pub fn verify_jwt(token: &str, keys: &KeyRing) -> AppResult<Claims> {
+    Ok(keys.decode(token)?)
 }